@@ -0,0 +1,139 @@
+# German; see en.ftl for what each message is
+
+## Errors
+error-database-unavailable = die Datenbank ist gerade nicht erreichbar; versuch es gleich noch einmal
+error-database = Datenbankfehler
+error-body-too-large = der Inhalt der Anfrage ist zu groß
+error-admin-token-required = Admin-Bearer-Token erforderlich
+error-admin-token-invalid = ungültiges Admin-Token
+error-api-key-unknown = unbekannter API-Schlüssel
+error-no-poll = keine Umfrage mit der ID { $id }
+error-no-restaurant = kein Restaurant mit der ID { $id }
+error-no-team = kein Team mit der ID { $id }
+error-no-vote = keine Stimme mit der ID { $id }
+error-no-poll-template = keine Umfragevorlage mit der ID { $id }
+error-voter-name-empty = voter_name darf nicht leer sein
+error-voter-name-missing = der Name darf nicht leer sein
+error-poll-closed = die Umfrage ist geschlossen
+error-poll-id-closed = die Umfrage { $poll } ist geschlossen
+error-poll-already-closed = die Umfrage ist schon geschlossen
+error-poll-nominating = die Umfrage { $poll } nimmt noch Vorschläge an
+error-only-closed-reopened = nur eine geschlossene Umfrage kann wieder geöffnet werden
+error-poll-changed = die Umfrage hat sich seit Version { $expected } geändert und ist jetzt bei Version { $current }; lade sie neu
+error-if-match-required = eine Umfrage zu ändern braucht einen If-Match-Header mit der Version, von der aus sie geändert wurde
+error-unknown-cursor = { $cursor } ist kein Cursor aus { $log }
+error-already-voted = { $voter } hat in dieser Umfrage schon abgestimmt
+error-already-voted-today = { $voter } hat heute schon abgestimmt
+error-link-used = dieser Abstimmungslink wurde schon benutzt
+error-not-voted = { $voter } hat in dieser Umfrage nicht abgestimmt
+error-did-you-mean = { $name } ist kein eingetragenes Restaurant; meinst du eines von diesen?
+error-looks-like = { $name } sieht aus wie ein Restaurant, das es schon gibt; mit allow_similar erneut senden, um es trotzdem hinzuzufügen
+error-not-registered = { $name } ist kein eingetragenes Restaurant
+error-not-approved = { $name } wurde noch nicht freigegeben
+error-inactive = { $name } ist nicht mehr aktiv
+error-not-candidate = { $name } steht in der Umfrage { $poll } nicht zur Wahl
+error-backup-without-poll = backup_restaurant_name gilt nur für Stimmen in einer Umfrage
+error-backup-same = die Ausweichwahl muss ein anderes Restaurant sein
+error-ranked-twice = { $name } ist mehr als einmal gereiht
+error-over-budget = dieser Stimmzettel kostet { $cost } Credits, übrig sind nur { $remaining }
+error-not-on-poll-team = { $voter } ist nicht im Team { $team }, dem diese Umfrage gehört
+error-not-on-team = { $voter } ist nicht im Team { $team }
+error-not-in-runoff = { $voter } hat nicht in der Umfrage abgestimmt, die die Stichwahl { $poll } entscheidet
+error-not-in-this-runoff = { $voter } hat nicht in der Umfrage abgestimmt, die diese Stichwahl entscheidet
+error-no-notification-settings = für { $name } sind keine Benachrichtigungseinstellungen gespeichert
+error-not-time-zone = { $name } ist keine Zeitzone wie Australia/Sydney
+error-not-locale = { $tag } ist keine der vorhandenen Sprachen: en, de, es
+error-ranking-required = die Umfrage { $poll } nimmt Stimmzettel mit Rangfolge: statt restaurant_name und backup_restaurant_name ein ranking senden
+error-ranking-not-allowed = nur Umfragen mit einem Wahlverfahren nach Rangfolge nehmen ein ranking an
+error-ranking-and-single-choice = ein Stimmzettel mit Rangfolge ersetzt restaurant_name und backup_restaurant_name; nur das ranking senden
+error-allocation-required = die Umfrage { $poll } stimmt quadratisch ab: eine allocation mit den Stimmen je Restaurant senden
+error-allocation-not-allowed = nur quadratische Umfragen nehmen eine allocation an
+error-allocation-and-single-choice = eine allocation ersetzt restaurant_name und backup_restaurant_name; nur die allocation senden
+error-allocation-too-few = die Stimmen für { $name } müssen mindestens 1 sein
+error-allocated-twice = { $name } kommt mehr als einmal vor
+error-seal-broken = die Umfrage { $poll } ist versiegelt, aber ihr Schlüssel fehlt oder SEALING_KEY hat sich geändert, daher nimmt sie keine Stimmzettel an
+error-not-the-picker = { $picker } sucht für die Umfrage { $poll } aus; Rotationsumfragen nehmen sonst niemandes Stimme an
+error-no-picker = niemand in der Rotation konnte für die Umfrage { $poll } aussuchen
+error-title-too-long = title darf höchstens { $max } Zeichen lang sein
+error-description-too-long = description darf höchstens { $max } Zeichen lang sein
+error-metadata-not-object = metadata muss ein JSON-Objekt sein
+error-metadata-too-large = metadata darf höchstens { $max } Bytes JSON lang sein
+error-only-for-restaurants = { $settings } gelten nur für Restaurant-Umfragen, nicht für solche mit options
+error-too-few-options = options muss mindestens zwei verschiedene Möglichkeiten enthalten
+error-not-local-time = { $field } muss eine Ortszeit wie 2024-05-17 12:30 sein
+error-both-given = { $first } und { $second } können nicht beide angegeben werden
+error-offset-too-small = die _after_minutes-Abstände müssen mindestens 1 sein
+error-nominations-after-close = die Vorschläge müssen schließen, bevor die Abstimmung schließt
+error-negative-distance = max_distance_meters und max_walking_minutes dürfen nicht negativ sein
+error-credit-budget-too-small = credit_budget muss mindestens 1 sein
+error-quadratic-sealed = quadratische Umfragen können nicht versiegelt werden
+error-runoff-not-allowed = { $method }-Umfragen können nicht in eine Stichwahl gehen
+error-majority-out-of-range = majority_percent muss mindestens 0 und kleiner als 100 sein
+error-unknown-candidates = candidate_ids muss vorhandene Restaurants aufführen
+error-not-nominating = die Umfrage nimmt keine Vorschläge an
+error-nominator-empty = nominated_by darf nicht leer sein
+error-not-nominable = { $name } kann in dieser Umfrage nicht vorgeschlagen werden
+error-already-nominated = { $name } wurde schon vorgeschlagen
+error-rate-limited = zu viele Anfragen; versuch es in { $seconds } Sekunden noch einmal
+
+## Notifications
+poll-opened-title = Mittagsumfrage { $code }
+poll-opened-subject = { $title } ist offen
+poll-opened-text =
+    Hallo { $voter },
+
+    die Umfrage { $code } für das Mittagessen am { $lunch_at } ist zur Abstimmung offen.
+poll-opened-text-until =
+    Hallo { $voter },
+
+    die Umfrage { $code } für das Mittagessen am { $lunch_at } ist bis { $closes_at } zur Abstimmung offen.
+winner-subject = Mittagessen gibt es bei { $winner }
+winner-text =
+    Hallo { $voter },
+
+    die Umfrage { $code } ist geschlossen: Mittagessen am { $lunch_at } gibt es bei { $winner }.
+reminder-subject = Die Mittagsumfrage { $poll } schließt in { $minutes } Minuten
+reminder-text =
+    Hallo { $voter },
+
+    du hast in der Umfrage { $poll } für das Mittagessen am { $lunch_at } noch nicht abgestimmt. Sie schließt in { $minutes } Minuten ({ $closes_at }).
+    { $links }
+    Um diese Erinnerungen abzustellen, schalte sie mit PUT /voters/{ $voter }/notifications/reminder aus.
+reminder-vote-links = Mit einem Klick abstimmen:
+reminder-pick-links = Mit einem Klick auswählen:
+rotation-subject = Du bist dran, das Mittagessen auszusuchen, { $voter }
+rotation-text =
+    Hallo { $voter },
+
+    du bist dran auszusuchen, wo alle am { $lunch_at } zu Mittag essen. Die Umfrage { $poll } schließt in { $minutes } Minuten ({ $closes_at }).
+    { $links }
+    Wenn du nicht kannst, sag per RSVP ab, dann geht die Auswahl an den Nächsten in der Reihe.
+email-unsubscribe = Um keine E-Mails mehr von der Mittagsabstimmung zu bekommen: { $url }
+announcement = Mittagessen am { $lunch_at } gibt es bei { $winner }
+vote-link-title = Abstimmen
+vote-link-button = Meine Stimme abgeben
+
+## The weekly digest
+digest-subject = Mittagessen diese Woche, { $week_of }
+digest-text =
+    Hallo { $name },
+
+    so lief das Mittagessen diese Woche.
+
+    Gewinner
+    { $winners }
+
+    Wer mitgemacht hat
+    { $participation }
+
+    Demnächst
+    { $upcoming }
+
+    Um diese Zusammenfassung nicht mehr zu bekommen: { $unsubscribe_url }
+    Um deine Daten löschen zu lassen: DELETE { $erasure_url }, mit Authorization: Bearer { $erasure_token }
+digest-no-polls = - diese Woche wurde keine Umfrage geschlossen
+digest-winner = - { $lunch_at }: { $winner } ({ $voted } abgestimmt, { $abstained } enthalten)
+digest-office-winner = - { $lunch_at } in { $office }: { $winner } ({ $voted } abgestimmt, { $abstained } enthalten)
+digest-no-winner = kein Gewinner
+digest-participation = { $polls } Umfragen, { $ballots } Stimmzettel und { $abstentions } Enthaltungen von { $people } Personen
+digest-nothing-upcoming = - nichts Außergewöhnliches
@@ -0,0 +1,141 @@
+# English, which the other catalogs are translated from; see i18n.rs. The error-* messages double as the patterns
+# errors are recognised by, so they read exactly as the code writes them, and the more specific of two that could
+# both fit a message come first
+
+## Errors
+error-database-unavailable = the database is unavailable for the moment; try again shortly
+error-database = database error
+error-body-too-large = the request body is too large
+error-admin-token-required = admin bearer token required
+error-admin-token-invalid = invalid admin token
+error-api-key-unknown = unknown API key
+error-no-poll = no poll with id { $id }
+error-no-restaurant = no restaurant with id { $id }
+error-no-team = no team with id { $id }
+error-no-vote = no vote with id { $id }
+error-no-poll-template = no poll template with id { $id }
+error-voter-name-empty = voter_name must not be empty
+error-voter-name-missing = voter name must not be empty
+error-poll-closed = the poll is closed
+error-poll-id-closed = poll { $poll } is closed
+error-poll-already-closed = the poll is already closed
+error-poll-nominating = poll { $poll } is still taking nominations
+error-only-closed-reopened = only a closed poll can be reopened
+error-poll-changed = the poll has changed since version { $expected } and is at version { $current } now; fetch it again
+error-if-match-required = changing a poll needs an If-Match header with the version it was changed from
+error-unknown-cursor = { $cursor } isn't a cursor from { $log }
+error-already-voted = { $voter } has already voted in this poll
+error-already-voted-today = { $voter } has already voted today
+error-link-used = that voting link has already been used
+error-not-voted = { $voter } hasn't voted in this poll
+error-did-you-mean = { $name } is not a registered restaurant; did you mean one of these?
+error-looks-like = { $name } looks like an existing restaurant; resend with allow_similar to add it anyway
+error-not-registered = { $name } is not a registered restaurant
+error-not-approved = { $name } has not been approved yet
+error-inactive = { $name } is no longer active
+error-not-candidate = { $name } is not a candidate in poll { $poll }
+error-backup-without-poll = backup_restaurant_name only applies to votes in a poll
+error-backup-same = the backup choice must be a different restaurant
+error-ranked-twice = { $name } is ranked more than once
+error-over-budget = this ballot costs { $cost } credits and only { $remaining } are left
+error-not-on-poll-team = { $voter } isn't on team { $team }, whose poll this is
+error-not-on-team = { $voter } isn't on team { $team }
+error-not-in-runoff = { $voter } didn't vote in the poll that runoff { $poll } settles
+error-not-in-this-runoff = { $voter } didn't vote in the poll that this runoff settles
+error-no-notification-settings = { $name } has no notification settings saved
+error-not-time-zone = { $name } is not a time zone like Australia/Sydney
+error-not-locale = { $tag } is not one of the locales there are: en, de, es
+error-ranking-required = poll { $poll } takes ranked ballots: send a ranking instead of restaurant_name and backup_restaurant_name
+error-ranking-not-allowed = only polls with a ranked voting method take a ranking
+error-ranking-and-single-choice = a ranked ballot replaces restaurant_name and backup_restaurant_name; send only the ranking
+error-allocation-required = poll { $poll } uses quadratic voting: send an allocation of votes per restaurant
+error-allocation-not-allowed = only quadratic polls take an allocation
+error-allocation-and-single-choice = an allocation replaces restaurant_name and backup_restaurant_name; send only the allocation
+error-allocation-too-few = votes for { $name } must be at least 1
+error-allocated-twice = { $name } appears more than once
+error-seal-broken = poll { $poll } is sealed but its key is gone, or SEALING_KEY has changed, so it can't take ballots
+error-not-the-picker = { $picker } is picking for poll { $poll }; rotation polls take no one else's vote
+error-no-picker = nobody in the rotation could pick for poll { $poll }
+error-title-too-long = title must be at most { $max } characters
+error-description-too-long = description must be at most { $max } characters
+error-metadata-not-object = metadata must be a JSON object
+error-metadata-too-large = metadata must be at most { $max } bytes of JSON
+error-only-for-restaurants = { $settings } only apply to restaurant polls, not ones with options
+error-too-few-options = options must list at least two different choices
+error-not-local-time = { $field } must be a local time like 2024-05-17 12:30
+error-both-given = { $first } and { $second } can't both be given
+error-offset-too-small = the _after_minutes offsets must be at least 1
+error-nominations-after-close = nominations must close before voting does
+error-negative-distance = max_distance_meters and max_walking_minutes must not be negative
+error-credit-budget-too-small = credit_budget must be at least 1
+error-quadratic-sealed = quadratic polls can't be sealed
+error-runoff-not-allowed = { $method } polls can't go to a runoff
+error-majority-out-of-range = majority_percent must be at least 0 and below 100
+error-unknown-candidates = candidate_ids must list existing restaurants
+error-not-nominating = the poll is not taking nominations
+error-nominator-empty = nominated_by must not be empty
+error-not-nominable = { $name } can't be nominated in this poll
+error-already-nominated = { $name } has already been nominated
+error-rate-limited = too many requests; try again in { $seconds } seconds
+
+## Notifications
+poll-opened-title = Lunch poll { $code }
+poll-opened-subject = { $title } is open
+poll-opened-text =
+    Hi { $voter },
+
+    poll { $code } for lunch at { $lunch_at } is open for votes.
+poll-opened-text-until =
+    Hi { $voter },
+
+    poll { $code } for lunch at { $lunch_at } is open for votes, until { $closes_at }.
+winner-subject = Lunch is at { $winner }
+winner-text =
+    Hi { $voter },
+
+    poll { $code } has closed: lunch at { $lunch_at } is at { $winner }.
+reminder-subject = Lunch poll { $poll } closes in { $minutes } minutes
+reminder-text =
+    Hi { $voter },
+
+    you haven't voted in poll { $poll } for lunch at { $lunch_at } yet. It closes in { $minutes } minutes, at { $closes_at }.
+    { $links }
+    To stop these reminders, turn them off with PUT /voters/{ $voter }/notifications/reminder.
+reminder-vote-links = Vote with one click:
+reminder-pick-links = Pick with one click:
+rotation-subject = It's your turn to pick lunch, { $voter }
+rotation-text =
+    Hi { $voter },
+
+    it's your turn to pick where everyone goes for lunch at { $lunch_at }. Poll { $poll } closes in { $minutes } minutes, at { $closes_at }.
+    { $links }
+    If you can't make it, RSVP that you're not coming and the pick goes to the next in line.
+email-unsubscribe = To stop all email from lunch voting: { $url }
+announcement = Lunch at { $lunch_at } is at { $winner }
+vote-link-title = Voting
+vote-link-button = Cast my vote
+
+## The weekly digest, unless DIGEST_TEMPLATE replaces it
+digest-subject = Lunch this week, { $week_of }
+digest-text =
+    Hi { $name },
+
+    here's how lunch went this week.
+
+    Winners
+    { $winners }
+
+    Who took part
+    { $participation }
+
+    Coming up
+    { $upcoming }
+
+    To stop getting this digest: { $unsubscribe_url }
+    To have what's stored about you erased: DELETE { $erasure_url }, with Authorization: Bearer { $erasure_token }
+digest-no-polls = - no polls closed this week
+digest-winner = - { $lunch_at }: { $winner } ({ $voted } voted, { $abstained } abstained)
+digest-office-winner = - { $lunch_at } in { $office }: { $winner } ({ $voted } voted, { $abstained } abstained)
+digest-no-winner = no winner
+digest-participation = { $polls } polls, { $ballots } ballots and { $abstentions } abstentions from { $people } people
+digest-nothing-upcoming = - nothing out of the ordinary
@@ -0,0 +1,139 @@
+# Spanish; see en.ftl for what each message is
+
+## Errors
+error-database-unavailable = la base de datos no está disponible por el momento; vuelve a intentarlo en breve
+error-database = error de la base de datos
+error-body-too-large = el cuerpo de la solicitud es demasiado grande
+error-admin-token-required = se necesita el token de administrador
+error-admin-token-invalid = token de administrador no válido
+error-api-key-unknown = clave de API desconocida
+error-no-poll = no hay ninguna encuesta con el id { $id }
+error-no-restaurant = no hay ningún restaurante con el id { $id }
+error-no-team = no hay ningún equipo con el id { $id }
+error-no-vote = no hay ningún voto con el id { $id }
+error-no-poll-template = no hay ninguna plantilla de encuesta con el id { $id }
+error-voter-name-empty = voter_name no puede estar vacío
+error-voter-name-missing = el nombre no puede estar vacío
+error-poll-closed = la encuesta está cerrada
+error-poll-id-closed = la encuesta { $poll } está cerrada
+error-poll-already-closed = la encuesta ya está cerrada
+error-poll-nominating = la encuesta { $poll } todavía acepta propuestas
+error-only-closed-reopened = solo se puede reabrir una encuesta cerrada
+error-poll-changed = la encuesta ha cambiado desde la versión { $expected } y ahora está en la versión { $current }; vuelve a cargarla
+error-if-match-required = para cambiar una encuesta hace falta una cabecera If-Match con la versión desde la que se cambia
+error-unknown-cursor = { $cursor } no es un cursor de { $log }
+error-already-voted = { $voter } ya ha votado en esta encuesta
+error-already-voted-today = { $voter } ya ha votado hoy
+error-link-used = ese enlace de votación ya se ha usado
+error-not-voted = { $voter } no ha votado en esta encuesta
+error-did-you-mean = { $name } no es un restaurante registrado; ¿querías decir alguno de estos?
+error-looks-like = { $name } parece un restaurante que ya existe; reenvía con allow_similar para añadirlo de todos modos
+error-not-registered = { $name } no es un restaurante registrado
+error-not-approved = { $name } todavía no ha sido aprobado
+error-inactive = { $name } ya no está activo
+error-not-candidate = { $name } no es una opción en la encuesta { $poll }
+error-backup-without-poll = backup_restaurant_name solo sirve para votos en una encuesta
+error-backup-same = la opción de reserva tiene que ser otro restaurante
+error-ranked-twice = { $name } aparece más de una vez en la clasificación
+error-over-budget = esta papeleta cuesta { $cost } créditos y solo quedan { $remaining }
+error-not-on-poll-team = { $voter } no está en el equipo { $team }, al que pertenece esta encuesta
+error-not-on-team = { $voter } no está en el equipo { $team }
+error-not-in-runoff = { $voter } no votó en la encuesta que decide la segunda vuelta { $poll }
+error-not-in-this-runoff = { $voter } no votó en la encuesta que decide esta segunda vuelta
+error-no-notification-settings = { $name } no tiene ajustes de notificaciones guardados
+error-not-time-zone = { $name } no es una zona horaria como Australia/Sydney
+error-not-locale = { $tag } no es ninguno de los idiomas disponibles: en, de, es
+error-ranking-required = la encuesta { $poll } usa papeletas clasificadas: envía un ranking en lugar de restaurant_name y backup_restaurant_name
+error-ranking-not-allowed = solo las encuestas con un método de votación clasificado aceptan un ranking
+error-ranking-and-single-choice = una papeleta clasificada sustituye a restaurant_name y backup_restaurant_name; envía solo el ranking
+error-allocation-required = la encuesta { $poll } usa votación cuadrática: envía una allocation de votos por restaurante
+error-allocation-not-allowed = solo las encuestas cuadráticas aceptan una allocation
+error-allocation-and-single-choice = una allocation sustituye a restaurant_name y backup_restaurant_name; envía solo la allocation
+error-allocation-too-few = los votos para { $name } deben ser al menos 1
+error-allocated-twice = { $name } aparece más de una vez
+error-seal-broken = la encuesta { $poll } está sellada pero su clave ya no está, o SEALING_KEY ha cambiado, así que no puede aceptar papeletas
+error-not-the-picker = { $picker } elige en la encuesta { $poll }; las encuestas por turnos no aceptan el voto de nadie más
+error-no-picker = nadie en la rotación pudo elegir en la encuesta { $poll }
+error-title-too-long = title debe tener como máximo { $max } caracteres
+error-description-too-long = description debe tener como máximo { $max } caracteres
+error-metadata-not-object = metadata debe ser un objeto JSON
+error-metadata-too-large = metadata debe ocupar como máximo { $max } bytes de JSON
+error-only-for-restaurants = { $settings } solo se aplican a encuestas de restaurantes, no a las que tienen options
+error-too-few-options = options debe incluir al menos dos opciones distintas
+error-not-local-time = { $field } debe ser una hora local como 2024-05-17 12:30
+error-both-given = { $first } y { $second } no pueden darse a la vez
+error-offset-too-small = los desfases _after_minutes deben ser al menos 1
+error-nominations-after-close = las propuestas deben cerrarse antes que la votación
+error-negative-distance = max_distance_meters y max_walking_minutes no pueden ser negativos
+error-credit-budget-too-small = credit_budget debe ser al menos 1
+error-quadratic-sealed = las encuestas cuadráticas no se pueden sellar
+error-runoff-not-allowed = las encuestas { $method } no pueden ir a una segunda vuelta
+error-majority-out-of-range = majority_percent debe ser al menos 0 y menor que 100
+error-unknown-candidates = candidate_ids debe incluir restaurantes existentes
+error-not-nominating = la encuesta no acepta propuestas
+error-nominator-empty = nominated_by no puede estar vacío
+error-not-nominable = { $name } no se puede proponer en esta encuesta
+error-already-nominated = { $name } ya ha sido propuesto
+error-rate-limited = demasiadas solicitudes; vuelve a intentarlo dentro de { $seconds } segundos
+
+## Notifications
+poll-opened-title = Encuesta de comida { $code }
+poll-opened-subject = { $title } está abierta
+poll-opened-text =
+    Hola { $voter }:
+
+    la encuesta { $code } para la comida del { $lunch_at } está abierta para votar.
+poll-opened-text-until =
+    Hola { $voter }:
+
+    la encuesta { $code } para la comida del { $lunch_at } está abierta para votar hasta el { $closes_at }.
+winner-subject = La comida es en { $winner }
+winner-text =
+    Hola { $voter }:
+
+    la encuesta { $code } se ha cerrado: la comida del { $lunch_at } es en { $winner }.
+reminder-subject = La encuesta de comida { $poll } se cierra en { $minutes } minutos
+reminder-text =
+    Hola { $voter }:
+
+    todavía no has votado en la encuesta { $poll } para la comida del { $lunch_at }. Se cierra en { $minutes } minutos ({ $closes_at }).
+    { $links }
+    Para dejar de recibir estos recordatorios, desactívalos con PUT /voters/{ $voter }/notifications/reminder.
+reminder-vote-links = Vota con un clic:
+reminder-pick-links = Elige con un clic:
+rotation-subject = Te toca elegir la comida, { $voter }
+rotation-text =
+    Hola { $voter }:
+
+    te toca elegir dónde comemos todos el { $lunch_at }. La encuesta { $poll } se cierra en { $minutes } minutos ({ $closes_at }).
+    { $links }
+    Si no puedes venir, responde que no vienes y la elección pasa al siguiente de la lista.
+email-unsubscribe = Para no recibir más correos de la votación de la comida: { $url }
+announcement = La comida del { $lunch_at } es en { $winner }
+vote-link-title = Votar
+vote-link-button = Emitir mi voto
+
+## The weekly digest
+digest-subject = La comida de esta semana, { $week_of }
+digest-text =
+    Hola { $name }:
+
+    así fue la comida esta semana.
+
+    Ganadores
+    { $winners }
+
+    Quién participó
+    { $participation }
+
+    Próximamente
+    { $upcoming }
+
+    Para dejar de recibir este resumen: { $unsubscribe_url }
+    Para borrar lo que se guarda sobre ti: DELETE { $erasure_url }, con Authorization: Bearer { $erasure_token }
+digest-no-polls = - esta semana no se cerró ninguna encuesta
+digest-winner = - { $lunch_at }: { $winner } ({ $voted } votaron, { $abstained } se abstuvieron)
+digest-office-winner = - { $lunch_at } en { $office }: { $winner } ({ $voted } votaron, { $abstained } se abstuvieron)
+digest-no-winner = sin ganador
+digest-participation = { $polls } encuestas, { $ballots } papeletas y { $abstentions } abstenciones de { $people } personas
+digest-nothing-upcoming = - nada fuera de lo normal
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS votes (
+    id INTEGER PRIMARY KEY,
+    voter_name VARCHAR(255) NOT NULL,
+    restaurant_name VARCHAR(255) NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
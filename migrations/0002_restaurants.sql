@@ -0,0 +1,6 @@
+-- Restaurants are matched to votes by name; cuisine is an optional free-form tag such as 'pizza' or 'thai'
+CREATE TABLE IF NOT EXISTS restaurants (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    cuisine VARCHAR(255)
+);
@@ -0,0 +1,5 @@
+-- price_tier runs from 1 ($) to 4 ($$$$); average_cost is per person; distance and travel time are from the office
+ALTER TABLE restaurants ADD COLUMN price_tier INTEGER CHECK (price_tier BETWEEN 1 AND 4);
+ALTER TABLE restaurants ADD COLUMN average_cost REAL;
+ALTER TABLE restaurants ADD COLUMN distance_meters INTEGER;
+ALTER TABLE restaurants ADD COLUMN travel_minutes INTEGER;
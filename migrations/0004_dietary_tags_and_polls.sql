@@ -0,0 +1,12 @@
+-- Tag lists are stored as JSON arrays of lowercase, hyphenated strings, e.g. '["gluten-free","halal"]'
+ALTER TABLE restaurants ADD COLUMN dietary_tags TEXT NOT NULL DEFAULT '[]';
+
+-- A poll narrows the candidate list: only restaurants carrying every required tag can be voted for in it
+CREATE TABLE IF NOT EXISTS polls (
+    id INTEGER PRIMARY KEY,
+    required_tags TEXT NOT NULL DEFAULT '[]',
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Votes cast without a poll keep poll_id NULL and behave exactly as before
+ALTER TABLE votes ADD COLUMN poll_id INTEGER REFERENCES polls(id);
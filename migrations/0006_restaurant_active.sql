@@ -0,0 +1,3 @@
+-- Inactive restaurants are kept (votes and stats still reference them by name) but are never offered as candidates
+ALTER TABLE restaurants ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE restaurants ADD COLUMN inactive_reason TEXT;
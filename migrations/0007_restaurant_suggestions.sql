@@ -0,0 +1,5 @@
+-- Restaurants registered before suggestions existed were added directly, so they count as approved
+ALTER TABLE restaurants ADD COLUMN status TEXT NOT NULL DEFAULT 'approved'
+    CHECK (status IN ('pending', 'approved', 'rejected'));
+ALTER TABLE restaurants ADD COLUMN suggested_by VARCHAR(255);
+ALTER TABLE restaurants ADD COLUMN review_note TEXT;
@@ -0,0 +1,8 @@
+-- Alternate names for a restaurant ("the taco truck"). alias_key is the alias in fuzzy::match_key form,
+-- which is what incoming names are looked up by
+CREATE TABLE IF NOT EXISTS restaurant_aliases (
+    id INTEGER PRIMARY KEY,
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    alias VARCHAR(255) NOT NULL,
+    alias_key VARCHAR(255) NOT NULL UNIQUE
+);
@@ -0,0 +1,11 @@
+ALTER TABLE restaurants ADD COLUMN address TEXT;
+ALTER TABLE restaurants ADD COLUMN google_rating REAL;
+
+-- Which record in an outside directory (Google Places, OpenStreetMap, ...) a restaurant came from or was
+-- matched to, so importing again updates the same row instead of adding a copy
+CREATE TABLE IF NOT EXISTS restaurant_external_ids (
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    source VARCHAR(32) NOT NULL,
+    external_id VARCHAR(255) NOT NULL,
+    PRIMARY KEY (source, external_id)
+);
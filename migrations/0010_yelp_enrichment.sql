@@ -0,0 +1,6 @@
+-- Filled in by the Yelp enrichment task; yelp_enriched_at records the last attempt, matched or not
+ALTER TABLE restaurants ADD COLUMN yelp_rating REAL;
+ALTER TABLE restaurants ADD COLUMN yelp_categories TEXT; -- JSON array of category titles
+ALTER TABLE restaurants ADD COLUMN yelp_hours TEXT; -- JSON array of Yelp's {day, start, end, is_overnight}
+ALTER TABLE restaurants ADD COLUMN yelp_photos TEXT; -- JSON array of photo URLs
+ALTER TABLE restaurants ADD COLUMN yelp_enriched_at TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE restaurants ADD COLUMN latitude REAL;
+ALTER TABLE restaurants ADD COLUMN longitude REAL;
@@ -0,0 +1,14 @@
+-- Weekly opening hours. weekday follows SQLite's strftime('%w'): 0 is Sunday. Times are 'HH:MM'; a period whose
+-- closes is not after opens runs past midnight. Restaurants without any rows are assumed to always be open
+CREATE TABLE IF NOT EXISTS opening_hours (
+    id INTEGER PRIMARY KEY,
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    weekday INTEGER NOT NULL CHECK (weekday BETWEEN 0 AND 6),
+    opens TEXT NOT NULL,
+    closes TEXT NOT NULL
+);
+
+-- When the team plans to eat, as local 'YYYY-MM-DD HH:MM'; candidates closed at that time are left out
+-- unless ignore_opening_hours is set
+ALTER TABLE polls ADD COLUMN lunch_at TEXT;
+ALTER TABLE polls ADD COLUMN ignore_opening_hours BOOLEAN NOT NULL DEFAULT FALSE;
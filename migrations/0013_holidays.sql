@@ -0,0 +1,13 @@
+-- Public holidays, as local 'YYYY-MM-DD'. No poll is scheduled on them, and restaurants with recorded
+-- opening hours count as closed unless they're known to open on holidays
+CREATE TABLE IF NOT EXISTS holidays (
+    day TEXT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+ALTER TABLE restaurants ADD COLUMN open_on_holidays BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The day a poll was created for by the daily scheduler; the unique index keeps it to one scheduled poll per day.
+-- SQLite can't add a UNIQUE column to an existing table, but a unique index does the same job
+ALTER TABLE polls ADD COLUMN scheduled_for TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS polls_scheduled_for ON polls (scheduled_for);
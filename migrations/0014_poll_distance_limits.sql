@@ -0,0 +1,3 @@
+-- Optional limits on how far a poll's candidates may be from the office
+ALTER TABLE polls ADD COLUMN max_distance_meters INTEGER CHECK (max_distance_meters >= 0);
+ALTER TABLE polls ADD COLUMN max_walking_minutes INTEGER CHECK (max_walking_minutes >= 0);
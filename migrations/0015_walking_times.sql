@@ -0,0 +1,4 @@
+-- Walking time from the office as computed by the routing service, and the route it was computed for
+-- ('office lat,lon;restaurant lat,lon'), so moving either end triggers a fresh lookup
+ALTER TABLE restaurants ADD COLUMN walking_minutes INTEGER;
+ALTER TABLE restaurants ADD COLUMN walking_route TEXT;
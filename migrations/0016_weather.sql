@@ -0,0 +1,5 @@
+-- Whether eating at a restaurant means sitting outside (a food truck, a beer garden), which matters when it rains
+ALTER TABLE restaurants ADD COLUMN outdoor_seating BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The forecast for the office at the poll's lunch time, as JSON, captured when the poll was created
+ALTER TABLE polls ADD COLUMN weather TEXT;
@@ -0,0 +1,2 @@
+-- An explicit shortlist for a poll, as a JSON array of restaurant ids; NULL puts every eligible restaurant on the ballot
+ALTER TABLE polls ADD COLUMN candidate_ids TEXT;
@@ -0,0 +1,14 @@
+-- Polls move from nominating (when they have a nomination window) to open to closed. Times are local 'YYYY-MM-DD HH:MM'
+ALTER TABLE polls ADD COLUMN status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('nominating', 'open', 'closed'));
+ALTER TABLE polls ADD COLUMN nominations_close_at TEXT;
+ALTER TABLE polls ADD COLUMN closes_at TEXT;
+
+-- Restaurants proposed during a poll's nomination window; only these are on the ballot once voting starts
+CREATE TABLE IF NOT EXISTS nominations (
+    id INTEGER PRIMARY KEY,
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    nominated_by TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (poll_id, restaurant_id)
+);
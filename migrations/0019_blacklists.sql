@@ -0,0 +1,12 @@
+-- Restaurants a voter never wants to see again. voter_key is the folded name (see names::fold), so the entry
+-- follows the voter whichever way they spell their name
+CREATE TABLE IF NOT EXISTS voter_blacklist (
+    voter_key TEXT NOT NULL,
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (voter_key, restaurant_id)
+);
+
+-- Who's coming to the poll's lunch, as a JSON array of names, and whether their blacklists apply to the ballot
+ALTER TABLE polls ADD COLUMN attendees TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE polls ADD COLUMN respect_blacklists BOOLEAN NOT NULL DEFAULT FALSE;
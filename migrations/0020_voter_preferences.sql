@@ -0,0 +1,13 @@
+-- What a voter likes and needs, keyed by folded name like voter_blacklist. cuisines and dietary_needs are JSON
+-- arrays; voter_name keeps the spelling used when the preferences were last saved
+CREATE TABLE IF NOT EXISTS voter_preferences (
+    voter_key TEXT PRIMARY KEY,
+    voter_name TEXT NOT NULL,
+    cuisines TEXT NOT NULL DEFAULT '[]',
+    max_price_tier INTEGER CHECK (max_price_tier BETWEEN 1 AND 4),
+    dietary_needs TEXT NOT NULL DEFAULT '[]',
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Whether the attendees' dietary needs and budgets narrow the poll's ballot
+ALTER TABLE polls ADD COLUMN respect_preferences BOOLEAN NOT NULL DEFAULT FALSE;
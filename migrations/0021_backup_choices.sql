@@ -0,0 +1,11 @@
+-- A voter's second choice, counted instead of their first choice if that one drops out of the poll
+ALTER TABLE votes ADD COLUMN backup_restaurant_name VARCHAR(255);
+
+-- Restaurants an admin has ruled out of a poll after the fact, e.g. because it turned out to be fully booked
+CREATE TABLE IF NOT EXISTS poll_unavailable (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    reason TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, restaurant_id)
+);
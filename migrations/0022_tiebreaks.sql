@@ -0,0 +1,7 @@
+-- How a poll settles a tie for first place. tiebreak_seed drives the random draw, fixed at creation so the draw comes
+-- out the same every time the results are read; runoff_poll_id links a poll to the runoff opened to settle its tie
+ALTER TABLE polls ADD COLUMN tiebreak TEXT NOT NULL DEFAULT 'first_vote'
+    CHECK (tiebreak IN ('first_vote', 'closest', 'random', 'fewest_recent_wins', 'runoff'));
+ALTER TABLE polls ADD COLUMN tiebreak_seed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE polls ADD COLUMN runoff_poll_id INTEGER REFERENCES polls(id);
+ALTER TABLE polls ADD COLUMN runoff_of INTEGER REFERENCES polls(id);
@@ -0,0 +1,4 @@
+-- How a poll's ballots are counted. Ranked methods read votes.ranking, a JSON array of restaurant names with the
+-- favourite first; restaurant_name still holds the first choice
+ALTER TABLE polls ADD COLUMN voting_method TEXT NOT NULL DEFAULT 'plurality';
+ALTER TABLE votes ADD COLUMN ranking TEXT;
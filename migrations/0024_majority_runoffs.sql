@@ -0,0 +1,4 @@
+-- A poll whose leader ends up with no more than majority_percent of the voters goes to a runoff between the top two.
+-- A runoff only takes votes from the voters of the poll it settles, listed in eligible_voters; NULL lets anyone vote
+ALTER TABLE polls ADD COLUMN majority_percent REAL;
+ALTER TABLE polls ADD COLUMN eligible_voters TEXT;
@@ -0,0 +1,15 @@
+-- Quadratic polls give every voter credit_budget credits. Each ballot's spending is written to the ledger, one row
+-- per restaurant: the votes it adds and the credits they cost given what the voter had already put there
+ALTER TABLE polls ADD COLUMN credit_budget INTEGER;
+
+CREATE TABLE IF NOT EXISTS vote_credits (
+    id INTEGER PRIMARY KEY,
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    vote_id INTEGER NOT NULL REFERENCES votes(id),
+    voter_key TEXT NOT NULL, -- the folded voter name, see names::fold
+    voter_name TEXT NOT NULL,
+    restaurant_name VARCHAR(255) NOT NULL,
+    votes INTEGER NOT NULL CHECK (votes > 0),
+    credits INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
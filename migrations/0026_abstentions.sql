@@ -0,0 +1,8 @@
+-- Voters who took part in a poll without backing any restaurant. voter_key is the folded name, as in voter_blacklist
+CREATE TABLE IF NOT EXISTS abstentions (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, voter_key)
+);
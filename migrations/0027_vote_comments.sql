@@ -0,0 +1,3 @@
+-- An optional note on a ballot, "only if we leave by 12:15". Admins can remove one later; comment_removed_at records when
+ALTER TABLE votes ADD COLUMN comment TEXT;
+ALTER TABLE votes ADD COLUMN comment_removed_at DATETIME;
@@ -0,0 +1,11 @@
+-- Emoji reactions to a poll's candidates. Each voter can leave each reaction once per restaurant; they never count as
+-- votes. voter_key is the folded name, as in voter_blacklist
+CREATE TABLE IF NOT EXISTS reactions (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    restaurant_id INTEGER NOT NULL REFERENCES restaurants(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    emoji TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, restaurant_id, voter_key, emoji)
+);
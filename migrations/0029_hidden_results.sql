@@ -0,0 +1,2 @@
+-- Polls that keep their results to themselves until they close, so early votes don't sway later ones
+ALTER TABLE polls ADD COLUMN hide_results BOOLEAN NOT NULL DEFAULT 0;
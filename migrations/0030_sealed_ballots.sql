@@ -0,0 +1,11 @@
+-- Sealed polls keep their ballots encrypted here until they close, when they're decrypted into votes and deleted.
+-- Who voted stays readable, for participation counts; what they voted for doesn't
+ALTER TABLE polls ADD COLUMN sealed BOOLEAN NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS sealed_ballots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_name TEXT NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
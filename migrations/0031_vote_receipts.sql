@@ -0,0 +1,9 @@
+-- Every accepted ballot, as signed in its receipt, in a hash chain: each link's hash covers the previous link's hash
+-- and this ballot, so a link that's changed or removed breaks every hash after it
+CREATE TABLE IF NOT EXISTS receipts (
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    body TEXT NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
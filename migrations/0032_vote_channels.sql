@@ -0,0 +1,2 @@
+-- Where each vote came from: web, slack, cli or api. Votes from before this was recorded count as api
+ALTER TABLE votes ADD COLUMN channel TEXT NOT NULL DEFAULT 'api';
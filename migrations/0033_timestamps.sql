@@ -0,0 +1,36 @@
+-- created_at and updated_at on votes, restaurants and polls, kept up to date by the database itself.
+-- ALTER TABLE can't add a column defaulting to CURRENT_TIMESTAMP, so new restaurants get theirs from a trigger,
+-- and updated_at stays NULL until a row's first update; reads fall back to created_at
+-- https://www.sqlite.org/lang_altertable.html#altertabaddcol
+-- https://www.sqlite.org/lang_createtrigger.html
+ALTER TABLE restaurants ADD COLUMN created_at TEXT;
+UPDATE restaurants SET created_at = CURRENT_TIMESTAMP;
+ALTER TABLE restaurants ADD COLUMN updated_at TEXT;
+ALTER TABLE polls ADD COLUMN updated_at TEXT;
+ALTER TABLE votes ADD COLUMN updated_at TEXT;
+
+CREATE TRIGGER IF NOT EXISTS restaurants_created_at AFTER INSERT ON restaurants
+WHEN NEW.created_at IS NULL
+BEGIN
+    UPDATE restaurants SET created_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+-- The WHEN clauses skip updates that set updated_at themselves, like the triggers' own, and a new restaurant being
+-- given its created_at
+CREATE TRIGGER IF NOT EXISTS restaurants_updated_at AFTER UPDATE ON restaurants
+WHEN NEW.updated_at IS OLD.updated_at AND OLD.created_at IS NOT NULL
+BEGIN
+    UPDATE restaurants SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS polls_updated_at AFTER UPDATE ON polls
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE polls SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS votes_updated_at AFTER UPDATE ON votes
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE votes SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
@@ -0,0 +1,33 @@
+-- Random UUIDs (version 4) identify polls and restaurants in the API; the integer ids stay internal, so nobody can
+-- walk through every record by counting, and a restaurant keeps its id when it's moved to another instance.
+-- SQLite has no UUID function, so one is put together from randomblob(), with the version and variant bits set
+-- https://www.rfc-editor.org/rfc/rfc9562#name-uuid-version-4
+ALTER TABLE restaurants ADD COLUMN public_id TEXT;
+ALTER TABLE polls ADD COLUMN public_id TEXT;
+UPDATE restaurants SET public_id = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+    || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1)
+    || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+UPDATE polls SET public_id = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+    || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1)
+    || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)));
+CREATE UNIQUE INDEX IF NOT EXISTS restaurants_public_id ON restaurants (public_id);
+CREATE UNIQUE INDEX IF NOT EXISTS polls_public_id ON polls (public_id);
+
+-- New rows get theirs on insert, unless they come with one
+CREATE TRIGGER IF NOT EXISTS restaurants_public_id AFTER INSERT ON restaurants
+WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE restaurants SET public_id = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+        || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1)
+        || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS polls_public_id AFTER INSERT ON polls
+WHEN NEW.public_id IS NULL
+BEGIN
+    UPDATE polls SET public_id = lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+        || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1)
+        || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))
+    WHERE id = NEW.id;
+END;
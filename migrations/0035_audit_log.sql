@@ -0,0 +1,14 @@
+-- One row per request that tried to change something, whatever came of it; see audit.rs
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor TEXT NOT NULL, -- "admin", the voter named in the request, or "anonymous"
+    actor_key TEXT NOT NULL, -- folded, see names::fold
+    method TEXT NOT NULL,
+    path TEXT NOT NULL, -- with the query string, if there was one
+    status INTEGER NOT NULL,
+    remote_addr TEXT NOT NULL,
+    forwarded_for TEXT, -- the X-Forwarded-For header, as given; only as trustworthy as the proxy in front
+    body TEXT, -- admin requests only, so ballots stay out of the log
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS audit_log_created_at ON audit_log (created_at);
@@ -0,0 +1,27 @@
+-- The append-only stream votes are derived from; see vote_events.rs. Rows are only ever inserted
+CREATE TABLE IF NOT EXISTS vote_events (
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    vote_id INTEGER NOT NULL, -- no foreign key: a retracted vote is gone from votes but not from here
+    type TEXT NOT NULL, -- cast, changed or retracted
+    payload TEXT NOT NULL, -- the event as JSON, type included
+    created_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS vote_events_vote_id ON vote_events (vote_id);
+
+-- Votes from before the stream start it off as casts, and comments already taken down as changes
+INSERT INTO vote_events (vote_id, type, payload, created_at)
+SELECT id, 'cast', json_object(
+    'type', 'cast',
+    'voter_name', voter_name,
+    'restaurant_name', restaurant_name,
+    'poll_id', (SELECT public_id FROM polls WHERE polls.id = votes.poll_id),
+    'backup_restaurant_name', backup_restaurant_name,
+    'ranking', json(ranking),
+    'comment', comment,
+    'channel', channel,
+    'cast_at', created_at
+), created_at
+FROM votes ORDER BY id;
+INSERT INTO vote_events (vote_id, type, payload, created_at)
+SELECT id, 'changed', json_object('type', 'changed', 'comment_removed', json('true')), comment_removed_at
+FROM votes WHERE comment_removed_at IS NOT NULL ORDER BY comment_removed_at;
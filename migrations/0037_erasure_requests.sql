@@ -0,0 +1,6 @@
+-- Requests to erase a voter's data that are waiting to be confirmed; see erasure.rs. One per voter, the latest
+CREATE TABLE IF NOT EXISTS erasure_requests (
+    voter_key TEXT PRIMARY KEY, -- folded, see names::fold
+    token TEXT NOT NULL,
+    expires_at DATETIME NOT NULL
+);
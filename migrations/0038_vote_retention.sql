@@ -0,0 +1,2 @@
+-- Set when a vote older than VOTE_RETENTION_DAYS has its voter's name replaced by a token; see retention.rs
+ALTER TABLE votes ADD COLUMN anonymized_at DATETIME;
@@ -0,0 +1,8 @@
+-- One row per pruning run, dry runs included, with how many rows it took from each table; see retention.rs
+CREATE TABLE IF NOT EXISTS prune_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    dry_run BOOLEAN NOT NULL,
+    horizon_days INTEGER NOT NULL,
+    removed JSON NOT NULL, -- {"polls": 3, "votes": 41, ...}
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,5 @@
+-- Deleting a restaurant or a vote only sets deleted_at; the row stays, in the trash, until an admin restores it.
+-- Everything outside the trash passes over rows that have one; see trash.rs. A vote's deletion and restoration are
+-- events like any other change to it
+ALTER TABLE restaurants ADD COLUMN deleted_at DATETIME;
+ALTER TABLE votes ADD COLUMN deleted_at DATETIME;
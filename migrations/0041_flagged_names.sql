@@ -0,0 +1,13 @@
+-- Voter and restaurant names that matched NAME_FLAGLIST, waiting for or past an admin's review; see moderation.rs.
+-- One row per name, so a name that keeps coming back is reviewed once
+CREATE TABLE IF NOT EXISTS flagged_names (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('voter', 'restaurant')),
+    name TEXT NOT NULL, -- as first submitted
+    name_key TEXT NOT NULL, -- folded, see names::fold
+    word TEXT NOT NULL, -- the NAME_FLAGLIST word it matched
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at DATETIME,
+    UNIQUE (kind, name_key)
+);
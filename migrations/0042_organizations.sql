@@ -0,0 +1,8 @@
+-- Companies sharing one hosted instance; see organizations.rs. Only the host's database has rows here: each
+-- organization's restaurants, voters and polls live in a database of its own. Only a hash of the API key is kept
+CREATE TABLE IF NOT EXISTS organizations (
+    id TEXT PRIMARY KEY, -- a slug of the name, "acme-corp"
+    name TEXT NOT NULL,
+    api_key_hash TEXT NOT NULL UNIQUE, -- SHA-256 of the key, hex
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
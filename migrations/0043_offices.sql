@@ -0,0 +1,19 @@
+-- Offices, each with its own clock, lunch time and pool of restaurants; see offices.rs. Restaurants and polls without
+-- an office belong to the instance as a whole, as they did before offices existed
+CREATE TABLE IF NOT EXISTS offices (
+    id TEXT PRIMARY KEY, -- a slug of the name, "berlin"
+    name TEXT NOT NULL,
+    latitude REAL,
+    longitude REAL,
+    utc_offset_minutes INTEGER NOT NULL DEFAULT 0, -- the office's local time is UTC plus this
+    lunch_time TEXT NOT NULL, -- local HH:MM, like LUNCH_TIME
+    daily_poll_time TEXT, -- local HH:MM, like DAILY_POLL_TIME
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE restaurants ADD COLUMN office_id TEXT REFERENCES offices (id);
+ALTER TABLE polls ADD COLUMN office_id TEXT REFERENCES offices (id);
+
+-- Every office gets its own scheduled poll each day; COALESCE so the instance-wide one is still unique
+DROP INDEX IF EXISTS polls_scheduled_for;
+CREATE UNIQUE INDEX IF NOT EXISTS polls_scheduled_for ON polls (scheduled_for, COALESCE(office_id, ''));
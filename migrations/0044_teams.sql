@@ -0,0 +1,20 @@
+-- Teams and who's on them; see teams.rs. Members are voters, by the spelling their votes are filed under and the
+-- folded key they're compared by
+CREATE TABLE IF NOT EXISTS teams (
+    id TEXT PRIMARY KEY, -- a slug of the name, "platform"
+    name TEXT NOT NULL,
+    office_id TEXT REFERENCES offices (id), -- where the team has lunch, when it's one office's team
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS team_members (
+    team_id TEXT NOT NULL REFERENCES teams (id) ON DELETE CASCADE,
+    voter_name TEXT NOT NULL,
+    voter_key TEXT NOT NULL,
+    captain BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (team_id, voter_key)
+);
+
+-- A team's poll is only open to its members, whoever is on the team when they vote
+ALTER TABLE polls ADD COLUMN team_id TEXT REFERENCES teams (id);
@@ -0,0 +1,12 @@
+-- Invitation links into one poll, for guests who aren't on its roster; see invitations.rs. The link itself is signed
+-- rather than stored, so a leaked copy of this table lets nobody in
+CREATE TABLE IF NOT EXISTS poll_invitations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    poll_id INTEGER NOT NULL REFERENCES polls (id),
+    guest_name TEXT, -- who it's for; when minted for anyone, whoever votes with it first
+    guest_key TEXT, -- guest_name folded, see names::fold
+    expires_at TEXT, -- in the poll's local time; NULL lasts as long as the poll does
+    revoked_at DATETIME,
+    used_at DATETIME, -- the first vote cast with it
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- Short codes like LUNCH-7F3K that someone can read out in standup and everyone else type in, to find the poll
+-- without its UUID. The server picks one for each new poll; see join_codes.rs
+ALTER TABLE polls ADD COLUMN join_code TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS polls_join_code ON polls (join_code);
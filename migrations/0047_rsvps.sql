@@ -0,0 +1,11 @@
+-- Whether someone is coming to a poll's lunch, kept apart from what they voted for: a voter may not be coming, and
+-- someone coming may not vote. voter_key is the folded name, as in abstentions
+CREATE TABLE IF NOT EXISTS rsvps (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    coming BOOLEAN NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME,
+    PRIMARY KEY (poll_id, voter_key)
+);
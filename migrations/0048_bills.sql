@@ -0,0 +1,12 @@
+-- What lunch cost, per person: what they paid towards the bill and what their own meal came to. Amounts are in
+-- cents, so sums come out exact. An unknown meal (NULL) is an even share of whatever the known meals leave over
+CREATE TABLE IF NOT EXISTS bill_entries (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    paid_cents INTEGER NOT NULL DEFAULT 0,
+    spent_cents INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME,
+    PRIMARY KEY (poll_id, voter_key)
+);
@@ -0,0 +1,9 @@
+-- How much a voter means to spend on lunch in a month, in cents. What they've spent comes from the bills of the
+-- month's polls; see budgets.rs. voter_key is the folded name, as in voter_preferences
+CREATE TABLE IF NOT EXISTS voter_budgets (
+    voter_key TEXT PRIMARY KEY,
+    voter_name TEXT NOT NULL,
+    monthly_cents INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME
+);
@@ -0,0 +1,14 @@
+-- What each person wants from the restaurant a poll settled on, one order each, for whoever phones it in.
+-- restaurant_name is the winner when the order was placed, so an order for a place that has since dropped out
+-- (see poll_unavailable) can be told apart. voter_key is the folded name, as in abstentions
+CREATE TABLE IF NOT EXISTS poll_orders (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    restaurant_name TEXT NOT NULL,
+    dish TEXT NOT NULL,
+    notes TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME,
+    PRIMARY KEY (poll_id, voter_key)
+);
@@ -0,0 +1,7 @@
+-- Booking a table at the winner when a poll closes. reserve asks for it; the rest is how it went: status is
+-- pending while the booking API is asked, then confirmed (with the provider's reference), failed or skipped (with
+-- why, in reservation_note). NULL means nobody has tried
+ALTER TABLE polls ADD COLUMN reserve BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE polls ADD COLUMN reservation_status TEXT;
+ALTER TABLE polls ADD COLUMN reservation_reference TEXT;
+ALTER TABLE polls ADD COLUMN reservation_note TEXT;
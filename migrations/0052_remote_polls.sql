@@ -0,0 +1,2 @@
+-- A remote day's lunch is delivered rather than eaten out, so its winner announcement carries delivery links
+ALTER TABLE polls ADD COLUMN remote BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,17 @@
+-- Money collected towards a poll's bill, in cents. A Stripe payment is pending while its Checkout page is open, then
+-- paid, or expired once the page lapses or is replaced; reference is the Checkout session's id. A manual payment is
+-- one an admin recorded as made some other way, so it's paid from the start
+CREATE TABLE IF NOT EXISTS bill_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    amount_cents INTEGER NOT NULL,
+    method TEXT NOT NULL CHECK (method IN ('stripe', 'manual')),
+    status TEXT NOT NULL CHECK (status IN ('pending', 'paid', 'expired')),
+    reference TEXT,
+    payment_url TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    paid_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS bill_payments_poll_id ON bill_payments (poll_id, voter_key);
@@ -0,0 +1,11 @@
+-- Badges voters have earned, each with the public id of the poll that earned it. That's not a foreign key, so
+-- badges outlast the polls when old ones are pruned. first_vote and streak are earned once each, kingmaker once
+-- in every poll the voter decided
+CREATE TABLE IF NOT EXISTS voter_badges (
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    badge TEXT NOT NULL CHECK (badge IN ('first_vote', 'streak', 'kingmaker')),
+    poll_id TEXT NOT NULL,
+    awarded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (voter_key, badge, poll_id)
+);
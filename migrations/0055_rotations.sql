@@ -0,0 +1,10 @@
+-- Rotation polls, where one person picks for everyone in turn. rotations is the order they take turns in: a team's
+-- for the team's polls, and team_id '' for the instance's. A rotation poll's picker is whose turn it is
+CREATE TABLE IF NOT EXISTS rotations (
+    team_id TEXT NOT NULL DEFAULT '',
+    position INTEGER NOT NULL,
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    PRIMARY KEY (team_id, voter_key)
+);
+ALTER TABLE polls ADD COLUMN picker TEXT;
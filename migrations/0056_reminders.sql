@@ -0,0 +1,18 @@
+-- How each voter wants to hear about polls still waiting on them: an email address, if they gave one, and whether
+-- they want reminders at all. voter_key is the folded name, as in abstentions
+CREATE TABLE IF NOT EXISTS voter_notifications (
+    voter_key TEXT PRIMARY KEY,
+    voter_name TEXT NOT NULL,
+    email TEXT,
+    reminders BOOLEAN NOT NULL DEFAULT 1,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+-- The reminders sent for a poll, one row per lead time in REMINDER_LEAD_MINUTES that has passed, so none goes twice.
+-- reminded is how many voters it went to
+CREATE TABLE IF NOT EXISTS poll_reminders (
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    lead_minutes INTEGER NOT NULL,
+    reminded INTEGER NOT NULL,
+    sent_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (poll_id, lead_minutes)
+);
@@ -0,0 +1,8 @@
+-- The weekly digest: whether each voter with an email address gets it, and the weeks it has gone out for, by the
+-- Monday they start on, so a restart on a Friday afternoon doesn't send it twice
+ALTER TABLE voter_notifications ADD COLUMN digest BOOLEAN NOT NULL DEFAULT 1;
+CREATE TABLE IF NOT EXISTS digests (
+    week_of TEXT PRIMARY KEY,
+    recipients INTEGER NOT NULL DEFAULT 0,
+    sent_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,16 @@
+-- Which notifications each voter gets, and how: one row per event they want and channel they want it on, email to
+-- their address or a POST to a webhook of their own (a chat DM, a phone push service). The on/off switches for
+-- reminders and the digest become rows for email, the only channel there was
+ALTER TABLE voter_notifications ADD COLUMN webhook_url TEXT;
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    voter_key TEXT NOT NULL,
+    event TEXT NOT NULL CHECK (event IN ('poll_opened', 'reminder', 'winner', 'digest')),
+    channel TEXT NOT NULL CHECK (channel IN ('email', 'webhook')),
+    PRIMARY KEY (voter_key, event, channel)
+);
+INSERT INTO notification_preferences (voter_key, event, channel)
+    SELECT voter_key, 'reminder', 'email' FROM voter_notifications WHERE reminders;
+INSERT INTO notification_preferences (voter_key, event, channel)
+    SELECT voter_key, 'digest', 'email' FROM voter_notifications WHERE digest;
+ALTER TABLE voter_notifications DROP COLUMN reminders;
+ALTER TABLE voter_notifications DROP COLUMN digest;
@@ -0,0 +1,11 @@
+-- One-click voting links: each lets one voter vote once in one poll, from a button in a notification. The token is
+-- the id signed with the receipt key, so it isn't stored; used_at is what keeps it from being replayed
+CREATE TABLE IF NOT EXISTS vote_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    poll_id INTEGER NOT NULL REFERENCES polls(id),
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    used_at DATETIME
+);
+CREATE INDEX IF NOT EXISTS vote_links_poll ON vote_links (poll_id);
@@ -0,0 +1,9 @@
+-- Addresses nothing is sent to any more, whoever's settings name them: an email address, lowercased, or a webhook
+-- URL. Added by the unsubscribe link in every notification, removed by an admin. Kept when a voter's data is erased,
+-- since it's what stops them being written to again
+CREATE TABLE IF NOT EXISTS suppressions (
+    channel TEXT NOT NULL CHECK (channel IN ('email', 'webhook')),
+    address TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (channel, address)
+);
@@ -0,0 +1,12 @@
+-- When voters are out of the office: each row one stretch of days, both ends included, in the server's local dates.
+-- Reminders, rotation turns and the RSVP headcount leave them out of polls for lunch on those days
+CREATE TABLE IF NOT EXISTS away_periods (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    voter_key TEXT NOT NULL,
+    voter_name TEXT NOT NULL,
+    starts_on DATE NOT NULL,
+    ends_on DATE NOT NULL,
+    note TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS away_periods_voter ON away_periods (voter_key);
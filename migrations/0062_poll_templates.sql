@@ -0,0 +1,11 @@
+-- Poll settings saved to be used again: settings is a POST /polls body, with times as offsets from when the poll is
+-- created rather than as times. An office's daily poll can come from one, and so can the instance's, named by
+-- DAILY_POLL_TEMPLATE
+CREATE TABLE IF NOT EXISTS poll_templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    settings TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME
+);
+ALTER TABLE offices ADD COLUMN daily_poll_template TEXT REFERENCES poll_templates(id);
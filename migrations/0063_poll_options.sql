@@ -0,0 +1,4 @@
+-- A poll over options of its own instead of restaurants: meeting times, a team name, snacks. A JSON array of the
+-- options, which are then the whole ballot; NULL for a restaurant poll. Votes name an option in restaurant_name, so
+-- everything that counts them works the same either way
+ALTER TABLE polls ADD COLUMN options TEXT;
@@ -0,0 +1,5 @@
+-- What a poll is called and about, for people, and a JSON object of whatever integrations want to keep with it,
+-- like the Slack channel it was posted to. The server never reads the metadata, only stores and returns it
+ALTER TABLE polls ADD COLUMN title TEXT;
+ALTER TABLE polls ADD COLUMN description TEXT;
+ALTER TABLE polls ADD COLUMN metadata TEXT;
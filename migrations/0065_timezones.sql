@@ -0,0 +1,5 @@
+-- IANA time zones for offices and polls; see timezones.rs. With one, utc_offset_minutes follows the zone's offset.
+-- A poll's offset is NULL without a zone of its own, and it keeps to its office's clock, or the server's
+ALTER TABLE offices ADD COLUMN timezone TEXT;
+ALTER TABLE polls ADD COLUMN timezone TEXT;
+ALTER TABLE polls ADD COLUMN utc_offset_minutes INTEGER;
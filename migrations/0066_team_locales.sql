@@ -0,0 +1,3 @@
+-- The language a team's notifications and announcements are written in, "en", "de" or "es"; see i18n.rs. NULL is
+-- English
+ALTER TABLE teams ADD COLUMN locale TEXT;
@@ -0,0 +1,4 @@
+-- A hash of each organization's admin token, so an organization whose database is opened again after a restart
+-- still knows its admin; see organizations.rs. NULL for organizations created before this was kept, whose admin
+-- endpoints stay disabled
+ALTER TABLE organizations ADD COLUMN admin_token_hash TEXT; -- SHA-256 of the token, hex
@@ -0,0 +1,12 @@
+-- A version on each poll, one more with every change to it, for the admin endpoints to check an If-Match against so
+-- that two admins changing the same poll at once can't overwrite each other; see polls.rs. The trigger that keeps
+-- updated_at is replaced by one that keeps both, so an update counts once however many columns it sets
+ALTER TABLE polls ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+DROP TRIGGER IF EXISTS polls_updated_at;
+
+CREATE TRIGGER IF NOT EXISTS polls_updated_at AFTER UPDATE ON polls
+WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE polls SET updated_at = CURRENT_TIMESTAMP, version = OLD.version + 1 WHERE id = NEW.id;
+END;
@@ -0,0 +1,58 @@
+-- Finding a restaurant by whatever anyone remembers of it: "that noodle place near the station". Each restaurant gets
+-- free-text notes of its own, and a row in an FTS5 index under its id with its name, aliases, cuisine, notes and
+-- address, for GET /restaurants/search to rank matches with; see restaurants.rs. The triggers keep the index in step
+-- with both tables. Restaurants in the trash or waiting for review stay in it, and are left out when searching
+-- https://www.sqlite.org/fts5.html
+ALTER TABLE restaurants ADD COLUMN notes TEXT;
+
+-- remove_diacritics has "pho" find "Phở"
+CREATE VIRTUAL TABLE IF NOT EXISTS restaurant_search USING fts5(
+    name, aliases, cuisine, notes, address,
+    tokenize = "unicode61 remove_diacritics 2"
+);
+
+INSERT INTO restaurant_search (rowid, name, aliases, cuisine, notes, address)
+SELECT id, name, (SELECT group_concat(alias, ' ') FROM restaurant_aliases WHERE restaurant_id = restaurants.id),
+    cuisine, notes, address
+FROM restaurants;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_insert AFTER INSERT ON restaurants
+BEGIN
+    INSERT INTO restaurant_search (rowid, name, aliases, cuisine, notes, address)
+    VALUES (NEW.id, NEW.name, NULL, NEW.cuisine, NEW.notes, NEW.address);
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_update AFTER UPDATE OF name, cuisine, notes, address ON restaurants
+BEGIN
+    UPDATE restaurant_search SET name = NEW.name, cuisine = NEW.cuisine, notes = NEW.notes, address = NEW.address
+    WHERE rowid = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_delete AFTER DELETE ON restaurants
+BEGIN
+    DELETE FROM restaurant_search WHERE rowid = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_alias_insert AFTER INSERT ON restaurant_aliases
+BEGIN
+    UPDATE restaurant_search
+    SET aliases = (SELECT group_concat(alias, ' ') FROM restaurant_aliases WHERE restaurant_id = NEW.restaurant_id)
+    WHERE rowid = NEW.restaurant_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_alias_update AFTER UPDATE ON restaurant_aliases
+BEGIN
+    UPDATE restaurant_search
+    SET aliases = (SELECT group_concat(alias, ' ') FROM restaurant_aliases WHERE restaurant_id = OLD.restaurant_id)
+    WHERE rowid = OLD.restaurant_id;
+    UPDATE restaurant_search
+    SET aliases = (SELECT group_concat(alias, ' ') FROM restaurant_aliases WHERE restaurant_id = NEW.restaurant_id)
+    WHERE rowid = NEW.restaurant_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS restaurant_search_alias_delete AFTER DELETE ON restaurant_aliases
+BEGIN
+    UPDATE restaurant_search
+    SET aliases = (SELECT group_concat(alias, ' ') FROM restaurant_aliases WHERE restaurant_id = OLD.restaurant_id)
+    WHERE rowid = OLD.restaurant_id;
+END;
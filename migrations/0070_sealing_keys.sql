@@ -0,0 +1,10 @@
+-- Each sealed poll's key, wrapped: encrypted under SEALING_KEY, which only the config has. Kept in the database
+-- rather than the process's memory, so the poll's ballots can still be opened after a restart, and by whichever
+-- instance sharing the database closes the poll. A table of its own rather than a column on polls, so making and
+-- dropping the key doesn't count as a change to the poll and move its version on. The row goes when the ballots
+-- are revealed; see sealing.rs
+CREATE TABLE IF NOT EXISTS sealing_keys (
+    poll_id INTEGER PRIMARY KEY REFERENCES polls(id),
+    nonce BLOB NOT NULL,
+    wrapped_key BLOB NOT NULL
+);
@@ -0,0 +1,143 @@
+// Winner announcements: what a closed poll settled on, for chat bots and the like. GET /polls/:id/announcement gives
+// it to whoever asks, and when WINNER_WEBHOOK_URL is set it's POSTed there as the poll closes on a winner. A remote
+// poll's announcement also links to the winner on DoorDash and Uber Eats, for whoever's ordering to start a group
+// order from and share. Neither opens its group-order API outside partnerships, so these are deep links: to the
+// restaurant's store page when its id there is known, as a restaurant_external_ids entry under "doordash" or
+// "ubereats", and to a search for its name otherwise. Its text is in the poll's team's language, or for GET the one
+// the request asks for; see i18n.rs
+use axum::extract::State;
+use axum::Json;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::i18n::{self, Locale};
+use crate::polls::{self, Poll};
+use crate::public_ids::PollId;
+use crate::restaurants::{self, Restaurant};
+use crate::{rsvps, AppState};
+
+#[derive(Serialize)]
+pub struct Announcement {
+    poll_id: String,
+    title: Option<String>,
+    metadata: Option<serde_json::Value>, // the poll's, so an integration can tell where to post it
+    lunch_at: String,
+    winner: String,
+    text: String, // "Lunch at 2024-05-17 12:30 is at Pizza Place", for posting as it is
+    restaurant: Option<Restaurant>, // None for a winner that was voted for but never registered
+    headcount: rsvps::Headcount,
+    remote: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delivery: Option<DeliveryLinks>, // remote polls only
+}
+
+#[derive(Serialize)]
+pub struct DeliveryLinks {
+    doordash: String,
+    uber_eats: String,
+}
+
+// The announcement for a poll that closed on a winner, in `locale` or else its team's; a Conflict for any other poll
+pub async fn announcement(state: &AppState, poll: &Poll, locale: Option<Locale>) -> Result<Announcement, ApiError> {
+    let winner = polls::winner(state, poll).await?;
+    let locale = match locale {
+        Some(locale) => locale,
+        None => i18n::team_locale(&state.db, poll.team_id.as_deref()).await?,
+    };
+    let text = i18n::text(locale, "announcement", &[("lunch_at", &poll.lunch_at), ("winner", &winner)]);
+    let restaurant = restaurants::find_by_name(&state.db, &winner).await?;
+    let delivery =
+        if poll.remote { Some(delivery_links(&state.db, &winner, restaurant.as_ref()).await?) } else { None };
+    Ok(Announcement {
+        poll_id: poll.public_id.clone(),
+        title: poll.title.clone(),
+        metadata: poll.metadata.as_ref().map(|metadata| metadata.0.clone()),
+        lunch_at: poll.lunch_at.clone(),
+        winner,
+        text,
+        restaurant,
+        headcount: rsvps::headcount(&state.db, poll).await?,
+        remote: poll.remote,
+        delivery,
+    })
+}
+
+async fn delivery_links(
+    db: &SqlitePool,
+    name: &str,
+    restaurant: Option<&Restaurant>,
+) -> Result<DeliveryLinks, sqlx::Error> {
+    let ids: Vec<(String, String)> = match restaurant {
+        Some(restaurant) => {
+            sqlx::query_as(
+                "SELECT source, external_id FROM restaurant_external_ids
+                WHERE restaurant_id = ? AND source IN ('doordash', 'ubereats')",
+            )
+            .bind(restaurant.id)
+            .fetch_all(db)
+            .await?
+        }
+        None => Vec::new(),
+    };
+    let id = |source: &str| ids.iter().find(|(known, _)| known == source).map(|(_, id)| id.as_str());
+    let doordash = match id("doordash") {
+        Some(id) => page("https://www.doordash.com", ["store"].into_iter().chain(id.split('/'))),
+        None => page("https://www.doordash.com", ["search", "store", name]),
+    };
+    // Uber Eats store ids are a slug and a UUID, "joes-pizza/3b0c...", so they're split into path segments too
+    let uber_eats = match id("ubereats") {
+        Some(id) => page("https://www.ubereats.com", ["store"].into_iter().chain(id.split('/'))),
+        None => reqwest::Url::parse_with_params("https://www.ubereats.com/search", &[("q", name)])
+            .expect("delivery URLs are valid")
+            .to_string(),
+    };
+    Ok(DeliveryLinks { doordash, uber_eats })
+}
+
+// path_segments_mut percent-encodes each segment, so a name with a slash or a question mark in it stays one segment
+// https://docs.rs/url/latest/url/struct.Url.html#method.path_segments_mut
+fn page<'a>(base: &str, segments: impl IntoIterator<Item = &'a str>) -> String {
+    let mut url = reqwest::Url::parse(base).expect("delivery URLs are valid");
+    url.path_segments_mut().expect("delivery URLs have paths").clear().extend(segments);
+    url.to_string()
+}
+
+// When a poll closes on a winner: announces it to WINNER_WEBHOOK_URL, if there is one, without holding up the close
+pub fn spawn_webhook(state: &AppState, poll_id: i64) {
+    let Some(url) = state.config.winner_webhook_url.clone() else {
+        return;
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        match post(&state, &url, poll_id).await {
+            Ok(winner) => println!("poll {poll_id}: announced {winner}"),
+            Err(err) => eprintln!("poll {poll_id}: could not announce the winner: {err:?}"),
+        }
+    });
+}
+
+async fn post(state: &AppState, url: &str, poll_id: i64) -> Result<String, ApiError> {
+    let poll = polls::find_poll(&state.db, poll_id)
+        .await?
+        .ok_or_else(|| Message::no_poll(poll_id))?;
+    let announcement = announcement(state, &poll, None).await?;
+    let response = state.http.post(url).json(&announcement).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("the winner webhook returned {status}: {body}")));
+    }
+    Ok(announcement.winner)
+}
+
+// GET /polls/:id/announcement: the winner announcement, the same as the webhook gets, once the poll has closed on one
+pub async fn get_announcement(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+) -> Result<Json<Announcement>, ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    Ok(Json(announcement(&state, &poll, i18n::accepted()).await?))
+}
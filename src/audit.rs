@@ -0,0 +1,221 @@
+// The audit log: every request that tries to change something is written down with who sent it, from where, and
+// how it went, so a winner "corrected" after lunch can be traced. It's a middleware around the whole router, so
+// new endpoints are covered without doing anything
+// https://docs.rs/axum/latest/axum/middleware/index.html
+use axum::body::{to_bytes, Body};
+use axum::extract::{ConnectInfo, Query, Request, State};
+use axum::http::{Method, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::auth::{self, Admin};
+use crate::error::{ApiError, Message};
+use crate::{cursors, graphql, grpc, names, AppState};
+
+// Bodies are read whole to find out who's asking; this is axum's own default limit for JSON bodies
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// The request fields that name the person acting, in the order they're looked for
+const ACTOR_FIELDS: [&str; 4] = ["voter_name", "nominated_by", "suggested_by", "rater_name"];
+
+// A handler that shouldn't be logged under the name it was called with (erasure, say, whose whole point is that the
+// name goes) puts one of these in its response's extensions, and the entry is written with it instead
+// https://docs.rs/axum/latest/axum/struct.Extension.html#as-response
+#[derive(Clone)]
+pub struct LoggedAs {
+    pub actor: String,
+    pub path: String,
+}
+
+// Runs around every request. Reads don't change anything and aren't logged, GraphQL queries posted included, and
+// nor are gRPC calls as such, whose votes are logged as the POST /vote they become; see grpc.rs. Everything else is,
+// after the handler has answered, failures included. The body has to be read to see who's asking, then handed on
+// to the handler
+pub async fn record(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let read = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if read || request.uri().path() == graphql::PATH || request.uri().path().starts_with(grpc::SERVICE) {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return ApiError::from(Message::body_too_large()).into_response();
+    };
+    let admin = auth::is_admin(&parts.headers, &state);
+    let actor = match admin {
+        true => "admin".to_string(),
+        false => actor(parts.uri.path(), &bytes).unwrap_or_else(|| "anonymous".to_string()),
+    };
+    let method = parts.method.to_string();
+    let path = parts.uri.path_and_query().map_or_else(|| parts.uri.path().to_string(), |path| path.to_string());
+    let forwarded_for = parts
+        .headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    // Ballots don't belong in the log, so only admin requests keep their bodies
+    let logged_body = match admin && !bytes.is_empty() {
+        true => Some(String::from_utf8_lossy(&bytes).into_owned()),
+        false => None,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+    let (actor, path) = match response.extensions().get::<LoggedAs>() {
+        Some(logged_as) => (logged_as.actor.clone(), logged_as.path.clone()),
+        None => (actor, path),
+    };
+
+    // A log entry that can't be written shouldn't undo what the request did, so the error only goes to the log
+    let logged = sqlx::query(
+        "INSERT INTO audit_log (actor, actor_key, method, path, status, remote_addr, forwarded_for, body)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&actor)
+    .bind(names::fold(&actor))
+    .bind(method)
+    .bind(path)
+    .bind(response.status().as_u16())
+    .bind(remote_addr.ip().to_string())
+    .bind(forwarded_for)
+    .bind(logged_body)
+    .execute(&state.db)
+    .await;
+    if let Err(err) = logged {
+        eprintln!("audit log: {err}");
+    }
+    response
+}
+
+// The person a request acts for: a voter in the path (/voters/:name/...), or one named in the JSON body
+fn actor(path: &str, body: &[u8]) -> Option<String> {
+    if let Some(name) = path_voter(path) {
+        return Some(name);
+    }
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    ACTOR_FIELDS
+        .iter()
+        .find_map(|field| body.get(field)?.as_str())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+// The voter a /voters/:name/... path is about, with the name's %-escapes decoded the way axum's Path does
+// https://url.spec.whatwg.org/#percent-decode
+pub fn path_voter(path: &str) -> Option<String> {
+    let segment = path.strip_prefix("/voters/")?.split(['/', '?']).next()?;
+    let mut bytes = Vec::new();
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned()).filter(|name| !name.is_empty())
+}
+
+// A logged body with the voter whose name folds to `key` renamed wherever the body names its actor, or None when
+// it doesn't name them
+pub fn rename_in_body(body: &str, key: &str, pseudonym: &str) -> Option<String> {
+    let mut body: serde_json::Value = serde_json::from_str(body).ok()?;
+    let mut renamed = false;
+    for field in ACTOR_FIELDS {
+        let Some(name) = body.get_mut(field) else {
+            continue;
+        };
+        if name.as_str().is_some_and(|name| names::fold(name) == key) {
+            *name = pseudonym.into();
+            renamed = true;
+        }
+    }
+    renamed.then(|| body.to_string())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    id: i64,
+    actor: String,
+    method: String,
+    path: String,
+    status: i64,
+    remote_addr: String,
+    forwarded_for: Option<String>,
+    body: Option<String>,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    actor: Option<String>,
+    path: Option<String>, // entries whose path starts with this, e.g. /polls/
+    since: Option<String>, // a UTC time, "2024-05-17 12:00"; entries from then on
+    #[serde(default = "default_limit")]
+    limit: i64,
+    cursor: Option<String>, // from the Link of the page before; see cursors.rs
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+// GET /audit?actor=admin&path=/polls/&since=2024-05-17&limit=100 (admin): newest first, from the replica when there
+// is one. The Link header has the next page, of older entries, when there is one
+pub async fn list_entries(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    if !(1..=1000).contains(&query.limit) {
+        return Err(ApiError::BadRequest("limit must be between 1 and 1000".to_string()));
+    }
+    let before = cursors::position("audit", query.cursor.as_deref())?;
+    let actor_key = query.actor.as_deref().map(names::fold);
+    // datetime() normalizes the time to match created_at, or is NULL for anything that isn't one
+    let since = match &query.since {
+        Some(since) => {
+            let since: Option<String> =
+                sqlx::query_scalar("SELECT datetime(?)").bind(since.trim()).fetch_one(&state.db).await?;
+            Some(since.ok_or_else(|| ApiError::BadRequest("since must be a time like 2024-05-17 12:00".to_string()))?)
+        }
+        None => None,
+    };
+    // instr() = 1 is a prefix match that doesn't treat % or _ in the path as wildcards, the way LIKE would
+    let entries = sqlx::query_as::<_, AuditEntry>(
+        "SELECT id, actor, method, path, status, remote_addr, forwarded_for, body, created_at FROM audit_log
+        WHERE (? IS NULL OR actor_key = ?)
+        AND (? IS NULL OR instr(path, ?) = 1)
+        AND (? IS NULL OR created_at >= ?)
+        AND (? IS NULL OR id < ?)
+        ORDER BY id DESC
+        LIMIT ?",
+    )
+    .bind(&actor_key)
+    .bind(&actor_key)
+    .bind(&query.path)
+    .bind(&query.path)
+    .bind(&since)
+    .bind(&since)
+    .bind(before)
+    .bind(before)
+    .bind(query.limit + 1)
+    .fetch_all(&state.reads)
+    .await?;
+    Ok(cursors::page("audit", &uri, query.limit, entries, |entry| entry.id))
+}
@@ -0,0 +1,64 @@
+// Extractors that gate handlers on who is calling
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use ring::digest;
+
+use crate::error::{ApiError, Message};
+use crate::receipts::hex;
+use crate::AppState;
+
+// Adding an Admin parameter to a handler makes it admin-only: axum runs this extractor first and
+// answers with the rejection instead of calling the handler when the token is missing or wrong
+// https://docs.rs/axum/latest/axum/extract/index.html#implementing-fromrequestparts
+pub struct Admin;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        if state.config.admin_token.is_none() && state.config.admin_token_hash.is_none() {
+            return Err(ApiError::Forbidden("admin endpoints are disabled; set ADMIN_TOKEN to enable them".to_string()));
+        }
+        let provided = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(Message::admin_token_required)?;
+        if !is_admin_token(provided, state) {
+            return Err(Message::admin_token_invalid().into());
+        }
+        Ok(Admin)
+    }
+}
+
+// Whether a request carries the admin token, for code that only needs to know rather than turn anyone away
+pub fn is_admin(headers: &HeaderMap, state: &AppState) -> bool {
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    provided.is_some_and(|provided| is_admin_token(provided, state))
+}
+
+// The instance's token is compared as it is; an organization's, whose hash is all that's kept, by hashing the one
+// provided
+fn is_admin_token(provided: &str, state: &AppState) -> bool {
+    match (&state.config.admin_token, &state.config.admin_token_hash) {
+        (Some(expected), _) => constant_time_eq(provided.as_bytes(), expected.as_bytes()),
+        (None, Some(expected)) => {
+            let hash = hex(digest::digest(&digest::SHA256, provided.as_bytes()).as_ref());
+            constant_time_eq(hash.as_bytes(), expected.as_bytes())
+        }
+        (None, None) => false,
+    }
+}
+
+// Compares every byte regardless of where the first mismatch is, so response timing doesn't leak the token
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
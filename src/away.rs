@@ -0,0 +1,137 @@
+// Out of the office: a voter marks the days they're away, on holiday or at a conference, and the polls for lunch on
+// those days count them out without anyone having to RSVP for them. They get no reminders to vote (see
+// reminders.rs), a rotation passes over them when it's their turn to pick (see rotation.rs), and they aren't among
+// those the headcount is still waiting on (see rsvps.rs). They can still vote or RSVP if they turn up after all.
+// A poll's day is the date of its lunch_at
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::{names, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AwayPeriod {
+    id: i64,
+    voter_name: String,
+    starts_on: String,
+    ends_on: String,
+    note: Option<String>, // "in Lisbon", for whoever wonders where they went
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewAwayPeriod {
+    starts_on: String,
+    ends_on: Option<String>, // the same day when left out
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AwayQuery {
+    on: Option<String>, // a YYYY-MM-DD date, today's when left out
+}
+
+const AWAY_COLUMNS: &str = "id, voter_name, starts_on, ends_on, note, created_at";
+
+// The folded names of the voters away on `day`, the date part of a lunch_at like 2024-05-17 12:30
+pub async fn away_on(db: &SqlitePool, day: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT DISTINCT voter_key FROM away_periods WHERE date(?1) BETWEEN starts_on AND ends_on ORDER BY 1",
+    )
+    .bind(day)
+    .fetch_all(db)
+    .await
+}
+
+// date() returns the day as YYYY-MM-DD, or NULL for anything that isn't a date
+async fn day(db: &SqlitePool, field: &str, day: &str) -> Result<String, ApiError> {
+    let normalized: Option<String> = sqlx::query_scalar("SELECT date(?)").bind(day.trim()).fetch_one(db).await?;
+    normalized.ok_or_else(|| ApiError::BadRequest(format!("{field} must be a YYYY-MM-DD date")))
+}
+
+// GET /away?on=2024-05-17: who's away that day
+pub async fn list_away(
+    State(state): State<AppState>,
+    Query(query): Query<AwayQuery>,
+) -> Result<Json<Vec<AwayPeriod>>, ApiError> {
+    let on = match &query.on {
+        Some(on) => day(&state.db, "on", on).await?,
+        None => sqlx::query_scalar("SELECT date('now', 'localtime')").fetch_one(&state.db).await?,
+    };
+    let periods = sqlx::query_as::<_, AwayPeriod>(&format!(
+        "SELECT {AWAY_COLUMNS} FROM away_periods WHERE ? BETWEEN starts_on AND ends_on ORDER BY voter_key, starts_on"
+    ))
+    .bind(on)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(periods))
+}
+
+// GET /voters/:name/away: their time away that hasn't ended yet, soonest first
+pub async fn get_away(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<AwayPeriod>>, ApiError> {
+    let periods = sqlx::query_as::<_, AwayPeriod>(&format!(
+        "SELECT {AWAY_COLUMNS} FROM away_periods WHERE voter_key = ? AND ends_on >= date('now', 'localtime')
+        ORDER BY starts_on"
+    ))
+    .bind(names::fold(&name))
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(periods))
+}
+
+// POST /voters/:name/away: {"starts_on": "2024-05-20", "ends_on": "2024-05-24", "note": "in Lisbon"}. Stretches
+// may overlap; a day is away if any of them covers it
+pub async fn add_away(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<NewAwayPeriod>,
+) -> Result<(StatusCode, Json<AwayPeriod>), ApiError> {
+    let voter_name = names::canonical_voter_name(&state.db, &name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    let starts_on = day(&state.db, "starts_on", &req.starts_on).await?;
+    let ends_on = match &req.ends_on {
+        Some(ends_on) => day(&state.db, "ends_on", ends_on).await?,
+        None => starts_on.clone(),
+    };
+    // The strings compare correctly because they all have the same fixed-width format
+    if ends_on < starts_on {
+        return Err(ApiError::BadRequest("ends_on must not be before starts_on".to_string()));
+    }
+    let note = req.note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    let period = sqlx::query_as::<_, AwayPeriod>(&format!(
+        "INSERT INTO away_periods (voter_key, voter_name, starts_on, ends_on, note) VALUES (?, ?, ?, ?, ?)
+        RETURNING {AWAY_COLUMNS}"
+    ))
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(starts_on)
+    .bind(ends_on)
+    .bind(note)
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(period)))
+}
+
+// DELETE /voters/:name/away/:id: back after all
+pub async fn delete_away(
+    State(state): State<AppState>,
+    Path((name, id)): Path<(String, i64)>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM away_periods WHERE id = ? AND voter_key = ?")
+        .bind(id)
+        .bind(names::fold(&name))
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{name} has no time away with id {id}")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
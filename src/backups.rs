@@ -0,0 +1,167 @@
+// Backups of the database, to S3 or any S3-compatible storage, while BACKUP_BUCKET is set. Each one is a snapshot
+// made with VACUUM INTO, which copies the database as it stands at one moment while votes keep coming in, gzipped
+// and uploaded as {BACKUP_PREFIX}{UTC time}.sqlite.gz. Without DATABASE_URL the database only lives in memory, and
+// these are the copies that outlast a restart: gunzip one and it's an ordinary SQLite file. The names sort in the
+// order the backups were taken, so after each upload everything under the prefix but the newest BACKUP_KEEP is
+// deleted. They're taken every BACKUP_INTERVAL_HOURS, by whichever instance runs the scheduled jobs, or by an admin
+// with POST /backups; how that went is counted for GET /metrics
+// https://www.sqlite.org/lang_vacuum.html#vacuuminto
+use axum::extract::State;
+use axum::Json;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::receipts::hex;
+use crate::s3::Bucket;
+use crate::{gzip, AppState};
+
+const SUFFIX: &str = ".sqlite.gz";
+
+// How the backups have gone since startup
+#[derive(Clone, Default)]
+pub struct Backups(Arc<Mutex<Runs>>);
+
+#[derive(Clone, Copy, Default)]
+pub struct Runs {
+    pub succeeded: u64,
+    pub failed: u64,
+    pub last_success: Option<u64>, // seconds since the Unix epoch
+}
+
+impl Backups {
+    pub fn runs(&self) -> Runs {
+        *self.0.lock().expect("backup counts poisoned")
+    }
+
+    fn record(&self, succeeded: bool) {
+        let mut runs = self.0.lock().expect("backup counts poisoned");
+        if succeeded {
+            runs.succeeded += 1;
+            runs.last_success = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+        } else {
+            runs.failed += 1;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Backup {
+    key: String,
+    bytes: usize,            // the database's size
+    compressed_bytes: usize, // what was uploaded
+    deleted: Vec<String>,    // older backups rotated out
+}
+
+// Starts the backup job. Unlike the other jobs it waits an interval before the first run: right after a restart an
+// in-memory database is empty, and a backup of that would only push a real one out of rotation
+pub fn spawn(state: AppState) {
+    if state.config.backup_bucket.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let every = state.config.backup_interval;
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if !state.lease.held(&state.config).await {
+                continue;
+            }
+            match back_up(&state).await {
+                Ok(backup) => println!(
+                    "backups: uploaded {} ({} bytes from {}), deleted {} older",
+                    backup.key,
+                    backup.compressed_bytes,
+                    backup.bytes,
+                    backup.deleted.len()
+                ),
+                Err(err) => eprintln!("backups: {err:?}"),
+            }
+        }
+    });
+}
+
+// POST /backups (admin): takes a backup now
+pub async fn back_up_now(_admin: Admin, State(state): State<AppState>) -> Result<Json<Backup>, ApiError> {
+    Ok(Json(back_up(&state).await?))
+}
+
+pub async fn back_up(state: &AppState) -> Result<Backup, ApiError> {
+    let config = &state.config;
+    let (Some(name), Some(access_key_id), Some(secret_access_key)) =
+        (&config.backup_bucket, &config.backup_access_key_id, &config.backup_secret_access_key)
+    else {
+        return Err(ApiError::NotConfigured(
+            "backups need BACKUP_BUCKET, BACKUP_ACCESS_KEY_ID and BACKUP_SECRET_ACCESS_KEY".to_string(),
+        ));
+    };
+    let bucket = Bucket {
+        http: &state.http,
+        endpoint: &config.backup_endpoint,
+        region: &config.backup_region,
+        name,
+        access_key_id,
+        secret_access_key,
+    };
+
+    let uploaded = async {
+        let snapshot = snapshot(&state.db).await?;
+        let bytes = snapshot.len();
+        // Compressing a database takes a while, so off the threads that serve requests
+        let compressed = tokio::task::spawn_blocking(move || gzip::compress(&snapshot))
+            .await
+            .map_err(|err| ApiError::Upstream(format!("compressing the backup failed: {err}")))?;
+        let taken: String = sqlx::query_scalar("SELECT strftime('%Y%m%dT%H%M%SZ', 'now')").fetch_one(&state.db).await?;
+        let key = format!("{}{taken}{SUFFIX}", config.backup_prefix);
+        let compressed_bytes = compressed.len();
+        bucket.put(&key, compressed).await?;
+        Ok::<_, ApiError>((key, bytes, compressed_bytes))
+    };
+    let (key, bytes, compressed_bytes) = match uploaded.await {
+        Ok(uploaded) => uploaded,
+        Err(err) => {
+            state.backups.record(false);
+            return Err(err);
+        }
+    };
+    state.backups.record(true);
+
+    // The backup is safely up by now, so a failure from here on only leaves one too many behind until next time
+    let mut backups: Vec<String> = bucket
+        .list(&config.backup_prefix)
+        .await?
+        .into_iter()
+        // Just this prefix's own, not those of organizations whose prefixes start with it
+        .filter(|other| other.strip_prefix(config.backup_prefix.as_str()).is_some_and(|rest| !rest.contains('/')))
+        .filter(|other| other.ends_with(SUFFIX))
+        .collect();
+    backups.sort();
+    let old = backups.len().saturating_sub(config.backup_keep);
+    let mut deleted = Vec::new();
+    for other in backups.drain(..old) {
+        bucket.delete(&other).await?;
+        deleted.push(other);
+    }
+    Ok(Backup { key, bytes, compressed_bytes, deleted })
+}
+
+// The database as a SQLite file, written by VACUUM INTO to a file of its own under the temporary directory
+async fn snapshot(db: &SqlitePool) -> Result<Vec<u8>, ApiError> {
+    let mut random = [0u8; 8];
+    SystemRandom::new().fill(&mut random).expect("the system random number generator failed");
+    let path = std::env::temp_dir().join(format!("lunch-voting-backup-{}.sqlite", hex(&random)));
+    // As a URI with its mode spelled out: a plain name would be opened like the database itself, in memory
+    let target = format!("file:{}?mode=rwc", path.to_string_lossy());
+    let written = sqlx::query("VACUUM INTO ?").bind(target).execute(db).await;
+    let read = match written {
+        Ok(_) => tokio::fs::read(&path).await.map_err(|err| ApiError::Upstream(format!("reading the snapshot: {err}"))),
+        Err(err) => Err(err.into()),
+    };
+    let _ = tokio::fs::remove_file(&path).await;
+    read
+}
@@ -0,0 +1,167 @@
+// Badges, for a bit of fun: first_vote for someone's first ballot in a poll, streak for voting on STREAK_DAYS poll
+// days in a row, and kingmaker for casting the deciding vote. They're worked out in the background once a poll
+// closes (see polls::on_close) and kept, so they outlast the votes that earned them. A poll day is a day one of the
+// office's polls was for (the instance's, for polls without an office), so weekends and holidays don't break a
+// streak. The deciding vote is the last ballot to come in for a plurality poll's winner when it won by one vote or
+// on a tiebreak; without that ballot it wouldn't have won outright
+use serde::Serialize;
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+use crate::error::{ApiError, Message};
+use crate::polls::{self, VotingMethod};
+use crate::{ballots, names, AppState};
+
+const STREAK_DAYS: usize = 30;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Badge {
+    badge: String,
+    #[sqlx(skip)]
+    description: &'static str,
+    count: i64, // how many times it was earned; only kingmaker can be earned more than once
+    first_awarded_at: String,
+    last_awarded_at: String,
+    poll_id: String, // the poll that earned it most recently
+}
+
+fn description(badge: &str) -> &'static str {
+    match badge {
+        "first_vote" => "voted in a poll for the first time",
+        "streak" => "voted on 30 poll days in a row",
+        "kingmaker" => "cast the vote that decided a poll",
+        _ => "",
+    }
+}
+
+// A voter's badges, in the order they were first earned
+pub async fn badges(db: &SqlitePool, voter_key: &str) -> Result<Vec<Badge>, sqlx::Error> {
+    let mut badges = sqlx::query_as::<_, Badge>(
+        "SELECT badge, COUNT(*) AS count, MIN(awarded_at) AS first_awarded_at, MAX(awarded_at) AS last_awarded_at, (
+            SELECT poll_id FROM voter_badges latest WHERE latest.voter_key = b.voter_key AND latest.badge = b.badge
+            ORDER BY awarded_at DESC, rowid DESC LIMIT 1
+        ) AS poll_id
+        FROM voter_badges b WHERE voter_key = ? GROUP BY badge ORDER BY MIN(awarded_at), badge",
+    )
+    .bind(voter_key)
+    .fetch_all(db)
+    .await?;
+    for badge in &mut badges {
+        badge.description = description(&badge.badge);
+    }
+    Ok(badges)
+}
+
+// When a poll has closed: works out who earned what in it, without holding up the close
+pub fn spawn_awards(state: &AppState, poll_id: i64) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        match award(&state, poll_id).await {
+            Ok(0) => {}
+            Ok(awarded) => println!("poll {poll_id}: awarded {awarded} badges"),
+            Err(err) => eprintln!("poll {poll_id}: could not award badges: {err:?}"),
+        }
+    });
+}
+
+async fn award(state: &AppState, poll_id: i64) -> Result<u64, ApiError> {
+    let poll = polls::find_poll(&state.db, poll_id)
+        .await?
+        .ok_or_else(|| Message::no_poll(poll_id))?;
+    // A ballot each, so each voter once however they spelled their name
+    let voters: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT voter_name FROM votes WHERE poll_id = ? AND id IN ({})",
+        ballots::counted_votes_sql()
+    ))
+    .bind(poll_id)
+    .fetch_all(&state.db)
+    .await?;
+    let mut awarded = 0;
+
+    // The poll days up to this poll's, newest first, and who voted on which of them
+    let days: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT substr(lunch_at, 1, 10) AS day FROM polls
+        WHERE status = 'closed' AND office_id IS ? AND substr(lunch_at, 1, 10) <= substr(?, 1, 10)
+        ORDER BY day DESC LIMIT ?",
+    )
+    .bind(&poll.office_id)
+    .bind(&poll.lunch_at)
+    .bind(STREAK_DAYS as i64)
+    .fetch_all(&state.db)
+    .await?;
+    let voted: Vec<(String, String)> = sqlx::query_as(
+        "SELECT DISTINCT v.voter_name, substr(p.lunch_at, 1, 10) FROM votes v JOIN polls p ON p.id = v.poll_id
+        WHERE v.deleted_at IS NULL AND p.status = 'closed' AND p.office_id IS ?
+        AND substr(p.lunch_at, 1, 10) IN (SELECT value FROM json_each(?))",
+    )
+    .bind(&poll.office_id)
+    .bind(JsonColumn(&days))
+    .fetch_all(&state.db)
+    .await?;
+
+    for voter in &voters {
+        let key = names::fold(voter);
+        awarded += earn_once(&state.db, voter, "first_vote", &poll.public_id).await?;
+        let days_voted: HashSet<&str> =
+            voted.iter().filter(|(name, _)| names::fold(name) == key).map(|(_, day)| day.as_str()).collect();
+        if days.len() == STREAK_DAYS && days_voted.len() == STREAK_DAYS {
+            awarded += earn_once(&state.db, voter, "streak", &poll.public_id).await?;
+        }
+    }
+
+    // A poll closed again after being reopened may have been decided by someone else this time
+    sqlx::query("DELETE FROM voter_badges WHERE badge = 'kingmaker' AND poll_id = ?")
+        .bind(&poll.public_id)
+        .execute(&state.db)
+        .await?;
+    if poll.voting_method == VotingMethod::Plurality && poll.runoff_poll_public_id.is_none() {
+        let voting = crate::tally(state, Some(&poll), None).await?;
+        if let Some(winner) = voting.votes.first() {
+            let runner_up = voting.votes.get(1).map_or(0, |restaurant| restaurant.score);
+            if winner.score - runner_up <= 1 {
+                let kingmaker: Option<String> = sqlx::query_scalar(
+                    "SELECT voter_name FROM votes
+                    WHERE poll_id = ? AND deleted_at IS NULL AND voter_name IN (SELECT value FROM json_each(?))
+                    ORDER BY COALESCE(updated_at, created_at) DESC, id DESC LIMIT 1",
+                )
+                .bind(poll_id)
+                .bind(JsonColumn(&winner.voters))
+                .fetch_optional(&state.db)
+                .await?;
+                if let Some(voter) = kingmaker {
+                    awarded += earn(&state.db, &voter, "kingmaker", &poll.public_id).await?;
+                }
+            }
+        }
+    }
+    Ok(awarded)
+}
+
+async fn earn(db: &SqlitePool, voter_name: &str, badge: &str, poll_id: &str) -> Result<u64, sqlx::Error> {
+    let earned = sqlx::query(
+        "INSERT OR IGNORE INTO voter_badges (voter_key, voter_name, badge, poll_id) VALUES (?, ?, ?, ?)",
+    )
+    .bind(names::fold(voter_name))
+    .bind(voter_name)
+    .bind(badge)
+    .bind(poll_id)
+    .execute(db)
+    .await?;
+    Ok(earned.rows_affected())
+}
+
+// For the badges that are only earned the first time
+async fn earn_once(db: &SqlitePool, voter_name: &str, badge: &str, poll_id: &str) -> Result<u64, sqlx::Error> {
+    let earned = sqlx::query(
+        "INSERT INTO voter_badges (voter_key, voter_name, badge, poll_id) SELECT ?1, ?2, ?3, ?4
+        WHERE NOT EXISTS (SELECT 1 FROM voter_badges WHERE voter_key = ?1 AND badge = ?3)",
+    )
+    .bind(names::fold(voter_name))
+    .bind(voter_name)
+    .bind(badge)
+    .bind(poll_id)
+    .execute(db)
+    .await?;
+    Ok(earned.rows_affected())
+}
@@ -0,0 +1,58 @@
+// One ballot a voter: in each poll, and on each day for votes outside any poll. A second ballot is turned away as
+// duplicate_vote when it's cast (see save_vote in main.rs), checked again where it's written so two sent at once
+// can't both get in, and taking the first back (DELETE /polls/:id/votes/:voter) is how a voter changes their mind.
+// Whatever two ballots got in anyway, from before the rule or restored from the trash, every tally counts only the
+// first: counted_votes_sql is the one place that decides which. Voters are told
+// apart by their folded names (see names::fold), through the voters collation registered on every connection in
+// main.rs, so "Zoë" and "zoe" are one voter here as everywhere else. Quadratic polls are the exception: a voter
+// there can send several ballots, which add up to one allocation; see quadratic.rs
+// https://www.sqlite.org/datatype3.html#collation
+use sqlx::SqliteExecutor;
+
+use crate::{names, timezones};
+
+// A voter name column compared, grouped and partitioned the way names::fold compares names
+pub fn voter_sql(column: &str) -> String {
+    format!("{column} COLLATE {}", names::VOTER_COLLATION)
+}
+
+// The ids of the votes that count: of each voter's ballots in a poll, or on a day outside polls, the first that's
+// still out of the trash. Days are the votes' own, see timezones::vote_day_sql
+// https://www.sqlite.org/windowfunctions.html
+pub fn counted_votes_sql() -> String {
+    format!(
+        "SELECT id FROM (
+            SELECT id, ROW_NUMBER() OVER (
+                PARTITION BY poll_id, CASE WHEN poll_id IS NULL THEN {} END, {} ORDER BY id
+            ) AS place
+            FROM votes WHERE deleted_at IS NULL
+        ) WHERE place = 1",
+        timezones::vote_day_sql("votes"),
+        voter_sql("voter_name")
+    )
+}
+
+// Whether the voter has a ballot in already: in the poll, sealed or not, or today outside polls
+pub async fn has_voted(
+    db: impl SqliteExecutor<'_>,
+    poll_id: Option<i64>, voter_name: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(&format!(
+        "SELECT EXISTS (
+            SELECT 1 FROM votes
+            WHERE deleted_at IS NULL AND {voter} = ?
+            AND (poll_id = ? OR (? IS NULL AND poll_id IS NULL AND {vote_day} = {today}))
+        )
+        OR EXISTS (SELECT 1 FROM sealed_ballots WHERE poll_id = ? AND {voter} = ?)",
+        voter = voter_sql("voter_name"),
+        vote_day = timezones::vote_day_sql("votes"),
+        today = timezones::day_sql("'now'", "'localtime'"),
+    ))
+    .bind(voter_name)
+    .bind(poll_id)
+    .bind(poll_id)
+    .bind(poll_id)
+    .bind(voter_name)
+    .fetch_one(db)
+    .await
+}
@@ -0,0 +1,242 @@
+// Splitting the bill after lunch. Everyone at the table can say what they paid and what their meal came to, or one
+// person can put in the whole total along with who was there; whoever didn't give their meal gets an even share of
+// what's left once the known meals are taken off. The settlement then says who pays whom, in as few transfers as
+// the greedy pairing of the biggest debt with the biggest credit finds, which is usually the fewest
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::polls;
+use crate::public_ids::PollId;
+use crate::{names, AppState};
+
+#[derive(Deserialize)]
+pub struct NewEntry {
+    voter_name: String,
+    paid: Option<f64>,  // what they put towards the bill, in place of what they said before
+    spent: Option<f64>, // what their own meal came to
+    // Who else was there, for a total paid by one person: they're added with nothing paid and an even share
+    #[serde(default)]
+    attendees: Vec<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Entry {
+    voter_name: String,
+    #[serde(skip)]
+    paid_cents: i64,
+    #[serde(skip)]
+    spent_cents: Option<i64>,
+    #[sqlx(skip)]
+    paid: f64,
+    #[sqlx(skip)]
+    spent: Option<f64>,
+    #[sqlx(skip)]
+    share: f64, // what they owe for their lunch: their meal, or their even share of the rest
+    #[sqlx(skip)]
+    #[serde(skip)]
+    share_cents: i64,
+    created_at: String,
+    updated_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Transfer {
+    from: String,
+    to: String,
+    amount: f64,
+}
+
+#[derive(Serialize)]
+pub struct Settlement {
+    total: f64,
+    // While the meals come to more than was paid, or to less with nobody left to share the difference, by how much;
+    // there's nothing to settle until someone corrects their entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unaccounted: Option<f64>,
+    entries: Vec<Entry>,
+    transfers: Vec<Transfer>,
+}
+
+impl Settlement {
+    // What each person on the bill still owes towards it in cents, their share less what they paid, for those who
+    // owe anything; None while the bill has amounts unaccounted for
+    pub fn debts(&self) -> Option<Vec<(String, i64)>> {
+        if self.unaccounted.is_some() {
+            return None;
+        }
+        let owing = self.entries.iter().filter(|entry| entry.share_cents > entry.paid_cents);
+        Some(owing.map(|entry| (entry.voter_name.clone(), entry.share_cents - entry.paid_cents)).collect())
+    }
+}
+
+pub fn cents(amount: f64, field: &str) -> Result<i64, ApiError> {
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(ApiError::BadRequest(format!("{field} must be an amount of zero or more")));
+    }
+    Ok((amount * 100.0).round() as i64)
+}
+
+pub fn amount(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+async fn entries(db: &SqlitePool, poll_id: i64) -> Result<Vec<Entry>, sqlx::Error> {
+    sqlx::query_as::<_, Entry>(
+        "SELECT voter_name, paid_cents, spent_cents, created_at, updated_at FROM bill_entries WHERE poll_id = ?
+        ORDER BY created_at, voter_key",
+    )
+    .bind(poll_id)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn settlement(db: &SqlitePool, poll_id: i64) -> Result<Settlement, sqlx::Error> {
+    Ok(settle(entries(db, poll_id).await?))
+}
+
+// What a voter's lunches cost them in the polls whose lunch_at starts with the month, "2024-05": each poll's
+// public id and lunch_at with their share in cents. Budgets are charged with these; see budgets.rs
+pub async fn shares(db: &SqlitePool, voter_key: &str, month: &str) -> Result<Vec<(String, String, i64)>, sqlx::Error> {
+    let polls: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT p.id, p.public_id, p.lunch_at FROM bill_entries b JOIN polls p ON p.id = b.poll_id
+        WHERE b.voter_key = ? AND substr(p.lunch_at, 1, 7) = ? ORDER BY p.lunch_at",
+    )
+    .bind(voter_key)
+    .bind(month)
+    .fetch_all(db)
+    .await?;
+    let mut shares = Vec::new();
+    for (id, public_id, lunch_at) in polls {
+        let settlement = settle(entries(db, id).await?);
+        if let Some(entry) = settlement.entries.iter().find(|entry| names::fold(&entry.voter_name) == voter_key) {
+            shares.push((public_id, lunch_at, entry.share_cents));
+        }
+    }
+    Ok(shares)
+}
+
+// Works out everyone's share, and who pays whom to square up
+fn settle(mut entries: Vec<Entry>) -> Settlement {
+    let total: i64 = entries.iter().map(|entry| entry.paid_cents).sum();
+    let known: i64 = entries.iter().filter_map(|entry| entry.spent_cents).sum();
+    let unknown = entries.iter().filter(|entry| entry.spent_cents.is_none()).count() as i64;
+    let left = total - known;
+    let unaccounted = (left < 0 || (unknown == 0 && left != 0)).then(|| amount(left));
+    // The cents that don't divide evenly go one each to the first in the list
+    let mut shares = Vec::new();
+    let mut extra = if unknown > 0 { left.max(0) % unknown } else { 0 };
+    for entry in &mut entries {
+        let share = match entry.spent_cents {
+            Some(spent) => spent,
+            None => {
+                let cent = i64::from(extra > 0);
+                extra -= cent;
+                left.max(0) / unknown + cent
+            }
+        };
+        entry.paid = amount(entry.paid_cents);
+        entry.spent = entry.spent_cents.map(amount);
+        entry.share = amount(share);
+        entry.share_cents = share;
+        shares.push((entry.voter_name.clone(), entry.paid_cents - share));
+    }
+    if unaccounted.is_some() {
+        return Settlement { total: amount(total), unaccounted, entries, transfers: Vec::new() };
+    }
+
+    let (mut creditors, mut debtors): (Vec<_>, Vec<_>) = shares.into_iter().partition(|(_, balance)| *balance > 0);
+    let mut transfers = Vec::new();
+    loop {
+        creditors.sort_by_key(|(_, balance)| -balance);
+        debtors.sort_by_key(|(_, balance)| *balance);
+        let (Some((to, credit)), Some((from, debt))) = (creditors.first_mut(), debtors.first_mut()) else {
+            break;
+        };
+        if *debt == 0 {
+            break;
+        }
+        let paid = (*credit).min(-*debt);
+        transfers.push(Transfer { from: from.clone(), to: to.clone(), amount: amount(paid) });
+        *credit -= paid;
+        *debt += paid;
+        creditors.retain(|(_, balance)| *balance > 0);
+        debtors.retain(|(_, balance)| *balance < 0);
+    }
+    Settlement { total: amount(total), unaccounted, entries, transfers }
+}
+
+// POST /polls/:id/bill: {"voter_name": "Sam", "paid": 42.50, "spent": 14.00}, or {"voter_name": "Sam",
+// "paid": 60, "attendees": ["Zoë", "Ann"]} for one person paying for everyone. Returns the settlement as it
+// now stands
+pub async fn record_entry(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewEntry>,
+) -> Result<(StatusCode, Json<Settlement>), ApiError> {
+    polls::find_poll(&state.db, id).await?.ok_or_else(|| Message::no_poll(&public_id))?;
+    let paid = req.paid.map(|paid| cents(paid, "paid")).transpose()?;
+    let spent = req.spent.map(|spent| cents(spent, "spent")).transpose()?;
+    let mut tx = state.db.begin().await?;
+    let voter_name = names::canonical_voter_name(&state.db, &req.voter_name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_empty().into());
+    }
+    sqlx::query(
+        "INSERT INTO bill_entries (poll_id, voter_key, voter_name, paid_cents, spent_cents) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (poll_id, voter_key) DO UPDATE SET paid_cents = COALESCE(?, paid_cents),
+            spent_cents = COALESCE(?, spent_cents), updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(id)
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(paid.unwrap_or(0))
+    .bind(spent)
+    .bind(paid)
+    .bind(spent)
+    .execute(&mut *tx)
+    .await?;
+    for attendee in &req.attendees {
+        let attendee = names::canonical_voter_name(&state.db, attendee).await?;
+        if attendee.is_empty() {
+            return Err(ApiError::BadRequest("attendee names must not be empty".to_string()));
+        }
+        // Someone already on the bill keeps what they said
+        sqlx::query("INSERT OR IGNORE INTO bill_entries (poll_id, voter_key, voter_name) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(names::fold(&attendee))
+            .bind(&attendee)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(settle(entries(&state.db, id).await?))))
+}
+
+// GET /polls/:id/bill: everyone's share and the transfers that settle up
+pub async fn get_settlement(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Settlement>, ApiError> {
+    Ok(Json(settle(entries(&state.db, id).await?)))
+}
+
+// DELETE /polls/:id/bill/:voter: takes someone off the bill, for a name that was put on by mistake
+pub async fn remove_entry(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let removed = sqlx::query("DELETE FROM bill_entries WHERE poll_id = ? AND voter_key = ?")
+        .bind(id)
+        .bind(names::fold(&voter))
+        .execute(&state.db)
+        .await?;
+    if removed.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{voter} isn't on this poll's bill")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
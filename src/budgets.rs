@@ -0,0 +1,150 @@
+// Monthly lunch budgets. A voter sets how much they mean to spend a month, and every poll bill they're on that month
+// (see bills.rs) is taken off it, by the poll's lunch_at. Recommendations made for a group warn about places that
+// would cost more than someone in it has left: the restaurant's average_cost when it's known, otherwise the usual
+// cost of its price tier, from PRICE_TIER_COSTS
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::bills::{amount, cents};
+use crate::config::Config;
+use crate::error::{ApiError, Message};
+use crate::restaurants::RestaurantDetails;
+use crate::{bills, names, AppState};
+
+#[derive(Deserialize)]
+pub struct NewBudget {
+    monthly: f64,
+}
+
+#[derive(Serialize)]
+pub struct BudgetStatus {
+    voter_name: String,
+    month: String, // "2024-05"
+    monthly: f64,
+    spent: f64,
+    remaining: f64, // below zero once they're over
+    lunches: Vec<Lunch>,
+}
+
+#[derive(Serialize)]
+pub struct Lunch {
+    poll_id: String,
+    lunch_at: String,
+    share: f64,
+}
+
+// Someone in a group whose budget is set, and what's left of it this month
+pub struct Remaining {
+    voter_name: String,
+    remaining: f64,
+}
+
+async fn this_month(db: &SqlitePool) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar("SELECT strftime('%Y-%m', 'now', 'localtime')").fetch_one(db).await
+}
+
+async fn status(db: &SqlitePool, voter_key: &str) -> Result<Option<BudgetStatus>, sqlx::Error> {
+    let budget: Option<(String, i64)> =
+        sqlx::query_as("SELECT voter_name, monthly_cents FROM voter_budgets WHERE voter_key = ?")
+            .bind(voter_key)
+            .fetch_optional(db)
+            .await?;
+    let Some((voter_name, monthly)) = budget else {
+        return Ok(None);
+    };
+    let month = this_month(db).await?;
+    let shares = bills::shares(db, voter_key, &month).await?;
+    let spent: i64 = shares.iter().map(|(_, _, share)| share).sum();
+    let lunches = shares
+        .into_iter()
+        .map(|(poll_id, lunch_at, share)| Lunch { poll_id, lunch_at, share: amount(share) })
+        .collect();
+    Ok(Some(BudgetStatus {
+        voter_name,
+        month,
+        monthly: amount(monthly),
+        spent: amount(spent),
+        remaining: amount(monthly - spent),
+        lunches,
+    }))
+}
+
+// What's left this month for each of the voters who has a budget
+pub async fn remaining(db: &SqlitePool, voters: &[String]) -> Result<Vec<Remaining>, sqlx::Error> {
+    let mut remaining = Vec::new();
+    for voter in voters {
+        if let Some(status) = status(db, &names::fold(voter)).await? {
+            remaining.push(Remaining { voter_name: status.voter_name, remaining: status.remaining });
+        }
+    }
+    Ok(remaining)
+}
+
+// What lunch at the restaurant is likely to cost one person; None when neither its cost nor its tier is known
+pub fn estimated_cost(config: &Config, details: &RestaurantDetails) -> Option<f64> {
+    details.average_cost().or_else(|| {
+        let tier = usize::try_from(details.price_tier()?).ok()?;
+        config.price_tier_costs.get(tier.checked_sub(1)?).copied()
+    })
+}
+
+// "over Sam's remaining 12.50" for the voters the restaurant's likely cost would put over budget, None for nobody
+pub fn warning(config: &Config, details: &RestaurantDetails, remaining: &[Remaining]) -> Option<String> {
+    let cost = estimated_cost(config, details)?;
+    let over: Vec<String> = remaining
+        .iter()
+        .filter(|voter| cost > voter.remaining)
+        .map(|voter| format!("{}'s remaining {:.2}", voter.voter_name, voter.remaining))
+        .collect();
+    (!over.is_empty()).then(|| format!("about {cost:.2} would go over {}", over.join(", ")))
+}
+
+// GET /voters/:name/budget: this month's budget, what the month's lunches have cost and what's left
+pub async fn get_budget(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<BudgetStatus>, ApiError> {
+    status(&state.db, &names::fold(&name))
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("{name} has no budget set")))
+}
+
+// PUT /voters/:name/budget: {"monthly": 200}
+pub async fn put_budget(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<NewBudget>,
+) -> Result<Json<BudgetStatus>, ApiError> {
+    let voter_name = names::canonical_voter_name(&state.db, &name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    let key = names::fold(&voter_name);
+    sqlx::query(
+        "INSERT INTO voter_budgets (voter_key, voter_name, monthly_cents) VALUES (?, ?, ?)
+        ON CONFLICT (voter_key) DO UPDATE SET voter_name = excluded.voter_name,
+            monthly_cents = excluded.monthly_cents, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(&key)
+    .bind(&voter_name)
+    .bind(cents(req.monthly, "monthly")?)
+    .execute(&state.db)
+    .await?;
+    Ok(Json(status(&state.db, &key).await?.ok_or(sqlx::Error::RowNotFound)?))
+}
+
+// DELETE /voters/:name/budget
+pub async fn delete_budget(State(state): State<AppState>, Path(name): Path<String>) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM voter_budgets WHERE voter_key = ?")
+        .bind(names::fold(&name))
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{name} has no budget set")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
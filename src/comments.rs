@@ -0,0 +1,89 @@
+// Short notes voters leave on their ballots, "only if we leave by 12:15". They're shown in the detailed results and
+// never change the tally
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::polls::HIDDEN_POLLS_SQL;
+use crate::vote_events::{self, VoteChange};
+use crate::AppState;
+
+#[derive(Clone, Serialize, sqlx::FromRow)]
+pub struct Comment {
+    vote_id: i64, // for DELETE /votes/:id/comment
+    voter_name: String,
+    restaurant_name: String,
+    comment: String,
+    created_at: String,
+    updated_at: String,
+}
+
+// Every comment goes through here before it's stored, so this is the one place for moderation rules: today a length
+// limit and the COMMENT_BLOCKLIST words. Returns the comment to store, None for a blank one, or why it was rejected
+pub fn moderate(config: &Config, comment: &str) -> Result<Option<String>, String> {
+    let comment = comment.trim();
+    if comment.is_empty() {
+        return Ok(None);
+    }
+    // chars() counts characters rather than bytes, so an emoji costs one like any letter
+    // https://doc.rust-lang.org/std/primitive.str.html#method.chars
+    let length = comment.chars().count();
+    if length > config.max_comment_length {
+        return Err(format!(
+            "comment is {length} characters long; the limit is {}",
+            config.max_comment_length
+        ));
+    }
+    let blocked = comment
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| config.comment_blocklist.contains(&word.to_lowercase()));
+    if blocked {
+        return Err("comment contains a blocked word".to_string());
+    }
+    Ok(Some(comment.to_string()))
+}
+
+// The comments left on a poll's ballots, or on every ballot without one, oldest first
+pub async fn comments(db: &SqlitePool, poll_id: Option<i64>) -> Result<Vec<Comment>, sqlx::Error> {
+    sqlx::query_as::<_, Comment>(&format!(
+        "SELECT id AS vote_id, voter_name, restaurant_name, comment, created_at,
+            COALESCE(updated_at, created_at) AS updated_at
+        FROM votes
+        WHERE comment IS NOT NULL AND comment_removed_at IS NULL AND deleted_at IS NULL AND (? IS NULL OR poll_id = ?)
+        AND (poll_id IS NULL OR poll_id NOT IN ({HIDDEN_POLLS_SQL}))
+        ORDER BY id"
+    ))
+    .bind(poll_id)
+    .bind(poll_id)
+    .fetch_all(db)
+    .await
+}
+
+// DELETE /votes/:id/comment (admin): takes a comment down after the fact. The vote itself still counts
+pub async fn remove_comment(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let has_comment: bool = sqlx::query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM votes
+            WHERE id = ? AND comment IS NOT NULL AND comment_removed_at IS NULL AND deleted_at IS NULL
+        )",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if !has_comment {
+        return Err(ApiError::NotFound(format!("vote {id} has no comment")));
+    }
+    vote_events::change(&mut tx, id, VoteChange { comment_removed: true, ..Default::default() }).await?;
+    tx.commit().await?;
+    println!("removed the comment on vote {id}");
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,55 @@
+// Conditional GETs on the results, for the dashboards asking for them every few seconds. The results come with an
+// ETag, a hash of the JSON they're sent as, which changes whenever anything in them does. A client that sends it back
+// in If-None-Match gets 304 Not Modified with no body while it's still the same, so a tally nobody has voted on since
+// costs a few headers rather than every restaurant's details again. Cache-Control: no-cache has a browser keep the
+// results but check with If-None-Match each time before showing them
+// https://developer.mozilla.org/en-US/docs/Web/HTTP/Conditional_requests
+// https://www.rfc-editor.org/rfc/rfc9110#name-if-none-match
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use ring::digest;
+use serde::Serialize;
+use std::convert::Infallible;
+
+use crate::receipts::hex;
+
+// The ETags from If-None-Match; none when it wasn't sent
+pub struct IfNoneMatch(Vec<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Comma-separated and possibly sent more than once. Weak and strong compare the same here, W/ or not; a
+        // header that can't be read is as good as none, and gets the results in full
+        let tags = parts
+            .headers
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/").to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Ok(IfNoneMatch(tags))
+    }
+}
+
+impl IfNoneMatch {
+    // The body as JSON with its ETag, or 304 when the client has it already
+    pub fn respond(&self, body: &impl Serialize) -> Response {
+        let json = serde_json::to_vec(body).expect("results serialize to JSON");
+        // Half of a SHA-256 is plenty to tell one tally from the next
+        let etag = format!("\"{}\"", hex(&digest::digest(&digest::SHA256, &json).as_ref()[..16]));
+        let headers = [(CACHE_CONTROL, "no-cache".to_string()), (ETAG, etag.clone())];
+        if self.0.iter().any(|tag| *tag == etag || tag == "*") {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+        (headers, [(CONTENT_TYPE, "application/json")], json).into_response()
+    }
+}
@@ -0,0 +1,462 @@
+// Settings read from environment variables at startup. Everything is optional so `cargo run` works out of the box;
+// only the secrets have to be given once the data is kept somewhere that outlasts the process (see secret_var)
+use reqwest::Url;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::time::Duration;
+
+use crate::geo::Coordinates;
+use crate::hours;
+use crate::publisher::Broker;
+
+// Clone so each organization can start from the instance's settings; see for_organization
+#[derive(Clone)]
+pub struct Config {
+    // Bearer token that admin endpoints require. When unset, admin endpoints refuse every request
+    pub admin_token: Option<String>,
+    // An organization's admin token instead, kept only as its SHA-256, hex; see organizations.rs
+    pub admin_token_hash: Option<String>,
+    // The PEM certificate chain and key for HTTPS, from TLS_CERT_FILE and TLS_KEY_FILE, and the port it's served on
+    // beside the plain listener, from TLS_PORT; see tls.rs
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub tls_port: u16,
+    // The SQLite database, from DATABASE_URL, in memory without one, and a read replica of it for statistics and
+    // exports, from DATABASE_READ_URL (a copy kept up to date by LiteFS or the like); see open in main.rs
+    pub database_url: String,
+    pub database_read_url: Option<String>,
+    // How many connections each pool opens at most, from DATABASE_MAX_CONNECTIONS; how long a query waits for one,
+    // from DATABASE_ACQUIRE_TIMEOUT_SECONDS; and after how many minutes a connection is replaced, from
+    // DATABASE_MAX_LIFETIME_MINUTES, 0 for never (an in-memory database's never are, since closing its last connection
+    // would throw it away)
+    pub database_max_connections: u32,
+    pub database_acquire_timeout: Duration,
+    pub database_max_lifetime: Option<Duration>,
+    // How long startup keeps trying to connect to the database, from DATABASE_STARTUP_TIMEOUT_SECONDS, for a volume
+    // that's mounted a little after the service starts
+    pub database_startup_timeout: Duration,
+    // Where organizations' databases are kept, one SQLite file each, from ORGANIZATION_DATA_DIR; in memory without
+    // it. See organizations.rs
+    pub organization_data_dir: Option<String>,
+    // How alike two restaurant names must be (0.0 to 1.0) before they're treated as possible duplicates
+    pub fuzzy_match_threshold: f64,
+    // Where the office is, from OFFICE_LATITUDE and OFFICE_LONGITUDE; distances are measured from here
+    pub office_location: Option<Coordinates>,
+    // Key for the Google Places API, needed by the Google Places import
+    pub google_places_api_key: Option<String>,
+    // How far from the office the Google Places import looks for restaurants, in meters
+    pub places_radius_meters: f64,
+    // Overpass API endpoint for the OpenStreetMap import
+    pub overpass_url: String,
+    // OSRM routing service used for walking times from the office, from ROUTING_URL
+    pub routing_url: String,
+    // Open-Meteo forecast endpoint, from WEATHER_URL; polls record the lunch-time forecast when the office is known
+    pub weather_url: String,
+    // Chance of rain, in percent, from which lunch counts as rainy
+    pub rain_probability_percent: f64,
+    // On rainy days, restaurants farther than this many minutes' walk are flagged and listed last
+    pub rain_max_walking_minutes: i64,
+    // Feature flag for GET /recommendations/llm, from LLM_SUGGESTIONS
+    pub llm_suggestions: bool,
+    // An OpenAI-compatible chat completions API: base URL, key (local servers may not need one) and model
+    pub llm_api_url: String,
+    pub llm_api_key: Option<String>,
+    pub llm_model: String,
+    // Key for the Yelp Fusion API; when set, restaurants are enriched with Yelp data in the background
+    pub yelp_api_key: Option<String>,
+    // How often Yelp data is refreshed, from YELP_REFRESH_HOURS
+    pub yelp_refresh_interval: Duration,
+    // Local time lunch usually happens, as HH:MM; polls created without a lunch_at are planned for today at this time
+    pub lunch_time: String,
+    // When set, from DAILY_POLL_TIME, a poll is opened at this local HH:MM every weekday that isn't a holiday
+    pub daily_poll_time: Option<String>,
+    // The poll template that poll is opened from, from DAILY_POLL_TEMPLATE; see poll_templates.rs
+    pub daily_poll_template: Option<String>,
+    // The zone database offices' and polls' time zones are read from, from ZONEINFO; see timezones.rs
+    pub zoneinfo_dir: String,
+    // Public holidays from HOLIDAYS, "2024-12-25=Christmas Day,2025-01-01=New Year's Day"; admins can add more later
+    pub holidays: Vec<(String, String)>,
+    // How long a runoff poll stays open, from RUNOFF_MINUTES
+    pub runoff_minutes: i64,
+    // What lunch usually costs one person at each price tier, 1 to 4, from PRICE_TIER_COSTS; budget warnings use it
+    // for restaurants without an average_cost
+    pub price_tier_costs: Vec<f64>,
+    // Where tables are booked at the winner, from RESERVATION_API_URL, with RESERVATION_API_KEY as a bearer token,
+    // and which provider's restaurant ids to send, from RESERVATION_PROVIDER (opentable or resy); see reservations.rs
+    pub reservation_api_url: Option<String>,
+    pub reservation_api_key: Option<String>,
+    pub reservation_provider: String,
+    // Collecting bill shares through Stripe Checkout: the secret key from STRIPE_API_KEY, the API from STRIPE_API_URL,
+    // the currency amounts are charged in from PAYMENT_CURRENCY, and where Stripe sends people once they've paid, from
+    // PAYMENT_RETURN_URL; see payments.rs
+    pub stripe_api_key: Option<String>,
+    pub stripe_api_url: String,
+    pub payment_currency: String,
+    pub payment_return_url: Option<String>,
+    // Where a poll's winner is announced when it closes, from WINNER_WEBHOOK_URL; see announcements.rs
+    pub winner_webhook_url: Option<String>,
+    // The Redis server that instances share tally refreshes through, from REDIS_URL, and the pub/sub channel they
+    // use, from REDIS_CHANNEL; see refresh.rs. The key they hold the scheduler's lease in, from SCHEDULER_LEASE_KEY;
+    // see leader.rs
+    pub redis_url: Option<String>,
+    pub redis_channel: String,
+    pub scheduler_lease_key: String,
+    // How many requests a client may send a minute, from RATE_LIMIT, unset for no limit; whether the client is the
+    // last address in X-Forwarded-For, from RATE_LIMIT_BEHIND_PROXY; and the prefix of the Redis keys the counts are
+    // kept under with REDIS_URL set, from RATE_LIMIT_KEY. See rate_limit.rs
+    pub rate_limit: Option<i64>,
+    pub rate_limit_behind_proxy: bool,
+    pub rate_limit_key: String,
+    // The broker VoteCast and PollClosed events are published to, from EVENTS_URL, and the Kafka topic or NATS
+    // subject they go to, from EVENTS_TOPIC; see publisher.rs
+    pub events_broker: Option<Broker>,
+    pub events_topic: String,
+    // The MQTT broker tally updates and winners are published to, from MQTT_URL, and the topics they go to, from
+    // MQTT_RESULTS_TOPIC and MQTT_WINNER_TOPIC; see mqtt.rs
+    pub mqtt_url: Option<Url>,
+    pub mqtt_results_topic: String,
+    pub mqtt_winner_topic: String,
+    // Reminders to vote for those on a poll's roster who haven't: how many minutes before closes_at they go out, from
+    // REMINDER_LEAD_MINUTES ("60,15" for two rounds), and where they're POSTed, from REMINDER_WEBHOOK_URL; see
+    // reminders.rs
+    pub reminder_lead_minutes: Vec<i64>,
+    pub reminder_webhook_url: Option<String>,
+    // The SendGrid-compatible API notifications are emailed through, from EMAIL_API_URL and EMAIL_API_KEY, and
+    // the address they're sent from, from EMAIL_FROM; see email.rs
+    pub email_api_url: String,
+    pub email_api_key: Option<String>,
+    pub email_from: Option<String>,
+    // The weekly digest goes out on Fridays at DIGEST_TIME, local HH:MM, written from the template in the file at
+    // DIGEST_TEMPLATE, or the built-in one, in each reader's language, without it; see digest.rs
+    pub digest_time: String,
+    pub digest_template: Option<String>,
+    // Where this instance can be reached from outside, from PUBLIC_URL, for links in emails
+    pub public_url: String,
+    // Longest comment a ballot can carry, in characters, from MAX_COMMENT_LENGTH
+    pub max_comment_length: usize,
+    // Words that get a comment rejected, from COMMENT_BLOCKLIST, comma-separated and matched case-insensitively
+    pub comment_blocklist: Vec<String>,
+    // Words that get a voter or restaurant name rejected, from NAME_BLOCKLIST, and words that let the name through
+    // but queue it for an admin to review, from NAME_FLAGLIST. Both comma-separated; see moderation.rs
+    pub name_blocklist: Vec<String>,
+    pub name_flaglist: Vec<String>,
+    // Secret that vote receipts are signed with, from RECEIPT_KEY, and that erasure and unsubscribe tokens are
+    // derived from
+    pub receipt_key: Vec<u8>,
+    // Votes older than this many days, from VOTE_RETENTION_DAYS, have their voter's name replaced by an opaque token.
+    // Unset keeps names for good
+    pub vote_retention_days: Option<i64>,
+    // Secret the tokens are derived from, from ANONYMIZATION_KEY
+    pub anonymization_key: Vec<u8>,
+    // Secret each sealed poll's own key is wrapped with, from SEALING_KEY, to be kept beside the poll; see sealing.rs
+    pub sealing_key: Vec<u8>,
+    // Closed polls and loose votes older than this many days, from PRUNE_AFTER_DAYS, are deleted. Unset keeps them
+    pub prune_after_days: Option<i64>,
+    // From PRUNE_DRY_RUN: the pruning job only counts what it would delete, and records that
+    pub prune_dry_run: bool,
+    // S3-compatible storage the database is backed up to, from BACKUP_BUCKET, unset for no backups: the service at
+    // BACKUP_ENDPOINT in BACKUP_REGION, signed in to with BACKUP_ACCESS_KEY_ID and BACKUP_SECRET_ACCESS_KEY. Backups
+    // are named after BACKUP_PREFIX, taken every BACKUP_INTERVAL_HOURS, and the newest BACKUP_KEEP are kept; see
+    // backups.rs
+    pub backup_bucket: Option<String>,
+    pub backup_endpoint: String,
+    pub backup_region: String,
+    pub backup_access_key_id: Option<String>,
+    pub backup_secret_access_key: Option<String>,
+    pub backup_prefix: String,
+    pub backup_interval: Duration,
+    pub backup_keep: usize,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let database_url = optional_var("DATABASE_URL").unwrap_or_else(|| "sqlite::memory:".to_string());
+        let organization_data_dir = optional_var("ORGANIZATION_DATA_DIR");
+        // Organizations' secrets are derived from these (see for_organization), so their files count too
+        let persistent = !in_memory(&database_url) || organization_data_dir.is_some();
+        Config {
+            admin_token: optional_var("ADMIN_TOKEN"),
+            admin_token_hash: None,
+            tls_cert_file: optional_var("TLS_CERT_FILE"),
+            tls_key_file: optional_var("TLS_KEY_FILE"),
+            tls_port: parse_var("TLS_PORT", 3443),
+            database_url,
+            database_read_url: optional_var("DATABASE_READ_URL"),
+            database_max_connections: match parse_var("DATABASE_MAX_CONNECTIONS", 10) {
+                0 => panic!("DATABASE_MAX_CONNECTIONS has to allow at least one connection"),
+                connections => connections,
+            },
+            database_acquire_timeout: Duration::from_secs(parse_var("DATABASE_ACQUIRE_TIMEOUT_SECONDS", 30)),
+            database_max_lifetime: match parse_var("DATABASE_MAX_LIFETIME_MINUTES", 30) {
+                0 => None,
+                minutes => Some(Duration::from_secs(minutes * 60)),
+            },
+            database_startup_timeout: Duration::from_secs(parse_var("DATABASE_STARTUP_TIMEOUT_SECONDS", 60)),
+            organization_data_dir,
+            fuzzy_match_threshold: parse_var("FUZZY_MATCH_THRESHOLD", 0.5),
+            office_location: office_location(),
+            google_places_api_key: optional_var("GOOGLE_PLACES_API_KEY"),
+            places_radius_meters: parse_var("PLACES_RADIUS_METERS", 800.0),
+            overpass_url: optional_var("OVERPASS_URL")
+                .unwrap_or_else(|| "https://overpass-api.de/api/interpreter".to_string()),
+            routing_url: optional_var("ROUTING_URL")
+                .unwrap_or_else(|| "https://routing.openstreetmap.de/routed-foot".to_string()),
+            weather_url: optional_var("WEATHER_URL")
+                .unwrap_or_else(|| "https://api.open-meteo.com/v1/forecast".to_string()),
+            rain_probability_percent: parse_var("RAIN_PROBABILITY_PERCENT", 50.0),
+            rain_max_walking_minutes: parse_var("RAIN_MAX_WALKING_MINUTES", 10),
+            llm_suggestions: parse_var("LLM_SUGGESTIONS", false),
+            llm_api_url: optional_var("LLM_API_URL").unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            llm_api_key: optional_var("LLM_API_KEY"),
+            llm_model: optional_var("LLM_MODEL").unwrap_or_else(|| "gpt-4o-mini".to_string()),
+            yelp_api_key: optional_var("YELP_API_KEY"),
+            yelp_refresh_interval: Duration::from_secs(parse_var("YELP_REFRESH_HOURS", 24) * 60 * 60),
+            lunch_time: lunch_time(),
+            daily_poll_time: optional_var("DAILY_POLL_TIME").map(|value| {
+                hours::parse_time(&value).unwrap_or_else(|| panic!("DAILY_POLL_TIME has an invalid value: {value}"))
+            }),
+            daily_poll_template: optional_var("DAILY_POLL_TEMPLATE"),
+            zoneinfo_dir: optional_var("ZONEINFO").unwrap_or_else(|| "/usr/share/zoneinfo".to_string()),
+            holidays: holidays(),
+            runoff_minutes: parse_var("RUNOFF_MINUTES", 15),
+            price_tier_costs: price_tier_costs(),
+            reservation_api_url: optional_var("RESERVATION_API_URL"),
+            reservation_api_key: optional_var("RESERVATION_API_KEY"),
+            reservation_provider: reservation_provider(),
+            stripe_api_key: optional_var("STRIPE_API_KEY"),
+            stripe_api_url: optional_var("STRIPE_API_URL").unwrap_or_else(|| "https://api.stripe.com".to_string()),
+            payment_currency: optional_var("PAYMENT_CURRENCY").unwrap_or_else(|| "usd".to_string()).to_lowercase(),
+            payment_return_url: optional_var("PAYMENT_RETURN_URL"),
+            winner_webhook_url: optional_var("WINNER_WEBHOOK_URL"),
+            redis_url: optional_var("REDIS_URL"),
+            redis_channel: optional_var("REDIS_CHANNEL").unwrap_or_else(|| "lunch-voting:refreshes".to_string()),
+            scheduler_lease_key: optional_var("SCHEDULER_LEASE_KEY")
+                .unwrap_or_else(|| "lunch-voting:scheduler".to_string()),
+            rate_limit: optional_var("RATE_LIMIT").map(|value| {
+                value.parse().unwrap_or_else(|_| panic!("RATE_LIMIT has an invalid value: {value}"))
+            }),
+            rate_limit_behind_proxy: parse_var("RATE_LIMIT_BEHIND_PROXY", false),
+            rate_limit_key: optional_var("RATE_LIMIT_KEY").unwrap_or_else(|| "lunch-voting:rate".to_string()),
+            events_broker: optional_var("EVENTS_URL").map(|url| {
+                Broker::parse(&url).unwrap_or_else(|| panic!("EVENTS_URL has an invalid value: {url}"))
+            }),
+            events_topic: optional_var("EVENTS_TOPIC").unwrap_or_else(|| "lunch-voting".to_string()),
+            mqtt_url: optional_var("MQTT_URL").map(|url| match Url::parse(&url) {
+                Ok(parsed) if parsed.scheme() == "mqtt" => parsed,
+                _ => panic!("MQTT_URL has an invalid value: {url}"),
+            }),
+            mqtt_results_topic: optional_var("MQTT_RESULTS_TOPIC")
+                .unwrap_or_else(|| "lunch-voting/results".to_string()),
+            mqtt_winner_topic: optional_var("MQTT_WINNER_TOPIC").unwrap_or_else(|| "lunch-voting/winner".to_string()),
+            reminder_lead_minutes: reminder_lead_minutes(),
+            reminder_webhook_url: optional_var("REMINDER_WEBHOOK_URL"),
+            email_api_url: optional_var("EMAIL_API_URL").unwrap_or_else(|| "https://api.sendgrid.com".to_string()),
+            email_api_key: optional_var("EMAIL_API_KEY"),
+            email_from: optional_var("EMAIL_FROM"),
+            digest_time: digest_time(),
+            digest_template: digest_template(),
+            public_url: optional_var("PUBLIC_URL").unwrap_or_else(|| "http://localhost:3000".to_string()),
+            max_comment_length: parse_var("MAX_COMMENT_LENGTH", 140),
+            comment_blocklist: word_list("COMMENT_BLOCKLIST"),
+            name_blocklist: word_list("NAME_BLOCKLIST"),
+            name_flaglist: word_list("NAME_FLAGLIST"),
+            receipt_key: secret_var("RECEIPT_KEY", persistent),
+            vote_retention_days: days_var("VOTE_RETENTION_DAYS"),
+            anonymization_key: secret_var("ANONYMIZATION_KEY", persistent),
+            sealing_key: secret_var("SEALING_KEY", persistent),
+            prune_after_days: days_var("PRUNE_AFTER_DAYS"),
+            prune_dry_run: parse_var("PRUNE_DRY_RUN", false),
+            backup_bucket: optional_var("BACKUP_BUCKET"),
+            backup_endpoint: optional_var("BACKUP_ENDPOINT").unwrap_or_else(|| "https://s3.amazonaws.com".to_string()),
+            backup_region: optional_var("BACKUP_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+            backup_access_key_id: optional_var("BACKUP_ACCESS_KEY_ID"),
+            backup_secret_access_key: optional_var("BACKUP_SECRET_ACCESS_KEY"),
+            backup_prefix: optional_var("BACKUP_PREFIX").unwrap_or_else(|| "lunch-voting/".to_string()),
+            backup_interval: Duration::from_secs(parse_var("BACKUP_INTERVAL_HOURS", 24) * 60 * 60),
+            backup_keep: match parse_var("BACKUP_KEEP", 7) {
+                0 => panic!("BACKUP_KEEP has to keep at least the backup just taken"),
+                keep => keep,
+            },
+        }
+    }
+
+    // An organization's settings: the instance's, except for its own admin token and secrets, so neither an admin
+    // nor a receipt of one organization is any good in another, and Redis keys, backups and MQTT topics of its own.
+    // The secrets are derived from the instance's with the organization's id, so they're the same again each time its
+    // database is opened, after a restart or on another instance, which is why RECEIPT_KEY, ANONYMIZATION_KEY and
+    // SEALING_KEY have to be set along with ORGANIZATION_DATA_DIR. Its data is kept in a file of its own under
+    // ORGANIZATION_DATA_DIR, or in memory, never in the instance's database
+    pub fn for_organization(&self, id: &str, admin_token_hash: Option<String>) -> Self {
+        let derive = |key: &[u8]| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+            hmac::sign(&key, format!("organization:{id}").as_bytes()).as_ref().to_vec()
+        };
+        Config {
+            admin_token: None,
+            admin_token_hash,
+            database_url: match &self.organization_data_dir {
+                Some(dir) => format!("sqlite://{}/{id}.sqlite", dir.trim_end_matches('/')),
+                None => "sqlite::memory:".to_string(),
+            },
+            database_read_url: None,
+            // Its database is opened for a request, which shouldn't be kept waiting while it's retried
+            database_startup_timeout: Duration::ZERO,
+            receipt_key: derive(&self.receipt_key),
+            anonymization_key: derive(&self.anonymization_key),
+            sealing_key: derive(&self.sealing_key),
+            redis_channel: format!("{}:{id}", self.redis_channel),
+            scheduler_lease_key: format!("{}:{id}", self.scheduler_lease_key),
+            rate_limit_key: format!("{}:{id}", self.rate_limit_key),
+            backup_prefix: format!("{}{id}/", self.backup_prefix),
+            mqtt_results_topic: format!("organizations/{id}/{}", self.mqtt_results_topic),
+            mqtt_winner_topic: format!("organizations/{id}/{}", self.mqtt_winner_topic),
+            ..self.clone()
+        }
+    }
+}
+
+// An environment variable that is unset or empty is the same as not configured
+fn optional_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn office_location() -> Option<Coordinates> {
+    let latitude = optional_var("OFFICE_LATITUDE")?;
+    let longitude = optional_var("OFFICE_LONGITUDE")?;
+    Some(Coordinates {
+        latitude: latitude
+            .parse()
+            .unwrap_or_else(|_| panic!("OFFICE_LATITUDE has an invalid value: {latitude}")),
+        longitude: longitude
+            .parse()
+            .unwrap_or_else(|_| panic!("OFFICE_LONGITUDE has an invalid value: {longitude}")),
+    })
+}
+
+fn lunch_time() -> String {
+    let value = optional_var("LUNCH_TIME").unwrap_or_else(|| "12:00".to_string());
+    hours::parse_time(&value).unwrap_or_else(|| panic!("LUNCH_TIME has an invalid value: {value}"))
+}
+
+// A holiday without a name after the = is still a holiday; it's just called "Holiday"
+fn holidays() -> Vec<(String, String)> {
+    let Some(value) = optional_var("HOLIDAYS") else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((day, name)) if !name.trim().is_empty() => (day.trim().to_string(), name.trim().to_string()),
+            Some((day, _)) => (day.trim().to_string(), "Holiday".to_string()),
+            None => (entry.to_string(), "Holiday".to_string()),
+        })
+        .collect()
+}
+
+fn reservation_provider() -> String {
+    let value = optional_var("RESERVATION_PROVIDER").unwrap_or_else(|| "opentable".to_string()).to_lowercase();
+    if value != "opentable" && value != "resy" {
+        panic!("RESERVATION_PROVIDER has an invalid value: {value}");
+    }
+    value
+}
+
+fn digest_time() -> String {
+    let value = optional_var("DIGEST_TIME").unwrap_or_else(|| "16:00".to_string());
+    hours::parse_time(&value).unwrap_or_else(|| panic!("DIGEST_TIME has an invalid value: {value}"))
+}
+
+// A digest without a way out of the next one isn't sent, so a template has to have the link
+fn digest_template() -> Option<String> {
+    let path = optional_var("DIGEST_TEMPLATE")?;
+    let template =
+        std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("could not read DIGEST_TEMPLATE {path}: {err}"));
+    if !template.contains("{{unsubscribe_url}}") {
+        panic!("DIGEST_TEMPLATE {path} has no {{{{unsubscribe_url}}}}");
+    }
+    Some(template)
+}
+
+// Positive minute counts, longest first, "30" by default
+fn reminder_lead_minutes() -> Vec<i64> {
+    let value = optional_var("REMINDER_LEAD_MINUTES").unwrap_or_else(|| "30".to_string());
+    let mut leads: Vec<i64> = Vec::new();
+    for lead in value.split(',').map(str::trim).filter(|lead| !lead.is_empty()) {
+        match lead.parse() {
+            Ok(minutes) if minutes > 0 => leads.push(minutes),
+            _ => panic!("REMINDER_LEAD_MINUTES has an invalid value: {value}"),
+        }
+    }
+    leads.sort_unstable_by(|a, b| b.cmp(a));
+    leads.dedup();
+    leads
+}
+
+// Four amounts, one per tier, "10,20,35,60" by default
+fn price_tier_costs() -> Vec<f64> {
+    let value = optional_var("PRICE_TIER_COSTS").unwrap_or_else(|| "10,20,35,60".to_string());
+    let costs: Vec<f64> = value.split(',').filter_map(|cost| cost.trim().parse().ok()).collect();
+    if costs.len() != 4 || costs.iter().any(|cost| !cost.is_finite() || *cost < 0.0) {
+        panic!("PRICE_TIER_COSTS has an invalid value: {value}");
+    }
+    costs
+}
+
+// A number of days that must be at least one, when it's set at all
+fn days_var(name: &str) -> Option<i64> {
+    optional_var(name).map(|value| {
+        value
+            .parse()
+            .ok()
+            .filter(|days| *days > 0)
+            .unwrap_or_else(|| panic!("{name} has an invalid value: {value}"))
+    })
+}
+
+// Whether a database URL is one of SQLite's in-memory ones, gone with the last connection to it
+pub fn in_memory(url: &str) -> bool {
+    url.contains(":memory:") || url.contains("mode=memory")
+}
+
+// A secret that signs or encrypts what's stored. With the data in memory a random one made at startup does, since
+// the data goes when it does; with the data in a file it has to be given, or whatever was signed or encrypted before
+// a restart, receipts, anonymized names or sealed polls' keys, could no longer be checked or read after it. The same
+// goes for the instances sharing a database, which all need the same
+fn secret_var(name: &str, persistent: bool) -> Vec<u8> {
+    match optional_var(name) {
+        Some(value) => value.into_bytes(),
+        None if !persistent => random_key(),
+        None => panic!("{name} has to be set when DATABASE_URL or ORGANIZATION_DATA_DIR keeps data in files"),
+    }
+}
+
+pub fn random_key() -> Vec<u8> {
+    let mut key = vec![0u8; 32];
+    SecureRandom::fill(&SystemRandom::new(), &mut key).expect("the system random number generator failed");
+    key
+}
+
+fn word_list(name: &str) -> Vec<String> {
+    let Some(value) = optional_var(name) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .map(|word| word.trim().to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+// Reads and parses an environment variable, falling back to the default when it's unset.
+// A value that is set but doesn't parse is a configuration mistake, so we stop rather than guess
+fn parse_var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} has an invalid value: {value}")),
+        Err(_) => default,
+    }
+}
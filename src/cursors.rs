@@ -0,0 +1,97 @@
+// Paging through the long logs, the vote events and the audit log, by where the last page ended rather than by how
+// many entries came before it. An offset drifts as entries are added, repeating some on the next page or skipping
+// others; a position doesn't. A page with more after it is answered with a Link header, rel="next", to the same
+// request carrying on from its last entry, so a client reads the whole log by following it until there's none. The
+// cursor in it stands for that entry's id in the log it's from, and is for passing back as it is, not taking apart
+// https://www.rfc-editor.org/rfc/rfc8288
+// https://use-the-index-luke.com/no-offset
+use axum::http::header::LINK;
+use axum::http::Uri;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+use crate::error::{ApiError, Message};
+use crate::receipts::hex;
+
+// Where a page starts: after the entry at `cursor`, from the log named `log`; none to start at the beginning
+pub fn position(log: &str, cursor: Option<&str>) -> Result<Option<i64>, ApiError> {
+    let Some(cursor) = cursor else {
+        return Ok(None);
+    };
+    let unknown = || ApiError::from(Message::unknown_cursor(cursor, log));
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|at| cursor.get(at..at + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(unknown)?;
+    let text = String::from_utf8(bytes).map_err(|_| unknown())?;
+    match text.split_once(':') {
+        Some((from, id)) if from == log => id.parse().map(Some).map_err(|_| unknown()),
+        _ => Err(unknown()),
+    }
+}
+
+// The cursor for after the entry at `id` in the log named `log`, which position reads back
+fn cursor(log: &str, id: i64) -> String {
+    hex(format!("{log}:{id}").as_bytes())
+}
+
+// Up to `limit` entries, with the Link to the next page when there are more. `entries` is read with one more than
+// the limit, to find out whether there are, and `id` gives an entry's position in the log
+pub fn page<T: Serialize>(
+    log: &str,
+    uri: &Uri,
+    limit: i64,
+    mut entries: Vec<T>,
+    id: impl Fn(&T) -> i64,
+) -> Response {
+    if entries.len() as i64 <= limit {
+        return Json(entries).into_response();
+    }
+    entries.truncate(limit as usize);
+    let last = entries.last().map(id).expect("the limit is at least 1");
+    let cursor = cursor(log, last);
+    // The request as it came, with its cursor swapped for this one
+    let mut query: Vec<&str> = uri.query().unwrap_or_default().split('&').collect();
+    query.retain(|pair| !pair.is_empty() && !pair.starts_with("cursor="));
+    let cursor = format!("cursor={cursor}");
+    query.push(&cursor);
+    let next = format!("<{}?{}>; rel=\"next\"", uri.path(), query.join("&"));
+    ([(LINK, next)], Json(entries)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_cursor_reads_back_as_its_position() {
+        assert_eq!(position("audit", Some(&cursor("audit", 42))).ok(), Some(Some(42)));
+        assert_eq!(position("vote-events", Some(&cursor("vote-events", 1))).ok(), Some(Some(1)));
+        assert_eq!(position("audit", None).ok(), Some(None));
+    }
+
+    #[test]
+    fn a_cursor_from_elsewhere_is_refused() {
+        assert!(position("audit", Some(&cursor("vote-events", 42))).is_err());
+        assert!(position("audit", Some("not hex")).is_err());
+        assert!(position("audit", Some("abc")).is_err()); // an odd number of digits
+        assert!(position("audit", Some(&hex(b"audit:many"))).is_err());
+        assert!(position("audit", Some(&hex(b"audit"))).is_err());
+    }
+
+    #[test]
+    fn a_full_page_links_to_the_rest_from_its_last_entry() {
+        let uri: Uri = "/audit?limit=2&cursor=00&actor=admin".parse().unwrap();
+        let response = page("audit", &uri, 2, vec![7, 5, 3], |&id| id);
+        let next = format!("</audit?limit=2&actor=admin&cursor={}>; rel=\"next\"", cursor("audit", 5));
+        assert_eq!(response.headers().get(LINK).and_then(|link| link.to_str().ok()), Some(next.as_str()));
+    }
+
+    #[test]
+    fn the_last_page_has_no_link() {
+        let uri: Uri = "/audit?limit=2".parse().unwrap();
+        assert!(page("audit", &uri, 2, vec![7, 5], |&id| id).headers().get(LINK).is_none());
+    }
+}
@@ -0,0 +1,271 @@
+// Riding out the database being briefly out of reach, say a file on a volume that's remounting, or too busy, with a
+// lock held longer than waiting for it allows. Those errors are transient, and instead of a 500 they come back as
+// 503 Service Unavailable with a Retry-After header, so a client knows trying again is worth it. GET and HEAD
+// requests, which change nothing, are tried again here first, up to ATTEMPTS times with a short backoff. Writes
+// aren't, since the request may have done something before the failure that a second run would do twice. Once the
+// database has been out of reach THRESHOLD times within FAILURE_WINDOW the circuit opens: for COOLDOWN every request
+// is turned away straight off, rather than each one waiting out DATABASE_ACQUIRE_TIMEOUT_SECONDS against a database
+// that isn't answering. The first request after that is let through only once a probe query has gone through. GET
+// /readyz runs the same probe and reports how the database is doing, for a load balancer to route by
+// https://martinfowler.com/bliki/CircuitBreaker.html
+// https://www.sqlite.org/rescode.html
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::{Sqlite, SqliteConnection, SqlitePool};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{ApiError, Message};
+use crate::AppState;
+
+const ATTEMPTS: u32 = 3;
+const FIRST_RETRY: Duration = Duration::from_millis(100);
+const THRESHOLD: u32 = 5;
+const FAILURE_WINDOW: Duration = Duration::from_secs(30);
+const COOLDOWN: Duration = Duration::from_secs(5);
+// A probe that hasn't been answered by then counts as failed; well short of the acquire timeout
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// The two ways the database can fail for now, rather than over anything wrong with the query. Both are worth trying
+// again after, but only the database being out of reach opens the circuit: a burst of votes all waiting on each
+// other's locks is the database answering, just not all at once
+#[derive(Clone, Copy, PartialEq)]
+pub enum Failure {
+    // SQLITE_BUSY or SQLITE_LOCKED: another connection held a lock longer than this one would wait
+    Busy,
+    // No connection to be had, or the file couldn't be read or opened: SQLITE_IOERR or SQLITE_CANTOPEN
+    Unreachable,
+}
+
+// sqlx gives SQLite's extended result code, whose low byte is the primary one
+pub fn classify(err: &sqlx::Error) -> Option<Failure> {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => Some(Failure::Unreachable),
+        sqlx::Error::Database(err) => match err.code()?.parse::<i64>().ok()? & 0xff {
+            5 | 6 => Some(Failure::Busy),
+            10 | 14 => Some(Failure::Unreachable),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Breaker(Arc<Mutex<Circuit>>);
+
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    first_failure: Option<Instant>, // when the failures being counted started
+    open_until: Option<Instant>,
+}
+
+enum Admission {
+    Pass,
+    // The cooldown is over; this request goes through if the probe does
+    Trial,
+    Reject(Duration),
+}
+
+impl Breaker {
+    fn admit(&self) -> Admission {
+        let mut circuit = self.0.lock().expect("circuit poisoned");
+        let now = Instant::now();
+        match circuit.open_until {
+            None => Admission::Pass,
+            Some(until) if until > now => Admission::Reject(until - now),
+            // Kept open meanwhile, so the requests arriving while the probe runs don't pile onto the database too
+            Some(_) => {
+                circuit.open_until = Some(now + COOLDOWN);
+                Admission::Trial
+            }
+        }
+    }
+
+    fn fail(&self) {
+        let mut circuit = self.0.lock().expect("circuit poisoned");
+        let now = Instant::now();
+        if circuit.first_failure.is_none_or(|first| now - first > FAILURE_WINDOW) {
+            circuit.failures = 0;
+            circuit.first_failure = Some(now);
+        }
+        circuit.failures += 1;
+        if circuit.failures >= THRESHOLD && circuit.open_until.is_none() {
+            let failures = circuit.failures;
+            eprintln!("database: {failures} failures in {FAILURE_WINDOW:?}, turning requests away for {COOLDOWN:?}");
+            circuit.open_until = Some(now + COOLDOWN);
+        }
+    }
+
+    // The failures stay counted until they're FAILURE_WINDOW old, so /readyz still reports them, and one more opens
+    // the circuit again straight away
+    fn close(&self) {
+        let mut circuit = self.0.lock().expect("circuit poisoned");
+        if circuit.open_until.take().is_some() {
+            eprintln!("database: answering again, letting requests through");
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.0.lock().expect("circuit poisoned").open_until.is_some()
+    }
+
+    fn recent_failures(&self) -> u32 {
+        let circuit = self.0.lock().expect("circuit poisoned");
+        match circuit.first_failure {
+            Some(first) if first.elapsed() <= FAILURE_WINDOW => circuit.failures,
+            _ => 0,
+        }
+    }
+}
+
+// Middleware: turns requests away while the circuit is open, and tries reads again after transient failures
+pub async fn guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    match state.breaker.admit() {
+        Admission::Pass => {}
+        Admission::Reject(left) => return unavailable(left),
+        Admission::Trial => match probe(&state.db).await {
+            Ok(()) => state.breaker.close(),
+            Err(err) => {
+                eprintln!("database: still out of reach: {err}");
+                return unavailable(COOLDOWN);
+            }
+        },
+    }
+
+    let retried = matches!(*request.method(), Method::GET | Method::HEAD);
+    let (parts, body) = request.into_parts();
+    let mut request = Request::from_parts(parts.clone(), body);
+    let mut delay = FIRST_RETRY;
+    let mut attempt = 1;
+    loop {
+        let response = next.clone().run(request).await;
+        let Some(&failure) = response.extensions().get::<Failure>() else {
+            return response;
+        };
+        if failure == Failure::Unreachable {
+            state.breaker.fail();
+        }
+        if !retried || attempt == ATTEMPTS || state.breaker.is_open() {
+            return response;
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+        // Reads have no body, so there's nothing lost in not keeping it
+        request = Request::from_parts(parts.clone(), Body::empty());
+    }
+}
+
+fn unavailable(left: Duration) -> Response {
+    ApiError::Unavailable {
+        message: Message::database_unavailable(),
+        // Whole seconds, rounded up, so a client waiting that long finds the circuit ready for a trial
+        retry_after: left.as_secs() + u64::from(left.subsec_nanos() > 0),
+    }
+    .into_response()
+}
+
+async fn probe(db: &SqlitePool) -> Result<(), String> {
+    match tokio::time::timeout(PROBE_TIMEOUT, sqlx::query("SELECT 1").execute(db)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err(format!("no answer within {PROBE_TIMEOUT:?}")),
+    }
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    // ok; degraded, still serving but with transient failures in the last FAILURE_WINDOW or the replica out of
+    // reach; or unavailable, with the database itself not answering
+    status: &'static str,
+    database: String, // ok, or why the probe failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replica: Option<String>, // the same for DATABASE_READ_URL, when there is one
+    circuit: &'static str, // open while requests are being turned away
+    recent_failures: u32,
+}
+
+// GET /readyz: 200 while requests can be served, 503 while they can't. A probe that goes through closes the circuit,
+// and one that doesn't counts as a failure
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let database = probe(&state.db).await;
+    match &database {
+        Ok(()) => state.breaker.close(),
+        Err(_) => state.breaker.fail(),
+    }
+    let replica = match &state.config.database_read_url {
+        Some(_) => Some(probe(&state.reads).await),
+        None => None,
+    };
+    let recent_failures = state.breaker.recent_failures();
+    let (code, status) = match (&database, &replica) {
+        (Err(_), _) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable"),
+        (Ok(()), Some(Err(_))) => (StatusCode::OK, "degraded"),
+        (Ok(()), _) if recent_failures > 0 => (StatusCode::OK, "degraded"),
+        (Ok(()), _) => (StatusCode::OK, "ok"),
+    };
+    let describe = |probed: Result<(), String>| probed.err().unwrap_or_else(|| "ok".to_string());
+    let readiness = Readiness {
+        status,
+        database: describe(database),
+        replica: replica.map(describe),
+        circuit: if state.breaker.is_open() { "open" } else { "closed" },
+        recent_failures,
+    };
+    (code, Json(readiness))
+}
+
+// A write transaction that takes the write lock as it begins, BEGIN IMMEDIATE, for one that reads what it's about to
+// write over, the receipt chain's head say. Two of sqlx's own transactions, which only ever begin deferred, can
+// each read, and then neither can write until the other gives up, which SQLite answers by failing one of them as
+// deadlocked; this one waits at the start instead. Used like a Transaction, through &mut *tx, and rolled back the
+// same way when it's dropped before commit: the connection is closed rather than going back to the pool mid-way
+// https://www.sqlite.org/lang_transaction.html#deferred_immediate_and_exclusive_transactions
+pub struct Immediate(Option<PoolConnection<Sqlite>>);
+
+impl Immediate {
+    pub async fn begin(db: &SqlitePool) -> Result<Immediate, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *conn).await?;
+        Ok(Immediate(Some(conn)))
+    }
+
+    pub async fn commit(mut self) -> Result<(), sqlx::Error> {
+        let conn = self.0.as_mut().expect("an immediate transaction's connection until it ends");
+        sqlx::query("COMMIT").execute(&mut **conn).await?;
+        // Only now is the connection fit to go back to the pool; a COMMIT that fails leaves it in the transaction
+        self.0.take();
+        Ok(())
+    }
+}
+
+impl Deref for Immediate {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        self.0.as_ref().expect("an immediate transaction's connection until it ends")
+    }
+}
+
+impl DerefMut for Immediate {
+    fn deref_mut(&mut self) -> &mut SqliteConnection {
+        self.0.as_mut().expect("an immediate transaction's connection until it ends")
+    }
+}
+
+impl Drop for Immediate {
+    fn drop(&mut self) {
+        // Closing it ends the transaction, which SQLite rolls back
+        if let Some(conn) = self.0.take() {
+            drop(conn.detach());
+        }
+    }
+}
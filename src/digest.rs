@@ -0,0 +1,307 @@
+// The weekly digest: on Fridays at DIGEST_TIME, everyone who wants it (by email unless they've said otherwise with
+// PUT /voters/:name/notifications) gets the week's winners, how many took part, and what's coming up in the week
+// ahead: holidays without a poll, and polls already planned. It's written from history, so GET /digest shows any
+// week's. The text is the catalogs' digest-text, in each recipient's language (see i18n.rs), unless DIGEST_TEMPLATE
+// names a file, whose {{name}}, {{week_of}}, {{winners}}, {{participation}}, {{upcoming}}, {{unsubscribe_url}},
+// {{erasure_url}} and {{erasure_token}} are filled in for each recipient, and whose first line is the subject when
+// it starts with "Subject:". The unsubscribe link carries the voter's key signed with the receipt key, like
+// invitation tokens, so it works from the email without logging in and can't be forged for someone else; the
+// erasure token is signed the same way, and is what lets them erase their data without the admin (see erasure.rs).
+// Times are the server's local time
+use axum::extract::{Query, State};
+use axum::Json;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeSet;
+
+use crate::auth::{self, Admin};
+use crate::error::{ApiError, Message};
+use crate::i18n::{self, Locale};
+use crate::notifications::{self, Event, Settings};
+use crate::receipts::hex;
+use crate::{erasure, names, participation, polls, AppState};
+
+#[derive(Serialize)]
+pub struct Digest {
+    week_of: String, // the Monday the week starts on
+    winners: Vec<Winner>,
+    participation: Participation,
+    upcoming: Vec<Upcoming>, // the seven days after the week's Friday
+}
+
+#[derive(Serialize)]
+struct Winner {
+    poll_id: String,
+    lunch_at: String,
+    office_id: Option<String>,
+    winner: Option<String>, // None when it closed without one
+    voted: i64,
+    abstained: i64,
+}
+
+#[derive(Serialize)]
+struct Participation {
+    polls: i64,
+    ballots: i64,
+    abstentions: i64,
+    people: i64, // everyone who voted or abstained at least once, each counted once
+}
+
+#[derive(Serialize)]
+struct Upcoming {
+    day: String,
+    what: String,
+}
+
+#[derive(Deserialize)]
+pub struct DigestQuery {
+    week_of: Option<String>, // any day of the week; this week without one
+}
+
+#[derive(Deserialize)]
+pub struct Unsubscribe {
+    voter: String,
+    token: String,
+}
+
+#[derive(Serialize)]
+pub struct Sent {
+    week_of: String,
+    recipients: u64,
+}
+
+// The Monday of the week `day` is in. 'weekday 0' moves forward to the Sunday that ends it (or stays put on one)
+// https://www.sqlite.org/lang_datefunc.html#modifiers
+async fn monday(state: &AppState, day: Option<&str>) -> Result<String, ApiError> {
+    let monday: Option<String> =
+        sqlx::query_scalar("SELECT date(COALESCE(?, date('now', 'localtime')), 'weekday 0', '-6 days')")
+            .bind(day)
+            .fetch_one(&state.db)
+            .await?;
+    monday.ok_or_else(|| ApiError::BadRequest("week_of must be a date like 2024-05-17".to_string()))
+}
+
+async fn digest(state: &AppState, week_of: &str) -> Result<Digest, ApiError> {
+    // A poll that went to a runoff is counted, but its runoff's winner is the one shown
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM polls WHERE status = 'closed'
+        AND substr(lunch_at, 1, 10) >= ?1 AND substr(lunch_at, 1, 10) < date(?1, '+7 days')
+        ORDER BY lunch_at, id",
+    )
+    .bind(week_of)
+    .fetch_all(&state.db)
+    .await?;
+    let mut winners = Vec::new();
+    let mut totals = Participation { polls: ids.len() as i64, ballots: 0, abstentions: 0, people: 0 };
+    let mut people = BTreeSet::new();
+    for id in ids {
+        let poll = polls::find_poll(&state.db, id).await?.ok_or(sqlx::Error::RowNotFound)?;
+        let counts = participation::participation(&state.db, id).await?;
+        totals.ballots += counts.voted;
+        totals.abstentions += counts.abstained;
+        people.extend(participation::participants(&state.db, id).await?.iter().map(|name| names::fold(name)));
+        if poll.runoff_poll_id.is_some() {
+            continue;
+        }
+        let winner = match polls::winner(state, &poll).await {
+            Ok(winner) => Some(winner),
+            Err(ApiError::Conflict(_)) => None,
+            Err(err) => return Err(err),
+        };
+        winners.push(Winner {
+            poll_id: poll.public_id,
+            lunch_at: poll.lunch_at,
+            office_id: poll.office_id,
+            winner,
+            voted: counts.voted,
+            abstained: counts.abstained,
+        });
+    }
+    totals.people = people.len() as i64;
+
+    let upcoming: Vec<(String, String)> = sqlx::query_as(
+        "SELECT day, name || ': no poll' FROM holidays
+            WHERE day >= date(?1, '+5 days') AND day < date(?1, '+12 days')
+        UNION ALL SELECT substr(lunch_at, 1, 10), 'poll ' || COALESCE(join_code, public_id) || ' for lunch at '
+            || substr(lunch_at, 12) || COALESCE(' in ' || office_id, '')
+            FROM polls WHERE status != 'closed'
+            AND substr(lunch_at, 1, 10) >= date(?1, '+5 days') AND substr(lunch_at, 1, 10) < date(?1, '+12 days')
+        ORDER BY 1, 2",
+    )
+    .bind(week_of)
+    .fetch_all(&state.db)
+    .await?;
+    let upcoming = upcoming.into_iter().map(|(day, what)| Upcoming { day, what }).collect();
+    Ok(Digest { week_of: week_of.to_string(), winners, participation: totals, upcoming })
+}
+
+// The prefix keeps this signature from passing for a receipt's or an invitation's, which share the key
+fn unsubscribe_token(state: &AppState, voter_key: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key);
+    hex(hmac::sign(&key, format!("digest:{voter_key}").as_bytes()).as_ref())
+}
+
+fn unsubscribe_url(state: &AppState, voter_name: &str) -> String {
+    let base = format!("{}/digest/unsubscribe", state.config.public_url.trim_end_matches('/'));
+    let token = unsubscribe_token(state, &names::fold(voter_name));
+    match reqwest::Url::parse_with_params(&base, &[("voter", voter_name), ("token", &token)]) {
+        Ok(url) => url.to_string(),
+        Err(_) => format!("{base}?token={token}"),
+    }
+}
+
+// Where DELETE /voters/:name/data is for them, to send their erasure token to (see erasure.rs)
+fn erasure_url(state: &AppState, voter_name: &str) -> String {
+    let base = state.config.public_url.trim_end_matches('/');
+    let Ok(mut url) = reqwest::Url::parse(base) else {
+        return format!("{base}/voters/{voter_name}/data");
+    };
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().extend(["voters", voter_name, "data"]);
+    }
+    url.to_string()
+}
+
+// The subject and body for one recipient
+fn render(state: &AppState, digest: &Digest, voter_name: &str, locale: Locale) -> (String, String) {
+    let winners = if digest.winners.is_empty() {
+        i18n::text(locale, "digest-no-polls", &[])
+    } else {
+        let lines = digest.winners.iter().map(|poll| {
+            let no_winner = i18n::text(locale, "digest-no-winner", &[]);
+            let (voted, abstained) = (poll.voted.to_string(), poll.abstained.to_string());
+            let mut args = vec![
+                ("lunch_at", poll.lunch_at.as_str()),
+                ("winner", poll.winner.as_deref().unwrap_or(&no_winner)),
+                ("voted", &voted),
+                ("abstained", &abstained),
+            ];
+            match &poll.office_id {
+                Some(office) => {
+                    args.push(("office", office));
+                    i18n::text(locale, "digest-office-winner", &args)
+                }
+                None => i18n::text(locale, "digest-winner", &args),
+            }
+        });
+        lines.collect::<Vec<_>>().join("\n")
+    };
+    let counts = &digest.participation;
+    let [polls, ballots, abstentions, people] =
+        [counts.polls, counts.ballots, counts.abstentions, counts.people].map(|count| count.to_string());
+    let participation = i18n::text(
+        locale,
+        "digest-participation",
+        &[("polls", &polls), ("ballots", &ballots), ("abstentions", &abstentions), ("people", &people)],
+    );
+    let upcoming = if digest.upcoming.is_empty() {
+        i18n::text(locale, "digest-nothing-upcoming", &[])
+    } else {
+        digest.upcoming.iter().map(|day| format!("- {}: {}", day.day, day.what)).collect::<Vec<_>>().join("\n")
+    };
+    let fields = [
+        ("name", voter_name.to_string()),
+        ("week_of", digest.week_of.clone()),
+        ("winners", winners),
+        ("participation", participation),
+        ("upcoming", upcoming),
+        ("unsubscribe_url", unsubscribe_url(state, voter_name)),
+        ("erasure_url", erasure_url(state, voter_name)),
+        ("erasure_token", erasure::erasure_token(state, &names::fold(voter_name))),
+    ];
+    let subject = i18n::text(locale, "digest-subject", &[("week_of", &digest.week_of)]);
+    let Some(template) = &state.config.digest_template else {
+        let args: Vec<(&str, &str)> = fields.iter().map(|(field, value)| (*field, value.as_str())).collect();
+        return (subject, format!("{}\n", i18n::text(locale, "digest-text", &args)));
+    };
+    let mut text = template.clone();
+    for (field, value) in fields {
+        text = text.replace(&format!("{{{{{field}}}}}"), &value);
+    }
+    match text.strip_prefix("Subject:").and_then(|rest| rest.split_once('\n')) {
+        Some((subject, body)) => (subject.trim().to_string(), body.trim_start_matches('\n').to_string()),
+        None => (subject, text),
+    }
+}
+
+// Sends the week's digest to everyone who wants it, on the channels they want it on; returns how many it reached
+async fn send(state: &AppState, week_of: &str) -> Result<u64, ApiError> {
+    let digest = digest(state, week_of).await?;
+    let recipients = notifications::subscribers(&state.db, Event::Digest).await?;
+    let sent = notifications::dispatch(state, Event::Digest, &recipients, None, &json!(digest), |voter_name, locale| {
+        render(state, &digest, voter_name, locale)
+    })
+    .await?;
+    sqlx::query("UPDATE digests SET recipients = ? WHERE week_of = ?")
+        .bind(sent as i64)
+        .bind(week_of)
+        .execute(&state.db)
+        .await?;
+    Ok(sent)
+}
+
+// Called by the scheduler every minute: sends this week's digest once it's Friday and DIGEST_TIME has passed.
+// The week is claimed before anything is sent, so it only goes out once even if sending takes longer than a minute
+pub async fn send_if_due(state: &AppState) -> Result<(), ApiError> {
+    let (now, weekday): (String, i64) = sqlx::query_as(
+        "SELECT strftime('%H:%M', 'now', 'localtime'), CAST(strftime('%w', 'now', 'localtime') AS INTEGER)",
+    )
+    .fetch_one(&state.db)
+    .await?;
+    if weekday != 5 || now < state.config.digest_time {
+        return Ok(());
+    }
+    let week_of = monday(state, None).await?;
+    let claimed = sqlx::query("INSERT OR IGNORE INTO digests (week_of) VALUES (?)")
+        .bind(&week_of)
+        .execute(&state.db)
+        .await?;
+    if claimed.rows_affected() == 0 {
+        return Ok(());
+    }
+    let sent = send(state, &week_of).await?;
+    println!("digest: sent the week of {week_of} to {sent} people");
+    Ok(())
+}
+
+// GET /digest?week_of=2024-05-17: what the week's digest says, for any week
+pub async fn get_digest(
+    State(state): State<AppState>,
+    Query(query): Query<DigestQuery>,
+) -> Result<Json<Digest>, ApiError> {
+    let week_of = monday(&state, query.week_of.as_deref()).await?;
+    Ok(Json(digest(&state, &week_of).await?))
+}
+
+// POST /digest/send (admin): sends this week's digest now, say before a long weekend. It counts as this week's, so
+// Friday's won't go out as well
+pub async fn send_now(_admin: Admin, State(state): State<AppState>) -> Result<Json<Sent>, ApiError> {
+    let week_of = monday(&state, None).await?;
+    sqlx::query("INSERT OR REPLACE INTO digests (week_of) VALUES (?)").bind(&week_of).execute(&state.db).await?;
+    let recipients = send(&state, &week_of).await?;
+    println!("digest: sent the week of {week_of} to {recipients} people");
+    Ok(Json(Sent { week_of, recipients }))
+}
+
+// GET /digest/unsubscribe?voter=Zoë&token=...: the link at the bottom of every digest. A GET, so it works straight
+// from an email. It turns the digest off on every channel; other notifications are left as they were
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<Unsubscribe>,
+) -> Result<Json<Settings>, ApiError> {
+    let key = names::fold(&query.voter);
+    let expected = unsubscribe_token(&state, &key);
+    if !auth::constant_time_eq(expected.as_bytes(), query.token.as_bytes()) {
+        return Err(ApiError::Forbidden("that unsubscribe link isn't valid".to_string()));
+    }
+    if notifications::settings(&state.db, &key).await?.is_none() {
+        return Err(Message::no_notification_settings(query.voter).into());
+    }
+    notifications::set_channels(&state.db, &key, Event::Digest, &[]).await?;
+    println!("digest: {} unsubscribed", query.voter);
+    notifications::settings(&state.db, &key)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::from(Message::no_notification_settings(query.voter)))
+}
@@ -0,0 +1,50 @@
+// Sending email, for the notifications voters ask for by email (see notifications.rs). It goes through SendGrid's
+// v3 mail send API, which other providers copy, at EMAIL_API_URL with EMAIL_API_KEY as a bearer token, from
+// EMAIL_FROM; without both of those nothing is emailed
+// https://www.twilio.com/docs/sendgrid/api-reference/mail-send/mail-send
+use serde_json::json;
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::i18n::{self, Locale};
+use crate::notifications::Channel;
+use crate::{suppressions, AppState};
+
+pub fn configured(config: &Config) -> bool {
+    config.email_api_key.is_some() && config.email_from.is_some()
+}
+
+// Plain text only. Every email ends with the link that stops them all, in `locale`; see suppressions.rs
+pub async fn send(
+    state: &AppState,
+    address: &str,
+    name: &str,
+    subject: &str,
+    text: &str,
+    locale: Locale,
+) -> Result<(), ApiError> {
+    let (Some(key), Some(from)) = (&state.config.email_api_key, &state.config.email_from) else {
+        return Err(ApiError::NotConfigured("EMAIL_API_KEY and EMAIL_FROM must be set to send email".to_string()));
+    };
+    let unsubscribe = suppressions::unsubscribe_url(state, Channel::Email, address);
+    let footer = i18n::text(locale, "email-unsubscribe", &[("url", &unsubscribe)]);
+    let text = format!("{text}\n--\n{footer}\n");
+    let response = state
+        .http
+        .post(format!("{}/v3/mail/send", state.config.email_api_url.trim_end_matches('/')))
+        .bearer_auth(key)
+        .json(&json!({
+            "personalizations": [{"to": [{"email": address, "name": name}]}],
+            "from": {"email": from},
+            "subject": subject,
+            "content": [{"type": "text/plain", "value": text}],
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("the email API returned {status}: {body}")));
+    }
+    Ok(())
+}
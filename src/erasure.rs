@@ -0,0 +1,564 @@
+// Erasing a voter's data on request (GDPR article 17). Their name is replaced everywhere by a pseudonym rather than
+// their rows deleted, so every poll still counts the same ballots and past winners stay the winners; what they wrote
+// in their own words (ballot and rating comments) goes, and so do their blacklist, preferences, budget, lunch
+// orders, badges, notification settings, voting links, time away and places on teams and in rotations, which only
+// ever served them, and so does their name's place in the moderation queue. It takes two calls: the first says what
+// would go and hands out a token, the second spends it. Either takes the admin token, or the voter's own erasure
+// token, which only their digest gives out (see digest.rs), signed with the receipt key like the digest's unsubscribe
+// link
+// https://gdpr-info.eu/art-17-gdpr/
+use axum::extract::{Path, Query, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqliteConnection;
+
+use crate::audit::{self, LoggedAs};
+use crate::auth;
+use crate::error::{ApiError, Message};
+use crate::{names, poll_templates, receipts, AppState};
+
+// How long a confirmation token can be spent for
+const CONFIRM_WITHIN: &str = "+15 minutes";
+
+// The tables keyed by the folded name: those whose rows are kept under the pseudonym, and those that only ever
+// served the voter and lose their rows. The erasure and its dry run both go by these
+const RENAMED: [&str; 6] = ["abstentions", "reactions", "vote_credits", "rsvps", "bill_entries", "bill_payments"];
+const DELETED: [&str; 11] = [
+    "voter_blacklist",
+    "voter_preferences",
+    "voter_budgets",
+    "poll_orders",
+    "voter_badges",
+    "rotations",
+    "voter_notifications",
+    "notification_preferences",
+    "vote_links",
+    "away_periods",
+    "team_members",
+];
+
+// What an erasure changed, or would change; each is a count of rows
+#[derive(Default, Serialize)]
+pub struct Erased {
+    votes: u64,
+    comments: u64, // on ballots and ratings, removed rather than renamed
+    ratings: u64,
+    nominations: u64,
+    suggestions: u64,  // restaurants they suggested
+    poll_lists: u64,   // polls with them among the attendees or the voters eligible for a runoff
+    templates: u64,    // poll templates with them among the attendees
+    // The RENAMED tables
+    abstentions: u64,
+    reactions: u64,
+    credits: u64, // quadratic credit ledger rows
+    rsvps: u64,
+    bill_entries: u64,
+    payments: u64,
+    // The DELETED ones
+    blacklist: u64,
+    preferences: u64,
+    budgets: u64,
+    orders: u64,
+    badges: u64,
+    rotations: u64,                // places in a rotation
+    notifications: u64,            // their address and whether they want reminders
+    notification_preferences: u64, // the events they chose, one row per event and channel
+    vote_links: u64,               // one-click voting links they were sent
+    away: u64,                     // their time out of the office
+    memberships: u64,              // places on teams
+    flags: u64,                    // their name waiting for a moderator, or reviewed by one; see moderation.rs
+    receipts: u64,
+    audit_entries: u64,
+}
+
+impl Erased {
+    fn is_empty(&self) -> bool {
+        [
+            self.votes,
+            self.comments,
+            self.ratings,
+            self.nominations,
+            self.suggestions,
+            self.poll_lists,
+            self.templates,
+            self.abstentions,
+            self.reactions,
+            self.credits,
+            self.rsvps,
+            self.bill_entries,
+            self.payments,
+            self.blacklist,
+            self.preferences,
+            self.budgets,
+            self.orders,
+            self.badges,
+            self.rotations,
+            self.notifications,
+            self.notification_preferences,
+            self.vote_links,
+            self.away,
+            self.memberships,
+            self.flags,
+            self.receipts,
+            self.audit_entries,
+        ]
+        .iter()
+        .all(|&count| count == 0)
+    }
+
+    // Where a RENAMED or DELETED table's rows are counted
+    fn count_for(&mut self, table: &str) -> &mut u64 {
+        match table {
+            "abstentions" => &mut self.abstentions,
+            "reactions" => &mut self.reactions,
+            "vote_credits" => &mut self.credits,
+            "rsvps" => &mut self.rsvps,
+            "bill_entries" => &mut self.bill_entries,
+            "bill_payments" => &mut self.payments,
+            "voter_blacklist" => &mut self.blacklist,
+            "voter_preferences" => &mut self.preferences,
+            "voter_budgets" => &mut self.budgets,
+            "poll_orders" => &mut self.orders,
+            "voter_badges" => &mut self.badges,
+            "rotations" => &mut self.rotations,
+            "voter_notifications" => &mut self.notifications,
+            "notification_preferences" => &mut self.notification_preferences,
+            "vote_links" => &mut self.vote_links,
+            "away_periods" => &mut self.away,
+            "team_members" => &mut self.memberships,
+            _ => unreachable!("{table} has no count in Erased"),
+        }
+    }
+}
+
+// The lists of names a poll keeps
+#[derive(sqlx::FromRow)]
+struct PollLists {
+    id: i64,
+    attendees: JsonColumn<Vec<String>>,
+    eligible_voters: Option<JsonColumn<Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+pub struct ErasureQuery {
+    confirm: Option<String>,
+}
+
+// The voter's proof that it's them asking. The prefix keeps it from passing for a receipt's, an invitation's or the
+// digest's unsubscribe link's signature, which share the key
+pub fn erasure_token(state: &AppState, voter_key: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key);
+    receipts::hex(hmac::sign(&key, format!("erasure:{voter_key}").as_bytes()).as_ref())
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut random = vec![0u8; bytes];
+    SystemRandom::new().fill(&mut random).expect("the system random number generator failed");
+    receipts::hex(&random)
+}
+
+// DELETE /voters/:name/data: without ?confirm, a dry run listing what would be erased, with a token to confirm it
+// by (202 Accepted); with ?confirm=<token>, the erasure. Both need Authorization: Bearer with the admin token or the
+// voter's erasure token; the confirmation guards against slips. The dry run only counts, so it doesn't hold up
+// anybody's votes while it looks
+pub async fn erase_voter_data(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<ErasureQuery>,
+) -> Result<Response, ApiError> {
+    let key = names::fold(&name);
+    if key.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    let bearer = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok());
+    let bearer = bearer.and_then(|value| value.strip_prefix("Bearer "));
+    let expected = erasure_token(&state, &key);
+    let theirs = bearer.is_some_and(|token| auth::constant_time_eq(expected.as_bytes(), token.as_bytes()));
+    if !theirs && !auth::is_admin(&headers, &state) {
+        return Err(ApiError::Unauthorized(format!(
+            "erasing what's stored about {name} takes the admin token, or their own erasure token from their digest"
+        )));
+    }
+
+    let Some(confirm) = &query.confirm else {
+        let mut conn = state.db.acquire().await?;
+        let erased = count(&mut conn, &key).await?;
+        drop(conn);
+        if erased.is_empty() {
+            return Err(ApiError::NotFound(format!("there's nothing stored about {name}")));
+        }
+        let (token, expires_at): (String, String) = sqlx::query_as(
+            "INSERT INTO erasure_requests (voter_key, token, expires_at) VALUES (?, ?, datetime('now', ?))
+            ON CONFLICT (voter_key) DO UPDATE SET token = excluded.token, expires_at = excluded.expires_at
+            RETURNING token, expires_at",
+        )
+        .bind(&key)
+        .bind(random_hex(16))
+        .bind(CONFIRM_WITHIN)
+        .fetch_one(&state.db)
+        .await?;
+        let body = json!({ "confirm": token, "expires_at": expires_at, "would_erase": erased });
+        return Ok((StatusCode::ACCEPTED, Json(body)).into_response());
+    };
+
+    let mut tx = state.db.begin().await?;
+    let confirmed =
+        sqlx::query("DELETE FROM erasure_requests WHERE voter_key = ? AND token = ? AND expires_at > datetime('now')")
+            .bind(&key)
+            .bind(confirm)
+            .execute(&mut *tx)
+            .await?;
+    if confirmed.rows_affected() == 0 {
+        return Err(ApiError::Forbidden(
+            "that confirmation has expired or isn't for this voter; ask again without ?confirm".to_string(),
+        ));
+    }
+    let pseudonym = format!("former-voter-{}", random_hex(4));
+    let erased = erase(&mut tx, &key, &pseudonym).await?;
+    if erased.is_empty() {
+        return Err(ApiError::NotFound(format!("there's nothing stored about {name}")));
+    }
+    tx.commit().await?;
+    println!("erased the data of a voter, now {pseudonym}");
+
+    // The audit entry for this request is written under the pseudonym too, or it would undo the erasure
+    let logged_as = LoggedAs { actor: pseudonym.clone(), path: format!("/voters/{pseudonym}/data") };
+    let body = json!({ "pseudonym": pseudonym, "erased": erased });
+    Ok((Extension(logged_as), Json(body)).into_response())
+}
+
+// Replaces the voter (every spelling of their name that folds to `key`) with `pseudonym`, in one transaction
+async fn erase(conn: &mut SqliteConnection, key: &str, pseudonym: &str) -> Result<Erased, ApiError> {
+    let spellings = names::spellings(conn, key).await?;
+    let spellings_json = JsonColumn(&spellings);
+
+    refuse_sealed(conn, &spellings).await?;
+
+    let mut erased = Erased::default();
+    let rows = |result: sqlx::sqlite::SqliteQueryResult| result.rows_affected();
+
+    // votes is a projection of vote_events, so the events are rewritten to match and a replay keeps the pseudonym.
+    // This is the one edit the stream ever takes
+    erased.comments += rows(
+        sqlx::query(
+            "UPDATE votes SET comment = NULL
+            WHERE comment IS NOT NULL AND voter_name IN (SELECT value FROM json_each(?))",
+        )
+        .bind(spellings_json)
+        .execute(&mut *conn)
+        .await?,
+    );
+    erased.votes = rows(
+        sqlx::query(
+            "UPDATE votes SET voter_name = ?
+            WHERE voter_name IN (SELECT value FROM json_each(?))",
+        )
+        .bind(pseudonym)
+        .bind(spellings_json)
+        .execute(&mut *conn)
+        .await?,
+    );
+    sqlx::query(
+        "UPDATE vote_events SET payload = json_set(payload, '$.voter_name', ?, '$.comment', NULL)
+        WHERE type = 'cast' AND json_extract(payload, '$.voter_name') IN (SELECT value FROM json_each(?))",
+    )
+    .bind(pseudonym)
+    .bind(spellings_json)
+    .execute(&mut *conn)
+    .await?;
+    // An admin's correction that moved a vote to them may say why, in words about them; the reason goes too
+    sqlx::query(
+        "UPDATE vote_events SET payload = json_remove(json_set(payload, '$.voter_name', ?), '$.reason')
+        WHERE type = 'changed' AND json_extract(payload, '$.voter_name') IN (SELECT value FROM json_each(?))",
+    )
+    .bind(pseudonym)
+    .bind(spellings_json)
+    .execute(&mut *conn)
+    .await?;
+
+    erased.comments += rows(
+        sqlx::query(
+            "UPDATE ratings SET comment = NULL
+            WHERE comment IS NOT NULL AND rater_name IN (SELECT value FROM json_each(?))",
+        )
+        .bind(spellings_json)
+        .execute(&mut *conn)
+        .await?,
+    );
+    erased.ratings = rows(
+        sqlx::query("UPDATE ratings SET rater_name = ? WHERE rater_name IN (SELECT value FROM json_each(?))")
+            .bind(pseudonym)
+            .bind(spellings_json)
+            .execute(&mut *conn)
+            .await?,
+    );
+    erased.nominations = rows(
+        sqlx::query("UPDATE nominations SET nominated_by = ? WHERE nominated_by IN (SELECT value FROM json_each(?))")
+            .bind(pseudonym)
+            .bind(spellings_json)
+            .execute(&mut *conn)
+            .await?,
+    );
+    erased.suggestions = rows(
+        sqlx::query("UPDATE restaurants SET suggested_by = ? WHERE suggested_by IN (SELECT value FROM json_each(?))")
+            .bind(pseudonym)
+            .bind(spellings_json)
+            .execute(&mut *conn)
+            .await?,
+    );
+    sqlx::query("UPDATE polls SET picker = ? WHERE picker IN (SELECT value FROM json_each(?))")
+        .bind(pseudonym)
+        .bind(spellings_json)
+        .execute(&mut *conn)
+        .await?;
+
+    // The tables keyed by the folded name take the pseudonym's key along with it
+    let pseudonym_key = names::fold(pseudonym);
+    for table in RENAMED {
+        // The table names are our own, never the client's, so formatting them into the SQL is safe
+        let renamed = rows(
+            sqlx::query(&format!("UPDATE {table} SET voter_name = ?, voter_key = ? WHERE voter_key = ?"))
+                .bind(pseudonym)
+                .bind(&pseudonym_key)
+                .bind(key)
+                .execute(&mut *conn)
+                .await?,
+        );
+        *erased.count_for(table) = renamed;
+    }
+    sqlx::query("UPDATE poll_invitations SET guest_name = ?, guest_key = ? WHERE guest_key = ?")
+        .bind(pseudonym)
+        .bind(&pseudonym_key)
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    for table in DELETED {
+        let sql = format!("DELETE FROM {table} WHERE voter_key = ?");
+        let deleted = rows(sqlx::query(&sql).bind(key).execute(&mut *conn).await?);
+        *erased.count_for(table) = deleted;
+    }
+    // A review is of the name itself, which is going. Were it to come back, it would be queued again
+    erased.flags = rows(
+        sqlx::query("DELETE FROM flagged_names WHERE kind = 'voter' AND name_key = ?")
+            .bind(key)
+            .execute(&mut *conn)
+            .await?,
+    );
+
+    let polls: Vec<PollLists> =
+        sqlx::query_as("SELECT id, attendees, eligible_voters FROM polls").fetch_all(&mut *conn).await?;
+    let rename = |list: &mut Vec<String>| {
+        let mut renamed = false;
+        for name in list.iter_mut().filter(|name| names::fold(name) == key) {
+            *name = pseudonym.to_string();
+            renamed = true;
+        }
+        renamed
+    };
+    for PollLists { id, attendees: JsonColumn(mut attendees), mut eligible_voters } in polls {
+        let attended = rename(&mut attendees);
+        let eligible = eligible_voters.as_mut().is_some_and(|JsonColumn(list)| rename(list));
+        if attended || eligible {
+            sqlx::query("UPDATE polls SET attendees = ?, eligible_voters = ? WHERE id = ?")
+                .bind(JsonColumn(&attendees))
+                .bind(eligible_voters)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+            erased.poll_lists += 1;
+        }
+    }
+    erased.templates = poll_templates::rename_attendees(conn, rename).await?;
+
+    erased.receipts = receipts::rename_voters(conn, |name, _| {
+        spellings.iter().any(|spelling| spelling == name).then(|| pseudonym.to_string())
+    })
+    .await?;
+
+    // The audit log keeps what was done, by whom it now only knows as the pseudonym
+    for entry in audit_entries(conn, key, pseudonym).await? {
+        sqlx::query("UPDATE audit_log SET actor = ?, actor_key = ?, path = ?, body = ? WHERE id = ?")
+            .bind(&entry.actor)
+            .bind(&entry.actor_key)
+            .bind(&entry.path)
+            .bind(&entry.body)
+            .bind(entry.id)
+            .execute(&mut *conn)
+            .await?;
+        erased.audit_entries += 1;
+    }
+    Ok(erased)
+}
+
+// A sealed ballot's name is bound into its encryption, so it can't be renamed until the poll opens it
+async fn refuse_sealed(conn: &mut SqliteConnection, spellings: &[String]) -> Result<(), ApiError> {
+    let sealed: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM sealed_ballots WHERE voter_name IN (SELECT value FROM json_each(?))")
+            .bind(JsonColumn(spellings))
+            .fetch_one(&mut *conn)
+            .await?;
+    if sealed > 0 {
+        return Err(ApiError::Conflict(
+            "there are sealed ballots under this name in polls still open; try again once they close".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// An audit entry as it reads once the voter is the pseudonym
+struct AuditEntry {
+    id: i64,
+    actor: String,
+    actor_key: String,
+    path: String,
+    body: Option<String>,
+}
+
+// The entries that change: those they made, and those about them (admin requests to /voters/:name/..., or naming
+// them in the body)
+async fn audit_entries(
+    conn: &mut SqliteConnection,
+    key: &str,
+    pseudonym: &str,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    let entries: Vec<(i64, String, String, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, actor, actor_key, path, body FROM audit_log
+        WHERE actor_key = ? OR instr(path, '/voters/') = 1 OR body IS NOT NULL",
+    )
+    .bind(key)
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut changed = Vec::new();
+    for (id, mut actor, mut actor_key, mut path, mut body) in entries {
+        let by_them = actor_key == key;
+        let about_them = audit::path_voter(&path).is_some_and(|name| names::fold(&name) == key);
+        let renamed_body = body.as_deref().and_then(|body| audit::rename_in_body(body, key, pseudonym));
+        if !by_them && !about_them && renamed_body.is_none() {
+            continue;
+        }
+        if by_them {
+            actor = pseudonym.to_string();
+            actor_key = names::fold(pseudonym);
+        }
+        if about_them {
+            let after = &path["/voters/".len()..];
+            let rest = &after[after.find(['/', '?']).unwrap_or(after.len())..];
+            path = format!("/voters/{pseudonym}{rest}");
+        }
+        if renamed_body.is_some() {
+            body = renamed_body;
+        }
+        changed.push(AuditEntry { id, actor, actor_key, path, body });
+    }
+    Ok(changed)
+}
+
+// The dry run: what erase would change, counted without writing anything
+async fn count(conn: &mut SqliteConnection, key: &str) -> Result<Erased, ApiError> {
+    let spellings = names::spellings(conn, key).await?;
+    refuse_sealed(conn, &spellings).await?;
+    let spellings_json = JsonColumn(&spellings);
+    let mut erased = Erased::default();
+    // Each of these counts the rows WHERE the condition holds, with the spellings bound to every ?
+    for (count, from, condition, bindings) in [
+        (&mut erased.votes, "votes", "voter_name IN (SELECT value FROM json_each(?))", 1),
+        (&mut erased.ratings, "ratings", "rater_name IN (SELECT value FROM json_each(?))", 1),
+        (&mut erased.nominations, "nominations", "nominated_by IN (SELECT value FROM json_each(?))", 1),
+        (&mut erased.suggestions, "restaurants", "suggested_by IN (SELECT value FROM json_each(?))", 1),
+        (
+            &mut erased.poll_lists,
+            "polls",
+            "EXISTS (SELECT 1 FROM json_each(polls.attendees) WHERE value IN (SELECT value FROM json_each(?)))
+            OR EXISTS (SELECT 1 FROM json_each(polls.eligible_voters) WHERE value IN (SELECT value FROM json_each(?)))",
+            2,
+        ),
+        (
+            &mut erased.templates,
+            "poll_templates",
+            "EXISTS (SELECT 1 FROM json_each(poll_templates.settings, '$.attendees')
+                WHERE value IN (SELECT value FROM json_each(?)))",
+            1,
+        ),
+        (&mut erased.receipts, "receipts", "json_extract(body, '$.voter_name') IN (SELECT value FROM json_each(?))", 1),
+    ] {
+        let sql = format!("SELECT COUNT(*) FROM {from} WHERE {condition}");
+        let mut query = sqlx::query_scalar::<_, i64>(&sql);
+        for _ in 0..bindings {
+            query = query.bind(spellings_json);
+        }
+        *count = query.fetch_one(&mut *conn).await? as u64;
+    }
+    erased.comments = sqlx::query_scalar::<_, i64>(
+        "SELECT (SELECT COUNT(*) FROM votes
+                WHERE comment IS NOT NULL AND voter_name IN (SELECT value FROM json_each(?)))
+            + (SELECT COUNT(*) FROM ratings
+                WHERE comment IS NOT NULL AND rater_name IN (SELECT value FROM json_each(?)))",
+    )
+    .bind(spellings_json)
+    .bind(spellings_json)
+    .fetch_one(&mut *conn)
+    .await? as u64;
+    for table in RENAMED.into_iter().chain(DELETED) {
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table} WHERE voter_key = ?"))
+            .bind(key)
+            .fetch_one(&mut *conn)
+            .await?;
+        *erased.count_for(table) = rows as u64;
+    }
+    let flags = "SELECT COUNT(*) FROM flagged_names WHERE kind = 'voter' AND name_key = ?";
+    erased.flags = sqlx::query_scalar::<_, i64>(flags)
+        .bind(key)
+        .fetch_one(&mut *conn)
+        .await? as u64;
+    // The pseudonym only shapes what the entries would say, not which they are
+    erased.audit_entries = audit_entries(conn, key, "former-voter").await?.len() as u64;
+    Ok(erased)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sqlx::Connection;
+
+    #[test]
+    fn every_table_is_counted_and_any_count_makes_it_not_empty() {
+        for table in RENAMED.into_iter().chain(DELETED) {
+            let mut erased = Erased::default();
+            *erased.count_for(table) = 1;
+            assert!(!erased.is_empty(), "{table}");
+        }
+        assert!(Erased::default().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_voter_with_only_notification_settings_has_something_to_erase() {
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!().run(&mut conn).await.unwrap();
+        sqlx::query("INSERT INTO voter_notifications (voter_key, voter_name, email) VALUES ('ana', 'Ana', 'a@b.c')")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO notification_preferences (voter_key, event, channel) VALUES ('ana', ?, 'email')")
+            .bind("winner")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let Ok(counted) = count(&mut conn, "ana").await else { panic!("the dry run failed") };
+        assert!(!counted.is_empty());
+        assert_eq!((counted.notifications, counted.notification_preferences), (1, 1));
+        let Ok(erased) = erase(&mut conn, "ana", "former-voter-0000").await else { panic!("the erasure failed") };
+        assert_eq!((erased.notifications, erased.notification_preferences), (1, 1));
+        let Ok(counted) = count(&mut conn, "ana").await else { panic!("the second dry run failed") };
+        assert!(counted.is_empty());
+    }
+}
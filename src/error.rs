@@ -0,0 +1,252 @@
+// A single error type for the HTTP handlers, so every endpoint can use the ? operator and still
+// hand axum something it knows how to turn into a response
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Map};
+
+use crate::fuzzy::NameMatch;
+use crate::i18n::{self, Locale};
+use crate::{database, problems};
+
+#[derive(Debug)]
+pub enum ApiError {
+    DbError(sqlx::Error),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    // A third-party API we depend on failed or returned something we couldn't use
+    Upstream(String),
+    // The feature needs configuration that hasn't been provided
+    NotConfigured(String),
+    // The name given is close to one or more existing restaurants; the client should pick one or confirm it's new
+    Ambiguous { message: Message, matches: Vec<NameMatch> },
+    // The client is over the rate limit until `retry_after` seconds from now; see rate_limit.rs
+    TooManyRequests { message: Message, retry_after: u64 },
+    // The database is out of reach for now, and worth trying again in `retry_after` seconds; see database.rs
+    Unavailable { message: Message, retry_after: u64 },
+    // One of the catalogs' messages, with the status and code it comes with; see Message below. The variants above
+    // that take words of their own are for errors the catalogs don't have, which stay in English
+    Catalogued(Message),
+}
+
+// An error message from the catalogs (see i18n.rs), picked where the error happens: its id, what goes into it, and
+// the status and problem code it's answered with, so nothing has to work out afterwards which error it was. It's
+// only written out, in the language asked for, once it's a response
+#[derive(Debug, Clone)]
+pub struct Message {
+    id: &'static str,
+    status: StatusCode,
+    code: Option<&'static str>, // a problem code of its own, rather than its status's; see problems.rs
+    args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn render(&self, locale: Locale) -> String {
+        let args: Vec<(&str, &str)> = self.args.iter().map(|(name, value)| (*name, value.as_str())).collect();
+        i18n::text(locale, self.id, &args)
+    }
+
+    // For the odd place an error is raised with another status than it usually is, a 400 for a team named in a
+    // body that isn't there, say, rather than a 404 for one in the path
+    pub fn with_status(self, status: StatusCode) -> Message {
+        Message { status, ..self }
+    }
+
+    fn code(&self) -> &'static str {
+        self.code.unwrap_or_else(|| problems::for_status(self.status))
+    }
+}
+
+// Each message is a function, Message::no_poll(id) say, taking what goes into it: its name, its variables, its id in
+// the catalogs, the status it's answered with, and its problem code if it has one of its own. Every catalog is
+// checked to have each of them, with the same variables, and every code to be in problems::CATALOG, at compile time
+macro_rules! messages {
+    ($($name:ident($($arg:ident),*) = $id:literal, $status:ident $(, $code:literal)?;)*) => {
+        impl Message {
+            $(
+                pub fn $name($($arg: impl std::fmt::Display),*) -> Message {
+                    Message {
+                        id: $id,
+                        status: StatusCode::$status,
+                        code: None $(.or(Some($code)))?,
+                        args: vec![$((stringify!($arg), $arg.to_string())),*],
+                    }
+                }
+            )*
+        }
+
+        // One constant a message, since the compiler only gives each so many steps to work it out in
+        $(
+            const _: () = {
+                let mut locale = 0;
+                while locale < Locale::ALL.len() {
+                    let catalog = Locale::ALL[locale].catalog();
+                    let defined = i18n::defines(catalog, $id, &[$(stringify!($arg)),*]);
+                    assert!(defined, concat!("every catalog needs ", $id, ", with these variables"));
+                    locale += 1;
+                }
+                $(assert!(problems::is_code($code), concat!($code, " isn't in problems::CATALOG"));)?
+            };
+        )*
+    };
+}
+
+messages! {
+    database_unavailable() = "error-database-unavailable", SERVICE_UNAVAILABLE;
+    database() = "error-database", INTERNAL_SERVER_ERROR;
+    body_too_large() = "error-body-too-large", BAD_REQUEST, "body_too_large";
+    admin_token_required() = "error-admin-token-required", UNAUTHORIZED;
+    admin_token_invalid() = "error-admin-token-invalid", UNAUTHORIZED;
+    api_key_unknown() = "error-api-key-unknown", UNAUTHORIZED;
+    no_poll(id) = "error-no-poll", NOT_FOUND;
+    no_restaurant(id) = "error-no-restaurant", NOT_FOUND, "unknown_restaurant";
+    no_team(id) = "error-no-team", NOT_FOUND;
+    no_vote(id) = "error-no-vote", NOT_FOUND;
+    no_poll_template(id) = "error-no-poll-template", NOT_FOUND;
+    voter_name_empty() = "error-voter-name-empty", BAD_REQUEST;
+    voter_name_missing() = "error-voter-name-missing", BAD_REQUEST;
+    poll_closed() = "error-poll-closed", CONFLICT, "poll_closed";
+    poll_id_closed(poll) = "error-poll-id-closed", CONFLICT, "poll_closed";
+    poll_already_closed() = "error-poll-already-closed", CONFLICT, "poll_closed";
+    poll_nominating(poll) = "error-poll-nominating", CONFLICT;
+    only_closed_reopened() = "error-only-closed-reopened", CONFLICT;
+    poll_changed(expected, current) = "error-poll-changed", CONFLICT, "version_conflict";
+    if_match_required() = "error-if-match-required", PRECONDITION_REQUIRED;
+    unknown_cursor(cursor, log) = "error-unknown-cursor", BAD_REQUEST;
+    already_voted(voter) = "error-already-voted", CONFLICT, "duplicate_vote";
+    already_voted_today(voter) = "error-already-voted-today", CONFLICT, "duplicate_vote";
+    link_used() = "error-link-used", CONFLICT, "duplicate_vote";
+    not_voted(voter) = "error-not-voted", NOT_FOUND;
+    did_you_mean(name) = "error-did-you-mean", CONFLICT, "unknown_restaurant";
+    looks_like(name) = "error-looks-like", CONFLICT, "similar_restaurant";
+    not_registered(name) = "error-not-registered", BAD_REQUEST, "unknown_restaurant";
+    not_approved(name) = "error-not-approved", BAD_REQUEST;
+    inactive(name) = "error-inactive", BAD_REQUEST;
+    not_candidate(name, poll) = "error-not-candidate", BAD_REQUEST;
+    backup_without_poll() = "error-backup-without-poll", BAD_REQUEST;
+    backup_same() = "error-backup-same", BAD_REQUEST;
+    ranked_twice(name) = "error-ranked-twice", BAD_REQUEST;
+    over_budget(cost, remaining) = "error-over-budget", BAD_REQUEST;
+    not_on_poll_team(voter, team) = "error-not-on-poll-team", FORBIDDEN;
+    not_on_team(voter, team) = "error-not-on-team", NOT_FOUND;
+    not_in_runoff(voter, poll) = "error-not-in-runoff", FORBIDDEN;
+    not_in_this_runoff(voter) = "error-not-in-this-runoff", FORBIDDEN;
+    no_notification_settings(name) = "error-no-notification-settings", NOT_FOUND;
+    not_time_zone(name) = "error-not-time-zone", BAD_REQUEST;
+    not_locale(tag) = "error-not-locale", BAD_REQUEST;
+    ranking_required(poll) = "error-ranking-required", BAD_REQUEST, "wrong_ballot";
+    ranking_not_allowed() = "error-ranking-not-allowed", BAD_REQUEST, "wrong_ballot";
+    ranking_and_single_choice() = "error-ranking-and-single-choice", BAD_REQUEST, "wrong_ballot";
+    allocation_required(poll) = "error-allocation-required", BAD_REQUEST, "wrong_ballot";
+    allocation_not_allowed() = "error-allocation-not-allowed", BAD_REQUEST, "wrong_ballot";
+    allocation_and_single_choice() = "error-allocation-and-single-choice", BAD_REQUEST, "wrong_ballot";
+    allocation_too_few(name) = "error-allocation-too-few", BAD_REQUEST, "invalid_allocation";
+    allocated_twice(name) = "error-allocated-twice", BAD_REQUEST, "invalid_allocation";
+    seal_broken(poll) = "error-seal-broken", CONFLICT, "seal_broken";
+    not_the_picker(picker, poll) = "error-not-the-picker", FORBIDDEN, "not_the_picker";
+    no_picker(poll) = "error-no-picker", FORBIDDEN, "not_the_picker";
+    title_too_long(max) = "error-title-too-long", BAD_REQUEST, "invalid_poll_details";
+    description_too_long(max) = "error-description-too-long", BAD_REQUEST, "invalid_poll_details";
+    metadata_not_object() = "error-metadata-not-object", BAD_REQUEST, "invalid_poll_details";
+    metadata_too_large(max) = "error-metadata-too-large", BAD_REQUEST, "invalid_poll_details";
+    only_for_restaurants(settings) = "error-only-for-restaurants", BAD_REQUEST, "invalid_poll_settings";
+    too_few_options() = "error-too-few-options", BAD_REQUEST, "invalid_poll_settings";
+    not_local_time(field) = "error-not-local-time", BAD_REQUEST, "invalid_schedule";
+    both_given(first, second) = "error-both-given", BAD_REQUEST, "invalid_schedule";
+    offset_too_small() = "error-offset-too-small", BAD_REQUEST, "invalid_schedule";
+    nominations_after_close() = "error-nominations-after-close", BAD_REQUEST, "invalid_schedule";
+    negative_distance() = "error-negative-distance", BAD_REQUEST, "invalid_poll_settings";
+    credit_budget_too_small() = "error-credit-budget-too-small", BAD_REQUEST, "invalid_poll_settings";
+    quadratic_sealed() = "error-quadratic-sealed", BAD_REQUEST, "invalid_poll_settings";
+    runoff_not_allowed(method) = "error-runoff-not-allowed", BAD_REQUEST, "invalid_poll_settings";
+    majority_out_of_range() = "error-majority-out-of-range", BAD_REQUEST, "invalid_poll_settings";
+    unknown_candidates() = "error-unknown-candidates", BAD_REQUEST, "unknown_restaurant";
+    not_nominating() = "error-not-nominating", CONFLICT, "not_nominating";
+    nominator_empty() = "error-nominator-empty", BAD_REQUEST;
+    not_nominable(name) = "error-not-nominable", BAD_REQUEST, "not_nominable";
+    already_nominated(name) = "error-already-nominated", CONFLICT, "already_nominated";
+    rate_limited(seconds) = "error-rate-limited", TOO_MANY_REQUESTS, "quota_exceeded";
+}
+
+impl From<Message> for ApiError {
+    fn from(message: Message) -> Self {
+        ApiError::Catalogued(message)
+    }
+}
+
+// Implementing From lets ? convert a sqlx::Error into an ApiError automatically
+// https://doc.rust-lang.org/rust-by-example/conversion/from_into.html
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        ApiError::DbError(err)
+    }
+}
+
+// IntoResponse is the axum trait for anything a handler can return
+// https://docs.rs/axum/latest/axum/response/trait.IntoResponse.html
+// Failed calls to external APIs surface as 502 Bad Gateway
+impl From<reqwest::Error> for ApiError {
+    fn from(err: reqwest::Error) -> Self {
+        ApiError::Upstream(err.to_string())
+    }
+}
+
+// A message from the catalogs is written in the language the request asked for; see i18n.rs. The body is a problem,
+// RFC 7807's, with the message as its detail and a code for it; see problems.rs
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let locale = i18n::accepted().unwrap_or_default();
+        if let ApiError::DbError(err) = &self {
+            if let Some(failure) = database::classify(err) {
+                eprintln!("database error: {err}");
+                let message = Message::database_unavailable();
+                let mut response = ApiError::Unavailable { message, retry_after: 1 }.into_response();
+                // So the guard in database.rs can try the request again, and count it against the circuit
+                response.extensions_mut().insert(failure);
+                return response;
+            }
+        }
+        let mut extensions = Map::new();
+        let mut retry_after = None;
+        // Words of their own are in English, with their kind's code; a catalog's message comes with its own
+        let (status, code, detail) = match self {
+            ApiError::DbError(err) => {
+                // The database error goes to the log; the client only needs to know it wasn't their fault
+                eprintln!("database error: {err}");
+                let message = Message::database();
+                (message.status, message.code(), message.render(locale))
+            }
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message),
+            ApiError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, "unauthorized", message),
+            ApiError::Forbidden(message) => (StatusCode::FORBIDDEN, "forbidden", message),
+            ApiError::NotFound(message) => (StatusCode::NOT_FOUND, "not_found", message),
+            ApiError::Conflict(message) => (StatusCode::CONFLICT, "conflict", message),
+            ApiError::Upstream(message) => {
+                eprintln!("upstream error: {message}");
+                (StatusCode::BAD_GATEWAY, "upstream_failed", message)
+            }
+            ApiError::NotConfigured(message) => (StatusCode::SERVICE_UNAVAILABLE, "not_configured", message),
+            ApiError::Ambiguous { message, matches } => {
+                extensions.insert("matches".to_string(), json!(matches));
+                (StatusCode::CONFLICT, message.code(), message.render(locale))
+            }
+            ApiError::TooManyRequests { message, retry_after: seconds } => {
+                retry_after = Some(seconds);
+                (StatusCode::TOO_MANY_REQUESTS, "quota_exceeded", message.render(locale))
+            }
+            ApiError::Unavailable { message, retry_after: seconds } => {
+                retry_after = Some(seconds);
+                (StatusCode::SERVICE_UNAVAILABLE, "unavailable", message.render(locale))
+            }
+            ApiError::Catalogued(message) => (message.status, message.code(), message.render(locale)),
+        };
+        let mut response = problems::respond(status, code, Some(detail), extensions);
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
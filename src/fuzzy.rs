@@ -0,0 +1,141 @@
+// Near-duplicate detection for restaurant names, so "Luigi's", "luigis" and "Luigis Pizza" don't end up
+// as three different restaurants splitting one restaurant's votes
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+
+use crate::names;
+
+// A registered restaurant that looks like the name we were given, with how alike they are from 0.0 to 1.0
+#[derive(Debug, Serialize)]
+pub struct NameMatch {
+    #[serde(skip)]
+    pub id: i64,
+    #[serde(rename = "id")]
+    pub public_id: String,
+    pub name: String,
+    pub score: f64,
+}
+
+// The form names are compared in: folded (no case, no accents, see names::fold), apostrophes and other
+// punctuation dropped, separators turned into spaces. Two names with the same key are the same restaurant
+pub fn match_key(name: &str) -> String {
+    let cleaned: String = names::fold(name)
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c.is_whitespace() => Some(c),
+            '-' | '&' | '/' | '_' | '.' | ',' => Some(' '),
+            _ => None,
+        })
+        .collect();
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Trigrams the way PostgreSQL's pg_trgm builds them: each word padded with two spaces in front and one behind,
+// so word beginnings weigh more than endings
+// https://www.postgresql.org/docs/current/pgtrgm.html
+fn trigrams(key: &str) -> HashSet<[char; 3]> {
+    let mut set = HashSet::new();
+    for word in key.split_whitespace() {
+        let padded: Vec<char> = format!("  {word} ").chars().collect();
+        // windows(3) walks every run of three consecutive characters
+        for window in padded.windows(3) {
+            set.insert([window[0], window[1], window[2]]);
+        }
+    }
+    set
+}
+
+fn trigram_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+// Classic edit distance with a single rolling row: the number of single-character insertions,
+// deletions and substitutions needed to turn a into b
+// https://en.wikipedia.org/wiki/Levenshtein_distance
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// Trigrams catch extra or reordered words ("Luigis" vs "Luigis Pizza"); edit distance catches typos in
+// short names ("Tacos" vs "Tacoz") where there are too few trigrams to go on. A pair scores as whichever is higher
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (match_key(a), match_key(b));
+    if a == b {
+        return 1.0;
+    }
+    let longest = a.chars().count().max(b.chars().count());
+    let edit_similarity = 1.0 - levenshtein(&a, &b) as f64 / longest as f64;
+    trigram_similarity(&a, &b).max(edit_similarity)
+}
+
+// True when every word of one name appears in the other, e.g. "Luigis" and "Luigi's Pizzeria". Too loose for
+// duplicate detection on its own, but a good signal when an outside directory's search has already narrowed
+// things down to the right neighborhood
+pub fn words_contained(a: &str, b: &str) -> bool {
+    let (a, b) = (match_key(a), match_key(b));
+    let (a, b): (HashSet<&str>, HashSet<&str>) = (a.split_whitespace().collect(), b.split_whitespace().collect());
+    !a.is_empty() && !b.is_empty() && (a.is_subset(&b) || b.is_subset(&a))
+}
+
+// Registered restaurants (other than rejected suggestions and the trash) whose name or one of whose aliases scores
+// at least threshold against name, best match first. Matches are always reported under the restaurant's real name
+pub async fn similar_restaurants(db: &SqlitePool, name: &str, threshold: f64) -> Result<Vec<NameMatch>, sqlx::Error> {
+    let labels: Vec<(i64, String, String, String)> = sqlx::query_as(
+        "SELECT r.id, r.public_id, r.name, r.name FROM restaurants r
+        WHERE r.status != 'rejected' AND r.deleted_at IS NULL
+        UNION ALL
+        SELECT r.id, r.public_id, r.name, a.alias FROM restaurant_aliases a
+        JOIN restaurants r ON r.id = a.restaurant_id
+        WHERE r.status != 'rejected' AND r.deleted_at IS NULL",
+    )
+    .fetch_all(db)
+    .await?;
+
+    // A restaurant can match through several labels; the entry API lets us keep only its best score
+    // https://doc.rust-lang.org/std/collections/hash_map/enum.Entry.html
+    let mut best: HashMap<i64, NameMatch> = HashMap::new();
+    for (id, public_id, restaurant_name, label) in labels {
+        let score = similarity(name, &label);
+        if score < threshold {
+            continue;
+        }
+        let entry = best.entry(id).or_insert(NameMatch {
+            id,
+            public_id,
+            name: restaurant_name,
+            score,
+        });
+        entry.score = entry.score.max(score);
+    }
+
+    let mut matches: Vec<NameMatch> = best.into_values().collect();
+    // f64 has no total order because of NaN, so sort with partial_cmp; scores here are never NaN
+    matches.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(matches)
+}
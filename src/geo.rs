@@ -0,0 +1,55 @@
+// Geographic helpers: where things are and how far apart they are
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+// Great-circle distance using the haversine formula. Treating the earth as a sphere is off by well under
+// one percent, which is plenty for "is this place a short walk away"
+// https://en.wikipedia.org/wiki/Haversine_formula
+pub fn distance_meters(a: Coordinates, b: Coordinates) -> f64 {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * h.sqrt().asin()
+}
+
+// A relaxed walking pace, about 4.8 km/h, for estimating walking time when no travel time has been recorded
+pub const WALKING_METERS_PER_MINUTE: f64 = 80.0;
+
+// Links that open a place in Google Maps and on OpenStreetMap, so the winner is one click away
+// https://developers.google.com/maps/documentation/urls/get-started#search-action
+// https://wiki.openstreetmap.org/wiki/Browsing#Other_URL_tricks
+#[derive(Serialize)]
+pub struct MapLinks {
+    google: String,
+    openstreetmap: String,
+}
+
+// Coordinates give an exact pin; without them the address is searched for instead. None when we know neither
+pub fn map_links(coordinates: Option<Coordinates>, address: Option<&str>) -> Option<MapLinks> {
+    if let Some(Coordinates { latitude, longitude }) = coordinates {
+        return Some(MapLinks {
+            google: format!("https://www.google.com/maps/search/?api=1&query={latitude},{longitude}"),
+            openstreetmap: format!(
+                "https://www.openstreetmap.org/?mlat={latitude}&mlon={longitude}#map=18/{latitude}/{longitude}"
+            ),
+        });
+    }
+    // parse_with_params percent-encodes the address for us
+    // https://docs.rs/url/latest/url/struct.Url.html#method.parse_with_params
+    let address = address?;
+    let search = |base: &str, params: &[(&str, &str)]| {
+        reqwest::Url::parse_with_params(base, params).expect("map search URLs are valid").to_string()
+    };
+    Some(MapLinks {
+        google: search("https://www.google.com/maps/search/", &[("api", "1"), ("query", address)]),
+        openstreetmap: search("https://www.openstreetmap.org/search", &[("query", address)]),
+    })
+}
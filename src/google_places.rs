@@ -0,0 +1,141 @@
+// Imports restaurants near the office from the Google Places API (New), using Nearby Search
+// https://developers.google.com/maps/documentation/places/web-service/nearby-search
+use axum::extract::State;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::geo::{self, Coordinates};
+use crate::imports::{self, ImportSummary, ImportedPlace};
+use crate::AppState;
+
+const NEARBY_SEARCH_URL: &str = "https://places.googleapis.com/v1/places:searchNearby";
+// The new Places API only returns the fields asked for in this mask (and bills by it)
+const FIELD_MASK: &str =
+    "places.id,places.displayName,places.formattedAddress,places.rating,places.priceLevel,places.location,places.primaryType";
+
+// rename_all = "camelCase" maps Google's field names like formattedAddress onto snake_case struct fields
+#[derive(Deserialize)]
+struct NearbyResponse {
+    #[serde(default)]
+    places: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Place {
+    id: String,
+    display_name: Option<LocalizedText>,
+    formatted_address: Option<String>,
+    rating: Option<f64>,
+    price_level: Option<String>,
+    location: Option<Coordinates>,
+    primary_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LocalizedText {
+    text: String,
+}
+
+// Google's price levels, mapped onto our 1-4 price tiers
+// https://developers.google.com/maps/documentation/places/web-service/reference/rest/v1/places#pricelevel
+fn price_tier(level: &str) -> Option<i64> {
+    match level {
+        "PRICE_LEVEL_FREE" | "PRICE_LEVEL_INEXPENSIVE" => Some(1),
+        "PRICE_LEVEL_MODERATE" => Some(2),
+        "PRICE_LEVEL_EXPENSIVE" => Some(3),
+        "PRICE_LEVEL_VERY_EXPENSIVE" => Some(4),
+        _ => None,
+    }
+}
+
+// Place types like "italian_restaurant" or "ramen_restaurant" double as a cuisine; plain "restaurant" does not
+fn cuisine(primary_type: &str) -> Option<String> {
+    primary_type
+        .strip_suffix("_restaurant")
+        .map(|cuisine| cuisine.replace('_', " "))
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    radius_meters: Option<f64>, // overrides PLACES_RADIUS_METERS for this run
+}
+
+// POST /restaurants/import/google-places (admin)
+pub async fn import(
+    _admin: Admin,
+    State(state): State<AppState>,
+    body: Option<Json<ImportRequest>>,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let config = &state.config;
+    let api_key = config
+        .google_places_api_key
+        .as_deref()
+        .ok_or_else(|| ApiError::NotConfigured("set GOOGLE_PLACES_API_KEY to import from Google Places".to_string()))?;
+    let office = config
+        .office_location
+        .ok_or_else(|| ApiError::NotConfigured("set OFFICE_LATITUDE and OFFICE_LONGITUDE to import nearby places".to_string()))?;
+    let radius = body
+        .and_then(|Json(body)| body.radius_meters)
+        .unwrap_or(config.places_radius_meters);
+    // Nearby Search accepts radii up to 50 km
+    if !(radius > 0.0 && radius <= 50_000.0) {
+        return Err(ApiError::BadRequest("radius_meters must be between 0 and 50000".to_string()));
+    }
+
+    let response = state
+        .http
+        .post(NEARBY_SEARCH_URL)
+        .header("X-Goog-Api-Key", api_key)
+        .header("X-Goog-FieldMask", FIELD_MASK)
+        .json(&json!({
+            "includedTypes": ["restaurant"],
+            "maxResultCount": 20,
+            "locationRestriction": {
+                "circle": {
+                    "center": { "latitude": office.latitude, "longitude": office.longitude },
+                    "radius": radius,
+                }
+            }
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("Google Places returned {status}: {body}")));
+    }
+    let nearby: NearbyResponse = response.json().await?;
+
+    let mut summary = ImportSummary::default();
+    for place in nearby.places {
+        let Some(name) = place.display_name.map(|name| name.text) else {
+            continue;
+        };
+        let imported = ImportedPlace {
+            source: "google_places",
+            external_id: place.id,
+            name,
+            address: place.formatted_address,
+            cuisine: place.primary_type.as_deref().and_then(cuisine),
+            price_tier: place.price_level.as_deref().and_then(price_tier),
+            distance_meters: place
+                .location
+                .map(|location| geo::distance_meters(office, location).round() as i64),
+            location: place.location,
+        };
+        let id = imports::upsert(&state.db, imported, &mut summary).await?;
+        // Google's own rating is refreshed on every import; it's theirs, so there's nothing of ours to preserve
+        if let Some(rating) = place.rating {
+            sqlx::query("UPDATE restaurants SET google_rating = ? WHERE id = ?")
+                .bind(rating)
+                .bind(id)
+                .execute(&state.db)
+                .await?;
+        }
+    }
+    Ok(Json(summary))
+}
@@ -0,0 +1,1003 @@
+// GraphQL over the same data as the endpoints, for frontends that would rather ask for everything a page shows in one
+// query and get back just the fields they named. POST /graphql takes {"query": ..., "variables": {...},
+// "operationName": ...}, and GET /graphql the same as ?query=...&variables=...; the answer is {"data": {...}} with
+// "errors" alongside when a field couldn't be had. Each field is answered by the endpoint it's named after, asked
+// here on the caller's behalf with the caller's Authorization and Accept-Language, so the data, the checks and the
+// admin-only parts are the endpoints' own. The arguments are those endpoints' query parameters, and the fields of
+// what comes back are the fields of their JSON, snake_case as everywhere else; see SCHEMA for the ones at the top,
+// and RELATIONS for the ones reaching from one thing to another, a poll's results say. Only as much of GraphQL as
+// that needs is read: operations with variables and their defaults, aliases, arguments, fragments named and inline,
+// and @include and @skip. There's no introspection, and types are only checked for by the endpoints themselves, so
+// a field the endpoint doesn't send comes back null rather than failing the query. The fields of an answer come in
+// alphabetical order, as serde_json keeps them, rather than the query's. Nothing can be changed here, so there are no
+// mutations. A subscription is answered as server-sent events, in the distinct connections mode of
+// GraphQL over SSE: a "next" event with the first result straight away, and another each time a refresh (see
+// refresh.rs) changes it
+// https://spec.graphql.org/October2021/
+// https://github.com/enisdenjo/graphql-sse/blob/master/PROTOCOL.md
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Query, Request, State};
+use axum::http::header::{ACCEPT_LANGUAGE, AUTHORIZATION};
+use axum::http::{Extensions, HeaderMap, Method, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tower::ServiceExt;
+
+use crate::refresh::Cause;
+use crate::{s3, AppState};
+
+pub const PATH: &str = "/graphql";
+
+// Queries are short; anything longer than this isn't one
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+// How many endpoints one query may ask, so a list of restaurants each asking for its hours can't run away
+const MAX_FETCHES: usize = 100;
+// How deep selections, values and types may nest, and the selections with fragments spread into them; reading and
+// answering them goes a call deeper with each level, and there's only so much stack
+const MAX_DEPTH: usize = 32;
+// How many fields an operation may come to with its fragments spread, since fragments that each spread the next
+// twice double it every time
+const MAX_FIELDS: usize = 10_000;
+
+// The fields at the top of a query: the type of what each gives, and the endpoints answering it. The first endpoint
+// whose {placeholders} all have arguments is asked; the arguments left over go on its query string
+const SCHEMA: &[(&str, &str, &[&str])] = &[
+    ("poll", "Poll", &["/polls/{id}"]),
+    ("results", "Results", &["/polls/{poll_id}/results", "/results"]),
+    ("restaurant", "Restaurant", &["/restaurants/{id}"]),
+    ("restaurants", "Restaurant", &["/restaurants"]),
+    ("search", "Restaurant", &["/restaurants/search"]),
+    ("offices", "Office", &["/offices"]),
+    ("teams", "Team", &["/teams"]),
+    ("team", "Team", &["/teams/{id}"]),
+    ("voter", "Voter", &["/voters/{name}"]),
+    ("holidays", "Holiday", &["/holidays"]),
+    ("votes", "VoteEvent", &["/vote-events"]),
+    ("trends", "Trends", &["/stats/trends"]),
+    ("cuisines", "CuisineStats", &["/stats/cuisines"]),
+    ("channels", "ChannelStats", &["/stats/channels"]),
+];
+
+// The fields that can be subscribed to, answered the same way
+const SUBSCRIPTIONS: &[(&str, &str, &[&str])] = &[("results", "Results", &["/polls/{poll_id}/results", "/results"])];
+
+// Fields of one type that another endpoint answers: the type, the field, the field's type, and the endpoint, with
+// {placeholders} filled in from the object's own fields first and the field's arguments after
+const RELATIONS: &[(&str, &str, &str, &str)] = &[
+    ("Poll", "results", "Results", "/polls/{id}/results"),
+    ("Poll", "candidates", "Restaurant", "/polls/{id}/candidates"),
+    ("Poll", "participants", "String", "/polls/{id}/participants"),
+    ("Poll", "rsvps", "Rsvp", "/polls/{id}/rsvps"),
+    ("Restaurant", "hours", "OpeningPeriod", "/restaurants/{id}/hours"),
+];
+
+#[derive(Deserialize)]
+struct Posted {
+    query: String,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+}
+
+// The same in a query string, with the variables as JSON text
+#[derive(Deserialize)]
+struct Asked {
+    query: String,
+    variables: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+}
+
+// GET and POST /graphql
+pub async fn serve(State(state): State<AppState>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let posted = match parts.method {
+        Method::POST => match to_bytes(body, MAX_REQUEST_BYTES).await {
+            Ok(bytes) => serde_json::from_slice::<Posted>(&bytes).map_err(|err| err.to_string()),
+            Err(_) => Err("the request body is too large".to_string()),
+        },
+        _ => match Query::<Asked>::try_from_uri(&parts.uri) {
+            Ok(Query(asked)) => match asked.variables.as_deref().map(serde_json::from_str).transpose() {
+                Ok(variables) => Ok(Posted { query: asked.query, variables, operation_name: asked.operation_name }),
+                Err(err) => Err(format!("variables must be a JSON object: {err}")),
+            },
+            Err(err) => Err(err.body_text()),
+        },
+    };
+    let posted = match posted {
+        Ok(posted) => posted,
+        Err(message) => return refuse(message, None),
+    };
+    let variables = posted.variables.unwrap_or_default();
+    let mut document = match Parser::new(&posted.query, &variables).document() {
+        Ok(document) => document,
+        Err((message, at)) => return refuse(message, Some(at)),
+    };
+    let operation = match document.operation(posted.operation_name.as_deref()) {
+        Ok(operation) => operation,
+        Err(message) => return refuse(message, None),
+    };
+    if let Err(message) = document.spread(&operation.selections, &mut Vec::new(), &mut 0) {
+        return refuse(message, None);
+    }
+
+    // The caller's own credentials and language go along with every request made for them
+    let mut headers = HeaderMap::new();
+    for name in [AUTHORIZATION, ACCEPT_LANGUAGE] {
+        if let Some(value) = parts.headers.get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    let context = Context {
+        router: crate::routes().with_state(state.clone()),
+        headers,
+        extensions: parts.extensions,
+        fragments: document.fragments,
+        errors: Mutex::new(Vec::new()),
+        fetches: AtomicUsize::new(0),
+    };
+    match operation.kind {
+        Kind::Query => Json(context.answer(SCHEMA, &operation.selections).await).into_response(),
+        Kind::Mutation => {
+            refuse("nothing can be changed through GraphQL; use the endpoints for that".to_string(), None)
+        }
+        Kind::Subscription => subscribe(state, context, operation.selections),
+    }
+}
+
+// A request that can't be run at all: 400, and no data
+fn refuse(message: String, at: Option<(usize, usize)>) -> Response {
+    let mut error = json!({ "message": message });
+    if let Some((line, column)) = at {
+        error["locations"] = json!([{ "line": line, "column": column }]);
+    }
+    (StatusCode::BAD_REQUEST, Json(json!({ "errors": [error] }))).into_response()
+}
+
+fn subscribe(state: AppState, context: Context, selections: Vec<Selection>) -> Response {
+    let fields = context.fields(&selections);
+    let [field] = fields.as_slice() else {
+        return refuse("a subscription is to exactly one field".to_string(), None);
+    };
+    if !SUBSCRIPTIONS.iter().any(|(name, _, _)| *name == field.name) {
+        return refuse(format!("there's no {} to subscribe to", field.name), None);
+    }
+    // The poll the subscription is to, by public id, to match against refreshes; none for the loose votes
+    let watched = field.arguments.iter().find(|(name, _)| name == "poll_id").and_then(|(_, id)| id.as_str());
+    let watched = watched.map(str::to_string);
+    let receiver = state.refreshes.subscribe();
+    let events = futures_util::stream::unfold(
+        (context, selections, receiver, None::<Value>),
+        move |(context, selections, mut receiver, last)| {
+            let watched = watched.clone();
+            async move {
+                // The first result goes out at once, and after that only those a refresh has changed
+                loop {
+                    if last.is_some() {
+                        match receiver.recv().await {
+                            Ok(refresh) if refresh.cause != Cause::Missed && refresh.poll_id != watched => continue,
+                            Ok(_) | Err(RecvError::Lagged(_)) => {}
+                            Err(RecvError::Closed) => return None,
+                        }
+                    }
+                    context.errors.lock().expect("errors poisoned").clear();
+                    context.fetches.store(0, Ordering::Relaxed);
+                    let answer = context.answer(SUBSCRIPTIONS, &selections).await;
+                    if last.as_ref() == Some(&answer) {
+                        continue;
+                    }
+                    let event = Event::default().event("next").json_data(&answer).expect("an answer is JSON");
+                    return Some((Ok::<_, Infallible>(event), (context, selections, receiver, Some(answer))));
+                }
+            }
+        },
+    );
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+// What a query is run with: where its fields are asked, on whose behalf, and what went wrong along the way
+struct Context {
+    router: Router,
+    headers: HeaderMap,
+    extensions: Extensions,
+    fragments: HashMap<String, Vec<Selection>>,
+    errors: Mutex<Vec<Value>>,
+    fetches: AtomicUsize,
+}
+
+impl Context {
+    // {"data": ...} for the operation's fields, with the errors beside it if there were any
+    async fn answer(&self, roots: &[(&str, &str, &[&str])], selections: &[Selection]) -> Value {
+        let mut data = Map::new();
+        for field in self.fields(selections) {
+            let key = field.key().to_string();
+            let path = vec![json!(key)];
+            let value = match roots.iter().find(|(name, _, _)| *name == field.name) {
+                Some((_, type_name, endpoints)) => {
+                    let endpoint = endpoints.iter().find(|endpoint| {
+                        placeholders(endpoint).all(|name| field.arguments.iter().any(|(argument, _)| argument == name))
+                    });
+                    match endpoint {
+                        Some(endpoint) => self.resolve(&field, endpoint, &Map::new(), type_name, path).await,
+                        None => self.fail(format!("{} is missing an argument", field.name), path),
+                    }
+                }
+                None if field.name == "__typename" => json!(if roots == SCHEMA { "Query" } else { "Subscription" }),
+                None => self.fail(format!("there's no field {} at the top of a query", field.name), path),
+            };
+            data.insert(key, value);
+        }
+        let errors = std::mem::take(&mut *self.errors.lock().expect("errors poisoned"));
+        match errors.is_empty() {
+            true => json!({ "data": data }),
+            false => json!({ "data": data, "errors": errors }),
+        }
+    }
+
+    // The fields of a selection set, with the fragments spread into it; Document::spread has made sure they can be
+    fn fields(&self, selections: &[Selection]) -> Vec<Field> {
+        let mut fields = Vec::new();
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => fields.push(field.clone()),
+                Selection::Inline(selections) => fields.extend(self.fields(selections)),
+                Selection::Spread(name) => {
+                    if let Some(selections) = self.fragments.get(name) {
+                        fields.extend(self.fields(selections));
+                    }
+                }
+            }
+        }
+        fields
+    }
+
+    fn fail(&self, message: String, path: Vec<Value>) -> Value {
+        self.errors.lock().expect("errors poisoned").push(json!({ "message": message, "path": path }));
+        Value::Null
+    }
+
+    // Asks the endpoint for the field, then picks out what the field's selection wants of it
+    async fn resolve(
+        &self,
+        field: &Field,
+        endpoint: &str,
+        object: &Map<String, Value>,
+        type_name: &str,
+        path: Vec<Value>,
+    ) -> Value {
+        match self.fetch(endpoint, object, &field.arguments).await {
+            Ok(value) => self.select(&field.selections, &value, Some(type_name), path).await,
+            Err(message) => self.fail(message, path),
+        }
+    }
+
+    async fn fetch(
+        &self,
+        endpoint: &str,
+        object: &Map<String, Value>,
+        arguments: &[(String, Value)],
+    ) -> Result<Value, String> {
+        if self.fetches.fetch_add(1, Ordering::Relaxed) >= MAX_FETCHES {
+            return Err(format!("a query can ask for at most {MAX_FETCHES} things at once"));
+        }
+        let text = |value: &Value| match value {
+            Value::String(text) => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            Value::Bool(flag) => Some(flag.to_string()),
+            _ => None,
+        };
+        let mut uri = endpoint.to_string();
+        let mut filled = Vec::new();
+        for name in placeholders(endpoint) {
+            let argument = || arguments.iter().find(|(argument, _)| argument == name).map(|(_, value)| value);
+            let value = object.get(name).or_else(argument);
+            let value = value.and_then(text).ok_or_else(|| format!("{name} is needed to look this up"))?;
+            uri = uri.replace(&format!("{{{name}}}"), &s3::encode(&value, false));
+            filled.push(name);
+        }
+        let mut query = Vec::new();
+        for (name, value) in arguments.iter().filter(|(name, _)| !filled.contains(&name.as_str())) {
+            match value {
+                Value::Null => {}
+                value => {
+                    let value = text(value).ok_or_else(|| format!("{name} takes a single value"))?;
+                    query.push(format!("{}={}", s3::encode(name, false), s3::encode(&value, false)));
+                }
+            }
+        }
+        if !query.is_empty() {
+            uri = format!("{uri}?{}", query.join("&"));
+        }
+
+        let mut request = Request::new(Body::empty());
+        *request.uri_mut() = uri.parse().map_err(|_| format!("{uri} isn't somewhere that can be asked"))?;
+        *request.headers_mut() = self.headers.clone();
+        *request.extensions_mut() = self.extensions.clone();
+        let response = self.router.clone().oneshot(request).await.unwrap_or_else(|never| match never {});
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.map_err(|err| err.to_string())?;
+        let value: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        if !status.is_success() {
+            // The endpoint's own error message, its problem's detail, with its status for want of one
+            return Err(value["detail"].as_str().map_or_else(|| status.to_string(), str::to_string));
+        }
+        Ok(value)
+    }
+
+    // A value cut down to what the selection set names. Lists are cut down item by item; a field with no selection
+    // set of its own comes whole
+    fn select<'a>(
+        &'a self,
+        selections: &'a [Selection],
+        value: &'a Value,
+        type_name: Option<&'a str>,
+        path: Vec<Value>,
+    ) -> BoxFuture<'a, Value> {
+        async move {
+            if selections.is_empty() {
+                return value.clone();
+            }
+            match value {
+                Value::Array(items) => {
+                    let mut selected = Vec::with_capacity(items.len());
+                    for (index, item) in items.iter().enumerate() {
+                        let mut path = path.clone();
+                        path.push(json!(index));
+                        selected.push(self.select(selections, item, type_name, path).await);
+                    }
+                    Value::Array(selected)
+                }
+                Value::Object(object) => {
+                    let mut selected = Map::new();
+                    for field in self.fields(selections) {
+                        let key = field.key().to_string();
+                        let mut path = path.clone();
+                        path.push(json!(key));
+                        let relation = RELATIONS
+                            .iter()
+                            .find(|(of, name, _, _)| Some(*of) == type_name && *name == field.name);
+                        let value = match relation {
+                            Some((_, _, child, endpoint)) => self.resolve(&field, endpoint, object, child, path).await,
+                            None if field.name == "__typename" => type_name.map_or(Value::Null, |name| json!(name)),
+                            None => {
+                                let child = object.get(&field.name).unwrap_or(&Value::Null);
+                                self.select(&field.selections, child, None, path).await
+                            }
+                        };
+                        selected.insert(key, value);
+                    }
+                    Value::Object(selected)
+                }
+                _ => value.clone(),
+            }
+        }
+        .boxed()
+    }
+}
+
+// The {names} in an endpoint
+fn placeholders(endpoint: &str) -> impl Iterator<Item = &str> {
+    endpoint.split('{').skip(1).filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+struct Operation {
+    kind: Kind,
+    name: Option<String>,
+    selections: Vec<Selection>,
+}
+
+struct Document {
+    operations: Vec<Operation>,
+    fragments: HashMap<String, Vec<Selection>>,
+}
+
+impl Document {
+    // The one operationName names, or the only one there is
+    fn operation(&mut self, name: Option<&str>) -> Result<Operation, String> {
+        let operations = &mut self.operations;
+        match name {
+            Some(name) => match operations.iter().position(|operation| operation.name.as_deref() == Some(name)) {
+                Some(at) => Ok(operations.swap_remove(at)),
+                None => Err(format!("there's no operation named {name}")),
+            },
+            None if operations.len() == 1 => Ok(operations.remove(0)),
+            None if operations.is_empty() => Err("the query has no operation in it".to_string()),
+            None => Err("the query has several operations; say which with operationName".to_string()),
+        }
+    }
+
+    // That the fragments in these selections can be spread: none spreads itself, however far round, and spread out
+    // they nest no deeper than MAX_DEPTH and come to no more than MAX_FIELDS fields. `expanding` is the fragments
+    // being spread on the way here, and `fields` how many have been counted so far
+    fn spread(&self, selections: &[Selection], expanding: &mut Vec<String>, fields: &mut usize) -> Result<(), String> {
+        if expanding.len() > MAX_DEPTH {
+            return Err(format!("the query nests deeper than {MAX_DEPTH} with its fragments spread"));
+        }
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => {
+                    *fields += 1;
+                    if *fields > MAX_FIELDS {
+                        return Err(format!("the query comes to more than {MAX_FIELDS} fields"));
+                    }
+                    // A field's selections are a level further down, as a spread is
+                    expanding.push(String::new());
+                    self.spread(&field.selections, expanding, fields)?;
+                    expanding.pop();
+                }
+                Selection::Inline(selections) => {
+                    expanding.push(String::new());
+                    self.spread(selections, expanding, fields)?;
+                    expanding.pop();
+                }
+                Selection::Spread(name) => {
+                    if expanding.contains(name) {
+                        return Err(format!("fragment {name} spreads itself"));
+                    }
+                    if let Some(selections) = self.fragments.get(name) {
+                        expanding.push(name.clone());
+                        self.spread(selections, expanding, fields)?;
+                        expanding.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+enum Selection {
+    Field(Field),
+    Spread(String),         // ...Name, a fragment defined elsewhere in the document
+    Inline(Vec<Selection>), // ... on Type { }, or just ... { }; the type isn't checked
+}
+
+#[derive(Clone)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, Value)>, // with the variables already filled in
+    selections: Vec<Selection>,
+}
+
+impl Field {
+    // What it's called in the answer
+    fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+// A recursive descent over the query's characters. Variables are filled in as they're read, from what the request
+// sent or else the defaults the operation declares, and @include and @skip are applied then too, so what comes out is
+// only what's to be asked. Errors carry the line and column they were found at
+struct Parser<'a> {
+    chars: Vec<char>,
+    at: usize,
+    variables: &'a Map<String, Value>,
+    defaults: Map<String, Value>,
+    depth: usize, // how many selection sets, lists, objects and list types the parser is inside
+}
+
+type Parsed<T> = Result<T, (String, (usize, usize))>;
+
+impl<'a> Parser<'a> {
+    fn new(query: &str, variables: &'a Map<String, Value>) -> Parser<'a> {
+        Parser { chars: query.chars().collect(), at: 0, variables, defaults: Map::new(), depth: 0 }
+    }
+
+    // Goes a level further in, unless that's deeper than MAX_DEPTH; out again with `leave`
+    fn enter(&mut self) -> Parsed<()> {
+        if self.depth == MAX_DEPTH {
+            return self.error(format!("the query nests deeper than {MAX_DEPTH}"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Parsed<T> {
+        let before = &self.chars[..self.at.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        Err((message.into(), (line, column)))
+    }
+
+    // The next character that means something: commas count as blank space in GraphQL, and # starts a comment
+    fn peek(&mut self) -> Option<char> {
+        while let Some(&c) = self.chars.get(self.at) {
+            match c {
+                '#' => {
+                    while self.chars.get(self.at).is_some_and(|&c| c != '\n') {
+                        self.at += 1;
+                    }
+                }
+                c if c.is_whitespace() || c == ',' || c == '\u{feff}' => self.at += 1,
+                _ => return Some(c),
+            }
+        }
+        None
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Parsed<()> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => self.error(format!("expected {c}")),
+        }
+    }
+
+    fn spread(&mut self) -> bool {
+        if self.peek() == Some('.') && self.chars.get(self.at..self.at + 3) == Some(&['.', '.', '.']) {
+            self.at += 3;
+            return true;
+        }
+        false
+    }
+
+    fn name(&mut self) -> Parsed<String> {
+        let start = match self.peek() {
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => self.at,
+            _ => return self.error("expected a name"),
+        };
+        while self.chars.get(self.at).is_some_and(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+            self.at += 1;
+        }
+        Ok(self.chars[start..self.at].iter().collect())
+    }
+
+    // The next name, if it's this one
+    fn keyword(&mut self, word: &str) -> bool {
+        let at = self.at;
+        match self.name() {
+            Ok(name) if name == word => true,
+            _ => {
+                self.at = at;
+                false
+            }
+        }
+    }
+
+    fn document(mut self) -> Parsed<Document> {
+        let mut document = Document { operations: Vec::new(), fragments: HashMap::new() };
+        while self.peek().is_some() {
+            if self.keyword("fragment") {
+                let name = self.name()?;
+                if !self.keyword("on") {
+                    return self.error("expected on and the type the fragment is on");
+                }
+                self.name()?;
+                self.directives()?;
+                let selections = self.selections()?;
+                document.fragments.insert(name, selections);
+                continue;
+            }
+            let kind = match self.peek() {
+                Some('{') => Kind::Query,
+                _ if self.keyword("query") => Kind::Query,
+                _ if self.keyword("mutation") => Kind::Mutation,
+                _ if self.keyword("subscription") => Kind::Subscription,
+                _ => return self.error("expected query, subscription or {"),
+            };
+            let name = match self.peek() {
+                Some(c) if c == '_' || c.is_ascii_alphabetic() => Some(self.name()?),
+                _ => None,
+            };
+            if self.eat('(') {
+                while !self.eat(')') {
+                    self.expect('$')?;
+                    let variable = self.name()?;
+                    self.expect(':')?;
+                    self.type_ref()?;
+                    if self.eat('=') {
+                        let default = self.value(true)?;
+                        self.defaults.insert(variable, default);
+                    }
+                    self.directives()?;
+                }
+            }
+            self.directives()?;
+            let selections = self.selections()?;
+            document.operations.push(Operation { kind, name, selections });
+        }
+        Ok(document)
+    }
+
+    // A variable's type, Name, [Type] or either with a !; read past, since the endpoints check what they're given
+    fn type_ref(&mut self) -> Parsed<()> {
+        if self.eat('[') {
+            self.enter()?;
+            self.type_ref()?;
+            self.expect(']')?;
+            self.leave();
+        } else {
+            self.name()?;
+        }
+        self.eat('!');
+        Ok(())
+    }
+
+    fn selections(&mut self) -> Parsed<Vec<Selection>> {
+        self.expect('{')?;
+        self.enter()?;
+        let mut selections = Vec::new();
+        while !self.eat('}') {
+            if self.peek().is_none() {
+                return self.error("expected }");
+            }
+            if self.spread() {
+                // ...Name @directives, or ... on Type @directives { } with the type and the directives optional
+                let on = self.keyword("on");
+                if on {
+                    self.name()?;
+                }
+                let spread = match self.peek() {
+                    Some('{' | '@') => None,
+                    _ if on => None,
+                    _ => Some(Selection::Spread(self.name()?)),
+                };
+                let included = self.directives()?;
+                let selection = match spread {
+                    Some(spread) => spread,
+                    None => Selection::Inline(self.selections()?),
+                };
+                if included {
+                    selections.push(selection);
+                }
+                continue;
+            }
+            let mut name = self.name()?;
+            let mut alias = None;
+            if self.eat(':') {
+                alias = Some(name);
+                name = self.name()?;
+            }
+            let mut arguments = Vec::new();
+            if self.eat('(') {
+                while !self.eat(')') {
+                    let argument = self.name()?;
+                    self.expect(':')?;
+                    arguments.push((argument, self.value(false)?));
+                }
+            }
+            let included = self.directives()?;
+            let children = match self.peek() {
+                Some('{') => self.selections()?,
+                _ => Vec::new(),
+            };
+            if included {
+                selections.push(Selection::Field(Field { alias, name, arguments, selections: children }));
+            }
+        }
+        self.leave();
+        Ok(selections)
+    }
+
+    // Whether what the directives follow stays in: only @include(if: false) and @skip(if: true) leave it out, and
+    // any other directive is read past
+    fn directives(&mut self) -> Parsed<bool> {
+        let mut included = true;
+        while self.eat('@') {
+            let directive = self.name()?;
+            let mut condition = None;
+            if self.eat('(') {
+                while !self.eat(')') {
+                    let argument = self.name()?;
+                    self.expect(':')?;
+                    let value = self.value(false)?;
+                    if argument == "if" {
+                        condition = value.as_bool();
+                    }
+                }
+            }
+            match (directive.as_str(), condition) {
+                ("include", Some(false)) | ("skip", Some(true)) => included = false,
+                _ => {}
+            }
+        }
+        Ok(included)
+    }
+
+    // A value as JSON; enum values come out as their names. Defaults can't refer to variables
+    fn value(&mut self, constant: bool) -> Parsed<Value> {
+        match self.peek() {
+            Some('$') if !constant => {
+                self.at += 1;
+                let name = self.name()?;
+                Ok(self.variables.get(&name).or_else(|| self.defaults.get(&name)).cloned().unwrap_or(Value::Null))
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => {
+                self.at += 1;
+                self.enter()?;
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    if self.peek().is_none() {
+                        return self.error("expected ]");
+                    }
+                    items.push(self.value(constant)?);
+                }
+                self.leave();
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.at += 1;
+                self.enter()?;
+                let mut fields = Map::new();
+                while !self.eat('}') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    fields.insert(name, self.value(constant)?);
+                }
+                self.leave();
+                Ok(Value::Object(fields))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.at;
+                self.at += 1;
+                while self.chars.get(self.at).is_some_and(|&c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    self.at += 1;
+                }
+                let number: String = self.chars[start..self.at].iter().collect();
+                match serde_json::from_str::<serde_json::Number>(&number) {
+                    Ok(number) => Ok(Value::Number(number)),
+                    Err(_) => self.error(format!("{number} isn't a number")),
+                }
+            }
+            _ => match self.name()?.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "null" => Ok(Value::Null),
+                name => Ok(Value::String(name.to_string())),
+            },
+        }
+    }
+
+    // "..." with the escapes JSON has, or a """block""" taken as it's written
+    fn string(&mut self) -> Parsed<String> {
+        if self.chars.get(self.at..self.at + 3) == Some(&['"', '"', '"']) {
+            self.at += 3;
+            let start = self.at;
+            while self.chars.get(self.at..self.at + 3).is_some_and(|end| end != ['"', '"', '"']) {
+                self.at += 1;
+            }
+            if self.at + 3 > self.chars.len() {
+                return self.error("expected \"\"\" to end the string");
+            }
+            let text: String = self.chars[start..self.at].iter().collect();
+            self.at += 3;
+            return Ok(text.trim().to_string());
+        }
+        self.at += 1;
+        let mut text = String::new();
+        loop {
+            let Some(&c) = self.chars.get(self.at) else {
+                return self.error("expected \" to end the string");
+            };
+            self.at += 1;
+            match c {
+                '"' => return Ok(text),
+                '\n' => return self.error("a string can't run over lines; use a \"\"\"block\"\"\""),
+                '\\' => {
+                    let escaped = self.chars.get(self.at).copied();
+                    self.at += 1;
+                    match escaped {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some('r') => text.push('\r'),
+                        Some('b') => text.push('\u{8}'),
+                        Some('f') => text.push('\u{c}'),
+                        Some('u') => {
+                            let hex: String = self.chars.iter().skip(self.at).take(4).collect();
+                            self.at += 4;
+                            match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                Some(c) => text.push(c),
+                                None => return self.error(format!("\\u{hex} isn't a character")),
+                            }
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                        _ => return self.error("that escape isn't one"),
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Parsed<Document> {
+        Parser::new(query, &Map::new()).document()
+    }
+
+    // Selections written back out, fragments by name, so what was read can be compared with a string
+    fn written(selections: &[Selection]) -> String {
+        let written: Vec<String> = selections.iter().map(|selection| match selection {
+            Selection::Field(field) => {
+                let mut out = match &field.alias {
+                    Some(alias) => format!("{alias}:{}", field.name),
+                    None => field.name.clone(),
+                };
+                if !field.arguments.is_empty() {
+                    let arguments: Vec<String> =
+                        field.arguments.iter().map(|(name, value)| format!("{name}={value}")).collect();
+                    out += &format!("({})", arguments.join(","));
+                }
+                if !field.selections.is_empty() {
+                    out += &format!("{{{}}}", written(&field.selections));
+                }
+                out
+            }
+            Selection::Spread(name) => format!("...{name}"),
+            Selection::Inline(selections) => format!("...{{{}}}", written(selections)),
+        }).collect();
+        written.join(" ")
+    }
+
+    fn only(query: &str) -> Operation {
+        parse(query).expect("the query parses").operation(None).expect("there's one operation")
+    }
+
+    #[test]
+    fn fields_aliases_and_arguments_are_read() {
+        let operation = only("query Lunch { polls(limit: 2, open: true) { id first: title, results { name } } }");
+        assert!(matches!(operation.kind, Kind::Query));
+        assert_eq!(operation.name.as_deref(), Some("Lunch"));
+        assert_eq!(written(&operation.selections), r#"polls(limit=2,open=true){id first:title results{name}}"#);
+    }
+
+    #[test]
+    fn values_come_out_as_json() {
+        let operation = only(r#"{ a(x: -1.5e2, y: null, z: [1, "two", THREE], w: {k: false}) }"#);
+        assert_eq!(written(&operation.selections), r#"a(x=-150.0,y=null,z=[1,"two","THREE"],w={"k":false})"#);
+    }
+
+    #[test]
+    fn variables_are_filled_in_with_the_defaults_behind_them() {
+        let variables = serde_json::json!({"limit": 5}).as_object().cloned().unwrap();
+        let query =
+            "query ($limit: Int = 10, $open: [Boolean!]! = true) { polls(limit: $limit, open: $open, to: $nil) }";
+        let mut document = Parser::new(query, &variables).document().unwrap();
+        let operation = document.operation(None).unwrap();
+        assert_eq!(written(&operation.selections), "polls(limit=5,open=true,to=null)");
+    }
+
+    #[test]
+    fn include_and_skip_leave_out_what_they_say() {
+        let operation = only(
+            "{ a @include(if: true) b @include(if: false) c @skip(if: true) d @skip(if: false) ...F @skip(if: true) \
+             ... @include(if: false) { e } f @deprecated }",
+        );
+        assert_eq!(written(&operation.selections), "a d f");
+    }
+
+    #[test]
+    fn fragments_are_kept_by_name() {
+        let mut document = parse("{ ...Names ... on Poll { id } } fragment Names on Query { title }").unwrap();
+        let operation = document.operation(None).unwrap();
+        assert_eq!(written(&operation.selections), "...Names ...{id}");
+        assert_eq!(written(&document.fragments["Names"]), "title");
+    }
+
+    #[test]
+    fn strings_take_json_escapes_and_blocks_as_written() {
+        let operation = only(r#"{ a(s: "tab\t \"quoted\" \u00e9", b: """  two
+lines \n  """) }"#);
+        assert_eq!(written(&operation.selections), r#"a(s="tab\t \"quoted\" é",b="two\nlines \\n")"#);
+    }
+
+    #[test]
+    fn commas_and_comments_are_blank_space() {
+        let operation = only("{ a,, b # c\n d }");
+        assert_eq!(written(&operation.selections), "a b d");
+    }
+
+    #[test]
+    fn errors_say_where_they_were_found() {
+        let Err((message, at)) = parse("{\n  polls(limit 2)\n}") else { panic!("the query parsed") };
+        assert_eq!(message, "expected :");
+        assert_eq!(at, (2, 15));
+        assert!(parse("{ a ").is_err());
+        assert!(parse(r#"{ a(s: "open) }"#).is_err());
+        assert!(parse("fragment F { a }").is_err());
+    }
+
+    #[test]
+    fn the_operation_is_picked_by_name_or_for_being_the_only_one() {
+        let mut document = parse("query A { a } subscription B { b }").unwrap();
+        assert!(document.operation(None).is_err());
+        assert!(document.operation(Some("C")).is_err());
+        let operation = document.operation(Some("B")).unwrap();
+        assert!(matches!(operation.kind, Kind::Subscription));
+        assert_eq!(written(&operation.selections), "b");
+        assert!(parse("fragment F on Query { a }").unwrap().operation(None).is_err());
+    }
+
+    #[test]
+    fn a_fragment_spreading_itself_is_refused() {
+        for query in [
+            "fragment F on Query { ...F } { ...F }",
+            "fragment F on Query { ...G } fragment G on Query { a { ...F } } { ...F }",
+        ] {
+            let mut document = parse(query).unwrap();
+            let operation = document.operation(None).unwrap();
+            let refused = document.spread(&operation.selections, &mut Vec::new(), &mut 0);
+            assert!(refused.is_err_and(|message| message.ends_with("spreads itself")), "{query}");
+        }
+    }
+
+    #[test]
+    fn a_fragment_spread_twice_side_by_side_is_fine() {
+        let mut document = parse("fragment F on Query { a } { ...F b { ...F } ...F }").unwrap();
+        let operation = document.operation(None).unwrap();
+        assert!(document.spread(&operation.selections, &mut Vec::new(), &mut 0).is_ok());
+    }
+
+    #[test]
+    fn fragments_doubling_up_are_refused_before_they_run_away() {
+        let mut query = "{ ...F0 }".to_string();
+        for level in 0..40 {
+            query += &format!(" fragment F{level} on Query {{ ...F{next} ...F{next} }}", next = level + 1);
+        }
+        query += " fragment F40 on Query { a }";
+        let mut document = parse(&query).unwrap();
+        let operation = document.operation(None).unwrap();
+        assert!(document.spread(&operation.selections, &mut Vec::new(), &mut 0).is_err());
+    }
+
+    #[test]
+    fn nesting_deeper_than_the_limit_is_refused() {
+        let deep = |open: &str, close: &str, depth: usize| open.repeat(depth) + &close.repeat(depth);
+        assert!(parse(&format!("{{ a(x: {}) }}", deep("[", "]", 30_000))).is_err());
+        assert!(parse(&format!("{{ a(x: {}) }}", deep("{k: ", "}", 30_000))).is_err());
+        assert!(parse(&deep("{ a ", "}", 30_000)).is_err());
+        assert!(parse(&format!("query ($x: {}) {{ a }}", deep("[", "]", 30_000).replace("[]", "[Int]"))).is_err());
+        // As deep as the limit is still fine: the selection set the argument is in counts as one
+        assert!(parse(&format!("{{ a(x: {}) }}", deep("[", "]", MAX_DEPTH - 1))).is_ok());
+        assert!(parse(&deep("{ a ", "}", MAX_DEPTH)).is_ok());
+        assert!(parse(&deep("{ a ", "}", MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn fragments_spread_deeper_than_the_limit_are_refused() {
+        let mut query = "{ ...F0 }".to_string();
+        for level in 0..MAX_DEPTH {
+            query += &format!(" fragment F{level} on Query {{ a {{ ...F{} }} }}", level + 1);
+        }
+        let mut document = parse(&query).unwrap();
+        let operation = document.operation(None).unwrap();
+        assert!(document.spread(&operation.selections, &mut Vec::new(), &mut 0).is_err());
+    }
+}
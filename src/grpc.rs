@@ -0,0 +1,616 @@
+// gRPC alongside the HTTP endpoints, for the internal services written in Go: the LunchVoting service of
+// proto/lunch_voting.proto, which protoc generates their clients from, with CastVote, GetResults and StreamResults.
+// gRPC is POSTs over HTTP/2, which the server already speaks in cleartext to clients that start with it (prior
+// knowledge, as grpc-go does with insecure credentials), to /lunchvoting.v1.LunchVoting/<method>. Each message is
+// protobuf behind a five-byte prefix, and how the call went comes last, in grpc-status and grpc-message trailers.
+// There's no crate for either here, so both are done by hand, only as far as these messages need: strings, int64s,
+// bools, repeated fields, maps and messages in messages. Each call is answered by the endpoint it stands for, asked on
+// the caller's behalf with the caller's Authorization and Accept-Language metadata, the way GraphQL does it (see
+// graphql.rs), so the checks and errors are the endpoints' own, with their HTTP statuses turned into the matching
+// gRPC codes. A vote goes in the audit log as the POST /vote it became. StreamResults sends the results at once,
+// then again each time a refresh (see refresh.rs) changes them. Compressed messages and deadlines aren't supported
+// https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+// https://protobuf.dev/programming-guides/encoding/
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Path, Request, State};
+use axum::http::header::{ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{Extensions, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use http_body::Frame;
+use serde_json::{json, Map, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tower::ServiceExt;
+
+use crate::refresh::Cause;
+use crate::{audit, s3, AppState};
+
+// Every call's path starts with the service's full name; ROUTE is the same, with the method as a parameter
+pub const SERVICE: &str = "/lunchvoting.v1.LunchVoting/";
+pub const ROUTE: &str = "/lunchvoting.v1.LunchVoting/:method";
+
+// gRPC's own default limit on a message a server takes
+const MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+
+// The status codes used here
+// https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+const OK: u8 = 0;
+const INVALID_ARGUMENT: u8 = 3;
+const NOT_FOUND: u8 = 5;
+const PERMISSION_DENIED: u8 = 7;
+const RESOURCE_EXHAUSTED: u8 = 8;
+const FAILED_PRECONDITION: u8 = 9;
+const UNIMPLEMENTED: u8 = 12;
+const INTERNAL: u8 = 13;
+const UNAVAILABLE: u8 = 14;
+const UNAUTHENTICATED: u8 = 16;
+
+// How a call went: a code, and a message when it failed
+struct Status(u8, String);
+
+// POST /lunchvoting.v1.LunchVoting/:method
+pub async fn serve(State(state): State<AppState>, Path(method): Path<String>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    // The only thing answered with an HTTP status rather than a gRPC one, as the protocol asks
+    let content_type = parts.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    let subtype = content_type.split(';').next().unwrap_or_default().trim();
+    if !matches!(subtype, "application/grpc" | "application/grpc+proto") {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    }
+    let message = match to_bytes(body, MAX_MESSAGE_BYTES + 5).await {
+        Ok(bytes) => bytes,
+        Err(_) => return fail(Status(RESOURCE_EXHAUSTED, "the message is too large".to_string())),
+    };
+    let message = match unframe(&message) {
+        Ok(message) => message,
+        Err(status) => return fail(status),
+    };
+
+    // The caller's own credentials and language go along with every request made for them
+    let mut headers = HeaderMap::new();
+    for name in [AUTHORIZATION, ACCEPT_LANGUAGE] {
+        if let Some(value) = parts.headers.get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    // The audit log goes around the endpoints here, so a vote is logged with the voter's name and how it went
+    let router = crate::routes()
+        .layer(axum::middleware::from_fn_with_state(state.clone(), audit::record))
+        .with_state(state.clone());
+    let caller = Caller { router, headers, extensions: parts.extensions };
+    let answered = match method.as_str() {
+        "CastVote" => caller.cast_vote(message).await,
+        "GetResults" => match results_uri(message) {
+            Ok(uri) => caller.ask(Method::GET, &uri, None).await.map(|value| results(&value)),
+            Err(status) => Err(status),
+        },
+        "StreamResults" => return stream_results(state, caller, message).await,
+        _ => Err(Status(UNIMPLEMENTED, format!("there's no method {method} on LunchVoting"))),
+    };
+    match answered {
+        Ok(reply) => respond(stream::iter([data(&reply), trailers(Status(OK, String::new()))]).boxed()),
+        Err(status) => fail(status),
+    }
+}
+
+// The results once, then whenever a refresh for the same poll changes them, until the client hangs up. Failing to
+// get them the first time fails the call outright; failing later ends the stream with the error
+async fn stream_results(state: AppState, caller: Caller, message: &[u8]) -> Response {
+    let (uri, watched) = match (results_uri(message), poll_id(message)) {
+        (Ok(uri), Ok(watched)) => (uri, watched),
+        (Err(status), _) | (_, Err(status)) => return fail(status),
+    };
+    let receiver = state.refreshes.subscribe();
+    let first = match caller.ask(Method::GET, &uri, None).await {
+        Ok(value) => results(&value),
+        Err(status) => return fail(status),
+    };
+    let frames = stream::unfold(Some((caller, receiver, first.clone())), move |streaming| {
+        let (uri, watched) = (uri.clone(), watched.clone());
+        async move {
+            let (caller, mut receiver, last) = streaming?;
+            loop {
+                match receiver.recv().await {
+                    Ok(refresh) if refresh.cause != Cause::Missed && refresh.poll_id != watched => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Some((trailers(Status(OK, String::new())), None)),
+                }
+                let next = match caller.ask(Method::GET, &uri, None).await {
+                    Ok(value) => results(&value),
+                    Err(status) => return Some((trailers(status), None)),
+                };
+                if next != last {
+                    return Some((data(&next), Some((caller, receiver, next))));
+                }
+            }
+        }
+    });
+    respond(stream::once(async move { data(&first) }).chain(frames).boxed())
+}
+
+// Where the calls are answered, and on whose behalf
+struct Caller {
+    router: Router,
+    headers: HeaderMap,
+    extensions: Extensions,
+}
+
+impl Caller {
+    // The endpoint's JSON, or its error as a gRPC status
+    async fn ask(&self, method: Method, uri: &str, body: Option<Value>) -> Result<Value, Status> {
+        let mut request = Request::new(body.map_or_else(Body::empty, |body| Body::from(body.to_string())));
+        *request.method_mut() = method;
+        *request.uri_mut() = uri.parse().map_err(|_| Status(INVALID_ARGUMENT, format!("{uri} isn't a path")))?;
+        *request.headers_mut() = self.headers.clone();
+        request.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        *request.extensions_mut() = self.extensions.clone();
+        let response = self.router.clone().oneshot(request).await.unwrap_or_else(|never| match never {});
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.map_err(|err| Status(INTERNAL, err.to_string()))?;
+        let value: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        if !status.is_success() {
+            // The endpoint's own error message, its problem's detail, with its status for want of one
+            let message = value["detail"].as_str().map_or_else(|| status.to_string(), str::to_string);
+            return Err(Status(code(status), message));
+        }
+        Ok(value)
+    }
+
+    // CastVoteRequest to POST /vote's JSON, and the receipt back to a Receipt
+    async fn cast_vote(&self, message: &[u8]) -> Result<Vec<u8>, Status> {
+        // Every ballot has a voter and, but for ranked and quadratic ones, a restaurant: left out, they're as good as
+        // empty, which /vote has its own message for
+        let mut vote = Map::new();
+        vote.insert("voter_name".to_string(), json!(""));
+        vote.insert("restaurant_name".to_string(), json!(""));
+        vote.insert("channel".to_string(), json!("grpc"));
+        let mut ranking = Vec::new();
+        let mut allocation = Map::new();
+        for (field, wire) in fields(message)? {
+            let name = match field {
+                1 => "voter_name",
+                2 => "restaurant_name",
+                3 => "poll_id",
+                4 => "backup_restaurant_name",
+                7 => "comment",
+                8 => "invitation",
+                5 => {
+                    ranking.push(json!(text(wire)?));
+                    continue;
+                }
+                6 => {
+                    let (key, value) = entry(wire)?;
+                    allocation.insert(key, json!(value));
+                    continue;
+                }
+                // Fields this server doesn't know of are skipped, as protobuf does
+                _ => continue,
+            };
+            vote.insert(name.to_string(), json!(text(wire)?));
+        }
+        if !ranking.is_empty() {
+            vote.insert("ranking".to_string(), Value::Array(ranking));
+        }
+        if !allocation.is_empty() {
+            vote.insert("allocation".to_string(), Value::Object(allocation));
+        }
+
+        let receipt = self.ask(Method::POST, "/vote", Some(Value::Object(vote))).await?;
+        let mut reply = Message::default();
+        reply.int64(1, receipt["sequence"].as_i64().unwrap_or_default());
+        let names = [(2, "poll_id"), (3, "voter_name"), (4, "restaurant_name"), (5, "backup_restaurant_name")];
+        for (field, name) in names {
+            reply.string(field, receipt[name].as_str().unwrap_or_default());
+        }
+        for name in strings(&receipt["ranking"]) {
+            reply.string(6, name);
+        }
+        for (key, value) in receipt["allocation"].as_object().into_iter().flatten() {
+            let mut entry = Message::default();
+            entry.string(1, key);
+            entry.int64(2, value.as_i64().unwrap_or_default());
+            reply.message(7, entry);
+        }
+        for (field, name) in [(8, "cast_at"), (9, "hash"), (10, "signature")] {
+            reply.string(field, receipt[name].as_str().unwrap_or_default());
+        }
+        Ok(reply.0)
+    }
+}
+
+// The endpoint a ResultsRequest is answered by: the poll's results, or the loose votes'
+fn results_uri(message: &[u8]) -> Result<String, Status> {
+    let mut uri = match poll_id(message)? {
+        Some(poll_id) => format!("/polls/{}/results", s3::encode(&poll_id, false)),
+        None => "/results".to_string(),
+    };
+    for (field, wire) in fields(message)? {
+        if field == 2 {
+            uri = format!("{uri}?tiebreak={}", s3::encode(&text(wire)?, false));
+        }
+    }
+    Ok(uri)
+}
+
+fn poll_id(message: &[u8]) -> Result<Option<String>, Status> {
+    let mut poll_id = None;
+    for (field, wire) in fields(message)? {
+        if field == 1 {
+            poll_id = Some(text(wire)?).filter(|id| !id.is_empty());
+        }
+    }
+    Ok(poll_id)
+}
+
+// The results' JSON as a Results message
+fn results(value: &Value) -> Vec<u8> {
+    let int = |value: &Value| value.as_i64().unwrap_or_default();
+    let mut results = Message::default();
+    for restaurant in value["votes"].as_array().into_iter().flatten() {
+        let mut result = Message::default();
+        result.string(1, restaurant["name"].as_str().unwrap_or_default());
+        for voter in strings(&restaurant["voters"]) {
+            result.string(2, voter);
+        }
+        for voter in strings(&restaurant["backup_voters"]) {
+            result.string(3, voter);
+        }
+        result.string(4, restaurant["cuisine"].as_str().unwrap_or_default());
+        result.int64(5, int(&restaurant["price_tier"]));
+        result.int64(6, int(&restaurant["walking_minutes"]));
+        result.int64(7, int(&restaurant["borda_score"]));
+        result.int64(8, int(&restaurant["effective_votes"]));
+        results.message(1, result);
+    }
+    for name in strings(&value["unavailable"]) {
+        results.string(2, name);
+    }
+    for name in strings(&value["tiebreak"]["tied"]) {
+        results.string(3, name);
+    }
+    results.int64(4, int(&value["abstentions"]));
+    results.int64(5, int(&value["headcount"]["coming"]));
+    results.int64(6, value["hidden"].as_bool().unwrap_or_default() as i64);
+    results.0
+}
+
+fn strings(value: &Value) -> impl Iterator<Item = &str> {
+    value.as_array().into_iter().flatten().filter_map(Value::as_str)
+}
+
+// The messages as they go out, one HTTP/2 frame each, then the trailers
+fn respond(frames: BoxStream<'static, Frame<Bytes>>) -> Response {
+    ([(CONTENT_TYPE, "application/grpc")], Body::new(Frames(frames))).into_response()
+}
+
+// A call that failed before it sent anything: the status goes in the headers, with no body or trailers after them
+fn fail(status: Status) -> Response {
+    let mut response = ([(CONTENT_TYPE, "application/grpc")], Body::empty()).into_response();
+    response.headers_mut().extend(status.headers());
+    response
+}
+
+// A message behind its prefix: 0 for uncompressed, then its length as four big-endian bytes
+fn data(message: &[u8]) -> Frame<Bytes> {
+    let mut framed = Vec::with_capacity(5 + message.len());
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    Frame::data(Bytes::from(framed))
+}
+
+fn trailers(status: Status) -> Frame<Bytes> {
+    Frame::trailers(status.headers())
+}
+
+// A unary call's one message, out of its prefix
+fn unframe(bytes: &[u8]) -> Result<&[u8], Status> {
+    let Some((prefix, message)) = bytes.split_first_chunk::<5>() else {
+        return Err(Status(INVALID_ARGUMENT, "the call has no message".to_string()));
+    };
+    if prefix[0] != 0 {
+        return Err(Status(UNIMPLEMENTED, "compressed messages aren't supported".to_string()));
+    }
+    let length = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+    if length != message.len() {
+        return Err(Status(INVALID_ARGUMENT, "a call here takes exactly one message".to_string()));
+    }
+    Ok(message)
+}
+
+impl Status {
+    // grpc-message is percent-encoded, everything outside printable ASCII and % itself, so any text fits in a header
+    fn headers(self) -> HeaderMap {
+        let Status(code, message) = self;
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-status", HeaderValue::from(u16::from(code)));
+        if !message.is_empty() {
+            let encoded: String = message
+                .bytes()
+                .map(|byte| match byte {
+                    b' '..=b'~' if byte != b'%' => (byte as char).to_string(),
+                    _ => format!("%{byte:02X}"),
+                })
+                .collect();
+            headers.insert("grpc-message", HeaderValue::from_str(&encoded).expect("printable ASCII is a header"));
+        }
+        headers
+    }
+}
+
+// The gRPC code for an endpoint's HTTP status
+// https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md
+fn code(status: StatusCode) -> u8 {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => INVALID_ARGUMENT,
+        StatusCode::UNAUTHORIZED => UNAUTHENTICATED,
+        StatusCode::FORBIDDEN => PERMISSION_DENIED,
+        StatusCode::NOT_FOUND => NOT_FOUND,
+        // A poll that isn't open, a seal that's broken: the ballot's fine, the poll isn't ready for it
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_REQUIRED => FAILED_PRECONDITION,
+        StatusCode::TOO_MANY_REQUESTS => RESOURCE_EXHAUSTED,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => UNAVAILABLE,
+        _ => INTERNAL,
+    }
+}
+
+// A body sending the frames as they come; axum's own bodies can't send trailers
+// https://docs.rs/http-body/1.0.0/http_body/trait.Body.html
+struct Frames(BoxStream<'static, Frame<Bytes>>);
+
+impl http_body::Body for Frames {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.get_mut().0.poll_next_unpin(cx).map(|frame| frame.map(Ok))
+    }
+}
+
+// Protobuf on the way out: each field is its number and wire type, then its value. Fields at their defaults, empty
+// strings and zeros, are left out, as proto3 does
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn int64(&mut self, field: u64, value: i64) {
+        if value != 0 {
+            self.varint(field << 3);
+            self.varint(value as u64);
+        }
+    }
+
+    fn string(&mut self, field: u64, text: &str) {
+        if !text.is_empty() {
+            self.bytes(field, text.as_bytes());
+        }
+    }
+
+    // Always written, even empty: these are the items of repeated fields and maps
+    fn message(&mut self, field: u64, message: Message) {
+        self.bytes(field, &message.0);
+    }
+
+    fn bytes(&mut self, field: u64, bytes: &[u8]) {
+        self.varint(field << 3 | 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+// Protobuf on the way in: a field's value by its wire type. Fixed-size numbers aren't in any message here, and are
+// only read past
+enum Wire<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+// A message's fields in the order they came; a repeated field comes once for each of its items
+fn fields(mut bytes: &[u8]) -> Result<Vec<(u64, Wire<'_>)>, Status> {
+    let malformed = || Status(INVALID_ARGUMENT, "the message isn't protobuf".to_string());
+    let mut fields = Vec::new();
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes).ok_or_else(malformed)?;
+        let wire = match key & 7 {
+            0 => Wire::Varint(varint(&mut bytes).ok_or_else(malformed)?),
+            2 => {
+                let length = varint(&mut bytes).ok_or_else(malformed)? as usize;
+                let (value, rest) = bytes.split_at_checked(length).ok_or_else(malformed)?;
+                bytes = rest;
+                Wire::Bytes(value)
+            }
+            wire @ (1 | 5) => {
+                bytes = bytes.get(if wire == 1 { 8 } else { 4 }..).ok_or_else(malformed)?;
+                Wire::Fixed
+            }
+            _ => return Err(malformed()),
+        };
+        fields.push((key >> 3, wire));
+    }
+    Ok(fields)
+}
+
+// Seven bits to a byte, least significant first, the top bit set on all but the last. Ten bytes hold 64 bits with
+// one to spare in the last, so a last byte with more than that, or an eleventh, isn't a varint
+fn varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        if shift == 63 && byte > 1 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn text(wire: Wire) -> Result<String, Status> {
+    match wire {
+        Wire::Bytes(bytes) => String::from_utf8(bytes.to_vec())
+            .map_err(|_| Status(INVALID_ARGUMENT, "a string in the message isn't UTF-8".to_string())),
+        _ => Err(Status(INVALID_ARGUMENT, "a string in the message isn't one".to_string())),
+    }
+}
+
+// A map's entry: its key as field 1, its value as field 2
+fn entry(wire: Wire) -> Result<(String, i64), Status> {
+    let Wire::Bytes(bytes) = wire else {
+        return Err(Status(INVALID_ARGUMENT, "a map in the message isn't one".to_string()));
+    };
+    let (mut key, mut value) = (String::new(), 0);
+    for (field, wire) in fields(bytes)? {
+        match (field, wire) {
+            (1, wire) => key = text(wire)?,
+            (2, Wire::Varint(varint)) => value = varint as i64,
+            _ => {}
+        }
+    }
+    Ok((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(write: impl FnOnce(&mut Message)) -> Vec<u8> {
+        let mut message = Message::default();
+        write(&mut message);
+        message.0
+    }
+
+    fn read(mut bytes: &[u8]) -> Option<u64> {
+        let value = varint(&mut bytes);
+        assert!(value.is_none() || bytes.is_empty(), "the varint left bytes behind");
+        value
+    }
+
+    #[test]
+    fn varints_are_seven_bits_to_a_byte() {
+        for (value, bytes) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (16_384, &[0x80, 0x80, 0x01]),
+            (u64::MAX, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+        ] {
+            assert_eq!(written(|message| message.varint(value)), bytes, "{value}");
+            assert_eq!(read(bytes), Some(value), "{value}");
+        }
+    }
+
+    #[test]
+    fn a_negative_int64_is_ten_bytes() {
+        let bytes = written(|message| message.int64(1, -1));
+        assert_eq!(bytes, [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        let fields = fields(&bytes).ok().unwrap();
+        assert!(matches!(fields[..], [(1, Wire::Varint(value))] if value as i64 == -1));
+        // Zero is the default, and left out
+        assert!(written(|message| message.int64(1, 0)).is_empty());
+    }
+
+    #[test]
+    fn truncated_and_overlong_varints_are_refused() {
+        assert_eq!(read(&[]), None);
+        assert_eq!(read(&[0x80]), None);
+        assert_eq!(read(&[0xff, 0xff, 0xff]), None);
+        // An eleventh byte, and a tenth with more than the one bit left to fill
+        assert_eq!(read(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x81, 0x00]), None);
+        assert_eq!(read(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]), None);
+        // In a message they're malformed rather than a panic
+        assert!(fields(&[0x08]).is_err());
+        assert!(fields(&[0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+        assert!(fields(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn map_entries_read_back_from_what_is_written() {
+        let mut entry_message = Message::default();
+        entry_message.string(1, "Thai Palace");
+        entry_message.int64(2, 300);
+        let bytes = written(|message| message.message(4, entry_message));
+        assert_eq!(bytes, b"\x22\x10\x0a\x0bThai Palace\x10\xac\x02");
+        let mut fields = fields(&bytes).ok().unwrap();
+        assert_eq!(fields.len(), 1);
+        let (field, wire) = fields.remove(0);
+        assert_eq!(field, 4);
+        assert_eq!(entry(wire).ok(), Some(("Thai Palace".to_string(), 300)));
+        // An entry at its defaults is still sent, and empty
+        assert_eq!(written(|message| message.message(4, Message::default())), [0x22, 0x00]);
+        assert_eq!(entry(Wire::Bytes(&[])).ok(), Some((String::new(), 0)));
+        assert!(entry(Wire::Varint(1)).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_read_past() {
+        // field 1 = "a", then unknown fields 9 (fixed64), 10 (fixed32), 11 (bytes) and 12 (varint), then field 2 = 5
+        let bytes = [
+            0x0a, 0x01, b'a', 0x49, 1, 2, 3, 4, 5, 6, 7, 8, 0x55, 1, 2, 3, 4, 0x5a, 0x02, 0xff, 0xff, 0x60, 0x96, 0x01,
+            0x10, 0x05,
+        ];
+        let fields = fields(&bytes).ok().unwrap();
+        let numbers: Vec<u64> = fields.iter().map(|(field, _)| *field).collect();
+        assert_eq!(numbers, [1, 9, 10, 11, 12, 2]);
+        assert!(matches!(fields[1].1, Wire::Fixed));
+        assert!(matches!(fields[4].1, Wire::Varint(150)));
+        // An entry skips the fields it doesn't know too
+        assert_eq!(entry(Wire::Bytes(&bytes)).ok(), Some(("a".to_string(), 5)));
+    }
+
+    #[test]
+    fn truncated_fields_are_refused() {
+        assert!(fields(&[0x0a, 0x05, b'a']).is_err()); // a string shorter than its length
+        assert!(fields(&[0x49, 1, 2, 3]).is_err()); // a fixed64 with half its bytes
+        assert!(fields(&[0x0b]).is_err()); // wire type 3, a group, isn't read
+        assert!(fields(&[0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err());
+    }
+
+    #[test]
+    fn a_frame_is_its_flag_and_length_then_the_message() {
+        let frame = data(b"abc").into_data().ok().unwrap();
+        assert_eq!(&frame[..], [0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(unframe(&frame).ok(), Some(&b"abc"[..]));
+        assert_eq!(unframe(&[0, 0, 0, 0, 0]).ok(), Some(&[][..]));
+    }
+
+    #[test]
+    fn truncated_compressed_and_extra_frames_are_refused() {
+        let code = |bytes: &[u8]| unframe(bytes).err().map(|Status(code, _)| code);
+        assert_eq!(code(&[]), Some(INVALID_ARGUMENT));
+        assert_eq!(code(&[0, 0, 0]), Some(INVALID_ARGUMENT));
+        assert_eq!(code(&[0, 0, 0, 0, 3, b'a']), Some(INVALID_ARGUMENT));
+        assert_eq!(code(&[0, 0, 0, 0, 1, b'a', 0, 0, 0, 0, 0]), Some(INVALID_ARGUMENT));
+        assert_eq!(code(&[1, 0, 0, 0, 1, b'a']), Some(UNIMPLEMENTED));
+    }
+
+    #[test]
+    fn the_status_goes_in_the_trailers_with_its_message_percent_encoded() {
+        let trailers = trailers(Status(NOT_FOUND, "no poll \"Friday\" — 100% gone".to_string()));
+        let headers = trailers.into_trailers().ok().unwrap();
+        assert_eq!(headers["grpc-status"], "5");
+        assert_eq!(headers["grpc-message"], "no poll \"Friday\" %E2%80%94 100%25 gone");
+        let headers = Status(OK, String::new()).headers();
+        assert_eq!(headers["grpc-status"], "0");
+        assert!(!headers.contains_key("grpc-message"));
+    }
+}
@@ -0,0 +1,212 @@
+// gzip, for backups: DEFLATE with the fixed Huffman codes, which needs no code tables shipped alongside the data, and
+// LZ77 matches found through hash chains over the last 32 KiB. Not as small as zlib's best, but a database's pages
+// are mostly repetition, so it still shrinks a backup several times over. Everything is in one final block
+// https://www.rfc-editor.org/rfc/rfc1951
+// https://www.rfc-editor.org/rfc/rfc1952
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier places with the same three bytes are tried before settling for the best match so far
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+// The lengths and distances a match is coded with: the base of each code, and how many extra bits follow it
+const LENGTH_BASES: [usize; 29] =
+    [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u32; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] =
+    [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // The header: the magic bytes, DEFLATE, no flags, no timestamp, no extra flags, an unknown OS
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    let mut bits = BitWriter { out: &mut out, buffer: 0, count: 0 };
+    bits.write(1, 1); // the final block
+    bits.write(1, 2); // with the fixed codes
+    deflate(data, &mut bits);
+    bits.literal(256); // end of block
+    bits.flush();
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+fn deflate(data: &[u8], bits: &mut BitWriter) {
+    let mut chains = Chains { head: vec![usize::MAX; 1 << HASH_BITS], prev: vec![usize::MAX; WINDOW] };
+    let mut at = 0;
+    while at < data.len() {
+        let (length, distance) = chains.longest_match(data, at);
+        if length >= MIN_MATCH {
+            bits.length(length);
+            bits.distance(distance);
+            for skipped in at..at + length {
+                chains.insert(data, skipped);
+            }
+            at += length;
+        } else {
+            bits.literal(u16::from(data[at]));
+            chains.insert(data, at);
+            at += 1;
+        }
+    }
+}
+
+// head holds the latest place each hash of three bytes was seen, and prev the place before that with the same hash,
+// for every place in the window, so following prev from head runs back through the window
+struct Chains {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl Chains {
+    fn hash(data: &[u8], at: usize) -> usize {
+        let key = u32::from(data[at]) << 16 | u32::from(data[at + 1]) << 8 | u32::from(data[at + 2]);
+        (key.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, data: &[u8], at: usize) {
+        if at + MIN_MATCH <= data.len() {
+            let hash = Chains::hash(data, at);
+            self.prev[at % WINDOW] = self.head[hash];
+            self.head[hash] = at;
+        }
+    }
+
+    // The longest earlier run matching what starts at `at`, as its length and how far back it is
+    fn longest_match(&self, data: &[u8], at: usize) -> (usize, usize) {
+        if at + MIN_MATCH > data.len() {
+            return (0, 0);
+        }
+        let longest = MAX_MATCH.min(data.len() - at);
+        let (mut best_length, mut best_distance) = (0, 0);
+        let mut candidate = self.head[Chains::hash(data, at)];
+        for _ in 0..MAX_CHAIN {
+            // usize::MAX ends the chain, and so does a place that has fallen out of the window, whose slot in prev
+            // has since been taken by a later place
+            if candidate == usize::MAX || at - candidate > WINDOW {
+                break;
+            }
+            let length = data[candidate..].iter().zip(&data[at..at + longest]).take_while(|(a, b)| a == b).count();
+            if length > best_length {
+                (best_length, best_distance) = (length, at - candidate);
+                if length == longest {
+                    break;
+                }
+            }
+            let earlier = self.prev[candidate % WINDOW];
+            if earlier == usize::MAX || earlier >= candidate {
+                break;
+            }
+            candidate = earlier;
+        }
+        (best_length, best_distance)
+    }
+}
+
+// DEFLATE packs bits starting from the least significant; Huffman codes go in most significant bit first
+struct BitWriter<'a> {
+    out: &'a mut Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter<'_> {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn code(&mut self, code: u32, bits: u32) {
+        self.write(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    // A literal byte, the end of the block (256) or a length code (257 to 285), in the fixed codes
+    fn literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let index = LENGTH_BASES.iter().rposition(|&base| base <= length).expect("matches are at least 3 long");
+        self.literal(257 + index as u16);
+        self.write((length - LENGTH_BASES[index]) as u32, LENGTH_EXTRA[index]);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASES.iter().rposition(|&base| base <= distance).expect("distances are at least 1");
+        self.code(index as u32, 5);
+        self.write((distance - DISTANCE_BASES[index]) as u32, DISTANCE_EXTRA[index]);
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// The CRC-32 the gzip trailer checks the data with, a byte at a time
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A gzip stream around the DEFLATE stream `deflated`, with the trailer for `crc` and `length`
+    fn gzip(deflated: &[u8], crc: u32, length: u32) -> Vec<u8> {
+        let mut stream = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+        stream.extend_from_slice(deflated);
+        stream.extend_from_slice(&crc.to_le_bytes());
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    // Literals only: the same as zlib's with the fixed codes forced (Z_FIXED)
+    #[test]
+    fn compresses_literals_to_known_streams() {
+        assert_eq!(compress(b""), gzip(&[0x03, 0x00], 0, 0));
+        assert_eq!(compress(b"a"), gzip(&[0x4b, 0x04, 0x00], 0xe8b7_be43, 1));
+    }
+
+    // Worked out by hand from RFC 1951's fixed codes, and inflated back by zlib; zlib's own streams for these differ,
+    // as it looks for matches its own way
+    #[test]
+    fn repeats_come_out_as_matches() {
+        // "abc", then a match of 6 at distance 3
+        assert_eq!(compress(b"abcabcabc"), gzip(&[0x4b, 0x4c, 0x4a, 0x86, 0x20, 0x00], 0x462d_4818, 9));
+        // "hello ", then a match of 17 at distance 6, overlapping itself
+        let deflated = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc0, 0x20, 0x01];
+        assert_eq!(compress(b"hello hello hello hello"), gzip(&deflated, 0x8d3d_51e3, 23));
+    }
+}
@@ -0,0 +1,101 @@
+// The public-holiday calendar. Seeded from the HOLIDAYS setting at startup and editable by admins;
+// the scheduler skips holidays and the opening-hours check treats them as closing days
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::AppState;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Holiday {
+    day: String, // YYYY-MM-DD
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct HolidayName {
+    name: String,
+}
+
+// Loads the configured holidays. A date SQLite can't read is a configuration mistake, so startup stops on it
+pub async fn seed(db: &SqlitePool, holidays: &[(String, String)]) -> Result<(), sqlx::Error> {
+    for (day, name) in holidays {
+        let Some(day) = normalize_day(db, day).await? else {
+            panic!("HOLIDAYS has an invalid date: {day}");
+        };
+        sqlx::query("INSERT OR REPLACE INTO holidays (day, name) VALUES (?, ?)")
+            .bind(day)
+            .bind(name)
+            .execute(db)
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn is_holiday(db: &SqlitePool, day: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM holidays WHERE day = ?)")
+        .bind(day)
+        .fetch_one(db)
+        .await
+}
+
+// date() returns the day as YYYY-MM-DD, or NULL for anything that isn't a date
+async fn normalize_day(db: &SqlitePool, day: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT date(?)").bind(day.trim()).fetch_one(db).await
+}
+
+// GET /holidays: upcoming holidays first, then past ones
+pub async fn list_holidays(State(state): State<AppState>) -> Result<Json<Vec<Holiday>>, ApiError> {
+    let holidays = sqlx::query_as::<_, Holiday>(
+        "SELECT day, name FROM holidays ORDER BY day < date('now', 'localtime'), day",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(holidays))
+}
+
+// PUT /holidays/:day (admin): adds a holiday, or renames it if it's already on the calendar
+pub async fn put_holiday(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(day): Path<String>,
+    Json(req): Json<HolidayName>,
+) -> Result<Json<Holiday>, ApiError> {
+    let day = normalize_day(&state.db, &day)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest(format!("{day} is not a YYYY-MM-DD date")))?;
+    let name = req.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("holiday name must not be empty".to_string()));
+    }
+    let holiday = sqlx::query_as::<_, Holiday>(
+        "INSERT INTO holidays (day, name) VALUES (?, ?)
+        ON CONFLICT (day) DO UPDATE SET name = excluded.name
+        RETURNING day, name",
+    )
+    .bind(day)
+    .bind(name)
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(holiday))
+}
+
+// DELETE /holidays/:day (admin)
+pub async fn delete_holiday(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(day): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM holidays WHERE day = date(?)")
+        .bind(day.trim())
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{day} is not a holiday")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
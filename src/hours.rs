@@ -0,0 +1,112 @@
+// Weekly opening hours per restaurant, and the check for whether a restaurant is open at a poll's lunch time
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::public_ids::RestaurantId;
+use crate::AppState;
+
+// A SQL condition that is true when restaurant r is open at a given local time, 'YYYY-MM-DD HH:MM', which is
+// bound once. Restaurants with no hours recorded count as open, and a period that closes before it opens runs past
+// midnight, so Friday 18:00-02:00 covers early Saturday too. On a public holiday only restaurants marked
+// open_on_holidays keep their usual hours; for the rest, recorded hours say nothing about holidays
+// https://www.sqlite.org/lang_datefunc.html
+pub const OPEN_AT_SQL: &str = "EXISTS (
+    SELECT 1 FROM (SELECT CAST(strftime('%w', at) AS INTEGER) AS weekday, strftime('%H:%M', at) AS at, date(at) AS day
+        FROM (SELECT ? AS at)) slot
+    WHERE NOT EXISTS (SELECT 1 FROM opening_hours h WHERE h.restaurant_id = r.id)
+    OR (
+        (r.open_on_holidays OR NOT EXISTS (SELECT 1 FROM holidays WHERE holidays.day = slot.day))
+        AND EXISTS (
+            SELECT 1 FROM opening_hours h
+            WHERE h.restaurant_id = r.id AND (
+                (h.weekday = slot.weekday AND h.opens <= slot.at AND (slot.at < h.closes OR h.closes <= h.opens))
+                OR (h.weekday = (slot.weekday + 6) % 7 AND h.closes <= h.opens AND slot.at < h.closes)
+            )
+        )
+    )
+)";
+
+// Indexed like SQLite's strftime('%w'), so the position of a name is its weekday number
+pub const WEEKDAYS: [&str; 7] = ["sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday"];
+
+pub fn parse_weekday(name: &str) -> Option<usize> {
+    WEEKDAYS.iter().position(|day| *day == name.trim().to_lowercase())
+}
+
+// Checks a 24-hour 'HH:MM' time and returns it zero-padded, so the string comparisons in OPEN_AT_SQL hold
+pub fn parse_time(time: &str) -> Option<String> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60 && minutes.to_string().len() <= 2).then(|| format!("{hours:02}:{minutes:02}"))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OpeningPeriod {
+    weekday: String, // "monday" through "sunday"
+    opens: String,
+    closes: String,
+}
+
+// GET /restaurants/:id/hours
+pub async fn get_hours(
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+) -> Result<Json<Vec<OpeningPeriod>>, ApiError> {
+    // Listed Monday first, the way a week reads on a restaurant's door
+    let rows: Vec<(i64, String, String)> = sqlx::query_as(
+        "SELECT weekday, opens, closes FROM opening_hours WHERE restaurant_id = ? ORDER BY (weekday + 6) % 7, opens",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    let periods = rows
+        .into_iter()
+        .map(|(weekday, opens, closes)| OpeningPeriod {
+            weekday: WEEKDAYS[weekday as usize].to_string(),
+            opens,
+            closes,
+        })
+        .collect();
+    Ok(Json(periods))
+}
+
+// PUT /restaurants/:id/hours (admin): replaces the whole week. An empty list clears the hours,
+// which makes the restaurant count as always open again
+pub async fn set_hours(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    Json(periods): Json<Vec<OpeningPeriod>>,
+) -> Result<Json<Vec<OpeningPeriod>>, ApiError> {
+    let mut parsed = Vec::new();
+    for period in &periods {
+        let weekday = parse_weekday(&period.weekday).ok_or_else(|| ApiError::BadRequest(format!("unknown weekday {}", period.weekday)))?;
+        let (Some(opens), Some(closes)) = (parse_time(&period.opens), parse_time(&period.closes)) else {
+            return Err(ApiError::BadRequest("opens and closes must be 24-hour HH:MM times".to_string()));
+        };
+        parsed.push((weekday as i64, opens, closes));
+    }
+
+    // A transaction makes the delete and the inserts one change: readers see the old week or the new one, never none
+    // https://docs.rs/sqlx/latest/sqlx/struct.Transaction.html
+    let mut tx = state.db.begin().await?;
+    sqlx::query("DELETE FROM opening_hours WHERE restaurant_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    for (weekday, opens, closes) in parsed {
+        sqlx::query("INSERT INTO opening_hours (restaurant_id, weekday, opens, closes) VALUES (?, ?, ?, ?)")
+            .bind(id)
+            .bind(weekday)
+            .bind(opens)
+            .bind(closes)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    get_hours(State(state), RestaurantId(id)).await
+}
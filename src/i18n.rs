@@ -0,0 +1,307 @@
+// Messages in the reader's language: English, German and Spanish so far. The text lives in Fluent-style catalogs
+// under locales/, one per language, built into the binary: "id = text with { $variable }", indented lines carrying
+// a message on, blank ones between them kept as paragraph breaks. Only that much of Fluent is read, with no
+// selectors or terms. API responses are in the language the request's Accept-Language prefers, of the ones there
+// are; notifications and winner announcements in their team's locale (PATCH /teams/:id), or the voter's team's for
+// messages that aren't about a team's poll, and English otherwise. Errors are raised with the error-* message they
+// are, and what goes into it (see error::Message), and only written out once the response is, in the language the
+// request asked for; an error given in words of its own stays in English
+// https://projectfluent.org/fluent/guide/
+// https://www.rfc-editor.org/rfc/rfc9110#name-accept-language
+use axum::extract::Request;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use crate::error::{ApiError, Message};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::En, Locale::De, Locale::Es];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Es => "es",
+        }
+    }
+
+    pub const fn catalog(self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.ftl"),
+            Locale::De => include_str!("../locales/de.ftl"),
+            Locale::Es => include_str!("../locales/es.ftl"),
+        }
+    }
+
+    // A language tag like de-CH is German; the region doesn't change anything yet
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        Locale::ALL.into_iter().find(|locale| locale.code() == language)
+    }
+}
+
+// A locale given in a request body, "de" or "de-DE"; an empty one is None, for clearing it
+pub fn parse_setting(tag: &str) -> Result<Option<Locale>, ApiError> {
+    if tag.trim().is_empty() {
+        return Ok(None);
+    }
+    match Locale::parse(tag) {
+        Some(locale) => Ok(Some(locale)),
+        None => Err(Message::not_locale(tag).into()),
+    }
+}
+
+// A message's text, and the variables that go into it
+#[derive(Debug)]
+enum Piece {
+    Text(String),
+    Variable(String),
+}
+
+type Catalog = BTreeMap<String, Vec<Piece>>;
+
+// The catalogs, read the first time a message is needed
+static CATALOGS: LazyLock<Vec<(Locale, Catalog)>> = LazyLock::new(|| {
+    Locale::ALL.into_iter().map(|locale| (locale, parse(locale.catalog()).into_iter().collect())).collect()
+});
+
+fn parse(catalog: &str) -> Vec<(String, Vec<Piece>)> {
+    let mut messages: Vec<(String, String)> = Vec::new();
+    let mut blank_lines = 0;
+    for line in catalog.lines() {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        match (line.starts_with([' ', '\t']), messages.last_mut()) {
+            (true, Some((_, text))) => {
+                if !text.is_empty() {
+                    text.push_str(&"\n".repeat(blank_lines + 1));
+                }
+                text.push_str(line.trim());
+            }
+            (true, None) => {}
+            (false, _) if line.starts_with('#') => {}
+            (false, _) => match line.split_once('=') {
+                Some((id, text)) => messages.push((id.trim().to_string(), text.trim().to_string())),
+                None => eprintln!("i18n: can't read the catalog line {line:?}"),
+            },
+        }
+        blank_lines = 0;
+    }
+    messages.into_iter().map(|(id, text)| (id, pieces(&text))).collect()
+}
+
+// "Hi { $voter }," is the text "Hi ", the variable voter and the text ","
+fn pieces(text: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            pieces.push(Piece::Text(rest[..start].to_string()));
+        }
+        let placeable = rest[start + 1..start + length].trim();
+        match placeable.strip_prefix('$') {
+            Some(variable) => pieces.push(Piece::Variable(variable.trim().to_string())),
+            None => pieces.push(Piece::Text(placeable.trim_matches('"').to_string())),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest.to_string()));
+    }
+    pieces
+}
+
+fn lookup(locale: Locale, id: &str) -> Option<&'static [Piece]> {
+    let (_, catalog) = CATALOGS.iter().find(|(known, _)| *known == locale)?;
+    catalog.get(id).map(Vec::as_slice)
+}
+
+fn fill(pieces: &[Piece], args: &[(&str, &str)]) -> String {
+    let mut text = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(part) => text.push_str(part),
+            Piece::Variable(name) => {
+                text.push_str(args.iter().find(|(known, _)| known == name).map_or("", |(_, value)| value))
+            }
+        }
+    }
+    text
+}
+
+// The message `id` in `locale`, in English when it hasn't been translated, with `args` filled in
+pub fn text(locale: Locale, id: &str, args: &[(&str, &str)]) -> String {
+    match lookup(locale, id).or_else(|| lookup(Locale::En, id)) {
+        Some(pieces) => fill(pieces, args),
+        None => {
+            eprintln!("i18n: no message {id}");
+            id.to_string()
+        }
+    }
+}
+
+// Whether `catalog` has the message `id`, with `variables` and no others in it. A const fn, so error::Message can
+// check its messages against every catalog as the binary is built, and one that's missing or renamed, or has had a
+// variable added or dropped, doesn't compile. Reads the catalog the way parse does: an id at the start of a line,
+// then "=", its text running on over the indented lines after it
+pub const fn defines(catalog: &str, id: &str, variables: &[&str]) -> bool {
+    let (catalog, id) = (catalog.as_bytes(), id.as_bytes());
+    let mut line = 0;
+    while line < catalog.len() {
+        if starts_with(catalog, line, id) {
+            let mut at = line + id.len();
+            while at < catalog.len() && catalog[at] == b' ' {
+                at += 1;
+            }
+            if at < catalog.len() && catalog[at] == b'=' {
+                let mut end = at;
+                while end < catalog.len() && (catalog[end] != b'\n' || continues(catalog, end + 1)) {
+                    end += 1;
+                }
+                return has_variables(catalog, at, end, variables);
+            }
+        }
+        while line < catalog.len() && catalog[line] != b'\n' {
+            line += 1;
+        }
+        line += 1;
+    }
+    false
+}
+
+// Whether the line starting at `at` carries the message before it on
+const fn continues(catalog: &[u8], at: usize) -> bool {
+    at < catalog.len() && (catalog[at] == b' ' || catalog[at] == b'\t')
+}
+
+const fn starts_with(text: &[u8], at: usize, prefix: &[u8]) -> bool {
+    if at + prefix.len() > text.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < prefix.len() {
+        if text[at + i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// Whether the text from `start` to `end` has each of `variables` as a { $variable }, and no other
+const fn has_variables(catalog: &[u8], start: usize, end: usize, variables: &[&str]) -> bool {
+    let mut found = 0;
+    let mut at = start;
+    while at < end {
+        if catalog[at] == b'$' {
+            found += 1;
+        }
+        at += 1;
+    }
+    if found != variables.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < variables.len() {
+        let variable = variables[i].as_bytes();
+        let mut at = start;
+        let mut there = false;
+        while at < end && !there {
+            let after = at + 1 + variable.len();
+            there = catalog[at] == b'$'
+                && starts_with(catalog, at + 1, variable)
+                && after < end
+                && !(catalog[after].is_ascii_alphanumeric() || catalog[after] == b'_' || catalog[after] == b'-');
+            at += 1;
+        }
+        if !there {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// The language the request asked for with Accept-Language, while its handler runs
+tokio::task_local! {
+    static ACCEPTED: Option<Locale>;
+}
+
+pub fn accepted() -> Option<Locale> {
+    ACCEPTED.try_with(|locale| *locale).ok().flatten()
+}
+
+// Middleware: reads Accept-Language, "de-CH, es;q=0.9, en;q=0.8", to the locale with the highest weight
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let header = request.headers().get(ACCEPT_LANGUAGE).and_then(|value| value.to_str().ok());
+    let locale = header.and_then(preferred);
+    ACCEPTED.scope(locale, next.run(request)).await
+}
+
+fn preferred(header: &str) -> Option<Locale> {
+    let mut best: Option<(Locale, f64)> = None;
+    for range in header.split(',') {
+        let mut parts = range.split(';');
+        let Some(locale) = parts.next().and_then(Locale::parse) else {
+            continue;
+        };
+        let weight = parts
+            .find_map(|part| part.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |weight| weight.trim().parse::<f64>().ok());
+        match weight {
+            Some(weight) if weight > 0.0 && best.is_none_or(|(_, best)| weight > best) => best = Some((locale, weight)),
+            _ => {}
+        }
+    }
+    best.map(|(locale, _)| locale)
+}
+
+// The locale to write to a voter in: that of the team whose poll it's about, or else of a team they're on
+pub async fn voter_locale(db: &SqlitePool, voter_key: &str, team_id: Option<&str>) -> Result<Locale, sqlx::Error> {
+    let locale: Option<Locale> = sqlx::query_scalar(
+        "SELECT locale FROM teams WHERE locale IS NOT NULL
+            AND (id IS ?1 OR id IN (SELECT team_id FROM team_members WHERE voter_key = ?2))
+        ORDER BY id IS ?1 DESC, id LIMIT 1",
+    )
+    .bind(team_id)
+    .bind(voter_key)
+    .fetch_optional(db)
+    .await?;
+    Ok(locale.unwrap_or_default())
+}
+
+// The locale a team's poll is announced in: its team's, English without one
+pub async fn team_locale(db: &SqlitePool, team_id: Option<&str>) -> Result<Locale, sqlx::Error> {
+    Ok(team_setting(db, team_id).await?.unwrap_or_default())
+}
+
+// The locale a list of names is sorted for (see names::collate): its team's, or else the one the request asked for
+pub async fn sorting_locale(db: &SqlitePool, team_id: Option<&str>) -> Result<Locale, sqlx::Error> {
+    Ok(team_setting(db, team_id).await?.or_else(accepted).unwrap_or_default())
+}
+
+async fn team_setting(db: &SqlitePool, team_id: Option<&str>) -> Result<Option<Locale>, sqlx::Error> {
+    let locale: Option<Option<Locale>> =
+        sqlx::query_scalar("SELECT locale FROM teams WHERE id = ?").bind(team_id).fetch_optional(db).await?;
+    Ok(locale.flatten())
+}
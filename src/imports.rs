@@ -0,0 +1,103 @@
+// Shared plumbing for pulling restaurants in from outside directories. Each source module turns its API's
+// response into ImportedPlaces; this module decides whether each one is new or a restaurant we already have
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::geo::Coordinates;
+use crate::{fuzzy, names};
+
+pub struct ImportedPlace {
+    pub source: &'static str, // e.g. "google_places"; together with external_id identifies the outside record
+    pub external_id: String,
+    pub name: String,
+    pub address: Option<String>,
+    pub cuisine: Option<String>,
+    pub price_tier: Option<i64>,
+    pub distance_meters: Option<i64>,
+    pub location: Option<Coordinates>,
+}
+
+// What an import run did, returned to the admin who triggered it
+#[derive(Default, Serialize)]
+pub struct ImportSummary {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+// Saves one imported place and returns the id of the restaurant it ended up as. A place we've imported before,
+// or one whose name matches a registered restaurant, fills in that restaurant's missing details; anything the
+// team has already entered is left alone. Everything else becomes a new, approved restaurant
+pub async fn upsert(db: &SqlitePool, place: ImportedPlace, summary: &mut ImportSummary) -> Result<i64, sqlx::Error> {
+    let name = names::clean(&place.name);
+    let cuisine = place.cuisine.map(|c| names::clean(&c).to_lowercase());
+
+    // Restaurants in the trash count as already there: an import updates one rather than adding it a second time,
+    // and leaves it in the trash
+    let mut existing: Option<(i64, String)> = sqlx::query_as(
+        "SELECT r.id, r.name FROM restaurant_external_ids e JOIN restaurants r ON r.id = e.restaurant_id
+        WHERE e.source = ? AND e.external_id = ?",
+    )
+    .bind(place.source)
+    .bind(&place.external_id)
+    .fetch_optional(db)
+    .await?;
+    if existing.is_none() {
+        let key = fuzzy::match_key(&name);
+        let registered: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM restaurants").fetch_all(db).await?;
+        existing = registered
+            .into_iter()
+            .find(|(_, registered)| fuzzy::match_key(registered) == key);
+    }
+
+    let id = match existing {
+        Some((id, registered_name)) => {
+            sqlx::query(
+                "UPDATE restaurants SET
+                    address = COALESCE(address, ?),
+                    cuisine = COALESCE(cuisine, ?),
+                    price_tier = COALESCE(price_tier, ?),
+                    distance_meters = COALESCE(distance_meters, ?),
+                    latitude = COALESCE(latitude, ?),
+                    longitude = COALESCE(longitude, ?)
+                WHERE id = ?",
+            )
+            .bind(place.address)
+            .bind(cuisine)
+            .bind(place.price_tier)
+            .bind(place.distance_meters)
+            .bind(place.location.map(|l| l.latitude))
+            .bind(place.location.map(|l| l.longitude))
+            .bind(id)
+            .execute(db)
+            .await?;
+            summary.updated.push(registered_name);
+            id
+        }
+        None => {
+            let id = sqlx::query_scalar(
+                "INSERT INTO restaurants (name, address, cuisine, price_tier, distance_meters, latitude, longitude)
+                VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id",
+            )
+            .bind(&name)
+            .bind(place.address)
+            .bind(cuisine)
+            .bind(place.price_tier)
+            .bind(place.distance_meters)
+            .bind(place.location.map(|l| l.latitude))
+            .bind(place.location.map(|l| l.longitude))
+            .fetch_one(db)
+            .await?;
+            summary.created.push(name);
+            id
+        }
+    };
+
+    // INSERT OR IGNORE: linking a place that is already linked is a no-op
+    sqlx::query("INSERT OR IGNORE INTO restaurant_external_ids (restaurant_id, source, external_id) VALUES (?, ?, ?)")
+        .bind(id)
+        .bind(place.source)
+        .bind(&place.external_id)
+        .execute(db)
+        .await?;
+    Ok(id)
+}
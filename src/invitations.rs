@@ -0,0 +1,218 @@
+// Invitation links: a token that lets a guest who isn't on a poll's roster (a team poll's members, a runoff's voters)
+// vote in that one poll anyway. The token is the invitation's id signed with the receipt key, so it can't be guessed
+// or moved to another poll, and it isn't stored. Each invitation is one guest's: it's either minted for a name, or
+// the first name to vote with it claims it. It lasts until the poll closes, or until its own expires_at if that's
+// sooner, and an admin can revoke it before then
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::{self, Admin};
+use crate::error::{ApiError, Message};
+use crate::offices::POLL_NOW_SQL;
+use crate::polls::{self, Poll, PollStatus};
+use crate::public_ids::PollId;
+use crate::receipts::hex;
+use crate::{names, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Invitation {
+    id: i64,
+    #[sqlx(skip)]
+    token: String, // what the guest votes with, as "invitation" in POST /vote
+    poll_id: String, // the poll's public id
+    guest_name: Option<String>,
+    expires_at: Option<String>,
+    revoked_at: Option<String>,
+    used_at: Option<String>,
+    created_at: String,
+    #[serde(skip)]
+    guest_key: Option<String>,
+}
+
+const INVITATION_SELECT: &str = "SELECT i.id, p.public_id AS poll_id, i.guest_name, i.guest_key, i.expires_at,
+    i.revoked_at, i.used_at, i.created_at
+    FROM poll_invitations i JOIN polls p ON p.id = i.poll_id";
+
+#[derive(Deserialize)]
+pub struct NewInvitation {
+    guest_name: Option<String>,
+    expires_at: Option<String>, // the poll's local time, like its closes_at
+}
+
+// The prefix keeps an invitation's signature from ever passing for a receipt's, which share the key
+fn signature(state: &AppState, id: i64, poll_id: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key);
+    hex(hmac::sign(&key, format!("invitation:{id}:{poll_id}").as_bytes()).as_ref())
+}
+
+fn with_token(state: &AppState, mut invitation: Invitation) -> Invitation {
+    invitation.token = format!("{}.{}", invitation.id, signature(state, invitation.id, &invitation.poll_id));
+    invitation
+}
+
+// The invitation a token stands for, if its signature holds
+async fn from_token(state: &AppState, token: &str) -> Result<Option<Invitation>, sqlx::Error> {
+    let Some(id) = token.split_once('.').and_then(|(id, _)| id.parse::<i64>().ok()) else {
+        return Ok(None);
+    };
+    let Some(invitation) = find(&state.db, id).await? else {
+        return Ok(None);
+    };
+    let invitation = with_token(state, invitation);
+    Ok(auth::constant_time_eq(invitation.token.as_bytes(), token.as_bytes()).then_some(invitation))
+}
+
+async fn find(db: &SqlitePool, id: i64) -> Result<Option<Invitation>, sqlx::Error> {
+    sqlx::query_as::<_, Invitation>(&format!("{INVITATION_SELECT} WHERE i.id = ?")).bind(id).fetch_optional(db).await
+}
+
+// Why the invitation can't be used right now, if it can't
+async fn unusable(db: &SqlitePool, invitation: &Invitation) -> Result<Option<String>, sqlx::Error> {
+    if invitation.revoked_at.is_some() {
+        return Ok(Some("this invitation has been revoked".to_string()));
+    }
+    let (closed, expired): (bool, bool) = sqlx::query_as(&format!(
+        "SELECT status = 'closed', ? IS NOT NULL AND ? <= {POLL_NOW_SQL} FROM polls WHERE public_id = ?"
+    ))
+    .bind(&invitation.expires_at)
+    .bind(&invitation.expires_at)
+    .bind(&invitation.poll_id)
+    .fetch_one(db)
+    .await?;
+    Ok(match (closed, expired) {
+        (true, _) => Some("the poll this invitation is for has closed".to_string()),
+        (_, true) => Some("this invitation has expired".to_string()),
+        _ => None,
+    })
+}
+
+// Lets voter_name past the poll's roster with the invitation, or says why not. The first vote with an invitation
+// minted for anyone claims it for that voter
+pub async fn admit(state: &AppState, poll: &Poll, token: &str, voter_name: &str) -> Result<(), ApiError> {
+    let refused = |message: &str| ApiError::Forbidden(message.to_string());
+    let invitation = from_token(state, token).await?.ok_or_else(|| refused("that's not a valid invitation"))?;
+    if invitation.poll_id != poll.public_id {
+        return Err(refused("this invitation is for another poll"));
+    }
+    if let Some(reason) = unusable(&state.db, &invitation).await? {
+        return Err(refused(&reason));
+    }
+    let voter_key = names::fold(voter_name);
+    // The guest_key check in the WHERE clause makes claiming atomic, so two names can't both get in on one link
+    sqlx::query(
+        "UPDATE poll_invitations SET guest_name = COALESCE(guest_name, ?1), guest_key = COALESCE(guest_key, ?2),
+            used_at = COALESCE(used_at, CURRENT_TIMESTAMP)
+        WHERE id = ?3 AND (guest_key IS NULL OR guest_key = ?2)",
+    )
+    .bind(voter_name)
+    .bind(&voter_key)
+    .bind(invitation.id)
+    .execute(&state.db)
+    .await?;
+    let claimed = find(&state.db, invitation.id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    if claimed.guest_key.as_deref() != Some(voter_key.as_str()) {
+        return Err(ApiError::Forbidden(format!(
+            "this invitation is {}'s",
+            claimed.guest_name.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+// POST /polls/:id/invitations (admin): {"guest_name": "Sam from accounting", "expires_at": "2024-05-17 11:30"},
+// both optional; the response carries the token, which GET /polls/:id/invitations shows again
+pub async fn create_invitation(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewInvitation>,
+) -> Result<(StatusCode, Json<Invitation>), ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.status == PollStatus::Closed {
+        return Err(Message::poll_closed().into());
+    }
+    let guest_name = match &req.guest_name {
+        Some(name) => Some(names::canonical_voter_name(&state.db, name).await?).filter(|name| !name.is_empty()),
+        None => None,
+    };
+    let expires_at = match &req.expires_at {
+        Some(time) => {
+            let time = polls::local_time(&state.db, time).await?.ok_or_else(|| {
+                ApiError::BadRequest("expires_at must be a local time like 2024-05-17 12:30".to_string())
+            })?;
+            let past: bool = sqlx::query_scalar(&format!("SELECT ? <= {POLL_NOW_SQL} FROM polls WHERE id = ?"))
+                .bind(&time)
+                .bind(id)
+                .fetch_one(&state.db)
+                .await?;
+            if past {
+                return Err(ApiError::BadRequest("expires_at must be in the future".to_string()));
+            }
+            Some(time)
+        }
+        None => None,
+    };
+    let invitation_id: i64 = sqlx::query_scalar(
+        "INSERT INTO poll_invitations (poll_id, guest_name, guest_key, expires_at) VALUES (?, ?, ?, ?) RETURNING id",
+    )
+    .bind(id)
+    .bind(&guest_name)
+    .bind(guest_name.as_deref().map(names::fold))
+    .bind(expires_at)
+    .fetch_one(&state.db)
+    .await?;
+    let invitation = find(&state.db, invitation_id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    Ok((StatusCode::CREATED, Json(with_token(&state, invitation))))
+}
+
+// GET /polls/:id/invitations (admin), newest first
+pub async fn list_invitations(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Vec<Invitation>>, ApiError> {
+    let sql = format!("{INVITATION_SELECT} WHERE i.poll_id = ? ORDER BY i.id DESC");
+    let invitations = sqlx::query_as::<_, Invitation>(&sql).bind(id).fetch_all(&state.db).await?;
+    Ok(Json(invitations.into_iter().map(|invitation| with_token(&state, invitation)).collect()))
+}
+
+// DELETE /polls/:id/invitations/:invitation_id (admin): the link stops working; votes already cast with it stay
+pub async fn revoke_invitation(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+    Path((_, invitation_id)): Path<(String, i64)>,
+) -> Result<StatusCode, ApiError> {
+    let revoked = sqlx::query(
+        "UPDATE poll_invitations SET revoked_at = CURRENT_TIMESTAMP
+        WHERE id = ? AND poll_id = ? AND revoked_at IS NULL",
+    )
+    .bind(invitation_id)
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+    if revoked.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("no unrevoked invitation with id {invitation_id} in this poll")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /invitations/:token: what a guest's link is good for, for the page they land on
+pub async fn check_invitation(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<Invitation>, ApiError> {
+    let invitation = from_token(&state, &token)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("that's not a valid invitation".to_string()))?;
+    if let Some(reason) = unusable(&state.db, &invitation).await? {
+        return Err(ApiError::Forbidden(reason));
+    }
+    Ok(Json(invitation))
+}
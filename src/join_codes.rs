@@ -0,0 +1,62 @@
+// Join codes: LUNCH- and four characters, short enough to say out loud in standup and type in from memory, that
+// stand for a poll the way its UUID does. The characters leave out 0, 1, I and O, which are easy to mix up when
+// heard or handwritten, and typed codes are read forgivingly: any case, with or without the LUNCH- and the dash.
+// A poll keeps its code once it's closed, so last week's code still finds last week's results
+use axum::extract::{Path, State};
+use axum::Json;
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::SqlitePool;
+
+use crate::error::ApiError;
+use crate::polls::{self, Poll};
+use crate::AppState;
+
+const PREFIX: &str = "LUNCH-";
+// 32 characters, so a random byte picks one without favouring any
+const ALPHABET: &[u8; 32] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+const LENGTH: usize = 4;
+
+fn random_code() -> String {
+    let mut bytes = [0u8; LENGTH];
+    SystemRandom::new().fill(&mut bytes).expect("the system random number generator failed");
+    let suffix: String = bytes.iter().map(|byte| ALPHABET[*byte as usize % ALPHABET.len()] as char).collect();
+    format!("{PREFIX}{suffix}")
+}
+
+// Gives a new poll its code. A code already taken is drawn again; with about a million codes to go round, that
+// hardly ever happens, and the unique index keeps two polls from ever sharing one
+pub async fn assign(db: &SqlitePool, poll_id: i64) -> Result<(), sqlx::Error> {
+    loop {
+        let code = random_code();
+        let assigned = sqlx::query(
+            "UPDATE polls SET join_code = ?1 WHERE id = ?2 AND NOT EXISTS (SELECT 1 FROM polls WHERE join_code = ?1)",
+        )
+        .bind(&code)
+        .bind(poll_id)
+        .execute(db)
+        .await?;
+        if assigned.rows_affected() > 0 {
+            return Ok(());
+        }
+    }
+}
+
+// "lunch 7f3k", "7F3K" and "LUNCH-7F3K" are all LUNCH-7F3K; None for anything that can't be a code
+fn normalize(typed: &str) -> Option<String> {
+    let compact: String = typed.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_ascii_uppercase();
+    let suffix = compact.strip_prefix("LUNCH").unwrap_or(&compact);
+    (suffix.len() == LENGTH && suffix.bytes().all(|byte| ALPHABET.contains(&byte))).then(|| format!("{PREFIX}{suffix}"))
+}
+
+// GET /join/:code: the poll a join code stands for, with the id to vote in it with
+pub async fn join_poll(State(state): State<AppState>, Path(code): Path<String>) -> Result<Json<Poll>, ApiError> {
+    let not_found = || ApiError::NotFound(format!("no poll with join code {code}"));
+    let code = normalize(&code).ok_or_else(not_found)?;
+    let id: i64 = sqlx::query_scalar("SELECT id FROM polls WHERE join_code = ?")
+        .bind(&code)
+        .fetch_optional(&state.db)
+        .await?
+        .ok_or_else(not_found)?;
+    let poll = polls::find_poll(&state.db, id).await?.ok_or_else(not_found)?;
+    Ok(Json(poll))
+}
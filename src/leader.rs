@@ -0,0 +1,67 @@
+// With several instances running, only one of them should open the daily poll, close polls, send reminders and the
+// digest, or prune old votes; the others would do it all again. With REDIS_URL set, the instances take turns holding
+// a lease on the Redis key SCHEDULER_LEASE_KEY, LEASE_TTL at a time. Whoever holds it runs the scheduled jobs,
+// renewing it each time they run, and when that instance goes away its lease runs out and the next one to check
+// takes over. Without Redis the one instance is always the leader; with Redis out of reach, no instance is, since
+// jobs run twice are worse than jobs run late
+// https://redis.io/docs/latest/develop/use/patterns/distributed-locks/
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use crate::config::{self, Config};
+use crate::redis;
+
+// Long enough to outlast a minute's scheduler tick between renewals, and short enough for another instance to take
+// over the jobs soon after the leader stops
+const LEASE_TTL: Duration = Duration::from_secs(150);
+
+#[derive(Clone)]
+pub struct Lease(Arc<Mutex<Holding>>);
+
+struct Holding {
+    holder: String, // this instance, as it's written into the lease
+    connection: Option<redis::Connection>,
+    held: bool,
+}
+
+impl Default for Lease {
+    fn default() -> Self {
+        let holder = config::random_key()[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+        Lease(Arc::new(Mutex::new(Holding { holder, connection: None, held: false })))
+    }
+}
+
+impl Lease {
+    // Whether this instance is the one to run the scheduled jobs now
+    pub async fn held(&self, config: &Config) -> bool {
+        let Some(url) = &config.redis_url else {
+            return true;
+        };
+        let mut holding = self.0.lock().await;
+        let Holding { holder, connection, .. } = &mut *holding;
+        let renewed = async {
+            if connection.is_none() {
+                *connection = Some(redis::Connection::connect(url).await?);
+            }
+            connection.as_mut().expect("connected above").lease(&config.scheduler_lease_key, holder, LEASE_TTL).await
+        };
+        let held = match renewed.await {
+            Ok(held) => held,
+            Err(err) => {
+                eprintln!("leader: could not reach Redis for the lease: {err}");
+                holding.connection = None;
+                false
+            }
+        };
+        if held != holding.held {
+            match held {
+                true => println!("leader: this instance now runs the scheduled jobs"),
+                false => println!("leader: this instance no longer runs the scheduled jobs"),
+            }
+            holding.held = held;
+        }
+        held
+    }
+}
@@ -0,0 +1,73 @@
+// The _links in poll and restaurant payloads, HAL style: where the thing itself is, its results, and what can be done
+// with it next, so clients follow these rather than putting URLs together from templates of their own. That leaves
+// this file as the one place that knows the paths, for when they move, under a version prefix say. The hrefs are
+// relative to wherever the client reached the API from. An action only shows up while it can be taken: vote while
+// the poll is open or the restaurant is on ballots, close while the poll is open
+// https://datatracker.ietf.org/doc/html/draft-kelly-json-hal#section-4.1.1
+use serde::Serialize;
+
+use crate::polls::PollStatus;
+use crate::restaurants::RestaurantStatus;
+
+#[derive(Serialize)]
+struct Link {
+    href: String,
+    // Left out for a GET, the same as with a plain link; the others change something, and say so
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<&'static str>,
+}
+
+fn get(href: String) -> Link {
+    Link { href, method: None }
+}
+
+fn post(href: String) -> Link {
+    Link { href, method: Some("POST") }
+}
+
+// Read from the poll's own row alongside the rest of it, through #[sqlx(flatten)], and serialized by hand so only
+// the links that apply show up
+#[derive(sqlx::FromRow)]
+pub struct PollLinks {
+    public_id: String,
+    status: PollStatus,
+}
+
+impl Serialize for PollLinks {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let id = &self.public_id;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("self", &get(format!("/polls/{id}")))?;
+        map.serialize_entry("results", &get(format!("/polls/{id}/results")))?;
+        if self.status == PollStatus::Open {
+            // With this poll's id as the body's poll_id
+            map.serialize_entry("vote", &post("/vote".to_string()))?;
+            // Admin only, and with the poll's ETag as If-Match
+            map.serialize_entry("close", &post(format!("/polls/{id}/advance")))?;
+        }
+        map.end()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RestaurantLinks {
+    public_id: String,
+    active: bool,
+    status: RestaurantStatus,
+}
+
+impl Serialize for RestaurantLinks {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("self", &get(format!("/restaurants/{}", self.public_id)))?;
+        // Its votes outside of any poll; a poll's own results are among that poll's links
+        map.serialize_entry("results", &get("/results".to_string()))?;
+        if self.active && self.status == RestaurantStatus::Approved {
+            // With the restaurant's name as the body's restaurant_name
+            map.serialize_entry("vote", &post("/vote".to_string()))?;
+        }
+        map.end()
+    }
+}
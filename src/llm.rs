@@ -0,0 +1,186 @@
+// Turns the team's recent history into a natural-language suggestion ("You haven't had Thai in 3 weeks and it's
+// cold out") by asking a large language model. Off unless LLM_SUGGESTIONS is set; any server that speaks the
+// OpenAI chat completions API will do, including local ones
+// https://platform.openai.com/docs/api-reference/chat/create
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::ApiError;
+use crate::weather::{self, Weather};
+use crate::{recommendations, restaurants, stats, AppState};
+
+const SYSTEM_PROMPT: &str = "You help an office team decide where to have lunch. From the context you're given, \
+    recommend one restaurant from the shortlist in one or two friendly sentences, and say why: what the team hasn't \
+    had in a while, the weather, dietary needs. Only recommend restaurants on the shortlist.";
+
+#[derive(Deserialize)]
+pub struct LlmQuery {
+    dietary: Option<String>, // comma-separated dietary tags everyone's choice has to respect, e.g. "halal,vegan"
+    weekday: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LlmSuggestion {
+    suggestion: String,
+    context: Context, // what the model was told, so the suggestion can be checked against the facts
+}
+
+#[derive(Serialize)]
+struct Context {
+    today: String,
+    weekday: String,
+    dietary_requirements: Vec<String>,
+    weather: Option<Weather>,
+    recent_winners: Vec<RecentWinner>,
+    cuisines: Vec<CuisineLastWon>,
+    shortlist: Vec<ShortlistEntry>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct RecentWinner {
+    day: String,
+    restaurant: String,
+    cuisine: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct CuisineLastWon {
+    cuisine: String,
+    days_since_last_win: i64,
+}
+
+#[derive(Serialize)]
+struct ShortlistEntry {
+    name: String,
+    cuisine: Option<String>,
+    dietary_tags: Vec<String>,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: String,
+}
+
+// GET /recommendations/llm?dietary=halal,vegan&weekday=friday
+pub async fn suggest(
+    State(state): State<AppState>,
+    Query(query): Query<LlmQuery>,
+) -> Result<Json<LlmSuggestion>, ApiError> {
+    if !state.config.llm_suggestions {
+        return Err(ApiError::NotConfigured("set LLM_SUGGESTIONS=true to enable LLM suggestions".to_string()));
+    }
+    let context = build_context(&state, query).await?;
+
+    let mut request = state.http.post(format!("{}/chat/completions", state.config.llm_api_url.trim_end_matches('/')));
+    if let Some(api_key) = &state.config.llm_api_key {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request
+        .json(&json!({
+            "model": state.config.llm_model,
+            "messages": [
+                { "role": "system", "content": SYSTEM_PROMPT },
+                { "role": "user", "content": serde_json::to_string(&context).unwrap_or_default() },
+            ],
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("LLM API returned {status}: {body}")));
+    }
+    let chat: ChatResponse = response.json().await?;
+    let suggestion = chat
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content.trim().to_string())
+        .filter(|content| !content.is_empty())
+        .ok_or_else(|| ApiError::Upstream("LLM API returned no suggestion".to_string()))?;
+
+    Ok(Json(LlmSuggestion { suggestion, context }))
+}
+
+async fn build_context(state: &AppState, query: LlmQuery) -> Result<Context, ApiError> {
+    let db = &state.db;
+    let weekday = recommendations::weekday_or_today(state, query.weekday.as_deref()).await?;
+    let dietary = restaurants::normalize_tags(
+        query.dietary.unwrap_or_default().split(',').map(str::to_string).collect(),
+    );
+    let (today, lunch_at): (String, String) =
+        sqlx::query_as("SELECT date('now', 'localtime'), date('now', 'localtime') || ' ' || ?")
+            .bind(&state.config.lunch_time)
+            .fetch_one(db)
+            .await?;
+
+    // Daily winners over the last three weeks, decided the same way as in /stats/cuisines
+    let winners_sql = stats::daily_winners_sql();
+    let recent_winners = sqlx::query_as::<_, RecentWinner>(&format!(
+        "{winners_sql}
+        SELECT w.day, w.restaurant_name AS restaurant, r.cuisine
+        FROM winners w LEFT JOIN restaurants r ON r.name = w.restaurant_name AND r.deleted_at IS NULL
+        WHERE w.place = 1 AND w.day >= date(?, '-21 days')
+        ORDER BY w.day DESC"
+    ))
+    .bind(&today)
+    .fetch_all(db)
+    .await?;
+    let cuisines = sqlx::query_as::<_, CuisineLastWon>(&format!(
+        "{winners_sql}
+        SELECT r.cuisine,
+            CAST(julianday(?) - julianday(MAX(w.day)) AS INTEGER) AS days_since_last_win
+        FROM winners w JOIN restaurants r ON r.name = w.restaurant_name AND r.deleted_at IS NULL
+        WHERE w.place = 1 AND r.cuisine IS NOT NULL
+        GROUP BY r.cuisine
+        ORDER BY days_since_last_win DESC"
+    ))
+    .bind(&today)
+    .fetch_all(db)
+    .await?;
+
+    // The shortlist the model picks from: the history-based recommendations, minus anything that breaks
+    // the dietary requirements
+    let ranked = recommendations::shortlist(state, weekday, usize::MAX, &Default::default(), &[]).await?;
+    let mut shortlist = Vec::new();
+    for entry in ranked.recommendations {
+        let Some(restaurant) = restaurants::find_by_name(db, &entry.name).await? else {
+            continue;
+        };
+        let tags = restaurant.details.dietary_tags();
+        if dietary.iter().all(|tag| tags.contains(tag)) {
+            shortlist.push(ShortlistEntry {
+                name: entry.name,
+                cuisine: restaurant.details.cuisine().map(str::to_string),
+                dietary_tags: tags.to_vec(),
+                score: entry.score,
+            });
+        }
+        if shortlist.len() == 8 {
+            break;
+        }
+    }
+
+    Ok(Context {
+        weekday: crate::hours::WEEKDAYS[weekday as usize].to_string(),
+        weather: weather::forecast(state, state.config.office_location, &lunch_at).await,
+        today,
+        dietary_requirements: dietary,
+        recent_winners,
+        cuisines,
+        shortlist,
+    })
+}
@@ -0,0 +1,60 @@
+// The lottery voting method: every vote is a ticket, and when the poll closes one ticket is drawn, so a restaurant's
+// chance of winning is its share of the votes and a place only a few people wanted still wins now and then. The draw
+// comes from the poll's seed, which goes to the log when the poll is created (see polls::insert_poll), and the
+// results list every restaurant's tickets, so anyone can redo it: the winning ticket is tiebreaks::draw(seed,
+// "lottery") modulo the number of tickets, and tickets are numbered from 0 through the restaurants in the order
+// they're listed before the draw, most votes first
+use serde::Serialize;
+
+use crate::polls::{Poll, PollStatus};
+use crate::{tiebreaks, Restaurant};
+
+#[derive(Clone, Serialize)]
+pub struct Lottery {
+    seed: i64,
+    tickets: i64, // one per vote
+    holders: Vec<Holder>,
+    // Only once the poll has closed, when the winner has been moved to the top of the results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drawn_ticket: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    winner: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct Holder {
+    restaurant: String,
+    first_ticket: i64,
+    last_ticket: i64,
+    chance_percent: f64,
+}
+
+// Numbers the tickets of `votes`, sorted best score first, and once the poll has closed moves the drawn restaurant
+// to the top. None when there are no votes to draw from
+pub fn draw(poll: &Poll, votes: &mut Vec<Restaurant>) -> Option<Lottery> {
+    let tickets: i64 = votes.iter().map(|restaurant| restaurant.score).sum();
+    if tickets == 0 {
+        return None;
+    }
+    let mut holders = Vec::new();
+    let mut next = 0;
+    for restaurant in votes.iter() {
+        holders.push(Holder {
+            restaurant: restaurant.name.clone(),
+            first_ticket: next,
+            last_ticket: next + restaurant.score - 1,
+            chance_percent: 100.0 * restaurant.score as f64 / tickets as f64,
+        });
+        next += restaurant.score;
+    }
+    let mut lottery = Lottery { seed: poll.tiebreak_seed, tickets, holders, drawn_ticket: None, winner: None };
+    if poll.status == PollStatus::Closed {
+        let ticket = (tiebreaks::draw(poll.tiebreak_seed, "lottery") % tickets as u64) as i64;
+        let drawn = lottery.holders.iter().position(|holder| holder.last_ticket >= ticket).unwrap_or(0);
+        let winner = votes.remove(drawn);
+        lottery.winner = Some(winner.name.clone());
+        lottery.drawn_ticket = Some(ticket);
+        votes.insert(0, winner);
+    }
+    Some(lottery)
+}
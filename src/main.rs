@@ -1,10 +1,102 @@
 // use declarations pull structs, functions, and traits into the current namespace from other crates and libraries
 // https://doc.rust-lang.org/reference/items/use-declarations.html
-use axum::extract::State;
-use axum::routing::{post};
-use axum::{Json, Router};
+use axum::extract::{Query, State};
+use axum::response::Response;
+use axum::routing::{delete, get, patch, post, put};
+use axum::{Form, Json, Router};
 use serde::{Deserialize, Serialize};
-use sqlx::{Executor, Row, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// mod declarations pull in the other files under src/ as modules of this crate
+// https://doc.rust-lang.org/book/ch07-05-separating-modules-into-different-files.html
+mod announcements;
+mod audit;
+mod auth;
+mod away;
+mod backups;
+mod badges;
+mod ballots;
+mod bills;
+mod budgets;
+mod comments;
+mod conditional;
+mod config;
+mod cursors;
+mod database;
+mod digest;
+mod email;
+mod erasure;
+mod error;
+mod fuzzy;
+mod geo;
+mod google_places;
+mod graphql;
+mod grpc;
+mod gzip;
+mod holidays;
+mod hours;
+mod i18n;
+mod imports;
+mod invitations;
+mod join_codes;
+mod leader;
+mod links;
+mod llm;
+mod lottery;
+mod merge;
+mod metrics;
+mod moderation;
+mod mqtt;
+mod names;
+mod notifications;
+mod offices;
+mod openstreetmap;
+mod orders;
+mod organizations;
+mod participation;
+mod payments;
+mod poll_templates;
+mod polls;
+mod problems;
+mod public_ids;
+mod publisher;
+mod quadratic;
+mod ranked;
+mod rate_limit;
+mod reactions;
+mod reservations;
+mod receipts;
+mod recommendations;
+mod redis;
+mod refresh;
+mod reminders;
+mod restaurants;
+mod retention;
+mod rotation;
+mod routing;
+mod rsvps;
+mod s3;
+mod scheduler;
+mod sealing;
+mod stats;
+mod suppressions;
+mod tallies;
+mod teams;
+mod tiebreaks;
+mod timezones;
+mod tls;
+mod trash;
+mod vote_batches;
+mod vote_events;
+mod vote_links;
+mod voters;
+mod weather;
+mod yelp;
 
 // #[] is a macro, and in this case declares an attribute, which applies metadata to the module, crate, or in this case, item below.
 // https://doc.rust-lang.org/rust-by-example/attribute.html
@@ -16,6 +108,35 @@ use sqlx::{Executor, Row, SqlitePool};
 struct AppState {
     // Here we are defining a struct to carry shared state for the whole app
     db: SqlitePool, // This will hold the current SQLite database connection so all functions with the state can access it
+    // Where statistics and exports are read from: the replica at DATABASE_READ_URL, or the same pool as db. A replica
+    // may be a moment behind, so anything that reads what it's about to change uses db
+    reads: SqlitePool,
+    // Arc is a reference-counted pointer: cloning the state for each request copies the pointer, not the config
+    // https://doc.rust-lang.org/std/sync/struct.Arc.html
+    config: Arc<config::Config>,
+    // One HTTP client for all calls to outside APIs; it pools connections internally and is cheap to clone
+    // https://docs.rs/reqwest/latest/reqwest/struct.Client.html
+    http: reqwest::Client,
+    // Where tally refreshes are sent for GET /results/stream; see refresh.rs
+    refreshes: refresh::Refreshes,
+    // The tallies counted lately, for asking again; see tallies.rs
+    tallies: tallies::Tallies,
+    // The organizations hosted alongside; only the host's state has any. See organizations.rs
+    organizations: organizations::Organizations,
+    // Where votes and closed polls are queued for the event broker; see publisher.rs
+    events: publisher::Events,
+    // Where polls that closed on a winner are queued for the MQTT broker; see mqtt.rs
+    mqtt: mqtt::Mqtt,
+    // Whether this instance is the one that runs the scheduled jobs; see leader.rs
+    lease: leader::Lease,
+    // How many requests each client has sent this minute; see rate_limit.rs
+    limiter: rate_limit::Limiter,
+    // How the database backups have gone; see backups.rs
+    backups: backups::Backups,
+    // Whether requests are being turned away while the database is out of reach; see database.rs
+    breaker: database::Breaker,
+    // Where ballots are queued to be written in batches; see vote_batches.rs
+    ballots: vote_batches::Ballots,
 }
 
 // This macro makes the code run on the tokio runtime
@@ -25,40 +146,382 @@ async fn main() {
     // Initializes tracing subscriber, which allows for better diagnostics in asynchronous tokio operations
     // https://docs.rs/tracing-subscriber/latest/tracing_subscriber/index.html
     tracing_subscriber::fmt::init();
-    // Initializes the database connection; in this case, just creates one in non-persistent memory
+    let state = match open(config::Config::from_env(), reqwest::Client::new()).await {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Failed to set up the database: {err}");
+            std::process::exit(1);
+        }
+    };
+    let tls = match tls::acceptor(&state.config) {
+        Ok(tls) => tls,
+        Err(err) => {
+            eprintln!("Failed to load the TLS certificate: {err}");
+            std::process::exit(1);
+        }
+    };
+    let tls_port = state.config.tls_port;
+    let app = app(
+        routes()
+            .route(
+                "/organizations",
+                get(organizations::list_organizations).post(organizations::create_organization),
+            )
+            .route("/organizations/:id/api-key", post(organizations::rotate_api_key))
+            .route("/organizations/:id/migrate", post(organizations::migrate))
+            .route("/organizations/:id/backup", post(organizations::back_up)),
+        state.clone(),
+    )
+    // Outside the audit layer, so an organization's requests are only logged in its own audit log
+    .layer(axum::middleware::from_fn_with_state(state, organizations::dispatch));
+
+    // With a certificate, HTTPS is served on TLS_PORT as well; see tls.rs
+    if let Some(acceptor) = tls {
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", tls_port)).await.unwrap();
+        tokio::spawn(tls::serve(listener, acceptor, app.clone()));
+    }
+
+    // run our app with hyper, listening globally on port 3000
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    // Run server and pause here, handling any incoming requests. The connect info gives handlers and the audit log
+    // the client's address
+    // https://docs.rs/axum/latest/axum/extract/struct.ConnectInfo.html
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap();
+}
+
+// The database at DATABASE_URL, a fresh in-memory one without it, with the schema and the configured holidays in
+// it, and the background jobs running over it. The host's data is set up here at startup, and each organization's
+// when it's created
+async fn open(config: config::Config, http: reqwest::Client) -> Result<AppState, sqlx::Error> {
+    // Initializes the database connection; by default, just creates one in non-persistent memory. A file gets a
+    // shared cache, as the in-memory database always has: a connection reading what another one is still writing
+    // waits for it to finish, rather than missing, say, the row an INSERT ... RETURNING has handed back a moment
+    // before it commits
     // https://docs.rs/sqlx/latest/sqlx/type.SqlitePool.html
-    let db = SqlitePool::connect("sqlite::memory:").await.unwrap();
-    let _res = sqlx::query("CREATE TABLE IF NOT EXISTS votes 
-        (id INTEGER PRIMARY KEY,voter_name VARCHAR(255) NOT NULL,restaurant_name VARCHAR(255) NOT NULL)")
-        .execute(&db)
-        .await
-        .expect("Failed to create votes table");
+    // https://www.sqlite.org/sharedcache.html
+    let options = connect_options(&config.database_url)?.create_if_missing(true).shared_cache(true);
+    let db = connect(&config, &config.database_url, options).await?;
+    // A replica gets the heavy read-only queries, so they don't hold up votes being written; without one they share
+    // the pool above. Read-only, so nothing written by mistake can make it drift from the database it copies
+    let reads = match &config.database_read_url {
+        Some(url) => connect(&config, url, connect_options(url)?.read_only(true)).await?,
+        None => db.clone(),
+    };
+    // The schema lives in numbered .sql files under migrations/, embedded into the binary at compile time
+    // and applied in order; sqlx records which ones have already run in its _sqlx_migrations table
+    // https://docs.rs/sqlx/latest/sqlx/macro.migrate.html
+    sqlx::migrate!().run(&db).await?;
 
     // Creates an app state instance with db set to the connection we just created
-    let state = AppState { db };
+    let (events, queue) = publisher::channel();
+    let (mqtt, winners) = mqtt::channel();
+    let (ballots, ballot_queue) = vote_batches::channel();
+    let tallies = tallies::Tallies::default();
+    let state = AppState {
+        db,
+        reads,
+        config: Arc::new(config),
+        http,
+        refreshes: refresh::Refreshes::new(tallies.clone()),
+        tallies,
+        organizations: Default::default(),
+        events,
+        mqtt,
+        lease: Default::default(),
+        limiter: Default::default(),
+        backups: Default::default(),
+        breaker: Default::default(),
+        ballots,
+    };
+    holidays::seed(&state.db, &state.config.holidays).await?;
+    // Background jobs get their own copy of the state; each one only starts if it has been configured
+    yelp::spawn_enrichment(state.clone());
+    scheduler::spawn(state.clone());
+    routing::spawn_walking_times(state.clone());
+    retention::spawn(state.clone());
+    publisher::spawn(state.clone(), queue);
+    mqtt::spawn(state.clone(), winners);
+    vote_batches::spawn(state.clone(), ballot_queue);
+    refresh::spawn_relay(state.clone());
+    backups::spawn(state.clone());
+    Ok(state)
+}
+
+// A pool sized and timed as configured. Connecting is retried with exponential backoff for as long as
+// DATABASE_STARTUP_TIMEOUT_SECONDS allows, so a database that isn't there yet at startup only delays it
+// https://docs.rs/sqlx/latest/sqlx/pool/struct.PoolOptions.html
+async fn connect(
+    config: &config::Config,
+    url: &str,
+    options: SqliteConnectOptions,
+) -> Result<SqlitePool, sqlx::Error> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.database_max_connections)
+        .acquire_timeout(config.database_acquire_timeout);
+    // An in-memory database lasts as long as one connection to it is open, so none are ever closed
+    let pool = match config::in_memory(url) {
+        true => pool.max_lifetime(None).idle_timeout(None),
+        false => pool.max_lifetime(config.database_max_lifetime),
+    };
+    let started = tokio::time::Instant::now();
+    let mut delay = Duration::from_millis(250);
+    loop {
+        match pool.clone().connect_with(options.clone()).await {
+            Ok(pool) => return Ok(pool),
+            Err(err) if started.elapsed() + delay <= config.database_startup_timeout => {
+                eprintln!("database: could not connect to {url}, trying again in {delay:?}: {err}");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(10));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Each connection gets the collations names are sorted with, one per locale (see names::collate), and the one voter
+// names are told apart with (see ballots.rs)
+fn connect_options(url: &str) -> Result<SqliteConnectOptions, sqlx::Error> {
+    let mut options = SqliteConnectOptions::from_str(url)?;
+    for locale in i18n::Locale::ALL {
+        options = options.collation(names::collation(locale), move |a, b| names::collate(locale, a, b));
+    }
+    options = options.collation(names::VOTER_COLLATION, |a, b| names::fold(a).cmp(&names::fold(b)));
+    Ok(options)
+}
+
+// The endpoints for one set of data, the host's or an organization's
+fn routes() -> Router<AppState> {
     // Instantiates the server app, defines handlers, services, and state
     // https://docs.rs/axum/latest/axum/struct.Router.html
-    // In this case, we are routing any requests to the /vote endpoint to the vote function as its handler
-    // and adding the app state variable created above
-    let app = Router::new().route("/vote", post(vote)).with_state(state);
+    // In this case, we are routing any requests to the /vote endpoint to the vote function as its handler;
+    // the app state is added in app below
+    Router::new()
+        .route("/vote", get(vote_links::confirm).post(vote))
+        .route("/vote/link", post(vote_by_link))
+        .route("/results", get(results))
+        .route("/results/stream", get(refresh::stream))
+        .route(graphql::PATH, get(graphql::serve).post(graphql::serve))
+        .route(grpc::ROUTE, post(grpc::serve))
+        .route("/receipts/verify", post(receipts::verify))
+        .route(
+            "/restaurants",
+            get(restaurants::list_restaurants).post(restaurants::create_restaurant),
+        )
+        .route(
+            "/restaurants/:id",
+            get(restaurants::get_restaurant)
+                .patch(restaurants::update_restaurant)
+                .delete(restaurants::delete_restaurant),
+        )
+        .route("/restaurants/random", get(restaurants::random_restaurant))
+        .route("/restaurants/search", get(restaurants::search_restaurants))
+        .route(
+            "/restaurants/suggestions",
+            get(restaurants::list_suggestions).post(restaurants::suggest_restaurant),
+        )
+        .route("/restaurants/import/google-places", post(google_places::import))
+        .route("/restaurants/import/openstreetmap", post(openstreetmap::import))
+        .route("/restaurants/enrich/yelp", post(yelp::enrich_now))
+        .route("/restaurants/enrich/walking-times", post(routing::update_now))
+        .route("/restaurants/:id/approve", post(restaurants::approve_suggestion))
+        .route("/restaurants/:id/reject", post(restaurants::reject_suggestion))
+        .route(
+            "/restaurants/:id/aliases",
+            get(restaurants::list_aliases).post(restaurants::add_alias),
+        )
+        .route("/restaurants/:id/aliases/:alias_id", delete(restaurants::delete_alias))
+        .route("/restaurants/:id/ratings", post(restaurants::rate_restaurant))
+        .route(
+            "/restaurants/:id/hours",
+            get(hours::get_hours).put(hours::set_hours),
+        )
+        .route("/restaurants/:id/deactivate", post(restaurants::deactivate_restaurant))
+        .route("/restaurants/:id/reactivate", post(restaurants::reactivate_restaurant))
+        .route("/polls", post(polls::create_poll))
+        .route("/poll-templates", get(poll_templates::list_templates))
+        .route(
+            "/poll-templates/:id",
+            get(poll_templates::get_template).put(poll_templates::put_template).delete(poll_templates::delete_template),
+        )
+        .route("/poll-templates/:id/polls", post(poll_templates::create_from_template))
+        .route("/polls/:id", get(polls::get_poll).patch(polls::update_poll))
+        .route("/polls/:id/candidates", get(polls::get_candidates))
+        .route("/polls/:id/clone", post(polls::clone_poll))
+        .route("/polls/:id/results", get(polls::get_results))
+        .route("/polls/:id/advance", post(polls::advance_poll))
+        .route("/polls/:id/reopen", post(polls::reopen_poll))
+        .route(
+            "/polls/:id/abstentions",
+            get(participation::list_abstentions).post(participation::abstain),
+        )
+        .route(
+            "/polls/:id/reactions",
+            get(reactions::list_reactions).post(reactions::add_reaction).delete(reactions::remove_reaction),
+        )
+        .route("/polls/:id/participants", get(participation::list_participants))
+        .route("/polls/:id/rsvps", get(rsvps::list_rsvps).post(rsvps::rsvp))
+        .route("/polls/:id/rsvps/:voter", delete(rsvps::withdraw_rsvp))
+        .route("/polls/:id/picker/skip", post(rotation::skip_picker))
+        .route("/polls/:id/bill", get(bills::get_settlement).post(bills::record_entry))
+        .route("/polls/:id/bill/:voter", delete(bills::remove_entry))
+        .route("/polls/:id/bill/payments", get(payments::list_payments))
+        .route("/polls/:id/bill/payments/:voter", post(payments::record_payment))
+        .route("/polls/:id/bill/payments/:voter/checkout", post(payments::checkout))
+        .route("/polls/:id/orders", get(orders::list_orders).post(orders::place_order))
+        .route("/polls/:id/orders/summary", get(orders::order_summary))
+        .route("/polls/:id/orders/:voter", delete(orders::cancel_order))
+        .route("/polls/:id/reservation", post(reservations::reserve_now))
+        .route("/polls/:id/announcement", get(announcements::get_announcement))
+        .route("/polls/:id/credits/:voter", get(quadratic::get_credits))
+        .route("/polls/:id/unavailable", post(polls::mark_unavailable))
+        .route("/votes/:id", patch(vote_events::correct_vote).delete(vote_events::delete_vote))
+        .route("/votes/:id/comment", delete(comments::remove_comment))
+        .route("/polls/:id/unavailable/:restaurant_id", delete(polls::clear_unavailable))
+        .route(
+            "/polls/:id/nominations",
+            get(polls::list_nominations).post(polls::nominate),
+        )
+        .route("/offices", get(offices::list_offices).post(offices::create_office))
+        .route("/offices/:id", patch(offices::update_office))
+        .route("/teams", get(teams::list_teams).post(teams::create_team))
+        .route("/teams/:id", get(teams::get_team).patch(teams::update_team).delete(teams::delete_team))
+        .route(
+            "/teams/:id/members/:name",
+            put(teams::add_member).delete(teams::remove_member),
+        )
+        .route(
+            "/teams/:id/captains/:name",
+            put(teams::add_captain).delete(teams::remove_captain),
+        )
+        .route("/rotation", get(rotation::get_rotation).put(rotation::put_rotation))
+        .route("/holidays", get(holidays::list_holidays))
+        .route(
+            "/holidays/:day",
+            put(holidays::put_holiday).delete(holidays::delete_holiday),
+        )
+        .route("/voters/:name", get(voters::get_profile))
+        .route("/voters/:name/blacklist", get(voters::get_blacklist))
+        .route(
+            "/voters/:name/blacklist/:restaurant_id",
+            put(voters::add_to_blacklist).delete(voters::remove_from_blacklist),
+        )
+        .route(
+            "/voters/:name/preferences",
+            get(voters::get_preferences).put(voters::put_preferences).delete(voters::delete_preferences),
+        )
+        .route(
+            "/voters/:name/budget",
+            get(budgets::get_budget).put(budgets::put_budget).delete(budgets::delete_budget),
+        )
+        .route(
+            "/voters/:name/notifications",
+            get(notifications::get_settings).put(notifications::put_settings),
+        )
+        .route("/voters/:name/notifications/:event", put(notifications::put_event))
+        .route("/voters/:name/away", get(away::get_away).post(away::add_away))
+        .route("/voters/:name/away/:id", delete(away::delete_away))
+        .route("/away", get(away::list_away))
+        .route("/voters/:name/data", delete(erasure::erase_voter_data))
+        .route("/voters/:name/merge", post(merge::merge_voters))
+        .route("/recommendations", get(recommendations::recommend))
+        .route("/digest", get(digest::get_digest))
+        .route("/digest/send", post(digest::send_now))
+        .route("/digest/unsubscribe", get(digest::unsubscribe))
+        .route("/unsubscribe", get(suppressions::unsubscribe))
+        .route("/suppressions", get(suppressions::list_suppressions).delete(suppressions::clear_suppression))
+        .route("/recommendations/llm", get(llm::suggest))
+        .route("/stats/trends", get(stats::trends))
+        .route("/stats/cuisines", get(stats::cuisines))
+        .route("/stats/channels", get(stats::channels))
+        .route(
+            "/polls/:id/invitations",
+            get(invitations::list_invitations).post(invitations::create_invitation),
+        )
+        .route("/polls/:id/invitations/:invitation_id", delete(invitations::revoke_invitation))
+        .route("/invitations/:token", get(invitations::check_invitation))
+        .route("/join/:code", get(join_codes::join_poll))
+        .route("/polls/:id/votes/:voter", delete(vote_events::retract_votes))
+        .route("/vote-events", get(vote_events::list_events))
+        .route("/vote-events/replay", post(vote_events::replay))
+        .route("/audit", get(audit::list_entries))
+        .route("/moderation/flags", get(moderation::list_flags))
+        .route("/moderation/flags/:id/approve", post(moderation::approve_flag))
+        .route("/moderation/flags/:id/reject", post(moderation::reject_flag))
+        .route("/trash", get(trash::list_trash))
+        .route("/trash/restaurants/:id/restore", post(trash::restore_restaurant))
+        .route("/trash/votes/:id/restore", post(trash::restore_vote))
+        .route("/retention/prune", post(retention::prune_now))
+        .route("/retention/prunes", get(retention::list_prunes))
+        .route("/backups", post(backups::back_up_now))
+        .route("/metrics", get(metrics::metrics))
+        .route("/problems", get(problems::list_problems))
+        .route("/problems/:code", get(problems::get_problem))
+}
 
-    // run our app with hyper, listening globally on port 3000
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    // Run server and pause here, handling any incoming requests
-    axum::serve(listener, app).await.unwrap();
+fn app(routes: Router<AppState>, state: AppState) -> Router {
+    routes
+        // Layers wrap every route added before them; from_fn_with_state turns a plain async fn into one
+        // https://docs.rs/axum/latest/axum/middleware/fn.from_fn_with_state.html
+        .layer(axum::middleware::from_fn_with_state(state.clone(), audit::record))
+        // Outside the audit log, which is written to the database too, so it isn't tried while the circuit is open
+        .layer(axum::middleware::from_fn_with_state(state.clone(), database::guard))
+        // Added after the guard, so it still answers while the guard is turning everything else away
+        .route("/readyz", get(database::readyz))
+        // Outside the audit log, so a client flooding the API doesn't flood the log as well
+        .layer(axum::middleware::from_fn_with_state(state.clone(), rate_limit::limit))
+        // Outermost, so even the audit log's errors come back in the language asked for
+        .layer(axum::middleware::from_fn(i18n::negotiate))
+        // Around all of it, and the routes not there too, so axum's own errors come back as problems as well
+        .layer(axum::middleware::from_fn(problems::standardize))
+        .with_state(state)
 }
 
 // the Serialize trait from the serde crate allows the structure to be serialized into JSON
 // https://docs.rs/serde/latest/serde/trait.Serialize.html
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 struct LunchVoting {
     votes: Vec<Restaurant>, // For this struct member, we are declaring it as a Vector who's elements are the Restaurant struct defined below
+    // Restaurants ruled out of the poll; their voters' backup choices are counted instead
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unavailable: Vec<String>,
+    // Only when restaurants tie for first place: how the tie was broken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tiebreak: Option<tiebreaks::TiebreakRecord>,
+    // Only for polls using the condorcet voting method: the head-to-head counts behind the order above
+    #[serde(skip_serializing_if = "Option::is_none")]
+    condorcet: Option<ranked::CondorcetResult>,
+    // Only for polls using the lottery voting method: everyone's tickets, and once the poll has closed, the draw
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lottery: Option<lottery::Lottery>,
+    // Only for polls: how many voters took part by abstaining rather than voting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    abstentions: Option<i64>,
+    // Only for polls: how many said they're coming to lunch, whatever they voted; see rsvps.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headcount: Option<rsvps::Headcount>,
+    // Only with ?detailed=true: the comments voters left on their ballots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comments: Option<Vec<comments::Comment>>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 struct Restaurant {
     name: String,
     voters: Vec<String>,
+    // The voters above whose first choice dropped out, so this is their backup
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    backup_voters: Vec<String>,
+    // Whatever the restaurants table knows about this place, written alongside name and voters in the JSON
+    #[serde(flatten)]
+    details: restaurants::RestaurantDetails,
+    // What the results are ordered by: the number of voters, or the voting method's own score.
+    // Tiebreaks apply between restaurants with equal scores
+    #[serde(skip)]
+    score: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    borda_score: Option<i64>, // only in polls using the borda voting method
+    #[serde(skip_serializing_if = "Option::is_none")]
+    effective_votes: Option<i64>, // only in quadratic polls: the votes bought for this restaurant
 }
 
 // The Debug trait allows us to print instances of the struct without needing to implement special formatting functionality
@@ -68,7 +531,36 @@ struct Restaurant {
 #[derive(Debug, Deserialize)]
 struct VoteRequest {
     voter_name: String,
-    restaurant_name: String,
+    #[serde(default)]
+    restaurant_name: String, // left out on ranked ballots, where it's the first of the ranking
+    // Polls with a ranked voting method take the candidates in order of preference instead, favourite first
+    ranking: Option<Vec<String>>,
+    // Quadratic polls take votes per restaurant instead, {"Luigi's": 3, "Taco Truck": 1}, paid for in credits
+    allocation: Option<BTreeMap<String, i64>>,
+    poll_id: Option<String>, // the poll's public id; votes outside of any poll leave this out
+    // A second choice, counted if restaurant_name is ruled out of the poll later on
+    backup_restaurant_name: Option<String>,
+    // A short note shown with the detailed results, "only if we leave by 12:15"
+    comment: Option<String>,
+    #[serde(default)]
+    channel: VoteChannel,
+    // A guest's invitation to the poll, for voters who aren't on its roster; see invitations.rs
+    invitation: Option<String>,
+}
+
+// Which client a vote came through, so /stats/channels can show whether the integrations get used. Clients say
+// so themselves; anything that doesn't is counted as a plain API call
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+enum VoteChannel {
+    Web,
+    Slack,
+    Cli,
+    Link, // a one-click link from a notification; see vote_links.rs
+    Grpc, // CastVote; see grpc.rs
+    #[default]
+    Api,
 }
 
 // Here is where we define our /vote endpoint handler. It gets passed the app state, and the request JSON payload since it is a post
@@ -76,11 +568,39 @@ struct VoteRequest {
 // The parameters for axum handler functions are called extractors, since they pull in different parts of the request and app
 // based on their type
 // https://docs.rs/axum/latest/axum/handler/index.html
-async fn vote(state: State<AppState>, req: Json<VoteRequest>) {
-    dbg!(&req);
+// Returning a Result lets axum send back the error response when the vote is rejected
+// The response is the ballot's receipt; keep it to check later that the vote was counted
+async fn vote(state: State<AppState>, req: Json<VoteRequest>) -> Result<Json<receipts::Receipt>, error::ApiError> {
     let vote_req: VoteRequest = req.0;
-    let res = save_vote(state, vote_req).await;
-    dbg!(res);
+    let receipt = save_vote(state, vote_req).await?;
+    Ok(Json(receipt))
+}
+
+// POST /vote/link, a form with token=...&restaurant=...: what the page the one-click links in notifications open
+// sends on; see vote_links.rs. The token is spent by a vote that goes through, and given back when it's turned down
+async fn vote_by_link(
+    State(state): State<AppState>,
+    Form(link): Form<vote_links::LinkVote>,
+) -> Result<Json<receipts::Receipt>, error::ApiError> {
+    let ballot = vote_links::redeem(&state, &link).await?;
+    let vote_req = VoteRequest {
+        voter_name: ballot.voter_name,
+        restaurant_name: ballot.restaurant_name,
+        ranking: None,
+        allocation: None,
+        poll_id: Some(ballot.poll_id),
+        backup_restaurant_name: None,
+        comment: None,
+        channel: VoteChannel::Link,
+        invitation: None,
+    };
+    match save_vote(State(state.clone()), vote_req).await {
+        Ok(receipt) => Ok(Json(receipt)),
+        Err(err) => {
+            vote_links::release(&state, ballot.link_id).await?;
+            Err(err.into())
+        }
+    }
 }
 
 // Here we are creating an enumeration. Enumerations are very flexible and powerful in Rust.
@@ -88,27 +608,486 @@ async fn vote(state: State<AppState>, req: Json<VoteRequest>) {
 // https://doc.rust-lang.org/rust-by-example/custom_types/enum.html?highlight=enum#enums
 enum SaveVoteError {
     DbError(sqlx::Error),
+    MissingVoterName,
     UnknownRestaurant(String),
+    AmbiguousRestaurant { name: String, matches: Vec<fuzzy::NameMatch> },
+    PendingRestaurant(String),
+    InactiveRestaurant(String),
+    // Polls are named by their public id in these
+    UnknownPoll(String),
+    PollNotOpen { poll_id: String, status: polls::PollStatus },
+    NotACandidate { restaurant: String, poll_id: String }, // enum variants can hold named fields, like a struct
+    BackupWithoutPoll,
+    BackupSameAsFirstChoice,
+    RankingRequired(String),
+    RankingNotAllowed,
+    RankingAndSingleChoice,
+    RankedTwice(String),
+    NotEligible { voter: String, poll_id: String },
+    NotOnTeam { voter: String, team: String },
+    NotThePicker { picker: Option<String>, poll_id: String },
+    AlreadyVoted { voter: String, in_poll: bool }, // see ballots.rs
+    AllocationRequired(String),
+    AllocationNotAllowed,
+    InvalidAllocation(error::Message),
+    OverBudget { cost: i64, remaining: i64 },
+    InvalidComment(String),
+    SealBroken(String),
+    RefusedName(error::ApiError), // see moderation::screen
+    NotInvited(error::ApiError),  // see invitations::admit
+}
+
+impl From<sqlx::Error> for SaveVoteError {
+    fn from(err: sqlx::Error) -> Self {
+        SaveVoteError::DbError(err)
+    }
+}
+
+// Lets the ? in the vote handler turn a SaveVoteError into the matching HTTP error
+impl From<SaveVoteError> for error::ApiError {
+    fn from(err: SaveVoteError) -> Self {
+        match err {
+            SaveVoteError::DbError(err) => error::ApiError::DbError(err),
+            SaveVoteError::MissingVoterName => error::Message::voter_name_empty().into(),
+            SaveVoteError::UnknownRestaurant(name) => error::Message::not_registered(name).into(),
+            SaveVoteError::AmbiguousRestaurant { name, matches } => error::ApiError::Ambiguous {
+                message: error::Message::did_you_mean(name),
+                matches,
+            },
+            SaveVoteError::PendingRestaurant(name) => error::Message::not_approved(name).into(),
+            SaveVoteError::InactiveRestaurant(name) => error::Message::inactive(name).into(),
+            SaveVoteError::UnknownPoll(id) => error::Message::no_poll(id).into(),
+            SaveVoteError::PollNotOpen { poll_id, status } => error::ApiError::from(match status {
+                polls::PollStatus::Nominating => error::Message::poll_nominating(poll_id),
+                _ => error::Message::poll_id_closed(poll_id),
+            }),
+            SaveVoteError::NotACandidate { restaurant, poll_id } => {
+                error::Message::not_candidate(restaurant, poll_id).into()
+            }
+            SaveVoteError::BackupWithoutPoll => error::Message::backup_without_poll().into(),
+            SaveVoteError::BackupSameAsFirstChoice => error::Message::backup_same().into(),
+            SaveVoteError::RankingRequired(poll_id) => error::Message::ranking_required(poll_id).into(),
+            SaveVoteError::RankingNotAllowed => error::Message::ranking_not_allowed().into(),
+            SaveVoteError::RankingAndSingleChoice => error::Message::ranking_and_single_choice().into(),
+            SaveVoteError::RankedTwice(name) => error::Message::ranked_twice(name).into(),
+            SaveVoteError::AllocationRequired(poll_id) => error::Message::allocation_required(poll_id).into(),
+            SaveVoteError::AllocationNotAllowed => error::Message::allocation_not_allowed().into(),
+            SaveVoteError::InvalidAllocation(message) => message.into(),
+            SaveVoteError::OverBudget { cost, remaining } => error::Message::over_budget(cost, remaining).into(),
+            SaveVoteError::InvalidComment(message) => error::ApiError::BadRequest(message),
+            SaveVoteError::RefusedName(err) => err,
+            SaveVoteError::NotInvited(err) => err,
+            SaveVoteError::SealBroken(poll_id) => error::Message::seal_broken(poll_id).into(),
+            SaveVoteError::NotEligible { voter, poll_id } => error::Message::not_in_runoff(voter, poll_id).into(),
+            SaveVoteError::NotOnTeam { voter, team } => error::Message::not_on_poll_team(voter, team).into(),
+            SaveVoteError::NotThePicker { picker: Some(picker), poll_id } => {
+                error::Message::not_the_picker(picker, poll_id).into()
+            }
+            SaveVoteError::AlreadyVoted { voter, in_poll: true } => error::Message::already_voted(voter).into(),
+            SaveVoteError::AlreadyVoted { voter, in_poll: false } => error::Message::already_voted_today(voter).into(),
+            SaveVoteError::NotThePicker { picker: None, poll_id } => error::Message::no_picker(poll_id).into(),
+        }
+    }
 }
 
 // Here we declare a function to handle saving submitted votes to the database we created
-async fn save_vote(state: State<AppState>, vote: VoteRequest) -> Result<(), sqlx::Error> {
-    // We run the query with fetch_all to indicate that the entire result should be brought into a vector rather than streamed
-    // https://docs.rs/sqlx/latest/sqlx/query/struct.Query.html
-    let res = sqlx::query("SELECT * FROM votes")
-    .fetch_all(&state.db)
-    .await?;
+async fn save_vote(state: State<AppState>, mut vote: VoteRequest) -> Result<receipts::Receipt, SaveVoteError> {
+    // Only approved, active restaurants can collect votes, poll or no poll. Unknown names have to go
+    // through POST /restaurants/suggestions first, which keeps typos and junk out of the results
+    vote.voter_name = names::canonical_voter_name(&state.db, &vote.voter_name).await?;
+    if vote.voter_name.is_empty() {
+        return Err(SaveVoteError::MissingVoterName);
+    }
+    moderation::screen(&state, moderation::NameKind::Voter, &vote.voter_name)
+        .await
+        .map_err(SaveVoteError::RefusedName)?;
 
-    for r in res {   
-        println!("{} vote for {}", r.get::<&str,_>("voter_name"), r.get::<&str,_>("restaurant_name"));
+    // The poll is named by its public id; from here on it's the internal one that matters
+    let poll_id = match &vote.poll_id {
+        Some(public_id) => match public_ids::poll(&state.db, public_id).await {
+            Ok(id) => Some(id),
+            Err(error::ApiError::DbError(err)) => return Err(SaveVoteError::DbError(err)),
+            Err(_) => return Err(SaveVoteError::UnknownPoll(public_id.clone())),
+        },
+        None => None,
+    };
+    let poll = match poll_id {
+        Some(poll_id) => Some(
+            polls::find_poll(&state.db, poll_id)
+                .await?
+                .ok_or_else(|| SaveVoteError::UnknownPoll(vote.poll_id.clone().unwrap_or_default()))?,
+        ),
+        None => None,
+    };
+
+    let comment = match &vote.comment {
+        Some(comment) => comments::moderate(&state.config, comment).map_err(SaveVoteError::InvalidComment)?,
+        None => None,
+    };
+
+    // A ranked ballot's first choice doubles as its restaurant_name, so plurality views like /stats still count it
+    if let Some(ranking) = vote.ranking.take() {
+        if !vote.restaurant_name.is_empty() || vote.backup_restaurant_name.is_some() {
+            return Err(SaveVoteError::RankingAndSingleChoice);
+        }
+        let Some(public_id) = &vote.poll_id else {
+            return Err(SaveVoteError::RankingNotAllowed);
+        };
+        if ranking.is_empty() {
+            return Err(SaveVoteError::RankingRequired(public_id.clone()));
+        }
+        let mut resolved: Vec<String> = Vec::new();
+        for name in ranking {
+            let name = ballot_name(&state, poll.as_ref(), name).await?;
+            if resolved.contains(&name) {
+                return Err(SaveVoteError::RankedTwice(name));
+            }
+            resolved.push(name);
+        }
+        vote.restaurant_name = resolved[0].clone();
+        vote.ranking = Some(resolved);
+    } else if let Some(allocation) = vote.allocation.take() {
+        if !vote.restaurant_name.is_empty() || vote.backup_restaurant_name.is_some() {
+            return Err(SaveVoteError::InvalidAllocation(error::Message::allocation_and_single_choice()));
+        }
+        let Some(public_id) = &vote.poll_id else {
+            return Err(SaveVoteError::AllocationNotAllowed);
+        };
+        if allocation.is_empty() {
+            return Err(SaveVoteError::AllocationRequired(public_id.clone()));
+        }
+        let mut resolved: BTreeMap<String, i64> = BTreeMap::new();
+        for (name, votes) in allocation {
+            if votes < 1 {
+                return Err(SaveVoteError::InvalidAllocation(error::Message::allocation_too_few(name)));
+            }
+            let name = ballot_name(&state, poll.as_ref(), name).await?;
+            if resolved.insert(name.clone(), votes).is_some() {
+                return Err(SaveVoteError::InvalidAllocation(error::Message::allocated_twice(name)));
+            }
+        }
+        vote.restaurant_name = resolved.iter().max_by_key(|(_, votes)| **votes).unwrap().0.clone();
+        vote.allocation = Some(resolved);
+    } else {
+        vote.restaurant_name = ballot_name(&state, poll.as_ref(), vote.restaurant_name).await?;
+    }
+    if let Some(backup) = vote.backup_restaurant_name {
+        if vote.poll_id.is_none() {
+            return Err(SaveVoteError::BackupWithoutPoll);
+        }
+        let backup = ballot_name(&state, poll.as_ref(), backup).await?;
+        if backup == vote.restaurant_name {
+            return Err(SaveVoteError::BackupSameAsFirstChoice);
+        }
+        vote.backup_restaurant_name = Some(backup);
     }
 
-    let _ = sqlx::query("INSERT INTO votes (voter_name, restaurant_name) VALUES (?, ?)")
-                .bind(vote.voter_name)
-                .bind(vote.restaurant_name)
-                .execute(&state.db)
-                .await?;
+    let choice = receipts::Choice {
+        restaurant_name: vote.restaurant_name.clone(),
+        backup_restaurant_name: vote.backup_restaurant_name.clone(),
+        ranking: vote.ranking.clone(),
+        allocation: vote.allocation.clone(),
+    };
+
+    // One ballot a voter, in a poll or on a day outside polls; quadratic ballots add up instead. See ballots.rs
+    let adds_up = poll.as_ref().is_some_and(|poll| poll.voting_method == polls::VotingMethod::Quadratic);
+    if !adds_up && ballots::has_voted(&state.db, poll_id, &vote.voter_name).await? {
+        return Err(SaveVoteError::AlreadyVoted { voter: vote.voter_name, in_poll: poll.is_some() });
+    }
+
+    // A vote inside a poll also has to be for one of that poll's candidates, and so does its backup or ranking
+    if let Some(poll) = poll {
+        let poll_id = poll.id;
+        let public_id = poll.public_id.clone();
+        if poll.status != polls::PollStatus::Open {
+            return Err(SaveVoteError::PollNotOpen { poll_id: public_id, status: poll.status });
+        }
+        // An invited guest gets past the roster: the runoff's voters, or the team
+        if let Some(token) = &vote.invitation {
+            invitations::admit(&state, &poll, token, &vote.voter_name).await.map_err(SaveVoteError::NotInvited)?;
+        } else {
+            if let Some(eligible) = &poll.eligible_voters {
+                if !eligible.iter().any(|voter| names::fold(voter) == names::fold(&vote.voter_name)) {
+                    return Err(SaveVoteError::NotEligible { voter: vote.voter_name, poll_id: public_id });
+                }
+            }
+            if let Some(team) = &poll.team_id {
+                if !teams::is_member(&state.db, team, &vote.voter_name).await? {
+                    return Err(SaveVoteError::NotOnTeam { voter: vote.voter_name, team: team.clone() });
+                }
+            }
+        }
+        // Whatever brought them here, only the picker gets a say in a rotation poll
+        if poll.voting_method == polls::VotingMethod::Rotation
+            && poll.picker.as_deref().map(names::fold) != Some(names::fold(&vote.voter_name))
+        {
+            return Err(SaveVoteError::NotThePicker { picker: poll.picker, poll_id: public_id });
+        }
+        match (poll.voting_method.is_ranked(), vote.ranking.is_some()) {
+            (true, false) => return Err(SaveVoteError::RankingRequired(public_id)),
+            (false, true) => return Err(SaveVoteError::RankingNotAllowed),
+            _ => {}
+        }
+        match (poll.voting_method == polls::VotingMethod::Quadratic, vote.allocation.is_some()) {
+            (true, false) => return Err(SaveVoteError::AllocationRequired(public_id)),
+            (false, true) => return Err(SaveVoteError::AllocationNotAllowed),
+            _ => {}
+        }
+        let candidates = polls::choices(&state, &poll).await?;
+        let allocated = vote.allocation.iter().flat_map(|allocation| allocation.keys());
+        let choices = vote.ranking.iter().flatten().chain(&vote.backup_restaurant_name).chain(allocated);
+        for choice in std::iter::once(&vote.restaurant_name).chain(choices) {
+            if !candidates.contains(choice) {
+                return Err(SaveVoteError::NotACandidate { restaurant: choice.clone(), poll_id: public_id });
+            }
+        }
+        // A sealed poll's ballots stay encrypted until it closes
+        if poll.sealed {
+            let ballot = sealing::Ballot {
+                restaurant_name: vote.restaurant_name,
+                backup_restaurant_name: vote.backup_restaurant_name,
+                ranking: vote.ranking,
+                comment,
+                channel: vote.channel,
+            };
+            sealing::seal(&state, poll_id, &vote.voter_name, &ballot).await.map_err(|err| match err {
+                sealing::SealError::DbError(err) => SaveVoteError::DbError(err),
+                sealing::SealError::Unreadable => SaveVoteError::SealBroken(public_id),
+                sealing::SealError::AlreadyVoted => {
+                    SaveVoteError::AlreadyVoted { voter: vote.voter_name.clone(), in_poll: true }
+                }
+            })?;
+            participation::withdraw_abstention(&state.db, poll_id, &vote.voter_name).await?;
+            return Ok(receipts::issue(&state, &vote.voter_name, vote.poll_id.clone(), choice).await?);
+        }
+        // Quadratic ballots are recorded together with their spending in the credits ledger
+        if let Some(allocation) = vote.allocation {
+            let allocation: Vec<(String, i64)> = allocation.into_iter().collect();
+            quadratic::cast(&state, &poll, &vote.voter_name, &allocation, comment.as_deref(), vote.channel).await?;
+            participation::withdraw_abstention(&state.db, poll_id, &vote.voter_name).await?;
+            state.refreshes.send(vote.poll_id.clone(), refresh::Cause::Vote);
+            return Ok(receipts::issue(&state, &vote.voter_name, vote.poll_id.clone(), choice).await?);
+        }
+    }
+
+    // The vote goes into the event stream, and from there into the votes table, along with whatever other ballots
+    // have just come in; see vote_events.rs and vote_batches.rs
+    let cast = vote_events::CastVote {
+        voter_name: vote.voter_name.clone(),
+        restaurant_name: vote.restaurant_name,
+        poll_id: vote.poll_id.clone(),
+        backup_restaurant_name: vote.backup_restaurant_name,
+        ranking: vote.ranking,
+        comment,
+        channel: vote.channel,
+        cast_at: None,
+        anonymized_at: None,
+    };
+    let (vote_id, receipt) = state.ballots.cast(cast, poll_id, choice).await.map_err(|err| match err {
+        vote_batches::CastError::AlreadyVoted => {
+            SaveVoteError::AlreadyVoted { voter: vote.voter_name.clone(), in_poll: poll_id.is_some() }
+        }
+        vote_batches::CastError::DbError(err) => SaveVoteError::DbError(err),
+    })?;
+    state.refreshes.send(vote.poll_id.clone(), refresh::Cause::Vote);
+    state.events.vote_cast(vote_id);
+    // Voting after abstaining is a change of mind: they're a voter in the poll from now on
+    if let Some(poll_id) = poll_id {
+        participation::withdraw_abstention(&state.db, poll_id, &vote.voter_name).await?;
+    }
 
 
-    Ok(())
+    Ok(receipt)
+}
+
+// What a ballot's name stands for in its poll. On a poll over options it's one of them, matched the way voter names
+// are, so "friday 3pm" votes for "Friday 3pm"; anything else is a restaurant
+async fn ballot_name(state: &AppState, poll: Option<&polls::Poll>, name: String) -> Result<String, SaveVoteError> {
+    let Some(poll) = poll.filter(|poll| poll.options().is_some()) else {
+        return voteable_name(state, name).await;
+    };
+    let options = poll.options().unwrap_or_default();
+    match options.iter().find(|option| names::fold(option) == names::fold(&name)) {
+        Some(option) => Ok(option.clone()),
+        None => Err(SaveVoteError::NotACandidate { restaurant: name, poll_id: poll.public_id.clone() }),
+    }
+}
+
+// Names are typed by hand (or by a Slack bot), so resolve them to the registered restaurant first; merely
+// similar names come back as suggestions for the voter to choose from. Returns the registered name
+async fn voteable_name(state: &AppState, name: String) -> Result<String, SaveVoteError> {
+    let registered = match restaurants::resolve_name(state, &name).await? {
+        restaurants::NameResolution::Found(registered) => *registered,
+        restaurants::NameResolution::Similar(matches) => {
+            return Err(SaveVoteError::AmbiguousRestaurant { name, matches });
+        }
+        restaurants::NameResolution::Unknown => return Err(SaveVoteError::UnknownRestaurant(name)),
+    };
+    if registered.status != restaurants::RestaurantStatus::Approved {
+        return Err(SaveVoteError::PendingRestaurant(registered.name));
+    }
+    if !registered.active {
+        return Err(SaveVoteError::InactiveRestaurant(registered.name));
+    }
+    Ok(registered.name)
+}
+
+#[derive(Deserialize)]
+struct TallyQuery {
+    tiebreak: Option<tiebreaks::Tiebreak>, // overrides the poll's own choice
+    #[serde(default)]
+    detailed: bool, // adds the ballots' comments
+}
+
+// The /results endpoint handler: every restaurant that received a vote, with its voters, most popular first
+// ?tiebreak=closest puts the nearest of any tied restaurants first; see tiebreaks.rs for the others. With an ETag,
+// for If-None-Match; see conditional.rs
+async fn results(
+    state: State<AppState>,
+    Query(query): Query<TallyQuery>,
+    if_none_match: conditional::IfNoneMatch,
+) -> Result<Response, error::ApiError> {
+    let mut voting = tally(&state, None, query.tiebreak).await?;
+    if query.detailed {
+        voting.comments = Some(comments::comments(&state.db, None).await?);
+    }
+    Ok(if_none_match.respond(&voting))
+}
+
+// Groups votes by restaurant. With a poll, only that poll's votes count, and restaurants that turn out to be
+// closed at the poll's lunch time drop out unless the poll ignores opening hours. A vote whose first choice
+// an admin has ruled out of the poll counts for its backup instead, or not at all if it has none. A tally counted
+// lately is kept for asking again; see tallies.rs
+async fn tally(
+    state: &AppState,
+    poll: Option<&polls::Poll>,
+    tiebreak: Option<tiebreaks::Tiebreak>,
+) -> Result<LunchVoting, sqlx::Error> {
+    let Some(key) = tallies::Key::new(poll, tiebreak) else {
+        return count(state, poll, tiebreak).await;
+    };
+    if let Some(voting) = state.tallies.get(&key) {
+        return Ok(voting);
+    }
+    let (generation, counted_at) = (state.tallies.generation(), std::time::Instant::now());
+    let voting = count(state, poll, tiebreak).await?;
+    state.tallies.keep(key, generation, counted_at, &voting);
+    Ok(voting)
+}
+
+async fn count(
+    state: &AppState,
+    poll: Option<&polls::Poll>,
+    tiebreak: Option<tiebreaks::Tiebreak>,
+) -> Result<LunchVoting, sqlx::Error> {
+    let unavailable = match poll {
+        Some(poll) => polls::unavailable_names(&state.db, poll.id).await?,
+        None => Vec::new(),
+    };
+    let (mut votes, condorcet) = match poll {
+        Some(poll) if poll.voting_method == polls::VotingMethod::Condorcet => {
+            let (votes, result) = ranked::condorcet(state, poll, &unavailable).await?;
+            (votes, Some(result))
+        }
+        Some(poll) if poll.voting_method == polls::VotingMethod::Borda => {
+            (ranked::borda(state, poll, &unavailable).await?, None)
+        }
+        Some(poll) if poll.voting_method == polls::VotingMethod::Quadratic => {
+            (quadratic::tally(state, poll, &unavailable).await?, None)
+        }
+        _ => (count_votes(state, poll, &unavailable).await?, None),
+    };
+    // sort_by_key is stable, so restaurants with equal scores keep the order their first vote arrived in
+    votes.sort_by_key(|restaurant| std::cmp::Reverse(restaurant.score));
+    // A lottery's draw settles ties along with everything else
+    let (tiebreak, lottery) = match poll {
+        Some(poll) if poll.voting_method == polls::VotingMethod::Lottery => (None, lottery::draw(poll, &mut votes)),
+        _ => {
+            let strategy = tiebreak.or(poll.map(|poll| poll.tiebreak)).unwrap_or_default();
+            (tiebreaks::apply(state, poll, strategy, &mut votes).await?, None)
+        }
+    };
+
+    let (abstentions, headcount) = match poll {
+        Some(poll) => (
+            Some(participation::abstention_count(&state.db, poll.id).await?),
+            Some(rsvps::headcount(&state.db, poll).await?),
+        ),
+        None => (None, None),
+    };
+
+    Ok(LunchVoting { votes, unavailable, tiebreak, condorcet, lottery, abstentions, headcount, comments: None })
+}
+
+// One vote, one point: each restaurant's voters, in the order their first vote arrived
+async fn count_votes(
+    state: &AppState,
+    poll: Option<&polls::Poll>,
+    unavailable: &[String],
+) -> Result<Vec<Restaurant>, sqlx::Error> {
+    // LEFT JOIN keeps votes for restaurants that were never registered; their detail columns simply come back NULL,
+    // and with no opening hours on record they count as open. Restaurants in the trash drop out, like unavailable ones
+    // do, so their voters' backup choices count instead. Only each voter's ballot that counts is read; see ballots.rs
+    let filter = match poll {
+        Some(_) => format!("AND v.poll_id = ? AND (? OR {})", hours::OPEN_AT_SQL),
+        None => format!("AND (v.poll_id IS NULL OR v.poll_id NOT IN ({}))", polls::HIDDEN_POLLS_SQL),
+    };
+    let sql = format!(
+        "WITH unavailable AS (
+            SELECT value AS name FROM json_each(?) UNION SELECT name FROM restaurants WHERE deleted_at IS NOT NULL
+        ),
+        counted AS (
+            SELECT id, voter_name, poll_id, restaurant_name IN (SELECT name FROM unavailable) AS promoted,
+                CASE WHEN restaurant_name IN (SELECT name FROM unavailable) THEN backup_restaurant_name
+                    ELSE restaurant_name END AS restaurant_name
+            FROM votes
+            WHERE id IN ({})
+        )
+        SELECT v.voter_name, v.restaurant_name, v.promoted,
+            r.address, r.latitude, r.longitude, r.cuisine, r.price_tier, r.average_cost,
+            r.distance_meters, r.travel_minutes, r.walking_minutes, COALESCE(r.dietary_tags, '[]') AS dietary_tags
+        FROM counted v
+        LEFT JOIN restaurants r ON r.name = v.restaurant_name AND r.deleted_at IS NULL
+        WHERE v.restaurant_name IS NOT NULL AND v.restaurant_name NOT IN (SELECT name FROM unavailable)
+        {filter}
+        ORDER BY v.id",
+        ballots::counted_votes_sql()
+    );
+    let mut query = sqlx::query(&sql).bind(sqlx::types::Json(unavailable));
+    if let Some(poll) = poll {
+        query = query
+            .bind(poll.id)
+            .bind(poll.ignore_opening_hours)
+            .bind(&poll.lunch_at);
+    }
+    let rows = query.fetch_all(&state.db).await?;
+
+    let mut votes: Vec<Restaurant> = Vec::new();
+    for row in rows {
+        let name: String = row.get("restaurant_name");
+        let voter: String = row.get("voter_name");
+        let promoted: bool = row.get("promoted");
+        // iter_mut().find() gives us a mutable reference to an existing entry, if there is one
+        let restaurant = match votes.iter_mut().find(|restaurant| restaurant.name == name) {
+            Some(restaurant) => restaurant,
+            None => {
+                votes.push(Restaurant {
+                    name,
+                    voters: Vec::new(),
+                    backup_voters: Vec::new(),
+                    details: sqlx::FromRow::from_row(&row)?,
+                    score: 0,
+                    borda_score: None,
+                    effective_votes: None,
+                });
+                votes.last_mut().unwrap()
+            }
+        };
+        if promoted {
+            restaurant.backup_voters.push(voter.clone());
+        }
+        restaurant.voters.push(voter);
+        restaurant.score += 1;
+    }
+    Ok(votes)
 }
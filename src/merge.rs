@@ -0,0 +1,249 @@
+// Merging two names that turn out to be one person, "bob" and "Bob S.". Everything stored under the first name moves
+// to the second, in one transaction: ballots and the events they're projected from, ratings, nominations,
+// suggestions, poll lists, abstentions, reactions, credits, blacklist, preferences, teams, RSVPs, bills, payments,
+// budgets, orders, badges, places in rotations, turns picking, notification settings, voting links, time away and
+// a name waiting for a moderator.
+// Where both already have a row that can only exist once (a preference, a reaction, a place on a team), the one
+// kept is the second's. Receipts and the audit log stay as they are, since they record who did what under which
+// name at the time
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqliteConnection;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::{names, poll_templates, AppState};
+
+#[derive(Deserialize)]
+pub struct MergeRequest {
+    into: String,
+}
+
+// What a merge moved; counts are rows
+#[derive(Default, Serialize)]
+pub struct Merged {
+    merged: Vec<String>, // the spellings that were merged away
+    into: String,
+    votes: u64,
+    ratings: u64,
+    nominations: u64,
+    suggestions: u64,
+    poll_lists: u64,
+    templates: u64, // poll templates with them among the attendees
+    abstentions: u64,
+    reactions: u64,
+    credits: u64, // quadratic credit ledger rows
+    blacklist: u64,
+    preferences: u64,
+    memberships: u64, // places on teams
+    rsvps: u64,
+    bill_entries: u64,
+    payments: u64,
+    budgets: u64,
+    orders: u64,
+    badges: u64,
+    rotations: u64, // places in a rotation
+    picks: u64,     // rotation polls they were picker for
+    notifications: u64,
+    notification_preferences: u64, // the events they chose, one row per event and channel
+    vote_links: u64,               // one-click voting links they were sent
+    away: u64,                     // their time out of the office
+    flags: u64,                    // the name waiting for a moderator, now the other name; see moderation.rs
+    dropped: u64, // rows of theirs the other name already had its own of
+    // Polls both names voted in; the merged voter now has ballots from both there, which an admin may want to trash
+    shared_polls: Vec<String>,
+}
+
+// The lists of names a poll keeps
+#[derive(sqlx::FromRow)]
+struct PollLists {
+    id: i64,
+    attendees: JsonColumn<Vec<String>>,
+    eligible_voters: Option<JsonColumn<Vec<String>>>,
+}
+
+// POST /voters/:name/merge (admin): {"into": "Bob S."} moves everything of :name's to that voter
+pub async fn merge_voters(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<MergeRequest>,
+) -> Result<Json<Merged>, ApiError> {
+    let key = names::fold(&name);
+    let into_key = names::fold(&req.into);
+    if key.is_empty() || into_key.is_empty() {
+        return Err(ApiError::BadRequest("voter names must not be empty".to_string()));
+    }
+    if key == into_key {
+        return Err(ApiError::BadRequest(format!("{name} and {} are already the same voter", req.into)));
+    }
+    // The merged voter goes by the spelling their votes are already filed under, as canonical_voter_name would pick
+    let into = names::canonical_voter_name(&state.db, &req.into).await?;
+    let mut tx = state.db.begin().await?;
+    let spellings = names::spellings(&mut tx, &key).await?;
+    if spellings.is_empty() {
+        return Err(ApiError::NotFound(format!("there's nothing stored about {name}")));
+    }
+    let into_spellings = names::spellings(&mut tx, &into_key).await?;
+    if into_spellings.is_empty() {
+        return Err(ApiError::NotFound(format!("there's nothing stored about {}", req.into)));
+    }
+    let merged = merge(&mut tx, &spellings, &key, &into).await?;
+    tx.commit().await?;
+    println!("merged voter {name} into {into}");
+    Ok(Json(merged))
+}
+
+async fn merge(conn: &mut SqliteConnection, spellings: &[String], key: &str, into: &str) -> Result<Merged, ApiError> {
+    let spellings_json = JsonColumn(spellings);
+    let into_key = names::fold(into);
+
+    // A sealed ballot's name is bound into its encryption, so it can't be moved until the poll opens it
+    let sealed: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM sealed_ballots WHERE voter_name IN (SELECT value FROM json_each(?))")
+            .bind(spellings_json)
+            .fetch_one(&mut *conn)
+            .await?;
+    if sealed > 0 {
+        return Err(ApiError::Conflict(
+            "there are sealed ballots under this name in polls still open; try again once they close".to_string(),
+        ));
+    }
+
+    let mut merged = Merged { merged: spellings.to_vec(), into: into.to_string(), ..Default::default() };
+    let rows = |result: sqlx::sqlite::SqliteQueryResult| result.rows_affected();
+
+    let voters: Vec<(String, String)> = sqlx::query_as(
+        "SELECT DISTINCT p.public_id, v.voter_name FROM votes v JOIN polls p ON p.id = v.poll_id
+        WHERE v.deleted_at IS NULL ORDER BY p.id",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for (poll, _) in voters.iter().filter(|(_, voter)| names::fold(voter) == key) {
+        let shared = voters.iter().any(|(other, voter)| other == poll && names::fold(voter) == into_key);
+        if shared && !merged.shared_polls.contains(poll) {
+            merged.shared_polls.push(poll.clone());
+        }
+    }
+
+    // votes is a projection of vote_events, so the events move too and a replay keeps the merge
+    merged.votes = rows(
+        sqlx::query("UPDATE votes SET voter_name = ? WHERE voter_name IN (SELECT value FROM json_each(?))")
+            .bind(into)
+            .bind(spellings_json)
+            .execute(&mut *conn)
+            .await?,
+    );
+    sqlx::query(
+        "UPDATE vote_events SET payload = json_set(payload, '$.voter_name', ?)
+        WHERE type IN ('cast', 'changed')
+        AND json_extract(payload, '$.voter_name') IN (SELECT value FROM json_each(?))",
+    )
+    .bind(into)
+    .bind(spellings_json)
+    .execute(&mut *conn)
+    .await?;
+    for (table, column, count) in [
+        ("ratings", "rater_name", &mut merged.ratings),
+        ("nominations", "nominated_by", &mut merged.nominations),
+        ("restaurants", "suggested_by", &mut merged.suggestions),
+        ("polls", "picker", &mut merged.picks),
+    ] {
+        // The table and column names are our own, never the client's, so formatting them into the SQL is safe
+        let sql = format!("UPDATE {table} SET {column} = ? WHERE {column} IN (SELECT value FROM json_each(?))");
+        *count = rows(sqlx::query(&sql).bind(into).bind(spellings_json).execute(&mut *conn).await?);
+    }
+
+    // The tables keyed by the folded name: OR IGNORE leaves alone the rows the other voter has their own of, and
+    // those are what's left under the old key afterwards
+    for (table, has_name, count) in [
+        ("abstentions", true, &mut merged.abstentions),
+        ("reactions", true, &mut merged.reactions),
+        ("vote_credits", true, &mut merged.credits),
+        ("voter_blacklist", false, &mut merged.blacklist),
+        ("voter_preferences", true, &mut merged.preferences),
+        ("team_members", true, &mut merged.memberships),
+        ("rsvps", true, &mut merged.rsvps),
+        ("bill_entries", true, &mut merged.bill_entries),
+        ("bill_payments", true, &mut merged.payments),
+        ("voter_budgets", true, &mut merged.budgets),
+        ("poll_orders", true, &mut merged.orders),
+        ("voter_badges", true, &mut merged.badges),
+        ("rotations", true, &mut merged.rotations),
+        ("voter_notifications", true, &mut merged.notifications),
+        ("notification_preferences", false, &mut merged.notification_preferences),
+        ("vote_links", true, &mut merged.vote_links),
+        ("away_periods", true, &mut merged.away),
+    ] {
+        let set = if has_name { "voter_name = ?1, voter_key = ?2" } else { "voter_key = ?2" };
+        let sql = format!("UPDATE OR IGNORE {table} SET {set} WHERE voter_key = ?3");
+        *count = rows(sqlx::query(&sql).bind(into).bind(&into_key).bind(key).execute(&mut *conn).await?);
+        let sql = format!("DELETE FROM {table} WHERE voter_key = ?");
+        merged.dropped += rows(sqlx::query(&sql).bind(key).execute(&mut *conn).await?);
+    }
+    // A name waiting for review now waits as the other one, whose ballots those are now. One that's been reviewed
+    // stays as it is: an approval or refusal is of that spelling, wherever its rows went
+    merged.flags = rows(
+        sqlx::query(
+            "UPDATE OR IGNORE flagged_names SET name = ?, name_key = ?
+            WHERE kind = 'voter' AND name_key = ? AND status = 'pending'",
+        )
+        .bind(into)
+        .bind(&into_key)
+        .bind(key)
+        .execute(&mut *conn)
+        .await?,
+    );
+    merged.dropped += rows(
+        sqlx::query("DELETE FROM flagged_names WHERE kind = 'voter' AND name_key = ? AND status = 'pending'")
+            .bind(key)
+            .execute(&mut *conn)
+            .await?,
+    );
+    sqlx::query("DELETE FROM erasure_requests WHERE voter_key = ?").bind(key).execute(&mut *conn).await?;
+    // An invitation they claimed stays theirs under the new name
+    sqlx::query("UPDATE poll_invitations SET guest_name = ?, guest_key = ? WHERE guest_key = ?")
+        .bind(into)
+        .bind(&into_key)
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+
+    let polls: Vec<PollLists> =
+        sqlx::query_as("SELECT id, attendees, eligible_voters FROM polls").fetch_all(&mut *conn).await?;
+    // Their name is swapped for the other one, or just dropped when the other one is on the list already
+    let rename = |list: &mut Vec<String>| {
+        if !list.iter().any(|name| names::fold(name) == key) {
+            return false;
+        }
+        let mut listed = list.iter().any(|name| names::fold(name) == into_key);
+        let mut renamed = Vec::new();
+        for name in list.drain(..) {
+            if names::fold(&name) != key {
+                renamed.push(name);
+            } else if !listed {
+                renamed.push(into.to_string());
+                listed = true;
+            }
+        }
+        *list = renamed;
+        true
+    };
+    for PollLists { id, attendees: JsonColumn(mut attendees), mut eligible_voters } in polls {
+        let attended = rename(&mut attendees);
+        let eligible = eligible_voters.as_mut().is_some_and(|JsonColumn(list)| rename(list));
+        if attended || eligible {
+            sqlx::query("UPDATE polls SET attendees = ?, eligible_voters = ? WHERE id = ?")
+                .bind(JsonColumn(&attendees))
+                .bind(eligible_voters)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+            merged.poll_lists += 1;
+        }
+    }
+    merged.templates = poll_templates::rename_attendees(conn, rename).await?;
+    Ok(merged)
+}
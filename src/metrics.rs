@@ -0,0 +1,26 @@
+// GET /metrics: counters in the Prometheus text format, for a scraper to alert on. So far that's the backups: how
+// many runs have succeeded and failed since startup, and when one last succeeded, 0 for not yet, so an alert can
+// fire once that's longer ago than BACKUP_INTERVAL_HOURS should allow
+// https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use std::fmt::Write;
+
+use crate::AppState;
+
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let runs = state.backups.runs();
+    let mut body = String::new();
+    let _ = writeln!(body, "# HELP lunch_voting_backups_total Backups taken since startup, by how they went");
+    let _ = writeln!(body, "# TYPE lunch_voting_backups_total counter");
+    let _ = writeln!(body, "lunch_voting_backups_total{{result=\"success\"}} {}", runs.succeeded);
+    let _ = writeln!(body, "lunch_voting_backups_total{{result=\"failure\"}} {}", runs.failed);
+    let _ = writeln!(
+        body,
+        "# HELP lunch_voting_backup_last_success_timestamp_seconds When the last backup was uploaded, in Unix time"
+    );
+    let _ = writeln!(body, "# TYPE lunch_voting_backup_last_success_timestamp_seconds gauge");
+    let _ = writeln!(body, "lunch_voting_backup_last_success_timestamp_seconds {}", runs.last_success.unwrap_or(0));
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -0,0 +1,170 @@
+// Screening the names people type in. A voter or suggested restaurant name containing a NAME_BLOCKLIST word is
+// refused outright. One containing a NAME_FLAGLIST word is let through but queued for an admin, who either approves it
+// (it's never flagged again) or rejects it: the name is refused from then on, and what was already stored under it
+// goes to the trash. Names the admin types in themselves aren't screened
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::{names, vote_events, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum NameKind {
+    Voter,
+    Restaurant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum FlagStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Flag {
+    id: i64,
+    kind: NameKind,
+    name: String,
+    word: String,
+    status: FlagStatus,
+    created_at: String,
+    reviewed_at: Option<String>,
+}
+
+const FLAG_SELECT: &str = "SELECT id, kind, name, word, status, created_at, reviewed_at FROM flagged_names";
+
+// The first word of the list that appears in the name. Both sides are folded, so "Ünder" can't slip past "under",
+// and the name is also tried with its separators squashed out, so spelling a word as "b.a.d" doesn't help
+fn matched<'a>(list: &'a [String], name: &str) -> Option<&'a String> {
+    let key = names::fold(name);
+    let words: Vec<&str> = key.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    let squashed = words.concat();
+    list.iter().find(|blocked| {
+        let blocked = names::fold(blocked);
+        squashed == blocked || words.contains(&blocked.as_str())
+    })
+}
+
+// Every voter or suggested restaurant name goes through here before it's stored. Err when the name is refused;
+// a name that is only flagged is queued (once) and let through
+pub async fn screen(state: &AppState, kind: NameKind, name: &str) -> Result<(), ApiError> {
+    if matched(&state.config.name_blocklist, name).is_some() {
+        return Err(ApiError::BadRequest(format!("{name} contains a blocked word")));
+    }
+    let key = names::fold(name);
+    let reviewed: Option<FlagStatus> =
+        sqlx::query_scalar("SELECT status FROM flagged_names WHERE kind = ? AND name_key = ?")
+            .bind(kind)
+            .bind(&key)
+            .fetch_optional(&state.db)
+            .await?;
+    match reviewed {
+        Some(FlagStatus::Rejected) => Err(ApiError::Forbidden(format!("{name} was turned down by a moderator"))),
+        Some(_) => Ok(()),
+        None => {
+            if let Some(word) = matched(&state.config.name_flaglist, name) {
+                sqlx::query("INSERT OR IGNORE INTO flagged_names (kind, name, name_key, word) VALUES (?, ?, ?, ?)")
+                    .bind(kind)
+                    .bind(name)
+                    .bind(&key)
+                    .bind(word)
+                    .execute(&state.db)
+                    .await?;
+                println!("moderation: queued {kind:?} name {name} for review");
+            }
+            Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FlagsQuery {
+    status: Option<FlagStatus>, // pending by default
+}
+
+// GET /moderation/flags?status=pending|approved|rejected (admin): the review queue, oldest first
+pub async fn list_flags(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<FlagsQuery>,
+) -> Result<Json<Vec<Flag>>, ApiError> {
+    let flags = sqlx::query_as::<_, Flag>(&format!("{FLAG_SELECT} WHERE status = ? ORDER BY id"))
+        .bind(query.status.unwrap_or(FlagStatus::Pending))
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(flags))
+}
+
+// POST /moderation/flags/:id/approve (admin): the name is fine and won't be flagged again
+pub async fn approve_flag(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Flag>, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let flag = review(&mut tx, id, FlagStatus::Approved).await?;
+    tx.commit().await?;
+    Ok(Json(flag))
+}
+
+// POST /moderation/flags/:id/reject (admin): the name is refused from now on. A voter's votes go to the trash,
+// and so does a restaurant of that name, where an admin can still restore them
+pub async fn reject_flag(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Flag>, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let flag = review(&mut tx, id, FlagStatus::Rejected).await?;
+    let key = names::fold(&flag.name);
+    match flag.kind {
+        NameKind::Voter => {
+            let votes: Vec<(i64, String)> =
+                sqlx::query_as("SELECT id, voter_name FROM votes WHERE deleted_at IS NULL ORDER BY id")
+                    .fetch_all(&mut *tx)
+                    .await?;
+            for (vote_id, _) in votes.iter().filter(|(_, voter)| names::fold(voter) == key) {
+                vote_events::delete(&mut tx, *vote_id).await?;
+            }
+        }
+        NameKind::Restaurant => {
+            let restaurants: Vec<(i64, String)> =
+                sqlx::query_as("SELECT id, name FROM restaurants WHERE deleted_at IS NULL").fetch_all(&mut *tx).await?;
+            for (restaurant_id, _) in restaurants.iter().filter(|(_, name)| names::fold(name) == key) {
+                sqlx::query("UPDATE restaurants SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+                    .bind(restaurant_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(Json(flag))
+}
+
+// Only pending flags can be reviewed; the status check in the WHERE clause makes that atomic
+async fn review(tx: &mut sqlx::SqliteConnection, id: i64, outcome: FlagStatus) -> Result<Flag, ApiError> {
+    let updated =
+        sqlx::query("UPDATE flagged_names SET status = ?, reviewed_at = CURRENT_TIMESTAMP WHERE id = ? AND status = ?")
+            .bind(outcome)
+            .bind(id)
+            .bind(FlagStatus::Pending)
+            .execute(&mut *tx)
+            .await?;
+    let flag = sqlx::query_as::<_, Flag>(&format!("{FLAG_SELECT} WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("no flagged name with id {id}")))?;
+    if updated.rows_affected() == 0 {
+        return Err(ApiError::Conflict(format!("{} has already been reviewed", flag.name)));
+    }
+    Ok(flag)
+}
@@ -0,0 +1,115 @@
+// Names arrive from people typing into web forms and chat bots, so the same voter or restaurant shows up with
+// different accents, capitalization and stray spaces. These helpers decide what gets stored and what gets compared
+use sqlx::{SqliteConnection, SqlitePool};
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::i18n::Locale;
+
+// The form a name is stored in: Unicode NFC (so "é" is always one code point, not "e" plus an accent),
+// trimmed, with runs of whitespace collapsed to a single space. Capitalization is kept for display
+// https://unicode.org/reports/tr15/
+pub fn clean(name: &str) -> String {
+    name.nfc().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The form names are compared in. On top of clean(), accents are stripped (decompose with NFKD, drop the
+// combining marks) and letters are case folded, so "Café Noir" and "cafe noir " fold to the same key.
+// to_lowercase covers almost all of case folding; the two exceptions that matter are spelled out
+// https://www.unicode.org/reports/tr44/#CaseFolding.txt
+// The collation that compares voter names by fold, registered on every connection in main.rs; see ballots.rs
+pub const VOTER_COLLATION: &str = "voters";
+
+pub fn fold(name: &str) -> String {
+    clean(name)
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+        .replace('ß', "ss")
+        .replace('ς', "σ")
+}
+
+// Names in the order a reader of `locale` looks for them in, which byte order isn't: that puts "Zebra Grill" before
+// "Ångström Café", and every capitalized name before every lowercase one. Names compare on their letters alone first,
+// folded as above, so Å is an A and ß is ss; then on their accents, unaccented first; then as written, so that two
+// that fold alike still come in the same order every time. German dictionary order and English read ä as a;
+// Spanish keeps ñ a letter of its own, after n. SQLite sorts with these as collations, one per locale, registered
+// on every connection in main.rs: ORDER BY r.name COLLATE names_es
+// https://unicode.org/reports/tr10/
+// https://www.sqlite.org/datatype3.html#collation
+pub fn collate(locale: Locale, a: &str, b: &str) -> Ordering {
+    let key = |name: &str| (letters(locale, name), clean(name).to_lowercase().nfd().collect::<String>());
+    key(a).cmp(&key(b)).then_with(|| a.cmp(b))
+}
+
+pub fn collation(locale: Locale) -> String {
+    format!("names_{}", locale.code())
+}
+
+// A name's letters, with the ones a locale sorts after their base letter marked
+fn letters(locale: Locale, name: &str) -> Vec<(char, bool)> {
+    let mut letters = Vec::new();
+    for c in clean(name).chars().flat_map(char::to_lowercase) {
+        match c {
+            'ñ' if locale == Locale::Es => letters.push(('n', true)),
+            'ß' => letters.extend([('s', false), ('s', false)]),
+            'ς' => letters.push(('σ', false)),
+            _ => letters.extend(std::iter::once(c).nfkd().filter(|c| !is_combining_mark(*c)).map(|c| (c, false))),
+        }
+    }
+    letters
+}
+
+// A name fit for a URL path, for things identified by their name: "Acme Corp." becomes acme-corp
+pub fn slug(name: &str) -> String {
+    let folded = fold(name);
+    let words: Vec<&str> = folded.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()).collect();
+    words.join("-")
+}
+
+// The spelling a voter's name is stored under. Whoever votes as "Zoë" first sets the spelling, and later votes
+// from "zoe " are filed under it, so tallies count one person rather than two
+pub async fn canonical_voter_name(db: &SqlitePool, name: &str) -> Result<String, sqlx::Error> {
+    let cleaned = clean(name);
+    let known: Vec<String> = sqlx::query_scalar("SELECT voter_name FROM votes GROUP BY voter_name ORDER BY MIN(id)")
+        .fetch_all(db)
+        .await?;
+    let key = fold(&cleaned);
+    Ok(known.into_iter().find(|voter| fold(voter) == key).unwrap_or(cleaned))
+}
+
+// Every spelling of a voter's name stored anywhere that folds to `key`: on ballots, sealed or not, in the events the
+// votes come from (casts, and corrections moving a vote to them), on ratings, nominations and suggestions, on
+// teams and in rotations, in notification settings, voting links and time away, in polls' attendee and runoff
+// voter lists and pickers, among poll templates' attendees, and waiting for a moderator
+pub async fn spellings(conn: &mut SqliteConnection, key: &str) -> Result<Vec<String>, sqlx::Error> {
+    let known: Vec<String> = sqlx::query_scalar(
+        "SELECT voter_name FROM votes
+        UNION SELECT voter_name FROM sealed_ballots
+        UNION SELECT json_extract(payload, '$.voter_name') FROM vote_events
+            WHERE type IN ('cast', 'changed') AND json_extract(payload, '$.voter_name') IS NOT NULL
+        UNION SELECT rater_name FROM ratings
+        UNION SELECT nominated_by FROM nominations
+        UNION SELECT suggested_by FROM restaurants WHERE suggested_by IS NOT NULL
+        UNION SELECT voter_name FROM team_members
+        UNION SELECT voter_name FROM rsvps
+        UNION SELECT voter_name FROM bill_entries
+        UNION SELECT voter_name FROM bill_payments
+        UNION SELECT voter_name FROM poll_orders
+        UNION SELECT voter_name FROM voter_badges
+        UNION SELECT voter_name FROM rotations
+        UNION SELECT voter_name FROM voter_notifications
+        UNION SELECT voter_name FROM vote_links
+        UNION SELECT voter_name FROM away_periods
+        UNION SELECT picker FROM polls WHERE picker IS NOT NULL
+        UNION SELECT value FROM polls, json_each(polls.attendees)
+        UNION SELECT value FROM polls, json_each(polls.eligible_voters)
+        UNION SELECT value FROM poll_templates, json_each(poll_templates.settings, '$.attendees')
+        UNION SELECT name FROM flagged_names WHERE kind = 'voter'",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(known.into_iter().filter(|name| fold(name) == key).collect())
+}
@@ -0,0 +1,378 @@
+// Notifications to voters, and what each of them wants. A voter's settings are an email address, a webhook of their
+// own (a chat DM, a phone push service) and, for each event, the channels they want it on:
+//   poll_opened  a poll they're on the roster of opens for votes; polls without a roster go to everyone who wants it
+//   reminder     they haven't voted yet and the poll closes soon; see reminders.rs
+//   winner       a poll they're on the roster of or voted in closes on a winner
+//   digest       the weekly digest; see digest.rs
+// Out of the box reminders go on both channels, the digest by email, and the others nowhere. Everything that
+// notifies voters goes through dispatch, which sends each one an event only on the channels they chose for it, and
+// never to an address on the suppression list (see suppressions.rs), in the voter's language (see i18n.rs). A
+// personal webhook gets {"event", "voter_name", "subject", "text", "data", "unsubscribe_url"}, data being the event's
+// own details
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+
+use crate::error::{ApiError, Message};
+use crate::i18n::{self, Locale};
+use crate::polls::{self, Poll};
+use crate::{email, names, participation, suppressions, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Event {
+    PollOpened,
+    Reminder,
+    Winner,
+    Digest,
+}
+
+const EVENTS: [Event; 4] = [Event::PollOpened, Event::Reminder, Event::Winner, Event::Digest];
+
+// Stored as lowercase text, like the events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Channel {
+    Email,
+    Webhook,
+}
+
+impl Channel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Webhook => "webhook",
+        }
+    }
+}
+
+impl Event {
+    fn default_channels(self) -> Vec<Channel> {
+        match self {
+            Event::Reminder => vec![Channel::Email, Channel::Webhook],
+            Event::Digest => vec![Channel::Email],
+            Event::PollOpened | Event::Winner => Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Settings {
+    voter_name: String,
+    email: Option<String>,
+    webhook_url: Option<String>,
+    events: BTreeMap<Event, Vec<Channel>>, // every event, with no channels for those they don't want
+    updated_at: String,
+}
+
+// PUT replaces the settings, so a field left out goes back to its default, and so does an event left out of events
+#[derive(Deserialize)]
+pub struct NewSettings {
+    email: Option<String>,
+    webhook_url: Option<String>,
+    #[serde(default)]
+    events: BTreeMap<Event, Vec<Channel>>,
+}
+
+#[derive(Deserialize)]
+pub struct EventChannels {
+    channels: Vec<Channel>,
+}
+
+pub async fn settings(db: &SqlitePool, voter_key: &str) -> Result<Option<Settings>, sqlx::Error> {
+    let row: Option<(String, Option<String>, Option<String>, String)> = sqlx::query_as(
+        "SELECT voter_name, email, webhook_url, updated_at FROM voter_notifications WHERE voter_key = ?",
+    )
+    .bind(voter_key)
+    .fetch_optional(db)
+    .await?;
+    let Some((voter_name, email, webhook_url, updated_at)) = row else {
+        return Ok(None);
+    };
+    let chosen: Vec<(Event, Channel)> =
+        sqlx::query_as("SELECT event, channel FROM notification_preferences WHERE voter_key = ? ORDER BY channel")
+            .bind(voter_key)
+            .fetch_all(db)
+            .await?;
+    let mut events: BTreeMap<Event, Vec<Channel>> = EVENTS.iter().map(|event| (*event, Vec::new())).collect();
+    for (event, channel) in chosen {
+        events.entry(event).or_default().push(channel);
+    }
+    Ok(Some(Settings { voter_name, email, webhook_url, events, updated_at }))
+}
+
+// The voters who want `event` on some channel
+pub async fn subscribers(db: &SqlitePool, event: Event) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT n.voter_name FROM voter_notifications n
+        WHERE EXISTS (SELECT 1 FROM notification_preferences p WHERE p.voter_key = n.voter_key AND p.event = ?)
+        ORDER BY n.voter_key",
+    )
+    .bind(event)
+    .fetch_all(db)
+    .await
+}
+
+// The folded names of the voters who have settings but don't want `event` anywhere, for notifications that also go
+// out on a shared channel, like reminders: those leave them out too
+pub async fn opted_out(db: &SqlitePool, event: Event) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT voter_key FROM voter_notifications n
+        WHERE NOT EXISTS (SELECT 1 FROM notification_preferences p WHERE p.voter_key = n.voter_key AND p.event = ?)",
+    )
+    .bind(event)
+    .fetch_all(db)
+    .await
+}
+
+// Sends `event` to each of `voters` on the channels they chose for it, with the subject and text `message` writes for
+// each in their locale, which is that of `team_id`, the team whose poll it's about, if it has one. A channel failing
+// is logged and the rest carry on. Returns how many voters it reached on some channel
+pub async fn dispatch(
+    state: &AppState,
+    event: Event,
+    voters: &[String],
+    team_id: Option<&str>,
+    data: &serde_json::Value,
+    message: impl Fn(&str, Locale) -> (String, String),
+) -> Result<u64, ApiError> {
+    let mut reached = 0;
+    for voter in voters {
+        let Some(settings) = settings(&state.db, &names::fold(voter)).await? else {
+            continue;
+        };
+        let channels = settings.events.get(&event).cloned().unwrap_or_default();
+        let locale = i18n::voter_locale(&state.db, &names::fold(voter), team_id).await?;
+        let (subject, text) = message(&settings.voter_name, locale);
+        let mut sent = false;
+        for channel in channels {
+            let address = match channel {
+                Channel::Email if email::configured(&state.config) => settings.email.as_deref(),
+                Channel::Email => None,
+                Channel::Webhook => settings.webhook_url.as_deref(),
+            };
+            let Some(address) = address else {
+                continue;
+            };
+            if suppressions::suppressed(&state.db, channel, address).await? {
+                continue;
+            }
+            let result = match channel {
+                Channel::Email => email::send(state, address, &settings.voter_name, &subject, &text, locale).await,
+                Channel::Webhook => {
+                    let body = json!({
+                        "event": event,
+                        "voter_name": settings.voter_name,
+                        "subject": subject,
+                        "text": text,
+                        "data": data,
+                        "unsubscribe_url": suppressions::unsubscribe_url(state, Channel::Webhook, address),
+                    });
+                    post(state, address, &body).await
+                }
+            };
+            match result {
+                Ok(()) => sent = true,
+                Err(err) => eprintln!("notifications: could not send {event:?} to {voter} by {channel:?}: {err:?}"),
+            }
+        }
+        reached += u64::from(sent);
+    }
+    Ok(reached)
+}
+
+async fn post(state: &AppState, url: &str, body: &serde_json::Value) -> Result<(), ApiError> {
+    let response = state.http.post(url).json(body).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("the voter's webhook returned {status}: {body}")));
+    }
+    Ok(())
+}
+
+// A poll's roster: its attendees and, on a runoff, the voters it's for
+pub fn roster(poll: &Poll) -> Vec<String> {
+    let mut roster: Vec<String> = Vec::new();
+    for voter in poll.attendees.iter().chain(poll.eligible_voters.iter().flat_map(|voters| voters.iter())) {
+        if !roster.iter().any(|known| names::fold(known) == names::fold(voter)) {
+            roster.push(voter.clone());
+        }
+    }
+    roster
+}
+
+// When a poll opens for votes, from the start or once its nominations close
+pub fn spawn_poll_opened(state: &AppState, poll_id: i64) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        match poll_opened(&state, poll_id).await {
+            Ok(0) => {}
+            Ok(reached) => println!("poll {poll_id}: told {reached} voters it's open"),
+            Err(err) => eprintln!("poll {poll_id}: could not tell voters it's open: {err:?}"),
+        }
+    });
+}
+
+async fn poll_opened(state: &AppState, poll_id: i64) -> Result<u64, ApiError> {
+    let poll = polls::find_poll(&state.db, poll_id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    let mut voters = roster(&poll);
+    if voters.is_empty() {
+        voters = subscribers(&state.db, Event::PollOpened).await?;
+    }
+    let code = poll.join_code.clone().unwrap_or_else(|| poll.public_id.clone());
+    let data = json!({"poll_id": poll.public_id, "join_code": poll.join_code, "title": poll.title,
+        "metadata": poll.metadata, "lunch_at": poll.lunch_at, "closes_at": poll.closes_at});
+    dispatch(state, Event::PollOpened, &voters, poll.team_id.as_deref(), &data, |voter, locale| {
+        let title = match &poll.title {
+            Some(title) => title.clone(),
+            None => i18n::text(locale, "poll-opened-title", &[("code", &code)]),
+        };
+        let mut args = vec![("voter", voter), ("code", code.as_str()), ("lunch_at", poll.lunch_at.as_str())];
+        let id = match &poll.closes_at {
+            Some(closes_at) => {
+                args.push(("closes_at", closes_at));
+                "poll-opened-text-until"
+            }
+            None => "poll-opened-text",
+        };
+        let subject = i18n::text(locale, "poll-opened-subject", &[("title", &title)]);
+        (subject, format!("{}\n", i18n::text(locale, id, &args)))
+    })
+    .await
+}
+
+// When a poll closes on a winner, to those on its roster and those who voted or abstained in it
+pub fn spawn_winner(state: &AppState, poll_id: i64) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        match winner(&state, poll_id).await {
+            Ok(0) => {}
+            Ok(reached) => println!("poll {poll_id}: told {reached} voters the winner"),
+            Err(err) => eprintln!("poll {poll_id}: could not tell voters the winner: {err:?}"),
+        }
+    });
+}
+
+async fn winner(state: &AppState, poll_id: i64) -> Result<u64, ApiError> {
+    let poll = polls::find_poll(&state.db, poll_id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    let winner = polls::winner(state, &poll).await?;
+    let mut voters = roster(&poll);
+    for participant in participation::participants(&state.db, poll_id).await? {
+        if !voters.iter().any(|known| names::fold(known) == names::fold(&participant)) {
+            voters.push(participant);
+        }
+    }
+    if voters.is_empty() {
+        voters = subscribers(&state.db, Event::Winner).await?;
+    }
+    let code = poll.join_code.clone().unwrap_or_else(|| poll.public_id.clone());
+    let data = json!({"poll_id": poll.public_id, "join_code": poll.join_code, "title": poll.title,
+        "metadata": poll.metadata, "lunch_at": poll.lunch_at, "winner": winner});
+    dispatch(state, Event::Winner, &voters, poll.team_id.as_deref(), &data, |voter, locale| {
+        let args = [("voter", voter), ("code", &code), ("lunch_at", &poll.lunch_at), ("winner", &winner)];
+        (i18n::text(locale, "winner-subject", &args), format!("{}\n", i18n::text(locale, "winner-text", &args)))
+    })
+    .await
+}
+
+// GET /voters/:name/notifications
+pub async fn get_settings(State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<Settings>, ApiError> {
+    settings(&state.db, &names::fold(&name))
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::from(Message::no_notification_settings(name)))
+}
+
+// PUT /voters/:name/notifications: {"email": "zoe@example.com", "webhook_url": "https://ntfy.sh/zoe-lunch",
+// "events": {"poll_opened": ["webhook"], "winner": ["email", "webhook"], "digest": []}}
+pub async fn put_settings(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<NewSettings>,
+) -> Result<Json<Settings>, ApiError> {
+    let voter_name = names::canonical_voter_name(&state.db, &name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    let email = req.email.map(|email| email.trim().to_string()).filter(|email| !email.is_empty());
+    // Only sanity checks; whether they reach anyone is the email API's and the webhook's business
+    if email.as_ref().is_some_and(|email| !email.contains('@') || email.contains(char::is_whitespace)) {
+        return Err(ApiError::BadRequest("email must be an email address".to_string()));
+    }
+    let webhook_url = req.webhook_url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+    if webhook_url.as_ref().is_some_and(|url| {
+        reqwest::Url::parse(url).map_or(true, |url| url.scheme() != "https" && url.scheme() != "http")
+    }) {
+        return Err(ApiError::BadRequest("webhook_url must be an http or https URL".to_string()));
+    }
+    let key = names::fold(&voter_name);
+    let mut tx = state.db.begin().await?;
+    sqlx::query(
+        "INSERT INTO voter_notifications (voter_key, voter_name, email, webhook_url) VALUES (?, ?, ?, ?)
+        ON CONFLICT (voter_key) DO UPDATE SET voter_name = excluded.voter_name, email = excluded.email,
+            webhook_url = excluded.webhook_url, updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(&key)
+    .bind(&voter_name)
+    .bind(email)
+    .bind(webhook_url)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM notification_preferences WHERE voter_key = ?").bind(&key).execute(&mut *tx).await?;
+    for event in EVENTS {
+        let channels = req.events.get(&event).cloned().unwrap_or_else(|| event.default_channels());
+        for channel in channels {
+            sqlx::query("INSERT OR IGNORE INTO notification_preferences (voter_key, event, channel) VALUES (?, ?, ?)")
+                .bind(&key)
+                .bind(event)
+                .bind(channel)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(Json(settings(&state.db, &key).await?.ok_or(sqlx::Error::RowNotFound)?))
+}
+
+// PUT /voters/:name/notifications/:event: {"channels": ["email"]} changes just that event, [] turning it off. Not
+// before the voter has settings to change
+pub async fn put_event(
+    State(state): State<AppState>,
+    Path((name, event)): Path<(String, Event)>,
+    Json(req): Json<EventChannels>,
+) -> Result<Json<Settings>, ApiError> {
+    let key = names::fold(&name);
+    if settings(&state.db, &key).await?.is_none() {
+        return Err(Message::no_notification_settings(name).into());
+    }
+    set_channels(&state.db, &key, event, &req.channels).await?;
+    Ok(Json(settings(&state.db, &key).await?.ok_or(sqlx::Error::RowNotFound)?))
+}
+
+pub async fn set_channels(db: &SqlitePool, key: &str, event: Event, channels: &[Channel]) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM notification_preferences WHERE voter_key = ? AND event = ?")
+        .bind(key)
+        .bind(event)
+        .execute(&mut *tx)
+        .await?;
+    for channel in channels {
+        sqlx::query("INSERT OR IGNORE INTO notification_preferences (voter_key, event, channel) VALUES (?, ?, ?)")
+            .bind(key)
+            .bind(event)
+            .bind(channel)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("UPDATE voter_notifications SET updated_at = CURRENT_TIMESTAMP WHERE voter_key = ?")
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
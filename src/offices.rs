@@ -0,0 +1,258 @@
+// Offices in different cities sharing one instance. Each office has its own location, clock, lunch time and daily
+// poll, and its own pool of restaurants: a restaurant registered for an office is only ever on that office's
+// ballots, while one registered without an office is on everyone's. A poll for an office runs on the office's
+// clock, so its lunch_at and deadlines are the office's local times, and its distances are measured from the office.
+// The clock is an offset from UTC: a fixed one, for an admin to move when the clocks go forward or back, or one that
+// follows the office's time zone by itself; see timezones.rs
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::geo::Coordinates;
+use crate::{hours, names, poll_templates, timezones, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Office {
+    pub id: String,
+    name: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    utc_offset_minutes: i64, // the office's local time is UTC plus this
+    pub timezone: Option<String>, // Australia/Sydney; utc_offset_minutes then follows it
+    pub lunch_time: String,
+    pub daily_poll_time: Option<String>,
+    pub daily_poll_template: Option<String>, // the poll template the daily poll is opened from; see poll_templates.rs
+    created_at: String,
+}
+
+const OFFICE_COLUMNS: &str =
+    "id, name, latitude, longitude, utc_offset_minutes, timezone, lunch_time, daily_poll_time, daily_poll_template,
+    created_at";
+
+// The clock for SQL over polls: each poll's current local time, "YYYY-MM-DD HH:MM", on its time zone's clock, its
+// office's or, for polls with neither, the server's
+pub const POLL_NOW_SQL: &str = "strftime('%Y-%m-%d %H:%M', 'now', COALESCE(
+    CASE WHEN polls.utc_offset_minutes IS NOT NULL THEN printf('%+d minutes', polls.utc_offset_minutes) END,
+    (SELECT printf('%+d minutes', utc_offset_minutes) FROM offices WHERE offices.id = polls.office_id), 'localtime'))";
+
+// The same clock as a SQLite modifier, for days: date(at, POLL_CLOCK_SQL), where polls is the poll `at` belongs to
+pub const POLL_CLOCK_SQL: &str = "COALESCE(
+    CASE WHEN polls.utc_offset_minutes IS NOT NULL THEN printf('%+d minutes', polls.utc_offset_minutes) END,
+    (SELECT printf('%+d minutes', utc_offset_minutes) FROM offices WHERE offices.id = polls.office_id), 'localtime')";
+
+impl Office {
+    pub fn location(&self) -> Option<Coordinates> {
+        Some(Coordinates {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+        })
+    }
+}
+
+// The SQLite modifier that takes 'now' to local time, put wherever 'localtime' would go: the office's offset, or the
+// server's own local time when there's no office
+// https://www.sqlite.org/lang_datefunc.html#modifiers
+pub fn clock(office: Option<&Office>) -> String {
+    match office {
+        Some(office) => format!("{:+} minutes", office.utc_offset_minutes),
+        None => "localtime".to_string(),
+    }
+}
+
+// Where distances and walking times are measured from: the office, or OFFICE_LATITUDE and OFFICE_LONGITUDE for the
+// instance as a whole. An office without coordinates doesn't fall back on those, which may be another city's
+pub fn location(state: &AppState, office: Option<&Office>) -> Option<Coordinates> {
+    match office {
+        Some(office) => office.location(),
+        None => state.config.office_location,
+    }
+}
+
+// The office a poll or restaurant belongs to; None for none
+pub async fn find(db: &SqlitePool, id: Option<&str>) -> Result<Option<Office>, sqlx::Error> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    sqlx::query_as::<_, Office>(&format!("SELECT {OFFICE_COLUMNS} FROM offices WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+}
+
+// The office an office_id in a request body names, which must exist
+pub async fn lookup(db: &SqlitePool, id: Option<&str>) -> Result<Option<Office>, ApiError> {
+    match find(db, id).await? {
+        None if id.is_some() => Err(ApiError::BadRequest(format!("no office with id {}", id.unwrap_or_default()))),
+        office => Ok(office),
+    }
+}
+
+pub async fn all(db: &SqlitePool) -> Result<Vec<Office>, sqlx::Error> {
+    sqlx::query_as::<_, Office>(&format!("SELECT {OFFICE_COLUMNS} FROM offices ORDER BY id")).fetch_all(db).await
+}
+
+#[derive(Deserialize)]
+pub struct NewOffice {
+    name: String,
+    #[serde(flatten)]
+    settings: OfficeUpdate,
+}
+
+// Fields missing from a PATCH body leave the stored value untouched
+#[derive(Deserialize)]
+pub struct OfficeUpdate {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    utc_offset_minutes: Option<i64>,
+    timezone: Option<String>,        // instead of utc_offset_minutes; an empty one goes back to a fixed offset
+    lunch_time: Option<String>,      // defaults to LUNCH_TIME
+    daily_poll_time: Option<String>, // no daily poll without one
+    daily_poll_template: Option<String>,
+}
+
+impl OfficeUpdate {
+    // The times come back as HH:MM, however they were written
+    fn validate(&mut self) -> Result<(), ApiError> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err(ApiError::BadRequest(
+                        "latitude must be within ±90 and longitude within ±180".to_string(),
+                    ));
+                }
+            }
+            (None, None) => {}
+            _ => return Err(ApiError::BadRequest("latitude and longitude must be given together".to_string())),
+        }
+        // Clocks around the world run from UTC-12 to UTC+14
+        if self.utc_offset_minutes.is_some_and(|minutes| !(-12 * 60..=14 * 60).contains(&minutes)) {
+            return Err(ApiError::BadRequest("utc_offset_minutes must be between -720 and 840".to_string()));
+        }
+        for (field, time) in [("lunch_time", &mut self.lunch_time), ("daily_poll_time", &mut self.daily_poll_time)] {
+            if let Some(value) = time {
+                let parsed = hours::parse_time(value)
+                    .ok_or_else(|| ApiError::BadRequest(format!("{field} must be a time like 12:30")))?;
+                *value = parsed;
+            }
+        }
+        Ok(())
+    }
+
+    // A time zone sets the offset to the zone's current one
+    fn check_timezone(&mut self, state: &AppState) -> Result<(), ApiError> {
+        let Some(zone) = self.timezone.as_deref().map(str::trim).filter(|zone| !zone.is_empty()) else {
+            return Ok(());
+        };
+        if self.utc_offset_minutes.is_some() {
+            return Err(ApiError::BadRequest("timezone and utc_offset_minutes can't both be given".to_string()));
+        }
+        let zone = timezones::validate(state, zone)?;
+        self.utc_offset_minutes = timezones::offset_now(state, &zone);
+        self.timezone = Some(zone);
+        Ok(())
+    }
+
+    async fn check_template(&self, db: &SqlitePool) -> Result<(), ApiError> {
+        if let Some(template) = &self.daily_poll_template {
+            if poll_templates::find(db, template).await?.is_none() {
+                return Err(Message::no_poll_template(template).with_status(StatusCode::BAD_REQUEST).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+// GET /offices
+pub async fn list_offices(State(state): State<AppState>) -> Result<Json<Vec<Office>>, ApiError> {
+    Ok(Json(all(&state.db).await?))
+}
+
+// POST /offices (admin): {"name": "Berlin", "latitude": 52.52, "longitude": 13.40, "timezone": "Europe/Berlin",
+// "lunch_time": "12:30", "daily_poll_time": "11:00"}
+pub async fn create_office(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Json(mut req): Json<NewOffice>,
+) -> Result<(StatusCode, Json<Office>), ApiError> {
+    let name = names::clean(&req.name);
+    let id = names::slug(&name);
+    if id.is_empty() {
+        return Err(ApiError::BadRequest("an office needs a name with letters or digits in it".to_string()));
+    }
+    req.settings.validate()?;
+    req.settings.check_timezone(&state)?;
+    req.settings.check_template(&state.db).await?;
+    let OfficeUpdate {
+        latitude,
+        longitude,
+        utc_offset_minutes,
+        timezone,
+        lunch_time,
+        daily_poll_time,
+        daily_poll_template,
+    } = req.settings;
+    let office = sqlx::query_as::<_, Office>(&format!(
+        "INSERT INTO offices
+            (id, name, latitude, longitude, utc_offset_minutes, timezone, lunch_time, daily_poll_time,
+            daily_poll_template)
+        VALUES (?, ?, ?, ?, ?, NULLIF(?, ''), ?, ?, ?) ON CONFLICT DO NOTHING
+        RETURNING {OFFICE_COLUMNS}"
+    ))
+    .bind(&id)
+    .bind(&name)
+    .bind(latitude)
+    .bind(longitude)
+    .bind(utc_offset_minutes.unwrap_or(0))
+    .bind(timezone)
+    .bind(lunch_time.unwrap_or_else(|| state.config.lunch_time.clone()))
+    .bind(daily_poll_time)
+    .bind(daily_poll_template)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| ApiError::Conflict(format!("there's already an office called {id}")))?;
+    Ok((StatusCode::CREATED, Json(office)))
+}
+
+// PATCH /offices/:id (admin), e.g. {"utc_offset_minutes": 60} when the clocks go back, for an office without a
+// timezone. One with a timezone gets a fixed offset with {"timezone": "", "utc_offset_minutes": 60}
+pub async fn update_office(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(mut req): Json<OfficeUpdate>,
+) -> Result<Json<Office>, ApiError> {
+    req.validate()?;
+    req.check_timezone(&state)?;
+    req.check_template(&state.db).await?;
+    let updated = sqlx::query(
+        "UPDATE offices SET
+            latitude = COALESCE(?, latitude),
+            longitude = COALESCE(?, longitude),
+            utc_offset_minutes = COALESCE(?, utc_offset_minutes),
+            timezone = CASE WHEN ? IS NULL THEN timezone ELSE NULLIF(?, '') END,
+            lunch_time = COALESCE(?, lunch_time),
+            daily_poll_time = COALESCE(?, daily_poll_time),
+            daily_poll_template = COALESCE(?, daily_poll_template)
+        WHERE id = ?",
+    )
+    .bind(req.latitude)
+    .bind(req.longitude)
+    .bind(req.utc_offset_minutes)
+    .bind(&req.timezone)
+    .bind(&req.timezone)
+    .bind(req.lunch_time)
+    .bind(req.daily_poll_time)
+    .bind(req.daily_poll_template)
+    .bind(&id)
+    .execute(&state.db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("no office with id {id}")));
+    }
+    let office = find(&state.db, Some(&id)).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))?;
+    Ok(Json(office))
+}
@@ -0,0 +1,135 @@
+// Imports restaurants near the office from OpenStreetMap through the Overpass API, for teams that can't or
+// won't use a commercial places API. OVERPASS_URL can point at a self-hosted instance
+// https://wiki.openstreetmap.org/wiki/Overpass_API
+use axum::extract::State;
+use axum::Json;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::geo::{self, Coordinates};
+use crate::imports::{self, ImportSummary, ImportedPlace};
+use crate::AppState;
+
+#[derive(Deserialize)]
+struct OverpassResponse {
+    #[serde(default)]
+    elements: Vec<Element>,
+}
+
+// Nodes carry lat/lon directly; ways (restaurants mapped as a building outline) get a center from `out center`
+#[derive(Deserialize)]
+struct Element {
+    #[serde(rename = "type")]
+    kind: String,
+    id: i64,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    center: Option<Center>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Center {
+    lat: f64,
+    lon: f64,
+}
+
+impl Element {
+    fn coordinates(&self) -> Option<Coordinates> {
+        let (latitude, longitude) = match (&self.center, self.lat, self.lon) {
+            (Some(center), _, _) => (center.lat, center.lon),
+            (None, Some(lat), Some(lon)) => (lat, lon),
+            _ => return None,
+        };
+        Some(Coordinates { latitude, longitude })
+    }
+
+    // OSM cuisine tags can list several values ("pizza;italian"); the first is the main one
+    // https://wiki.openstreetmap.org/wiki/Key:cuisine
+    fn cuisine(&self) -> Option<String> {
+        self.tags
+            .get("cuisine")
+            .and_then(|cuisine| cuisine.split(';').next())
+            .map(|cuisine| cuisine.trim().replace('_', " "))
+            .filter(|cuisine| !cuisine.is_empty())
+    }
+
+    fn address(&self) -> Option<String> {
+        let street = self.tags.get("addr:street")?;
+        let line = match self.tags.get("addr:housenumber") {
+            Some(number) => format!("{street} {number}"),
+            None => street.clone(),
+        };
+        Some(match self.tags.get("addr:city") {
+            Some(city) => format!("{line}, {city}"),
+            None => line,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ImportRequest {
+    radius_meters: Option<f64>, // overrides PLACES_RADIUS_METERS for this run
+}
+
+// POST /restaurants/import/openstreetmap (admin)
+pub async fn import(
+    _admin: Admin,
+    State(state): State<AppState>,
+    body: Option<Json<ImportRequest>>,
+) -> Result<Json<ImportSummary>, ApiError> {
+    let config = &state.config;
+    let office = config
+        .office_location
+        .ok_or_else(|| ApiError::NotConfigured("set OFFICE_LATITUDE and OFFICE_LONGITUDE to import nearby places".to_string()))?;
+    let radius = body
+        .and_then(|Json(body)| body.radius_meters)
+        .unwrap_or(config.places_radius_meters);
+    if !(radius > 0.0 && radius <= 50_000.0) {
+        return Err(ApiError::BadRequest("radius_meters must be between 0 and 50000".to_string()));
+    }
+
+    // Overpass QL: restaurants and fast food places, mapped as points or outlines, within the radius
+    // https://wiki.openstreetmap.org/wiki/Overpass_API/Overpass_QL
+    let around = format!("(around:{radius},{},{})", office.latitude, office.longitude);
+    let query = format!(
+        "[out:json][timeout:25];\
+        (nwr[\"amenity\"~\"^(restaurant|fast_food)$\"][\"name\"]{around};);\
+        out center;"
+    );
+    let response = state
+        .http
+        .post(&config.overpass_url)
+        .form(&[("data", query)])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("Overpass returned {status}: {body}")));
+    }
+    let overpass: OverpassResponse = response.json().await?;
+
+    let mut summary = ImportSummary::default();
+    for element in overpass.elements {
+        let Some(name) = element.tags.get("name").cloned() else {
+            continue;
+        };
+        let location = element.coordinates();
+        let imported = ImportedPlace {
+            source: "openstreetmap",
+            external_id: format!("{}/{}", element.kind, element.id), // e.g. "node/123", as on openstreetmap.org
+            name,
+            address: element.address(),
+            cuisine: element.cuisine(),
+            price_tier: None, // OSM has no price information
+            distance_meters: location.map(|location| geo::distance_meters(office, location).round() as i64),
+            location,
+        };
+        imports::upsert(&state.db, imported, &mut summary).await?;
+    }
+    Ok(Json(summary))
+}
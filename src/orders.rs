@@ -0,0 +1,157 @@
+// Orders for the winning restaurant. Once a poll has closed on a winner, everyone going can put in the dish they
+// want, and the summary gathers them for whoever calls the restaurant: each dish with how many and for whom, and the
+// notes ("no onions") to read out. A poll whose tie or missing majority went to a runoff takes its orders there.
+// If an admin rules the winner out afterwards, orders placed for it are listed separately as needing a new dish
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, Message};
+use crate::polls;
+use crate::public_ids::PollId;
+use crate::{names, AppState};
+
+#[derive(Deserialize)]
+pub struct NewOrder {
+    voter_name: String,
+    dish: String,
+    notes: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Order {
+    voter_name: String,
+    restaurant_name: String,
+    dish: String,
+    notes: Option<String>,
+    created_at: String,
+    updated_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OrderSummary {
+    restaurant_name: String,
+    orders: usize,
+    dishes: Vec<Dish>,
+    // Orders placed for a restaurant that has since dropped out of the poll; these people need to order again
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    outdated: Vec<Order>,
+}
+
+// One line of the order to read out: the same dish, however many want it
+#[derive(Serialize)]
+pub struct Dish {
+    dish: String,
+    count: usize,
+    voters: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>, // "Sam: no onions"
+}
+
+const ORDER_COLUMNS: &str = "voter_name, restaurant_name, dish, notes, created_at, updated_at";
+
+async fn poll_winner(state: &AppState, id: i64, public_id: &str) -> Result<String, ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(public_id))?;
+    if poll.options().is_some() {
+        return Err(ApiError::BadRequest(
+            "this poll is over options, not restaurants, so there's nothing to order".to_string(),
+        ));
+    }
+    polls::winner(state, &poll).await
+}
+
+// POST /polls/:id/orders: {"voter_name": "Sam", "dish": "Pad thai", "notes": "no peanuts"}. Ordering again
+// replaces the earlier order
+pub async fn place_order(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewOrder>,
+) -> Result<(StatusCode, Json<Order>), ApiError> {
+    let winner = poll_winner(&state, id, &public_id).await?;
+    let voter_name = names::canonical_voter_name(&state.db, &req.voter_name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_empty().into());
+    }
+    let dish = names::clean(&req.dish);
+    if dish.is_empty() {
+        return Err(ApiError::BadRequest("dish must not be empty".to_string()));
+    }
+    let notes = req.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty());
+    let order = sqlx::query_as::<_, Order>(&format!(
+        "INSERT INTO poll_orders (poll_id, voter_key, voter_name, restaurant_name, dish, notes)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (poll_id, voter_key) DO UPDATE SET restaurant_name = excluded.restaurant_name,
+            dish = excluded.dish, notes = excluded.notes, updated_at = CURRENT_TIMESTAMP
+        RETURNING {ORDER_COLUMNS}"
+    ))
+    .bind(id)
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(&winner)
+    .bind(&dish)
+    .bind(notes)
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(order)))
+}
+
+// GET /polls/:id/orders: every order, in the order they came in
+pub async fn list_orders(State(state): State<AppState>, PollId(id, _): PollId) -> Result<Json<Vec<Order>>, ApiError> {
+    let orders = sqlx::query_as::<_, Order>(&format!(
+        "SELECT {ORDER_COLUMNS} FROM poll_orders WHERE poll_id = ? ORDER BY rowid"
+    ))
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(orders))
+}
+
+// GET /polls/:id/orders/summary: the consolidated order for the winner, the most wanted dish first
+pub async fn order_summary(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+) -> Result<Json<OrderSummary>, ApiError> {
+    let winner = poll_winner(&state, id, &public_id).await?;
+    let Json(orders) = list_orders(State(state), PollId(id, public_id)).await?;
+    let (current, outdated): (Vec<Order>, Vec<Order>) =
+        orders.into_iter().partition(|order| order.restaurant_name == winner);
+    let mut dishes: Vec<Dish> = Vec::new();
+    for order in &current {
+        // Dishes are matched like names are, so "pad thai" and "Pad Thai" are one line
+        let dish = match dishes.iter_mut().find(|dish| names::fold(&dish.dish) == names::fold(&order.dish)) {
+            Some(dish) => dish,
+            None => {
+                dishes.push(Dish { dish: order.dish.clone(), count: 0, voters: Vec::new(), notes: Vec::new() });
+                dishes.last_mut().expect("just pushed")
+            }
+        };
+        dish.count += 1;
+        dish.voters.push(order.voter_name.clone());
+        if let Some(notes) = &order.notes {
+            dish.notes.push(format!("{}: {notes}", order.voter_name));
+        }
+    }
+    // sort_by_key is stable, so dishes wanted equally often stay in the order they were first ordered
+    dishes.sort_by_key(|dish| std::cmp::Reverse(dish.count));
+    Ok(Json(OrderSummary { restaurant_name: winner, orders: current.len(), dishes, outdated }))
+}
+
+// DELETE /polls/:id/orders/:voter: cancels someone's order
+pub async fn cancel_order(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let cancelled = sqlx::query("DELETE FROM poll_orders WHERE poll_id = ? AND voter_key = ?")
+        .bind(id)
+        .bind(names::fold(&voter))
+        .execute(&state.db)
+        .await?;
+    if cancelled.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{voter} hasn't ordered in this poll")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
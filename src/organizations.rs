@@ -0,0 +1,243 @@
+// Several companies on one hosted instance. Each organization gets a database of its own, with the same schema, so
+// its restaurants, voters, polls and audit log are out of every other organization's reach by construction rather
+// than by a WHERE clause someone might forget. A request carrying an organization's key in X-Api-Key is handed to
+// that organization's copy of the app; one without goes to the host's own data, as before organizations existed.
+// Every organization has its own admin token too, so the host's ADMIN_TOKEN manages organizations but isn't an
+// admin inside any of them.
+// With ORGANIZATION_DATA_DIR set, each organization's database is a SQLite file there, {id}.sqlite, so with the
+// host's at DATABASE_URL they all outlast a restart. Their secrets are derived from the host's (see
+// Config::for_organization), and sealed polls' keys are kept wrapped in the organization's own database, so a
+// receipt or sealed ballot from before a restart, or from another instance serving the same files, still checks
+// out and opens. An organization is opened the first time it's needed, by a request for it or an admin migrating or
+// backing it up, rather than all of them at startup; its scheduled jobs start when it is
+use axum::extract::{Path, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::{Json, Router};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tower::ServiceExt;
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::receipts::hex;
+use crate::{backups, config, names, AppState};
+
+// The organizations opened since startup, by id
+#[derive(Clone, Default)]
+pub struct Organizations {
+    open: Arc<RwLock<HashMap<String, Tenant>>>,
+    // Held while one is being opened, so two first requests for it don't both run its migrations
+    opening: Arc<tokio::sync::Mutex<()>>,
+}
+
+// An organization's copy of the app, and the state behind it, with its pool
+#[derive(Clone)]
+struct Tenant {
+    app: Router,
+    state: AppState,
+}
+
+#[derive(Deserialize)]
+pub struct CreateOrganization {
+    name: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Organization {
+    id: String,
+    name: String,
+    created_at: String,
+}
+
+// The secrets are only ever shown in this response
+#[derive(Serialize)]
+pub struct CreatedOrganization {
+    #[serde(flatten)]
+    organization: Organization,
+    api_key: String,     // goes in X-Api-Key on every request for the organization
+    admin_token: String, // the bearer token for the organization's admin endpoints
+}
+
+#[derive(Serialize)]
+pub struct Migrated {
+    id: String,
+    schema_version: i64, // the last migration its database has had
+}
+
+#[derive(Serialize)]
+pub struct ApiKey {
+    id: String,
+    api_key: String,
+}
+
+fn hash(key: &str) -> String {
+    hex(digest::digest(&digest::SHA256, key.as_bytes()).as_ref())
+}
+
+fn secret(prefix: &str) -> String {
+    format!("{prefix}_{}", hex(&config::random_key()))
+}
+
+// The organization with this id, opened if it isn't yet; None if there's no such organization
+async fn tenant(state: &AppState, id: &str) -> Result<Option<Tenant>, ApiError> {
+    if let Some(tenant) = state.organizations.open.read().unwrap().get(id) {
+        return Ok(Some(tenant.clone()));
+    }
+    let _opening = state.organizations.opening.lock().await;
+    // Someone else may have opened it while we waited
+    if let Some(tenant) = state.organizations.open.read().unwrap().get(id) {
+        return Ok(Some(tenant.clone()));
+    }
+    let admin_token_hash: Option<Option<String>> =
+        sqlx::query_scalar("SELECT admin_token_hash FROM organizations WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await?;
+    match admin_token_hash {
+        Some(admin_token_hash) => Ok(Some(open_tenant(state, id, admin_token_hash).await?)),
+        None => Ok(None),
+    }
+}
+
+// Opens an organization's database, running whatever migrations it hasn't had, and starts its app; only with the
+// opening lock held
+async fn open_tenant(state: &AppState, id: &str, admin_token_hash: Option<String>) -> Result<Tenant, ApiError> {
+    if let Some(dir) = &state.config.organization_data_dir {
+        tokio::fs::create_dir_all(dir).await.map_err(sqlx::Error::Io)?;
+    }
+    let config = state.config.for_organization(id, admin_token_hash);
+    let tenant_state = crate::open(config, state.http.clone()).await?;
+    let tenant = Tenant { app: crate::app(crate::routes(), tenant_state.clone()), state: tenant_state };
+    state.organizations.open.write().unwrap().insert(id.to_string(), tenant.clone());
+    println!("organization {id} opened");
+    Ok(tenant)
+}
+
+// Middleware on the host's app: sends a request with an organization's key on to that organization's app
+pub async fn dispatch(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    let Some(key) = request.headers().get("x-api-key") else {
+        return Ok(next.run(request).await);
+    };
+    let unknown = || ApiError::from(Message::api_key_unknown());
+    let key = key.to_str().map_err(|_| unknown())?;
+    let id: Option<String> = sqlx::query_scalar("SELECT id FROM organizations WHERE api_key_hash = ?")
+        .bind(hash(key))
+        .fetch_optional(&state.db)
+        .await?;
+    let tenant = match id {
+        Some(id) => tenant(&state, &id).await?,
+        None => None,
+    };
+    let tenant = tenant.ok_or_else(unknown)?;
+    // A Router is a tower Service that never fails; oneshot waits for it to be ready and calls it once
+    // https://docs.rs/tower/latest/tower/trait.ServiceExt.html#method.oneshot
+    let response = tenant.app.oneshot(request).await;
+    Ok(response.unwrap_or_else(|never| match never {}))
+}
+
+// POST /organizations (admin): {"name": "Acme Corp"} sets up an empty organization
+pub async fn create_organization(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Json(req): Json<CreateOrganization>,
+) -> Result<(StatusCode, Json<CreatedOrganization>), ApiError> {
+    let name = req.name.trim().to_string();
+    let id = names::slug(&name);
+    if id.is_empty() {
+        return Err(ApiError::BadRequest("an organization needs a name with letters or digits in it".to_string()));
+    }
+    let taken: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM organizations WHERE id = ?)")
+        .bind(&id)
+        .fetch_one(&state.db)
+        .await?;
+    if taken {
+        return Err(ApiError::Conflict(format!("there's already an organization called {id}")));
+    }
+
+    let api_key = secret("org");
+    let admin_token = secret("orgadmin");
+    // The primary key settles a race between two requests for the same name
+    let organization = sqlx::query_as::<_, Organization>(
+        "INSERT INTO organizations (id, name, api_key_hash, admin_token_hash) VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING
+        RETURNING id, name, created_at",
+    )
+    .bind(&id)
+    .bind(&name)
+    .bind(hash(&api_key))
+    .bind(hash(&admin_token))
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| ApiError::Conflict(format!("there's already an organization called {id}")))?;
+    let opened = {
+        let _opening = state.organizations.opening.lock().await;
+        open_tenant(&state, &id, Some(hash(&admin_token))).await
+    };
+    // An organization whose database couldn't be set up is no organization at all
+    if let Err(err) = opened {
+        sqlx::query("DELETE FROM organizations WHERE id = ?").bind(&id).execute(&state.db).await?;
+        return Err(err);
+    }
+    println!("organization {id} created");
+    Ok((StatusCode::CREATED, Json(CreatedOrganization { organization, api_key, admin_token })))
+}
+
+// GET /organizations (admin), oldest first
+pub async fn list_organizations(
+    _admin: Admin,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Organization>>, ApiError> {
+    let organizations = sqlx::query_as::<_, Organization>(
+        "SELECT id, name, created_at FROM organizations ORDER BY created_at, id",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(organizations))
+}
+
+// POST /organizations/:id/api-key (admin): a new key for a leaked one; the old key stops working at once
+pub async fn rotate_api_key(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKey>, ApiError> {
+    let api_key = secret("org");
+    let updated = sqlx::query("UPDATE organizations SET api_key_hash = ? WHERE id = ?")
+        .bind(hash(&api_key))
+        .bind(&id)
+        .execute(&state.db)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("no organization with id {id}")));
+    }
+    Ok(Json(ApiKey { id, api_key }))
+}
+
+// POST /organizations/:id/migrate (admin): opens the organization, which brings its database up to the schema this
+// version of the app has, say right after an upgrade rather than waiting for its first request
+pub async fn migrate(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Migrated>, ApiError> {
+    let tenant = tenant(&state, &id).await?.ok_or_else(|| ApiError::NotFound(format!("no organization with id {id}")))?;
+    // Already open, it has had them all; this only matters for a file swapped in underneath it since
+    sqlx::migrate!().run(&tenant.state.db).await.map_err(sqlx::Error::from)?;
+    let schema_version: i64 =
+        sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations").fetch_one(&tenant.state.db).await?;
+    Ok(Json(Migrated { id, schema_version }))
+}
+
+// POST /organizations/:id/backup (admin): backs the organization's database up now, under BACKUP_PREFIX{id}/; see
+// backups.rs
+pub async fn back_up(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<backups::Backup>, ApiError> {
+    let tenant = tenant(&state, &id).await?.ok_or_else(|| ApiError::NotFound(format!("no organization with id {id}")))?;
+    Ok(Json(backups::back_up(&tenant.state).await?))
+}
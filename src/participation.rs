@@ -0,0 +1,155 @@
+// Who has taken part in a poll. Voting is one way; abstaining is the other, for someone who has seen the poll and
+// doesn't mind where the team goes (or isn't coming). An abstention counts as taking part, unlike not voting at all
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::moderation::{self, NameKind};
+use crate::polls::{self, PollStatus};
+use crate::public_ids::PollId;
+use crate::{ballots, names, teams, AppState};
+
+#[derive(Deserialize)]
+pub struct NewAbstention {
+    voter_name: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Participation {
+    pub voted: i64,
+    pub abstained: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Abstention {
+    voter_name: String,
+    created_at: String,
+}
+
+// Everyone who has voted or abstained in the poll, by their canonical names
+pub async fn participants(db: &SqlitePool, poll_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT voter_name FROM votes WHERE poll_id = ? AND deleted_at IS NULL
+        UNION SELECT voter_name FROM sealed_ballots WHERE poll_id = ?
+        UNION SELECT voter_name FROM abstentions WHERE poll_id = ?
+        ORDER BY 1",
+    )
+    .bind(poll_id)
+    .bind(poll_id)
+    .bind(poll_id)
+    .fetch_all(db)
+    .await
+}
+
+// How many have voted and abstained, without saying who or for what
+pub async fn participation(db: &SqlitePool, poll_id: i64) -> Result<Participation, sqlx::Error> {
+    sqlx::query_as::<_, Participation>(
+        "SELECT (
+                SELECT COUNT(*) FROM (
+                    SELECT voter_name FROM votes WHERE poll_id = ? AND deleted_at IS NULL
+                    UNION SELECT voter_name FROM sealed_ballots WHERE poll_id = ?
+                )
+            ) AS voted,
+            (SELECT COUNT(*) FROM abstentions WHERE poll_id = ?) AS abstained",
+    )
+    .bind(poll_id)
+    .bind(poll_id)
+    .bind(poll_id)
+    .fetch_one(db)
+    .await
+}
+
+pub async fn abstention_count(db: &SqlitePool, poll_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM abstentions WHERE poll_id = ?")
+        .bind(poll_id)
+        .fetch_one(db)
+        .await
+}
+
+// A vote replaces its voter's abstention; called when a vote is saved
+pub async fn withdraw_abstention(db: &SqlitePool, poll_id: i64, voter_name: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM abstentions WHERE poll_id = ? AND voter_key = ?")
+        .bind(poll_id)
+        .bind(names::fold(voter_name))
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+// POST /polls/:id/abstentions: takes part without voting. Only while the poll is open, by someone allowed to vote
+// in it who hasn't voted yet; abstaining twice is harmless
+pub async fn abstain(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewAbstention>,
+) -> Result<(StatusCode, Json<Abstention>), ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    match poll.status {
+        PollStatus::Open => {}
+        PollStatus::Nominating => {
+            return Err(ApiError::Conflict("the poll is still taking nominations".to_string()));
+        }
+        _ => return Err(Message::poll_closed().into()),
+    }
+    let voter_name = names::canonical_voter_name(&state.db, &req.voter_name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_empty().into());
+    }
+    moderation::screen(&state, NameKind::Voter, &voter_name).await?;
+    let voter_key = names::fold(&voter_name);
+    if let Some(eligible) = &poll.eligible_voters {
+        if !eligible.iter().any(|voter| names::fold(voter) == voter_key) {
+            return Err(Message::not_in_this_runoff(voter_name).into());
+        }
+    }
+    if let Some(team) = &poll.team_id {
+        if !teams::is_member(&state.db, team, &voter_name).await? {
+            return Err(Message::not_on_poll_team(voter_name, team).into());
+        }
+    }
+    if ballots::has_voted(&state.db, Some(id), &voter_name).await? {
+        return Err(Message::already_voted(voter_name).into());
+    }
+
+    sqlx::query("INSERT OR IGNORE INTO abstentions (poll_id, voter_key, voter_name) VALUES (?, ?, ?)")
+        .bind(id)
+        .bind(&voter_key)
+        .bind(&voter_name)
+        .execute(&state.db)
+        .await?;
+    let abstention = sqlx::query_as::<_, Abstention>(
+        "SELECT voter_name, created_at FROM abstentions WHERE poll_id = ? AND voter_key = ?",
+    )
+    .bind(id)
+    .bind(&voter_key)
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(abstention)))
+}
+
+// GET /polls/:id/participants: everyone who has voted or abstained, so far
+pub async fn list_participants(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Vec<String>>, ApiError> {
+    Ok(Json(participants(&state.db, id).await?))
+}
+
+// GET /polls/:id/abstentions
+pub async fn list_abstentions(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Vec<Abstention>>, ApiError> {
+    let abstentions = sqlx::query_as::<_, Abstention>(
+        "SELECT voter_name, created_at FROM abstentions WHERE poll_id = ? ORDER BY created_at, voter_name",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(abstentions))
+}
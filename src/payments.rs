@@ -0,0 +1,325 @@
+// Collecting what the bill says people owe (see bills.rs). Anyone who owes can be sent to a Stripe Checkout page for
+// what's still outstanding, and the payment counts once Stripe says it's paid; that's checked whenever payments are
+// looked at, rather than through Stripe's webhooks, which would need the instance to be reachable from outside. An
+// admin can also record money that changed hands some other way, in cash or by bank transfer. Card payments go to
+// the Stripe account behind STRIPE_API_KEY, the team's or the office's, which pays back whoever covered the bill.
+// Stripe is only told the amount and the lunch, never who's paying
+// https://docs.stripe.com/api/checkout/sessions
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::bills::{self, amount, cents};
+use crate::error::{ApiError, Message};
+use crate::polls::{self, Poll};
+use crate::public_ids::PollId;
+use crate::{names, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Payment {
+    id: i64,
+    #[serde(skip)]
+    voter_key: String,
+    voter_name: String,
+    #[serde(skip)]
+    amount_cents: i64,
+    #[sqlx(skip)]
+    amount: f64,
+    method: String, // stripe or manual
+    status: String, // pending, paid or expired
+    #[serde(skip)]
+    reference: Option<String>,
+    payment_url: Option<String>, // the Checkout page, while it's pending
+    created_at: String,
+    paid_at: Option<String>,
+}
+
+const PAYMENT_COLUMNS: &str =
+    "id, voter_key, voter_name, amount_cents, method, status, reference, payment_url, created_at, paid_at";
+
+// Where someone on the bill stands
+#[derive(Serialize)]
+pub struct Attendee {
+    voter_name: String,
+    owes: f64, // their share less what they paid towards the bill itself
+    paid: f64, // what's been collected from them since
+    #[serde(skip)]
+    outstanding_cents: i64,
+    status: &'static str, // paid, pending while a Checkout page is open, or unpaid
+    payments: Vec<Payment>,
+}
+
+#[derive(Deserialize)]
+pub struct ManualPayment {
+    amount: f64,
+}
+
+// Stripe's answer about a Checkout session; status is open, complete or expired
+#[derive(Deserialize)]
+struct Session {
+    id: String,
+    url: Option<String>,
+    status: String,
+    payment_status: String,
+}
+
+async fn payments(db: &SqlitePool, poll_id: i64) -> Result<Vec<Payment>, sqlx::Error> {
+    let mut payments = sqlx::query_as::<_, Payment>(&format!(
+        "SELECT {PAYMENT_COLUMNS} FROM bill_payments WHERE poll_id = ? ORDER BY id"
+    ))
+    .bind(poll_id)
+    .fetch_all(db)
+    .await?;
+    for payment in &mut payments {
+        payment.amount = amount(payment.amount_cents);
+    }
+    Ok(payments)
+}
+
+async fn existing_poll(db: &SqlitePool, id: i64, public_id: &str) -> Result<Poll, ApiError> {
+    polls::find_poll(db, id).await?.ok_or_else(|| ApiError::from(Message::no_poll(public_id)))
+}
+
+// Everyone who owes towards the bill or has paid something, in the bill's order
+async fn attendees(db: &SqlitePool, poll_id: i64) -> Result<Vec<Attendee>, ApiError> {
+    let debts = bills::settlement(db, poll_id).await?.debts().ok_or_else(|| {
+        ApiError::Conflict("the bill doesn't add up yet, so there's nothing to collect".to_string())
+    })?;
+    let mut payments = payments(db, poll_id).await?;
+    let mut attendees = Vec::new();
+    let mut add = |voter_name: String, owes: i64, payments: Vec<Payment>| {
+        let paid: i64 =
+            payments.iter().filter(|payment| payment.status == "paid").map(|payment| payment.amount_cents).sum();
+        let status = if paid >= owes {
+            "paid"
+        } else if payments.iter().any(|payment| payment.status == "pending") {
+            "pending"
+        } else {
+            "unpaid"
+        };
+        attendees.push(Attendee {
+            voter_name,
+            owes: amount(owes),
+            paid: amount(paid),
+            outstanding_cents: owes - paid,
+            status,
+            payments,
+        });
+    };
+    for (voter_name, owes) in debts {
+        let key = names::fold(&voter_name);
+        let (theirs, rest): (Vec<_>, Vec<_>) = payments.into_iter().partition(|payment| payment.voter_key == key);
+        payments = rest;
+        add(voter_name, owes, theirs);
+    }
+    // Someone who paid and then turned out to owe nothing, after an entry on the bill was corrected
+    while let Some(first) = payments.first() {
+        let key = first.voter_key.clone();
+        let voter_name = first.voter_name.clone();
+        let (theirs, rest): (Vec<_>, Vec<_>) = payments.into_iter().partition(|payment| payment.voter_key == key);
+        payments = rest;
+        add(voter_name, 0, theirs);
+    }
+    Ok(attendees)
+}
+
+fn stripe(state: &AppState) -> Result<(&str, &str), ApiError> {
+    let config = &state.config;
+    match (&config.stripe_api_key, &config.payment_return_url) {
+        (Some(key), Some(return_url)) => Ok((key, return_url)),
+        _ => Err(ApiError::NotConfigured(
+            "set STRIPE_API_KEY and PAYMENT_RETURN_URL to collect payments through Stripe".to_string(),
+        )),
+    }
+}
+
+async fn stripe_call(state: &AppState, request: reqwest::RequestBuilder) -> Result<Session, ApiError> {
+    let (key, _) = stripe(state)?;
+    let response = request.bearer_auth(key).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("Stripe returned {status}: {body}")));
+    }
+    Ok(response.json().await?)
+}
+
+fn session_url(state: &AppState, path: &str) -> String {
+    format!("{}/v1/checkout/sessions{path}", state.config.stripe_api_url.trim_end_matches('/'))
+}
+
+// Asks Stripe about the poll's pending payments and records the ones that have been paid or have lapsed. A session
+// Stripe can't be asked about right now stays pending until next time
+async fn refresh(state: &AppState, poll_id: i64) -> Result<(), sqlx::Error> {
+    if state.config.stripe_api_key.is_none() {
+        return Ok(());
+    }
+    let pending: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, reference FROM bill_payments
+        WHERE poll_id = ? AND method = 'stripe' AND status = 'pending' AND reference IS NOT NULL",
+    )
+    .bind(poll_id)
+    .fetch_all(&state.db)
+    .await?;
+    for (id, reference) in pending {
+        let session = match stripe_call(state, state.http.get(session_url(state, &format!("/{reference}")))).await {
+            Ok(session) => session,
+            Err(err) => {
+                eprintln!("payment {id}: could not check with Stripe: {err:?}");
+                continue;
+            }
+        };
+        if session.payment_status == "paid" {
+            sqlx::query(
+                "UPDATE bill_payments SET status = 'paid', paid_at = CURRENT_TIMESTAMP, payment_url = NULL
+                WHERE id = ?",
+            )
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+        } else if session.status == "expired" {
+            expired(&state.db, id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn expired(db: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE bill_payments SET status = 'expired', payment_url = NULL WHERE id = ? AND status = 'pending'")
+        .bind(id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+// GET /polls/:id/bill/payments: who has paid what they owe, checking with Stripe on the pending ones first
+pub async fn list_payments(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+) -> Result<Json<Vec<Attendee>>, ApiError> {
+    existing_poll(&state.db, id, &public_id).await?;
+    refresh(&state, id).await?;
+    Ok(Json(attendees(&state.db, id).await?))
+}
+
+// POST /polls/:id/bill/payments/:voter/checkout: a Stripe Checkout page for what :voter still owes, to send them to.
+// Asking again while it's open gives the same page, unless what they owe has changed since
+pub async fn checkout(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<(StatusCode, Json<Payment>), ApiError> {
+    stripe(&state)?;
+    let poll = existing_poll(&state.db, id, &public_id).await?;
+    refresh(&state, id).await?;
+    let key = names::fold(&voter);
+    let attendee = attendees(&state.db, id)
+        .await?
+        .into_iter()
+        .find(|attendee| names::fold(&attendee.voter_name) == key)
+        .ok_or_else(|| ApiError::Conflict(format!("{voter} doesn't owe anything towards this poll's bill")))?;
+    let outstanding = attendee.outstanding_cents;
+    if outstanding <= 0 {
+        return Err(ApiError::Conflict(format!("{} has paid what they owe", attendee.voter_name)));
+    }
+    for payment in attendee.payments.into_iter().filter(|payment| payment.status == "pending") {
+        if payment.amount_cents == outstanding {
+            return Ok((StatusCode::OK, Json(payment)));
+        }
+        // The page is for an amount that's out of date; Stripe is asked to close it, and if it can't, it's
+        // dropped here anyway so nobody pays the old amount unnoticed on top of the new one
+        if let Some(reference) = &payment.reference {
+            let url = session_url(&state, &format!("/{reference}/expire"));
+            if let Err(err) = stripe_call(&state, state.http.post(url)).await {
+                eprintln!("payment {}: could not expire it at Stripe: {err:?}", payment.id);
+            }
+        }
+        expired(&state.db, payment.id).await?;
+    }
+
+    let payment_id: i64 = sqlx::query_scalar(
+        "INSERT INTO bill_payments (poll_id, voter_key, voter_name, amount_cents, method, status)
+        VALUES (?, ?, ?, ?, 'stripe', 'pending') RETURNING id",
+    )
+    .bind(id)
+    .bind(&key)
+    .bind(&attendee.voter_name)
+    .bind(outstanding)
+    .fetch_one(&state.db)
+    .await?;
+    let lunch = match polls::winner(&state, &poll).await {
+        Ok(winner) => format!("Lunch at {winner}, {}", poll.lunch_at),
+        Err(_) => format!("Lunch, {}", poll.lunch_at),
+    };
+    let (_, return_url) = stripe(&state)?;
+    // Stripe takes form-encoded bodies, with nested fields written as line_items[0][price_data][...]
+    let form = [
+        ("mode", "payment".to_string()),
+        ("success_url", return_url.to_string()),
+        ("client_reference_id", payment_id.to_string()),
+        ("line_items[0][quantity]", "1".to_string()),
+        ("line_items[0][price_data][currency]", state.config.payment_currency.clone()),
+        ("line_items[0][price_data][unit_amount]", outstanding.to_string()),
+        ("line_items[0][price_data][product_data][name]", lunch),
+    ];
+    let session = match stripe_call(&state, state.http.post(session_url(&state, "")).form(&form)).await {
+        Ok(session) => session,
+        Err(err) => {
+            sqlx::query("DELETE FROM bill_payments WHERE id = ?").bind(payment_id).execute(&state.db).await?;
+            return Err(err);
+        }
+    };
+    sqlx::query("UPDATE bill_payments SET reference = ?, payment_url = ? WHERE id = ?")
+        .bind(&session.id)
+        .bind(&session.url)
+        .bind(payment_id)
+        .execute(&state.db)
+        .await?;
+    let payment = find(&state.db, payment_id).await?;
+    Ok((StatusCode::CREATED, Json(payment)))
+}
+
+async fn find(db: &SqlitePool, id: i64) -> Result<Payment, sqlx::Error> {
+    let mut payment = sqlx::query_as::<_, Payment>(&format!("SELECT {PAYMENT_COLUMNS} FROM bill_payments WHERE id = ?"))
+        .bind(id)
+        .fetch_one(db)
+        .await?;
+    payment.amount = amount(payment.amount_cents);
+    Ok(payment)
+}
+
+// POST /polls/:id/bill/payments/:voter (admin): {"amount": 14.00} records money :voter paid outside Stripe
+pub async fn record_payment(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Path((_, voter)): Path<(String, String)>,
+    Json(req): Json<ManualPayment>,
+) -> Result<(StatusCode, Json<Payment>), ApiError> {
+    existing_poll(&state.db, id, &public_id).await?;
+    let amount_cents = cents(req.amount, "amount")?;
+    if amount_cents == 0 {
+        return Err(ApiError::BadRequest("amount must be more than zero".to_string()));
+    }
+    let voter_name: Option<String> =
+        sqlx::query_scalar("SELECT voter_name FROM bill_entries WHERE poll_id = ? AND voter_key = ?")
+            .bind(id)
+            .bind(names::fold(&voter))
+            .fetch_optional(&state.db)
+            .await?;
+    let voter_name = voter_name.ok_or_else(|| ApiError::NotFound(format!("{voter} isn't on this poll's bill")))?;
+    let payment_id: i64 = sqlx::query_scalar(
+        "INSERT INTO bill_payments (poll_id, voter_key, voter_name, amount_cents, method, status, paid_at)
+        VALUES (?, ?, ?, ?, 'manual', 'paid', CURRENT_TIMESTAMP) RETURNING id",
+    )
+    .bind(id)
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(amount_cents)
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(find(&state.db, payment_id).await?)))
+}
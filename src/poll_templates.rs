@@ -0,0 +1,201 @@
+// Poll templates: a poll's settings saved under a name, to open polls like it with one call. The settings are the
+// body of a POST /polls, with everything but the times: a template is for other days, so its deadlines are
+// closes_after_minutes and nominations_close_after_minutes, counted from when each poll is created, and its lunch
+// is that day's at the usual lunch time. That covers the candidates (candidate_ids, required_tags and the rest of
+// the filters), the voting method and tiebreak, and who sees what (hide_results, sealed). The daily scheduler opens
+// each office's poll from its daily_poll_template, and the instance's from DAILY_POLL_TEMPLATE (see scheduler.rs)
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::Json as JsonColumn;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::polls::{self, NewPoll, Poll};
+use crate::{names, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PollTemplate {
+    id: String,
+    name: String,
+    settings: JsonColumn<Map<String, Value>>,
+    created_at: String,
+    updated_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewTemplate {
+    name: Option<String>, // the id as it was written, when left out
+    settings: Map<String, Value>,
+}
+
+const TEMPLATE_COLUMNS: &str = "id, name, settings, created_at, updated_at";
+
+// The times a poll gets on the day it's created, never kept in a template
+const DATED_FIELDS: [&str; 3] = ["lunch_at", "nominations_close_at", "closes_at"];
+
+pub async fn find(db: &SqlitePool, id: &str) -> Result<Option<PollTemplate>, sqlx::Error> {
+    sqlx::query_as::<_, PollTemplate>(&format!("SELECT {TEMPLATE_COLUMNS} FROM poll_templates WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+}
+
+// For erasing and merging voters: `rename` changes the names in a template's attendees, saying whether it did, and
+// the templates it changed are written back. Returns how many
+pub async fn rename_attendees(
+    conn: &mut SqliteConnection,
+    rename: impl Fn(&mut Vec<String>) -> bool,
+) -> Result<u64, sqlx::Error> {
+    let templates: Vec<(String, JsonColumn<Map<String, Value>>)> =
+        sqlx::query_as("SELECT id, settings FROM poll_templates").fetch_all(&mut *conn).await?;
+    let mut renamed = 0;
+    for (id, JsonColumn(mut settings)) in templates {
+        // Settings were checked to make a poll when saved, so attendees is a list of names if it's there at all
+        let Some(mut attendees) = settings.get("attendees").and_then(|list| Vec::<String>::deserialize(list).ok())
+        else {
+            continue;
+        };
+        if !rename(&mut attendees) {
+            continue;
+        }
+        settings.insert("attendees".to_string(), attendees.into());
+        sqlx::query("UPDATE poll_templates SET settings = ? WHERE id = ?")
+            .bind(JsonColumn(settings))
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+        renamed += 1;
+    }
+    Ok(renamed)
+}
+
+pub async fn lookup(db: &SqlitePool, id: &str) -> Result<PollTemplate, ApiError> {
+    find(db, id).await?.ok_or_else(|| ApiError::from(Message::no_poll_template(id)))
+}
+
+// A template's settings, with `overrides` laid over them field by field, as the poll to create. A time given outright
+// replaces the template's offset for it
+pub fn new_poll(template: &PollTemplate, overrides: Map<String, Value>) -> Result<NewPoll, ApiError> {
+    let mut settings = template.settings.0.clone();
+    for field in ["nominations_close_at", "closes_at"] {
+        if overrides.contains_key(field) {
+            settings.remove(&format!("{}_after_minutes", field.trim_end_matches("_at")));
+        }
+    }
+    settings.extend(overrides);
+    serde_json::from_value(Value::Object(settings))
+        .map_err(|err| ApiError::BadRequest(format!("template {} doesn't make a poll: {err}", template.id)))
+}
+
+// The scheduler's daily poll for an office, or for the instance as a whole, from `template` when there is one. A
+// template that has gone missing is logged and the poll opened as it comes, rather than not at all
+pub async fn daily_poll(
+    db: &SqlitePool,
+    template: Option<&str>,
+    office_id: Option<String>,
+) -> Result<NewPoll, ApiError> {
+    let Some(id) = template else {
+        return Ok(NewPoll::daily(office_id));
+    };
+    let Some(template) = find(db, id).await? else {
+        eprintln!("scheduler: there's no poll template {id}; opening the daily poll without one");
+        return Ok(NewPoll::daily(office_id));
+    };
+    let mut overrides = Map::new();
+    if let Some(office_id) = office_id {
+        overrides.insert("office_id".to_string(), Value::String(office_id));
+    }
+    new_poll(&template, overrides)
+}
+
+// GET /poll-templates
+pub async fn list_templates(State(state): State<AppState>) -> Result<Json<Vec<PollTemplate>>, ApiError> {
+    let templates = sqlx::query_as::<_, PollTemplate>(&format!(
+        "SELECT {TEMPLATE_COLUMNS} FROM poll_templates ORDER BY id"
+    ))
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(templates))
+}
+
+// GET /poll-templates/:id
+pub async fn get_template(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<PollTemplate>, ApiError> {
+    Ok(Json(lookup(&state.db, &id).await?))
+}
+
+// PUT /poll-templates/:id (admin): {"name": "Friday treat", "settings": {"voting_method": "borda",
+// "candidate_ids": [...], "closes_after_minutes": 45, "hide_results": true}} saves a template, or replaces it
+pub async fn put_template(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<NewTemplate>,
+) -> Result<Json<PollTemplate>, ApiError> {
+    let name = names::clean(req.name.as_deref().unwrap_or(&id));
+    let id = names::slug(&id);
+    if id.is_empty() {
+        return Err(ApiError::BadRequest("a template id needs letters or digits in it".to_string()));
+    }
+    if let Some(field) = DATED_FIELDS.iter().find(|field| req.settings.contains_key(**field)) {
+        return Err(ApiError::BadRequest(format!(
+            "a template can't have a {field}, since it's used on other days; give deadlines as \
+            closes_after_minutes and nominations_close_after_minutes"
+        )));
+    }
+    // Caught now rather than the first time a poll is made from it
+    serde_json::from_value::<NewPoll>(Value::Object(req.settings.clone()))
+        .map_err(|err| ApiError::BadRequest(format!("those settings don't make a poll: {err}")))?;
+    let template = sqlx::query_as::<_, PollTemplate>(&format!(
+        "INSERT INTO poll_templates (id, name, settings) VALUES (?, ?, ?)
+        ON CONFLICT (id) DO UPDATE SET name = excluded.name, settings = excluded.settings,
+            updated_at = CURRENT_TIMESTAMP
+        RETURNING {TEMPLATE_COLUMNS}"
+    ))
+    .bind(&id)
+    .bind(name)
+    .bind(JsonColumn(req.settings))
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(template))
+}
+
+// DELETE /poll-templates/:id (admin): not while an office's daily poll comes from it. Polls made from it stay
+pub async fn delete_template(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let offices: Vec<String> = sqlx::query_scalar("SELECT id FROM offices WHERE daily_poll_template = ? ORDER BY id")
+        .bind(&id)
+        .fetch_all(&state.db)
+        .await?;
+    if !offices.is_empty() {
+        return Err(ApiError::Conflict(format!("the daily poll of {} comes from this template", offices.join(", "))));
+    }
+    let deleted = sqlx::query("DELETE FROM poll_templates WHERE id = ?").bind(&id).execute(&state.db).await?;
+    if deleted.rows_affected() == 0 {
+        return Err(Message::no_poll_template(id).into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /poll-templates/:id/polls: opens a poll from the template. The body is optional, with any POST /polls
+// fields to set for this one poll, {"attendees": ["Zoë", "Sam"], "closes_at": "2024-05-17 11:45"}
+pub async fn create_from_template(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    overrides: Option<Json<Map<String, Value>>>,
+) -> Result<(StatusCode, Json<Poll>), ApiError> {
+    let template = lookup(&state.db, &id).await?;
+    let req = new_poll(&template, overrides.map(|Json(overrides)| overrides).unwrap_or_default())?;
+    let poll = polls::insert_poll(&state, req, None).await?;
+    println!("poll {}: opened from template {id}", poll.id);
+    Ok((StatusCode::CREATED, Json(poll)))
+}
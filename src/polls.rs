@@ -0,0 +1,1350 @@
+// Polls group votes for a particular lunch and decide which restaurants are allowed on the ballot. A poll can also
+// be over options of its own, plain strings, for settling anything else the same way: its ballot is those, and the
+// filters, nominations, bookings and everything else about restaurants are left out
+use std::collections::HashSet;
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Query, State};
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::conditional::IfNoneMatch;
+use crate::error::{ApiError, Message};
+use crate::hours::OPEN_AT_SQL;
+use crate::links::PollLinks;
+use crate::moderation::{self, NameKind};
+use crate::public_ids::{self, PollId, RestaurantId};
+use crate::refresh::Cause;
+use crate::restaurants::{
+    self, NameResolution, Restaurant, RestaurantOrder, RestaurantStatus, OFFICE_POOL_SQL, RESTAURANT_SELECT,
+};
+use crate::tiebreaks::Tiebreak;
+use crate::weather::{self, Weather};
+use crate::offices::{self, POLL_NOW_SQL};
+use crate::{
+    announcements, badges, ballots, comments, i18n, join_codes, names, notifications, participation, quadratic,
+    reservations, rotation, rsvps, sealing, teams, timezones, voters, AppState, LunchVoting, TallyQuery,
+};
+
+// The public ids of the restaurants and polls a poll refers to are looked up alongside it, for the API to show
+const POLL_COLUMNS: &str = "id, public_id, title, description, metadata, required_tags, lunch_at,
+    ignore_opening_hours, max_distance_meters, max_walking_minutes, candidate_ids, attendees, respect_blacklists,
+    respect_preferences, weather, status, nominations_close_at, closes_at, voting_method, credit_budget, tiebreak,
+    tiebreak_seed, majority_percent, runoff_poll_id, eligible_voters, hide_results, sealed, scheduled_for, office_id,
+    team_id, join_code, reserve, reservation_status, reservation_reference, reservation_note, remote, picker, options,
+    timezone, utc_offset_minutes, created_at, COALESCE(updated_at, created_at) AS updated_at, version,
+    CASE WHEN candidate_ids IS NULL THEN NULL ELSE (
+        SELECT json_group_array(r.public_id) FROM json_each(polls.candidate_ids) c
+        JOIN restaurants r ON r.id = c.value AND r.deleted_at IS NULL
+    ) END AS candidate_public_ids,
+    (SELECT public_id FROM polls p WHERE p.id = polls.runoff_poll_id) AS runoff_poll_public_id,
+    (SELECT public_id FROM polls p WHERE p.id = polls.runoff_of) AS runoff_of_public_id";
+
+// The polls whose votes are still secret: hiding their results and not closed yet. Anything reading votes across
+// polls, like GET /results or /stats, leaves these out, and polls over options too, whose votes aren't for lunch
+pub const HIDDEN_POLLS_SQL: &str =
+    "SELECT id FROM polls WHERE (hide_results AND status != 'closed') OR options IS NOT NULL";
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Poll {
+    #[serde(skip)]
+    pub id: i64,
+    #[serde(rename = "id")]
+    pub public_id: String, // what clients know the poll by; see public_ids.rs
+    pub title: Option<String>,
+    description: Option<String>,
+    pub metadata: Option<JsonColumn<serde_json::Value>>, // a JSON object integrations keep with the poll
+    required_tags: JsonColumn<Vec<String>>, // every candidate must carry all of these dietary tags
+    pub lunch_at: String, // local "YYYY-MM-DD HH:MM" the team plans to eat; places closed then are left out
+    pub ignore_opening_hours: bool, // keep closed places on the ballot anyway, e.g. when the hours are known to be wrong
+    // Places farther than this from the office are left out; places whose distance isn't known stay in
+    max_distance_meters: Option<i64>,
+    max_walking_minutes: Option<i64>,
+    #[serde(skip)]
+    candidate_ids: Option<JsonColumn<Vec<i64>>>, // a shortlist, e.g. from GET /recommendations; None means no shortlist
+    #[serde(rename = "candidate_ids")]
+    candidate_public_ids: Option<JsonColumn<Vec<String>>>,
+    pub attendees: JsonColumn<Vec<String>>, // who's coming
+    respect_blacklists: bool, // leave out anything an attendee has blacklisted
+    // leave out anything missing an attendee's dietary needs or over their budget; see voters::GroupPreferences
+    respect_preferences: bool,
+    weather: Option<JsonColumn<Weather>>, // the lunch-time forecast at creation; None without an office location
+    pub status: PollStatus,
+    // With a nomination window the ballot is whatever gets nominated before it closes; without one, every
+    // eligible restaurant is a candidate from the start
+    nominations_close_at: Option<String>,
+    pub closes_at: Option<String>, // voting ends here; None leaves the poll open until an admin closes it
+    pub voting_method: VotingMethod,
+    pub credit_budget: Option<i64>, // on quadratic polls, the credits each voter gets
+    pub tiebreak: Tiebreak, // how a tie for first place is settled; see tiebreaks.rs
+    // Drawn when the poll is created, and only revealed in the results of a tie it has settled
+    #[serde(skip)]
+    pub tiebreak_seed: i64,
+    // Closing with the leader on no more than this share of the voters, in percent, opens a runoff between the top two
+    majority_percent: Option<f64>,
+    #[serde(skip)]
+    pub runoff_poll_id: Option<i64>, // the runoff opened to settle this poll's tie or lack of a majority
+    #[serde(rename = "runoff_poll_id")]
+    pub runoff_poll_public_id: Option<String>,
+    #[serde(rename = "runoff_of")]
+    runoff_of_public_id: Option<String>, // on a runoff, the poll it settles
+    pub eligible_voters: Option<JsonColumn<Vec<String>>>, // on a runoff, the voters of that poll, who alone may vote
+    // Until the poll closes its results only say how many have taken part, so nobody jumps on a bandwagon
+    pub hide_results: bool,
+    // Ballots are kept encrypted until the poll closes, and its results hidden until then; see sealing.rs
+    pub sealed: bool,
+    scheduled_for: Option<String>, // the day the scheduler opened this poll for; None for polls created by hand
+    // Only restaurants of this office, or of none, are on the ballot, and the poll runs on the office's clock
+    pub office_id: Option<String>,
+    // Or on this time zone's, Australia/Sydney, whose offset right now is kept in utc_offset_minutes; see timezones.rs
+    pub timezone: Option<String>,
+    #[serde(skip)]
+    utc_offset_minutes: Option<i64>,
+    pub team_id: Option<String>, // only this team's members may vote or abstain; see teams.rs
+    pub join_code: Option<String>, // LUNCH-7F3K, for finding the poll by; see join_codes.rs
+    // Whether a table is booked at the winner when the poll closes, and how that went; see reservations.rs
+    pub reserve: bool,
+    reservation_status: Option<String>, // pending, confirmed, failed or skipped
+    pub reservation_reference: Option<String>, // the provider's confirmation
+    reservation_note: Option<String>,
+    pub remote: bool, // lunch is delivered, so the winner announcement has delivery links; see announcements.rs
+    pub picker: Option<String>, // whose turn it is to pick, for rotation polls; None once nobody's left to ask
+    options: Option<JsonColumn<Vec<String>>>, // the ballot, on a poll that isn't over restaurants
+    created_at: String,
+    updated_at: String, // changes with the poll's status, among other things; votes don't count
+    // One more every time updated_at changes; the poll's ETag, which the admin endpoints check If-Match against
+    pub version: i64,
+    // Worked out from the id and status above, which sqlx reads a second time for them; see links.rs
+    #[sqlx(flatten)]
+    #[serde(rename = "_links")]
+    links: PollLinks,
+}
+
+// Stored as lowercase text, like RestaurantStatus
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum PollStatus {
+    Nominating,
+    Open,
+    Closed,
+}
+
+// How the ballots are counted. Plurality ballots name one restaurant; ranked ones list candidates favourite first
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum VotingMethod {
+    #[default]
+    Plurality, // most votes wins
+    Condorcet, // whoever beats every other restaurant head to head; see ranked.rs for what happens without one
+    Borda, // points for every place on every ballot, more the higher it's ranked
+    Quadratic, // votes bought from a budget of credits, n votes for one place costing n²; see quadratic.rs
+    Lottery, // every vote a ticket in a draw for the winner when the poll closes; see lottery.rs
+    Rotation, // no vote: one person picks for everyone, taking turns; see rotation.rs
+}
+
+impl Poll {
+    // The SQLite modifier for the poll's clock, like offices::clock: its time zone's offset, or its office's clock
+    pub async fn clock(&self, db: &SqlitePool) -> Result<String, sqlx::Error> {
+        match self.utc_offset_minutes {
+            Some(minutes) => Ok(format!("{minutes:+} minutes")),
+            None => Ok(offices::clock(offices::find(db, self.office_id.as_deref()).await?.as_ref())),
+        }
+    }
+
+    // For a poll made from this one, a clone or a runoff, to carry the same
+    fn details(&self) -> PollDetails {
+        PollDetails {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            metadata: self.metadata.as_ref().map(|JsonColumn(metadata)| metadata.clone()),
+        }
+    }
+
+    // What a poll over options can be voted for; None for a restaurant poll
+    pub fn options(&self) -> Option<&[String]> {
+        self.options.as_ref().map(|JsonColumn(options)| options.as_slice())
+    }
+}
+
+impl VotingMethod {
+    pub fn is_ranked(self) -> bool {
+        matches!(self, VotingMethod::Condorcet | VotingMethod::Borda)
+    }
+}
+
+// The longest title and description a poll can have, in characters, and the most metadata, in bytes of JSON
+const MAX_TITLE: usize = 200;
+const MAX_DESCRIPTION: usize = 2000;
+const MAX_METADATA: usize = 16 * 1024;
+
+// A poll's own words and the integrations' metadata, given when it's created and changed with PATCH /polls/:id
+#[derive(Default, Clone, Deserialize)]
+pub struct PollDetails {
+    title: Option<String>,
+    description: Option<String>,
+    metadata: Option<serde_json::Value>,
+}
+
+impl PollDetails {
+    // Trimmed, with an empty title or description coming back as Some(""), which clears it on an update
+    fn validate(self) -> Result<PollDetails, ApiError> {
+        let title = self.title.map(|title| names::clean(&title));
+        if title.as_ref().is_some_and(|title| title.chars().count() > MAX_TITLE) {
+            return Err(Message::title_too_long(MAX_TITLE).into());
+        }
+        let description = self.description.map(|description| description.trim().to_string());
+        if description.as_ref().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION) {
+            return Err(Message::description_too_long(MAX_DESCRIPTION).into());
+        }
+        if let Some(metadata) = &self.metadata {
+            if !metadata.is_object() {
+                return Err(Message::metadata_not_object().into());
+            }
+            if metadata.to_string().len() > MAX_METADATA {
+                return Err(Message::metadata_too_large(MAX_METADATA).into());
+            }
+        }
+        Ok(PollDetails { title, description, metadata: self.metadata })
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct NewPoll {
+    // flatten reads the details' fields from the top level of the body, next to the others
+    // https://serde.rs/attr-flatten.html
+    #[serde(flatten)]
+    details: PollDetails,
+    #[serde(default)]
+    required_tags: Vec<String>,
+    lunch_at: Option<String>, // defaults to today at LUNCH_TIME
+    #[serde(default)]
+    ignore_opening_hours: bool,
+    max_distance_meters: Option<i64>,
+    max_walking_minutes: Option<i64>,
+    candidate_ids: Option<Vec<String>>, // restaurants' public ids
+    #[serde(default)]
+    attendees: Vec<String>,
+    #[serde(default)]
+    respect_blacklists: bool,
+    #[serde(default)]
+    respect_preferences: bool,
+    nominations_close_at: Option<String>,
+    closes_at: Option<String>,
+    // Instead of the times, how long after the poll is created they come; what a template keeps, being used on
+    // other days
+    nominations_close_after_minutes: Option<i64>,
+    closes_after_minutes: Option<i64>,
+    #[serde(default)]
+    voting_method: VotingMethod,
+    credit_budget: Option<i64>, // defaults to quadratic::DEFAULT_CREDIT_BUDGET
+    #[serde(default)]
+    tiebreak: Tiebreak,
+    majority_percent: Option<f64>,
+    #[serde(default)]
+    hide_results: bool,
+    #[serde(default)]
+    sealed: bool,
+    office_id: Option<String>, // defaults to the team's office
+    timezone: Option<String>,  // defaults to the office's
+    team_id: Option<String>,
+    #[serde(default)]
+    reserve: bool,
+    #[serde(default)]
+    remote: bool,
+    // Makes it a poll over these rather than restaurants, so none of the settings about restaurants can be given
+    options: Option<Vec<String>>,
+    // Only set for runoffs, never from the request body
+    #[serde(skip)]
+    eligible_voters: Option<Vec<String>>,
+}
+
+impl NewPoll {
+    // The scheduler's daily poll: everything as it comes, for an office or for the instance as a whole
+    pub fn daily(office_id: Option<String>) -> Self {
+        NewPoll { office_id, ..Default::default() }
+    }
+
+    // The settings given that only mean something on a restaurant poll
+    fn restaurant_settings(&self) -> Vec<&'static str> {
+        [
+            ("candidate_ids", self.candidate_ids.is_some()),
+            ("required_tags", !self.required_tags.is_empty()),
+            ("max_distance_meters", self.max_distance_meters.is_some()),
+            ("max_walking_minutes", self.max_walking_minutes.is_some()),
+            ("respect_blacklists", self.respect_blacklists),
+            ("respect_preferences", self.respect_preferences),
+            ("nominations_close_at", self.nominations_close_at.is_some()),
+            ("nominations_close_after_minutes", self.nominations_close_after_minutes.is_some()),
+            ("reserve", self.reserve),
+            ("remote", self.remote),
+            ("tiebreak closest", self.tiebreak == Tiebreak::Closest),
+        ]
+        .into_iter()
+        .filter_map(|(setting, given)| given.then_some(setting))
+        .collect()
+    }
+}
+
+// The If-Match header the admin endpoints that change a poll need: the version the change was made against, "3" as
+// the poll's ETag has it, so a change made while looking at an out-of-date poll is refused with 409 Conflict rather
+// than undoing someone else's. * makes the change whatever version the poll is at
+// https://www.rfc-editor.org/rfc/rfc9110#name-if-match
+pub struct IfMatch(Option<i64>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IF_MATCH) else {
+            return Err(Message::if_match_required().into());
+        };
+        let value = value.to_str().unwrap_or_default().trim();
+        if value == "*" {
+            return Ok(IfMatch(None));
+        }
+        match value.trim_start_matches("W/").trim_matches('"').parse() {
+            Ok(version) => Ok(IfMatch(Some(version))),
+            Err(_) => Err(ApiError::BadRequest(format!("If-Match must be a poll's version, like \"3\", not {value}"))),
+        }
+    }
+}
+
+impl IfMatch {
+    fn check(&self, poll: &Poll) -> Result<(), ApiError> {
+        match self.0 {
+            Some(expected) if expected != poll.version => Err(changed(expected, poll.version)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn changed(expected: i64, current: i64) -> ApiError {
+    ApiError::from(Message::poll_changed(expected, current))
+}
+
+// Why a change checked against version `expected` changed nothing: the poll has gone, or changed meanwhile
+async fn not_changed<'e>(db: impl sqlx::SqliteExecutor<'e>, id: i64, public_id: &str, expected: i64) -> ApiError {
+    match sqlx::query_scalar("SELECT version FROM polls WHERE id = ?").bind(id).fetch_optional(db).await {
+        Ok(Some(current)) => changed(expected, current),
+        Ok(None) => ApiError::from(Message::no_poll(public_id)),
+        Err(err) => err.into(),
+    }
+}
+
+// A poll with its version as the ETag, for the If-Match of the next change to it
+pub struct Versioned(Poll);
+
+impl IntoResponse for Versioned {
+    fn into_response(self) -> Response {
+        ([(ETAG, etag(self.0.version))], Json(self.0)).into_response()
+    }
+}
+
+fn etag(version: i64) -> String {
+    format!("\"{version}\"")
+}
+
+pub async fn find_poll(db: &SqlitePool, id: i64) -> Result<Option<Poll>, sqlx::Error> {
+    sqlx::query_as::<_, Poll>(&format!("SELECT {POLL_COLUMNS} FROM polls WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+}
+
+// The restaurant a closed poll settled on, for what happens after it: orders, the booking, the announcement. A
+// Conflict while there's none, because the poll is still running, went to a runoff or had no votes
+pub async fn winner(state: &AppState, poll: &Poll) -> Result<String, ApiError> {
+    if poll.status != PollStatus::Closed {
+        return Err(ApiError::Conflict("the poll hasn't closed yet".to_string()));
+    }
+    if let Some(runoff) = &poll.runoff_poll_public_id {
+        return Err(ApiError::Conflict(format!("this poll went to a runoff, {runoff}, which decides instead")));
+    }
+    let voting = crate::tally(state, Some(poll), None).await?;
+    voting
+        .votes
+        .first()
+        .map(|restaurant| restaurant.name.clone())
+        .ok_or_else(|| ApiError::Conflict("the poll closed without a winner".to_string()))
+}
+
+// The restaurants that may be voted for in this poll. Vote validation goes through here too,
+// so the ballot and the candidate list can never disagree. In a poll with a nomination window,
+// that's the nominated restaurants (so far, while nominations are still open). None on a poll over options
+pub async fn candidates(state: &AppState, poll: &Poll, order: RestaurantOrder) -> Result<Vec<Restaurant>, sqlx::Error> {
+    if poll.options.is_some() {
+        return Ok(Vec::new());
+    }
+    eligible(state, poll, order, poll.nominations_close_at.is_some()).await
+}
+
+// The names on the ballot, whatever the poll is over: its options, or its candidates' names
+pub async fn choices(state: &AppState, poll: &Poll) -> Result<Vec<String>, sqlx::Error> {
+    match poll.options() {
+        Some(options) => Ok(options.to_vec()),
+        None => Ok(candidates(state, poll, RestaurantOrder::Name).await?.into_iter().map(|r| r.name).collect()),
+    }
+}
+
+// The restaurants that pass the poll's filters, optionally narrowed to the ones nominated in it
+async fn eligible(
+    state: &AppState,
+    poll: &Poll,
+    order: RestaurantOrder,
+    nominated_only: bool,
+) -> Result<Vec<Restaurant>, sqlx::Error> {
+    let locale = i18n::sorting_locale(&state.db, poll.team_id.as_deref()).await?;
+    // json_each turns a JSON array into rows, so "is any required tag missing from this restaurant?"
+    // becomes an ordinary NOT EXISTS subquery
+    // https://www.sqlite.org/json1.html#jeach
+    let restaurants = sqlx::query_as::<_, Restaurant>(&format!(
+        "{RESTAURANT_SELECT}
+        WHERE r.active AND r.status = ? AND NOT EXISTS (
+            SELECT 1 FROM json_each(?) required
+            WHERE required.value NOT IN (SELECT value FROM json_each(r.dietary_tags))
+        )
+        AND {OFFICE_POOL_SQL}
+        AND (? OR {OPEN_AT_SQL})
+        AND (? IS NULL OR r.id IN (SELECT value FROM json_each(?)))
+        AND (NOT ? OR r.id IN (SELECT restaurant_id FROM nominations WHERE poll_id = ?))
+        AND r.id NOT IN (SELECT restaurant_id FROM poll_unavailable WHERE poll_id = ?)
+        AND NOT (? AND r.id IN (
+            SELECT restaurant_id FROM voter_blacklist WHERE voter_key IN (SELECT value FROM json_each(?))
+        ))
+        ORDER BY {}",
+        order.order_by_sql(locale)
+    ))
+    .bind(RestaurantStatus::Approved)
+    .bind(&poll.required_tags)
+    .bind(&poll.office_id)
+    .bind(&poll.office_id)
+    .bind(poll.ignore_opening_hours)
+    .bind(&poll.lunch_at)
+    .bind(&poll.candidate_ids)
+    .bind(&poll.candidate_ids)
+    .bind(nominated_only)
+    .bind(poll.id)
+    .bind(poll.id)
+    .bind(poll.respect_blacklists)
+    .bind(JsonColumn(poll.attendees.iter().map(|name| names::fold(name)).collect::<Vec<_>>()))
+    .fetch_all(&state.db)
+    .await?;
+
+    // Distances may come from coordinates rather than a stored column, so the limits are applied here rather than in SQL.
+    // The attendees' preferences are combined in Rust too
+    let office = offices::find(&state.db, poll.office_id.as_deref()).await?;
+    let office = offices::location(state, office.as_ref());
+    let preferences = match poll.respect_preferences {
+        true => voters::GroupPreferences::load(&state.db, &poll.attendees).await?,
+        false => voters::GroupPreferences::default(),
+    };
+    let mut restaurants: Vec<Restaurant> = restaurants
+        .into_iter()
+        .filter(|restaurant| {
+            restaurant
+                .details
+                .within(office, poll.max_distance_meters, poll.max_walking_minutes)
+                && preferences.allows(&restaurant.details)
+        })
+        .collect();
+
+    // In the rain, places where you'd get wet are flagged and moved to the end; sort_by_key is stable,
+    // so the requested order holds within each group
+    if let Some(JsonColumn(weather)) = &poll.weather {
+        for restaurant in &mut restaurants {
+            restaurant.weather_warning = weather::warning(state, weather, office, restaurant);
+        }
+        restaurants.sort_by_key(|restaurant| restaurant.weather_warning.is_some());
+    }
+    Ok(restaurants)
+}
+
+// POST /polls
+pub async fn create_poll(
+    State(state): State<AppState>,
+    Json(req): Json<NewPoll>,
+) -> Result<(StatusCode, Json<Poll>), ApiError> {
+    let poll = insert_poll(&state, req, None).await?;
+    Ok((StatusCode::CREATED, Json(poll)))
+}
+
+// Shared by POST /polls and the daily scheduler, which passes the day it's opening the poll for
+// An office's poll has its times in the office's local time, and defaults to its lunch time. A team's poll starts
+// with the team as its attendees
+pub async fn insert_poll(state: &AppState, mut req: NewPoll, scheduled_for: Option<String>) -> Result<Poll, ApiError> {
+    // Options are screened like restaurant names, since everyone sees them, and are matched the way voter names are,
+    // so two that only differ in case or accents can't both be on the ballot
+    let details = std::mem::take(&mut req.details).validate()?;
+    let options = match req.options.take() {
+        Some(given) => {
+            let restaurant_settings = req.restaurant_settings();
+            if !restaurant_settings.is_empty() {
+                return Err(Message::only_for_restaurants(restaurant_settings.join(", ")).into());
+            }
+            let mut options: Vec<String> = Vec::new();
+            for option in given {
+                let option = names::clean(&option);
+                if !option.is_empty() && !options.iter().any(|known| names::fold(known) == names::fold(&option)) {
+                    moderation::screen(state, NameKind::Restaurant, &option).await?;
+                    options.push(option);
+                }
+            }
+            if options.len() < 2 {
+                return Err(Message::too_few_options().into());
+            }
+            Some(options)
+        }
+        None => None,
+    };
+    let team = teams::lookup(&state.db, req.team_id.as_deref()).await?;
+    let office_id = req.office_id.clone().or_else(|| team.as_ref().and_then(|team| team.office_id.clone()));
+    let office = offices::lookup(&state.db, office_id.as_deref()).await?;
+    // Every time the poll is given, or defaults to, is on its time zone's clock when it has one
+    let timezone = match req.timezone.as_deref().map(str::trim).filter(|zone| !zone.is_empty()) {
+        Some(zone) => Some(timezones::validate(state, zone)?),
+        None => office.as_ref().and_then(|office| office.timezone.clone()),
+    };
+    let utc_offset_minutes = timezone.as_deref().and_then(|zone| timezones::offset_now(state, zone));
+    let clock = match utc_offset_minutes {
+        Some(minutes) => format!("{minutes:+} minutes"),
+        None => offices::clock(office.as_ref()),
+    };
+    let lunch_at: Option<String> = match req.lunch_at {
+        Some(lunch_at) => local_time(&state.db, &lunch_at).await?,
+        None => sqlx::query_scalar("SELECT date('now', ?) || ' ' || ?")
+            .bind(&clock)
+            .bind(office.as_ref().map_or(&state.config.lunch_time, |office| &office.lunch_time))
+            .fetch_one(&state.db)
+            .await?,
+    };
+    let invalid_time = |field: &str| Message::not_local_time(field);
+    let nominations_close_at = match (&req.nominations_close_at, req.nominations_close_after_minutes) {
+        (Some(_), Some(_)) => {
+            return Err(Message::both_given("nominations_close_at", "nominations_close_after_minutes").into())
+        }
+        (Some(time), None) => {
+            Some(local_time(&state.db, time).await?.ok_or_else(|| invalid_time("nominations_close_at"))?)
+        }
+        (None, Some(minutes)) => Some(minutes_from_now(&state.db, &clock, minutes).await?),
+        (None, None) => None,
+    };
+    let closes_at = match (&req.closes_at, req.closes_after_minutes) {
+        (Some(_), Some(_)) => {
+            return Err(Message::both_given("closes_at", "closes_after_minutes").into())
+        }
+        (Some(time), None) => Some(local_time(&state.db, time).await?.ok_or_else(|| invalid_time("closes_at"))?),
+        (None, Some(minutes)) => Some(minutes_from_now(&state.db, &clock, minutes).await?),
+        (None, None) => None,
+    };
+    // The strings compare correctly because they all have the same fixed-width format
+    if let (Some(nominations_close_at), Some(closes_at)) = (&nominations_close_at, &closes_at) {
+        if nominations_close_at >= closes_at {
+            return Err(Message::nominations_after_close().into());
+        }
+    }
+    let status = match nominations_close_at {
+        Some(_) => PollStatus::Nominating,
+        None => PollStatus::Open,
+    };
+    if req.max_distance_meters.is_some_and(|meters| meters < 0) || req.max_walking_minutes.is_some_and(|minutes| minutes < 0)
+    {
+        return Err(Message::negative_distance().into());
+    }
+    let lunch_at = lunch_at.ok_or_else(|| invalid_time("lunch_at"))?;
+    let credit_budget = match req.voting_method {
+        VotingMethod::Quadratic => Some(req.credit_budget.unwrap_or(quadratic::DEFAULT_CREDIT_BUDGET)),
+        _ => None,
+    };
+    if credit_budget.is_some_and(|budget| budget < 1) {
+        return Err(Message::credit_budget_too_small().into());
+    }
+    // A quadratic ballot's spending is checked against what the voter has already spent, which sealing would hide
+    if req.sealed && req.voting_method == VotingMethod::Quadratic {
+        return Err(Message::quadratic_sealed().into());
+    }
+    // A drawn winner stands, and so does a picked one: there's no tie to break, and no majority to fall short of
+    let runoff = req.tiebreak == Tiebreak::Runoff || req.majority_percent.is_some();
+    if matches!(req.voting_method, VotingMethod::Lottery | VotingMethod::Rotation) && runoff {
+        let method = if req.voting_method == VotingMethod::Lottery { "lottery" } else { "rotation" };
+        return Err(Message::runoff_not_allowed(method).into());
+    }
+    if req.majority_percent.is_some_and(|percent| !(0.0..100.0).contains(&percent)) {
+        return Err(Message::majority_out_of_range().into());
+    }
+
+    let candidate_ids = match &req.candidate_ids {
+        Some(public_ids) => {
+            let mut ids = Vec::new();
+            for public_id in public_ids {
+                match public_ids::restaurant(&state.db, public_id).await {
+                    Ok(id) => ids.push(id),
+                    Err(ApiError::NotFound(_)) => {
+                        return Err(Message::unknown_candidates().into());
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            ids.sort();
+            ids.dedup();
+            if ids.is_empty() {
+                return Err(Message::unknown_candidates().into());
+            }
+            Some(ids)
+        }
+        None => None,
+    };
+    let listed = match (&team, req.attendees.is_empty()) {
+        (Some(team), true) => team.members.iter().map(|member| member.voter_name.clone()).collect(),
+        _ => req.attendees,
+    };
+    let mut attendees = Vec::new();
+    for name in &listed {
+        let name = names::canonical_voter_name(&state.db, name).await?;
+        if !name.is_empty() && !attendees.iter().any(|known: &String| names::fold(known) == names::fold(&name)) {
+            attendees.push(name);
+        }
+    }
+    let picker = match req.voting_method {
+        VotingMethod::Rotation => {
+            let team_id = team.as_ref().map(|team| team.id.as_str());
+            Some(rotation::picker_for(&state.db, team_id, &attendees, &lunch_at).await?)
+        }
+        _ => None,
+    };
+    // Nobody has to go out to decide on options, and with no restaurants there are no opening hours to worry about
+    let weather = match options {
+        Some(_) => None,
+        None => weather::forecast(state, offices::location(state, office.as_ref()), &lunch_at).await,
+    };
+    let ignore_opening_hours = req.ignore_opening_hours || options.is_some();
+
+    // The public id comes from a trigger, which RETURNING wouldn't see, so the poll is read back afterwards
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO polls
+            (required_tags, lunch_at, ignore_opening_hours, max_distance_meters, max_walking_minutes, candidate_ids,
+            attendees, respect_blacklists, respect_preferences, weather, status, nominations_close_at, closes_at,
+            voting_method, credit_budget, tiebreak, tiebreak_seed, majority_percent, eligible_voters, hide_results,
+            sealed, scheduled_for, office_id, team_id, reserve, remote, picker, options, title, description, metadata,
+            timezone, utc_offset_minutes)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, random(), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
+            NULLIF(?, ''), NULLIF(?, ''), ?, ?, ?)
+        RETURNING id",
+    )
+    .bind(JsonColumn(restaurants::normalize_tags(req.required_tags)))
+    .bind(lunch_at)
+    .bind(ignore_opening_hours)
+    .bind(req.max_distance_meters)
+    .bind(req.max_walking_minutes)
+    .bind(candidate_ids.map(JsonColumn))
+    .bind(JsonColumn(attendees))
+    .bind(req.respect_blacklists)
+    .bind(req.respect_preferences)
+    .bind(weather.map(JsonColumn))
+    .bind(status)
+    .bind(nominations_close_at)
+    .bind(closes_at)
+    .bind(req.voting_method)
+    .bind(credit_budget)
+    .bind(req.tiebreak)
+    .bind(req.majority_percent)
+    .bind(req.eligible_voters.map(JsonColumn))
+    .bind(req.hide_results)
+    .bind(req.sealed)
+    .bind(scheduled_for)
+    .bind(office.map(|office| office.id))
+    .bind(team.map(|team| team.id))
+    .bind(req.reserve)
+    .bind(req.remote)
+    .bind(&picker)
+    .bind(options.map(JsonColumn))
+    .bind(details.title)
+    .bind(details.description)
+    .bind(details.metadata.map(JsonColumn))
+    .bind(timezone)
+    .bind(utc_offset_minutes)
+    .fetch_one(&state.db)
+    .await?;
+    join_codes::assign(&state.db, id).await?;
+    let poll = find_poll(&state.db, id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    if poll.sealed {
+        sealing::create(state, &state.db, poll.id).await?;
+    }
+    // The seed goes to the log before any votes are in, so nobody can claim it was picked to suit the outcome
+    if poll.voting_method == VotingMethod::Lottery {
+        println!("poll {}: the winner will be drawn with seed {}", poll.id, poll.tiebreak_seed);
+    } else if poll.tiebreak == Tiebreak::Random {
+        println!("poll {}: ties will be drawn with seed {}", poll.id, poll.tiebreak_seed);
+    }
+    if let Some(picker) = &picker {
+        println!("poll {}: it's {picker}'s turn to pick", poll.id);
+    }
+    if poll.status == PollStatus::Open {
+        notifications::spawn_poll_opened(state, poll.id);
+    }
+    Ok(poll)
+}
+
+// The local time `minutes` from now on the poll's clock, in the same format local_time gives
+async fn minutes_from_now(db: &SqlitePool, clock: &str, minutes: i64) -> Result<String, ApiError> {
+    if minutes < 1 {
+        return Err(Message::offset_too_small().into());
+    }
+    let time = sqlx::query_scalar("SELECT strftime('%Y-%m-%d %H:%M', 'now', ?, ?)")
+        .bind(clock)
+        .bind(format!("+{minutes} minutes"))
+        .fetch_one(db)
+        .await?;
+    Ok(time)
+}
+
+// strftime both checks a time and normalizes it, so "2024-05-17T12:30:00" is stored as "2024-05-17 12:30";
+// anything SQLite can't read as a time comes back NULL
+// https://www.sqlite.org/lang_datefunc.html
+pub async fn local_time(db: &SqlitePool, time: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT strftime('%Y-%m-%d %H:%M', ?)")
+        .bind(time.trim())
+        .fetch_one(db)
+        .await
+}
+
+// Moves polls whose nomination window or voting deadline has passed on to their next phase. Run by the
+// scheduler every minute, each poll on its own clock; returns the polls that changed and the status they're now in
+pub async fn advance_due_polls(db: &SqlitePool) -> Result<Vec<(i64, PollStatus)>, sqlx::Error> {
+    let now = POLL_NOW_SQL;
+    let mut advanced: Vec<(i64, PollStatus)> = sqlx::query_as(&format!(
+        "UPDATE polls SET status = 'open' WHERE status = 'nominating' AND nominations_close_at <= {now}
+        RETURNING id, status"
+    ))
+    .fetch_all(db)
+    .await?;
+    // A poll can pass both deadlines while the server is down; closing second lets it go straight through
+    let closed: Vec<(i64, PollStatus)> = sqlx::query_as(&format!(
+        "UPDATE polls SET status = 'closed' WHERE status = 'open' AND closes_at <= {now} RETURNING id, status"
+    ))
+    .fetch_all(db)
+    .await?;
+    advanced.retain(|(id, _)| !closed.iter().any(|(closed_id, _)| closed_id == id));
+    advanced.extend(closed);
+    Ok(advanced)
+}
+
+// POST /polls/:id/advance (admin): ends the current phase now instead of waiting for its deadline
+pub async fn advance_poll(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    expected: IfMatch,
+) -> Result<Versioned, ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    expected.check(&poll)?;
+    let next = match poll.status {
+        PollStatus::Nominating => PollStatus::Open,
+        PollStatus::Open => PollStatus::Closed,
+        PollStatus::Closed => return Err(Message::poll_already_closed().into()),
+    };
+    // The status check makes this a no-op if the scheduler got there first; with a version, which the scheduler
+    // moves on too, that's a conflict like any other change
+    let advanced =
+        sqlx::query("UPDATE polls SET status = ?1 WHERE id = ?2 AND status = ?3 AND (?4 IS NULL OR version = ?4)")
+            .bind(next)
+            .bind(id)
+            .bind(poll.status)
+            .bind(expected.0)
+            .execute(&state.db)
+            .await?;
+    if let (0, Some(expected)) = (advanced.rows_affected(), expected.0) {
+        return Err(not_changed(&state.db, id, &public_id, expected).await);
+    }
+    if advanced.rows_affected() > 0 {
+        match next {
+            PollStatus::Open => notifications::spawn_poll_opened(&state, id),
+            _ => on_close(&state, id).await?,
+        }
+    }
+    get_poll(State(state), PollId(id, public_id)).await
+}
+
+#[derive(Default, Deserialize)]
+pub struct Cloning {
+    lunch_at: Option<String>, // defaults to today, at the time the original's lunch was
+    closes_at: Option<String>, // defaults to as long before lunch as the original closed
+}
+
+// POST /polls/:id/clone: {"lunch_at": "2024-05-24 12:30"}, both fields optional, opens a new poll with the same
+// ballot and settings as this one, "the same options as last Friday". The ballot is its options, or the candidates
+// it had, nominated ones included, as the new poll's candidate_ids, so it's open for votes straight away; the new
+// poll's own filters still apply at its lunch_at. Nothing that happened in the original comes along: no votes, RSVPs
+// or runoff, and a rotation poll gets whoever's turn it is now
+pub async fn clone_poll(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    req: Option<Json<Cloning>>,
+) -> Result<(StatusCode, Json<Poll>), ApiError> {
+    let original = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    let Json(req) = req.unwrap_or_default();
+    let clock = original.clock(&state.db).await?;
+    let invalid_time = |field: &str| Message::not_local_time(field);
+    let lunch_at = match &req.lunch_at {
+        Some(time) => local_time(&state.db, time).await?.ok_or_else(|| invalid_time("lunch_at"))?,
+        None => sqlx::query_scalar("SELECT date('now', ?) || ' ' || substr(?, 12)")
+            .bind(&clock)
+            .bind(&original.lunch_at)
+            .fetch_one(&state.db)
+            .await?,
+    };
+    let closes_at = match (&req.closes_at, &original.closes_at) {
+        (Some(time), _) => Some(local_time(&state.db, time).await?.ok_or_else(|| invalid_time("closes_at"))?),
+        (None, Some(closed_at)) => {
+            let (closes_at, passed): (String, bool) = sqlx::query_as(
+                "SELECT closes_at, closes_at <= strftime('%Y-%m-%d %H:%M', 'now', ?1) FROM (
+                    SELECT strftime('%Y-%m-%d %H:%M', ?2, printf('%+d minutes',
+                        CAST(ROUND((julianday(?3) - julianday(?4)) * 1440) AS INTEGER))) AS closes_at
+                )",
+            )
+            .bind(&clock)
+            .bind(&lunch_at)
+            .bind(closed_at)
+            .bind(&original.lunch_at)
+            .fetch_one(&state.db)
+            .await?;
+            if passed {
+                return Err(ApiError::BadRequest(format!(
+                    "the copy would close at {closes_at}, which has passed; give it a lunch_at or closes_at"
+                )));
+            }
+            Some(closes_at)
+        }
+        (None, None) => None,
+    };
+    let candidate_ids: Vec<String> = candidates(&state, &original, RestaurantOrder::Name)
+        .await?
+        .into_iter()
+        .map(|restaurant| restaurant.public_id)
+        .collect();
+    let copy = NewPoll {
+        details: original.details(),
+        required_tags: original.required_tags.0.clone(),
+        lunch_at: Some(lunch_at),
+        ignore_opening_hours: original.ignore_opening_hours,
+        max_distance_meters: original.max_distance_meters,
+        max_walking_minutes: original.max_walking_minutes,
+        candidate_ids: (!candidate_ids.is_empty()).then_some(candidate_ids),
+        options: original.options().map(<[String]>::to_vec),
+        timezone: original.timezone.clone(),
+        attendees: original.attendees.0.clone(),
+        respect_blacklists: original.respect_blacklists,
+        respect_preferences: original.respect_preferences,
+        closes_at,
+        voting_method: original.voting_method,
+        credit_budget: original.credit_budget,
+        tiebreak: original.tiebreak,
+        majority_percent: original.majority_percent,
+        hide_results: original.hide_results,
+        sealed: original.sealed,
+        office_id: original.office_id.clone(),
+        team_id: original.team_id.clone(),
+        reserve: original.reserve,
+        remote: original.remote,
+        ..Default::default()
+    };
+    let poll = insert_poll(&state, copy, None).await?;
+    println!("poll {}: cloned from poll {id}", poll.id);
+    Ok((StatusCode::CREATED, Json(poll)))
+}
+
+#[derive(Deserialize)]
+pub struct Reopening {
+    closes_at: String,
+}
+
+// POST /polls/:id/reopen (admin): {"closes_at": "2024-05-17 12:45"} takes a closed poll back to open until the new
+// deadline, when the scheduler closes it again and the close workflow runs afresh. The result it closed with is
+// withdrawn: the runoff it opened goes, as long as nobody has voted in it yet, and GET /results/stream tells
+// listeners the tally is live again. A sealed poll gets a new key, since the old one went with the reveal
+pub async fn reopen_poll(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    expected: IfMatch,
+    Json(req): Json<Reopening>,
+) -> Result<Versioned, ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    expected.check(&poll)?;
+    if poll.status != PollStatus::Closed {
+        return Err(Message::only_closed_reopened().into());
+    }
+    let closes_at = local_time(&state.db, &req.closes_at)
+        .await?
+        .ok_or_else(|| Message::not_local_time("closes_at"))?;
+    let now: String = sqlx::query_scalar("SELECT strftime('%Y-%m-%d %H:%M', 'now', ?)")
+        .bind(poll.clock(&state.db).await?)
+        .fetch_one(&state.db)
+        .await?;
+    if closes_at <= now {
+        return Err(ApiError::BadRequest("closes_at must be in the future".to_string()));
+    }
+
+    let mut tx = state.db.begin().await?;
+    if let Some(runoff_id) = poll.runoff_poll_id {
+        let started: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM votes WHERE poll_id = ?1)
+                OR EXISTS (SELECT 1 FROM sealed_ballots WHERE poll_id = ?1)
+                OR EXISTS (SELECT 1 FROM abstentions WHERE poll_id = ?1)",
+        )
+        .bind(runoff_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if started {
+            return Err(ApiError::Conflict(format!(
+                "the runoff this poll opened, {}, has already been voted in",
+                poll.runoff_poll_public_id.clone().unwrap_or_default()
+            )));
+        }
+    }
+    // The status check makes this a no-op if another reopening got there first, and the version if anything else did
+    let reopened = sqlx::query(
+        "UPDATE polls SET status = ?1, closes_at = ?2, runoff_poll_id = NULL
+        WHERE id = ?3 AND status = ?4 AND (?5 IS NULL OR version = ?5)",
+    )
+    .bind(PollStatus::Open)
+    .bind(&closes_at)
+    .bind(id)
+    .bind(PollStatus::Closed)
+    .bind(expected.0)
+    .execute(&mut *tx)
+    .await?;
+    if reopened.rows_affected() == 0 {
+        return Err(match expected.0 {
+            Some(expected) => not_changed(&mut *tx, id, &public_id, expected).await,
+            None => ApiError::from(Message::only_closed_reopened()),
+        });
+    }
+    if let Some(runoff_id) = poll.runoff_poll_id {
+        // The table names are our own, never the client's, so formatting them into the SQL is safe
+        for table in [
+            "reactions",
+            "poll_unavailable",
+            "nominations",
+            "poll_invitations",
+            "rsvps",
+            "bill_entries",
+            "bill_payments",
+            "poll_orders",
+            "poll_reminders",
+            "vote_links",
+            "sealed_ballots",
+            "sealing_keys",
+        ] {
+            let sql = format!("DELETE FROM {table} WHERE poll_id = ?");
+            sqlx::query(&sql).bind(runoff_id).execute(&mut *tx).await?;
+        }
+        sqlx::query("DELETE FROM polls WHERE id = ?").bind(runoff_id).execute(&mut *tx).await?;
+        println!("poll {id}: withdrew runoff poll {runoff_id}");
+    }
+    // A new deadline gets its own reminders
+    sqlx::query("DELETE FROM poll_reminders WHERE poll_id = ?").bind(id).execute(&mut *tx).await?;
+    if poll.sealed {
+        sealing::create(&state, &mut *tx, id).await?;
+    }
+    tx.commit().await?;
+    println!("poll {id}: reopened until {closes_at}");
+    state.refreshes.send(Some(poll.public_id.clone()), Cause::Reopened);
+    get_poll(State(state), PollId(id, public_id)).await
+}
+
+// The close workflow, run once a poll's status has become closed: a sealed poll's ballots are revealed first, so
+// they're counted by everything that follows, then a runoff is opened if the result calls for one. Without a
+// runoff the winner stands: it's announced to WINNER_WEBHOOK_URL, the MQTT broker and the voters who want it, and a
+// table is booked there if the poll asked for one. Either way its voters' badges are worked out
+pub async fn on_close(state: &AppState, id: i64) -> Result<(), ApiError> {
+    let revealed = sealing::reveal(state, id).await?;
+    if revealed > 0 {
+        println!("poll {id}: revealed {revealed} sealed ballots");
+    }
+    if open_runoff_if_needed(state, id).await?.is_none() {
+        let reserve: bool = sqlx::query_scalar("SELECT reserve FROM polls WHERE id = ?")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await?;
+        if reserve {
+            reservations::spawn_booking(state, id);
+        }
+        announcements::spawn_webhook(state, id);
+        state.mqtt.winner(id);
+        notifications::spawn_winner(state, id);
+    }
+    badges::spawn_awards(state, id);
+    state.events.poll_closed(id);
+    Ok(())
+}
+
+// Part of closing a poll. A new poll, open for RUNOFF_MINUTES, is opened between the tied restaurants when the
+// poll settles ties with a runoff and finished on one, or between the top two when the leader's share of the voters
+// (those whose first choice it is, on ranked polls) didn't pass majority_percent. Only the original poll's voters
+// may vote in it, and it breaks its own ties by a draw, so it always decides
+async fn open_runoff_if_needed(state: &AppState, id: i64) -> Result<Option<Poll>, ApiError> {
+    let Some(poll) = find_poll(&state.db, id).await? else {
+        return Ok(None);
+    };
+    if poll.runoff_poll_id.is_some() {
+        return Ok(None);
+    }
+    let results = crate::tally(state, Some(&poll), None).await?;
+    // Each voter once, though a quadratic ballot spread over several restaurants lists its voter under each
+    let voters: HashSet<String> =
+        results.votes.iter().flat_map(|restaurant| &restaurant.voters).map(|voter| names::fold(voter)).collect();
+    let finalists: Vec<String> = match (results.tiebreak, poll.majority_percent) {
+        (Some(tie), _) if poll.tiebreak == Tiebreak::Runoff => tie.tied,
+        (_, Some(percent)) if results.votes.len() >= 2 => {
+            let leader_percent = 100.0 * results.votes[0].voters.len() as f64 / voters.len() as f64;
+            if leader_percent > percent {
+                return Ok(None);
+            }
+            results.votes[..2].iter().map(|restaurant| restaurant.name.clone()).collect()
+        }
+        _ => return Ok(None),
+    };
+    let eligible_voters: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT voter_name FROM votes WHERE poll_id = ? AND id IN ({}) ORDER BY voter_name",
+        ballots::counted_votes_sql()
+    ))
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    // A poll over options has its finalists as the runoff's options instead
+    let mut candidate_ids = Vec::new();
+    if poll.options.is_none() {
+        for name in &finalists {
+            if let Some(restaurant) = restaurants::find_by_name(&state.db, name).await? {
+                candidate_ids.push(restaurant.public_id);
+            }
+        }
+    }
+    let closes_at: String = sqlx::query_scalar("SELECT strftime('%Y-%m-%d %H:%M', 'now', ?, ?)")
+        .bind(poll.clock(&state.db).await?)
+        .bind(format!("+{} minutes", state.config.runoff_minutes))
+        .fetch_one(&state.db)
+        .await?;
+    // The finalists already passed this poll's filters, so only the lunch and its people carry over
+    let req = NewPoll {
+        details: poll.details(),
+        lunch_at: Some(poll.lunch_at.clone()),
+        ignore_opening_hours: poll.ignore_opening_hours,
+        candidate_ids: poll.options.is_none().then_some(candidate_ids),
+        options: poll.options.is_some().then(|| finalists.clone()),
+        attendees: poll.attendees.0.clone(),
+        closes_at: Some(closes_at),
+        tiebreak: Tiebreak::Random,
+        hide_results: poll.hide_results,
+        sealed: poll.sealed,
+        eligible_voters: Some(eligible_voters),
+        office_id: poll.office_id.clone(),
+        timezone: poll.timezone.clone(),
+        team_id: poll.team_id.clone(),
+        reserve: poll.reserve,
+        remote: poll.remote,
+        ..Default::default()
+    };
+    let runoff = insert_poll(state, req, None).await?;
+    sqlx::query("UPDATE polls SET runoff_of = ? WHERE id = ?")
+        .bind(id)
+        .bind(runoff.id)
+        .execute(&state.db)
+        .await?;
+    sqlx::query("UPDATE polls SET runoff_poll_id = ? WHERE id = ?")
+        .bind(runoff.id)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    println!("poll {id}: opened runoff poll {} between {}", runoff.id, finalists.join(", "));
+    Ok(find_poll(&state.db, runoff.id).await?)
+}
+
+#[derive(Deserialize)]
+pub struct NewNomination {
+    nominated_by: String,
+    restaurant_name: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Nomination {
+    restaurant_id: String, // the restaurant's public id
+    restaurant_name: String,
+    nominated_by: String,
+    created_at: String,
+}
+
+// POST /polls/:id/nominations: proposes a restaurant while the nomination window is open.
+// The name is resolved the same way as a vote's, and the restaurant has to pass the poll's filters
+pub async fn nominate(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewNomination>,
+) -> Result<(StatusCode, Json<Nomination>), ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.status != PollStatus::Nominating {
+        return Err(Message::not_nominating().into());
+    }
+    let nominated_by = names::canonical_voter_name(&state.db, &req.nominated_by).await?;
+    if nominated_by.is_empty() {
+        return Err(Message::nominator_empty().into());
+    }
+    moderation::screen(&state, NameKind::Voter, &nominated_by).await?;
+    let restaurant = match restaurants::resolve_name(&state, &req.restaurant_name).await? {
+        NameResolution::Found(restaurant) => restaurant,
+        NameResolution::Similar(matches) => {
+            return Err(ApiError::Ambiguous {
+                message: Message::did_you_mean(&req.restaurant_name),
+                matches,
+            });
+        }
+        NameResolution::Unknown => {
+            return Err(Message::not_registered(req.restaurant_name).into());
+        }
+    };
+    let eligible = eligible(&state, &poll, RestaurantOrder::Name, false).await?;
+    if !eligible.iter().any(|candidate| candidate.id == restaurant.id) {
+        return Err(Message::not_nominable(restaurant.name).into());
+    }
+
+    let inserted =
+        sqlx::query("INSERT OR IGNORE INTO nominations (poll_id, restaurant_id, nominated_by) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(restaurant.id)
+            .bind(&nominated_by)
+            .execute(&state.db)
+            .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(Message::already_nominated(restaurant.name).into());
+    }
+    let nomination = sqlx::query_as::<_, Nomination>(&format!("{NOMINATION_SELECT} AND n.restaurant_id = ?"))
+        .bind(id)
+        .bind(restaurant.id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok((StatusCode::CREATED, Json(nomination)))
+}
+
+const NOMINATION_SELECT: &str = "SELECT r.public_id AS restaurant_id, r.name AS restaurant_name, n.nominated_by,
+    n.created_at FROM nominations n JOIN restaurants r ON r.id = n.restaurant_id AND r.deleted_at IS NULL
+    WHERE n.poll_id = ?";
+
+// GET /polls/:id/nominations, in the order they came in
+pub async fn list_nominations(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Vec<Nomination>>, ApiError> {
+    let nominations = sqlx::query_as::<_, Nomination>(&format!("{NOMINATION_SELECT} ORDER BY n.id"))
+        .bind(id)
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(nominations))
+}
+
+// GET /polls/:id, with its version as the ETag
+pub async fn get_poll(State(state): State<AppState>, PollId(id, public_id): PollId) -> Result<Versioned, ApiError> {
+    find_poll(&state.db, id)
+        .await?
+        .map(Versioned)
+        .ok_or_else(|| ApiError::from(Message::no_poll(&public_id)))
+}
+
+// PATCH /polls/:id (admin): {"title": "...", "description": "...", "metadata": {...}}, any of them. An empty title
+// or description removes it, and metadata replaces what was there as a whole
+pub async fn update_poll(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    expected: IfMatch,
+    Json(req): Json<PollDetails>,
+) -> Result<Versioned, ApiError> {
+    let req = req.validate()?;
+    let updated = sqlx::query(
+        "UPDATE polls SET
+            title = CASE WHEN ?1 IS NULL THEN title ELSE NULLIF(?1, '') END,
+            description = CASE WHEN ?2 IS NULL THEN description ELSE NULLIF(?2, '') END,
+            metadata = COALESCE(?3, metadata)
+        WHERE id = ?4 AND (?5 IS NULL OR version = ?5)",
+    )
+    .bind(req.title)
+    .bind(req.description)
+    .bind(req.metadata.map(JsonColumn))
+    .bind(id)
+    .bind(expected.0)
+    .execute(&state.db)
+    .await?;
+    if let (0, Some(expected)) = (updated.rows_affected(), expected.0) {
+        return Err(not_changed(&state.db, id, &public_id, expected).await);
+    }
+    let poll = find_poll(&state.db, id).await?.ok_or_else(|| Message::no_poll(&public_id))?;
+    Ok(Versioned(poll))
+}
+
+// For changes to what's on a poll's ballot, which live in tables of their own: moves the poll's version on in `tx`,
+// as long as it's the one expected, so the change shows in it and two made against the same version can't both go in
+async fn claim(tx: &mut sqlx::SqliteConnection, id: i64, public_id: &str, expected: &IfMatch) -> Result<(), ApiError> {
+    let claimed =
+        sqlx::query("UPDATE polls SET updated_at = CURRENT_TIMESTAMP WHERE id = ?1 AND (?2 IS NULL OR version = ?2)")
+            .bind(id)
+            .bind(expected.0)
+            .execute(&mut *tx)
+            .await?;
+    match (claimed.rows_affected(), expected.0) {
+        (0, Some(expected)) => Err(not_changed(&mut *tx, id, public_id, expected).await),
+        (0, None) => Err(Message::no_poll(public_id).into()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CandidatesQuery {
+    #[serde(default)]
+    sort: RestaurantOrder,
+    voter: Option<String>, // hides the restaurants this voter has blacklisted
+}
+
+// GET /polls/:id/candidates?sort=name|rating&voter=...
+pub async fn get_candidates(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Query(query): Query<CandidatesQuery>,
+) -> Result<Json<Vec<Restaurant>>, ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    let mut candidates = candidates(&state, &poll, query.sort).await?;
+    if let Some(voter) = query.voter {
+        let blacklisted = voters::blacklisted_ids(&state.db, &voter).await?;
+        candidates.retain(|restaurant| !blacklisted.contains(&restaurant.id));
+    }
+    Ok(Json(candidates))
+}
+
+// untagged serializes each variant as its contents alone, with no wrapper naming the variant
+// https://serde.rs/enum-representations.html#untagged
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum PollResults {
+    Hidden { hidden: bool, participation: participation::Participation, headcount: rsvps::Headcount },
+    Full(Box<LunchVoting>),
+}
+
+// GET /polls/:id/results?tiebreak=first_vote|closest: the tally for this poll alone, or with hide_results only how
+// many have voted or abstained until the poll closes. With an ETag, for If-None-Match; see conditional.rs
+pub async fn get_results(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Query(query): Query<TallyQuery>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if (poll.hide_results || poll.sealed) && poll.status != PollStatus::Closed {
+        let participation = participation::participation(&state.db, id).await?;
+        let headcount = rsvps::headcount(&state.db, &poll).await?;
+        return Ok(if_none_match.respond(&PollResults::Hidden { hidden: true, participation, headcount }));
+    }
+    let mut voting = crate::tally(&state, Some(&poll), query.tiebreak).await?;
+    if query.detailed {
+        voting.comments = Some(comments::comments(&state.db, Some(id)).await?);
+    }
+    Ok(if_none_match.respond(&PollResults::Full(Box::new(voting))))
+}
+
+#[derive(Deserialize)]
+pub struct Unavailability {
+    restaurant_id: String, // the restaurant's public id
+    reason: Option<String>, // e.g. "fully booked"
+}
+
+// The names of the restaurants ruled out of a poll
+pub async fn unavailable_names(db: &SqlitePool, poll_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT r.name FROM poll_unavailable u JOIN restaurants r ON r.id = u.restaurant_id AND r.deleted_at IS NULL
+        WHERE u.poll_id = ? ORDER BY u.created_at, r.name",
+    )
+    .bind(poll_id)
+    .fetch_all(db)
+    .await
+}
+
+// POST /polls/:id/unavailable (admin): the winner turned out to be closed or full. The restaurant leaves the
+// ballot, and its votes move to their backup choices without anyone voting again. Returns the new results, with the
+// poll's new version as the ETag
+pub async fn mark_unavailable(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    expected: IfMatch,
+    Json(req): Json<Unavailability>,
+) -> Result<impl IntoResponse, ApiError> {
+    let poll = find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.options.is_some() {
+        return Err(ApiError::BadRequest("this poll is over options, not restaurants".to_string()));
+    }
+    let restaurant_id = public_ids::restaurant(&state.db, &req.restaurant_id).await?;
+    let restaurant = restaurants::find_by_id(&state.db, restaurant_id)
+        .await?
+        .ok_or_else(|| Message::no_restaurant(req.restaurant_id))?;
+    let mut tx = state.db.begin().await?;
+    claim(&mut tx, id, &public_id, &expected).await?;
+    let inserted =
+        sqlx::query("INSERT OR IGNORE INTO poll_unavailable (poll_id, restaurant_id, reason) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(restaurant.id)
+            .bind(req.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty()))
+            .execute(&mut *tx)
+            .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(ApiError::Conflict(format!("{} is already unavailable in this poll", restaurant.name)));
+    }
+    tx.commit().await?;
+    let poll = find_poll(&state.db, id).await?.ok_or_else(|| Message::no_poll(&public_id))?;
+    let tally = crate::tally(&state, Some(&poll), Default::default()).await?;
+    Ok(([(ETAG, etag(poll.version))], Json(tally)))
+}
+
+// DELETE /polls/:id/unavailable/:restaurant_id (admin): it's available after all, so its votes count for it again.
+// The poll's new version comes back as the ETag
+pub async fn clear_unavailable(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    RestaurantId(restaurant_id): RestaurantId,
+    expected: IfMatch,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut tx = state.db.begin().await?;
+    claim(&mut tx, id, &public_id, &expected).await?;
+    let deleted = sqlx::query("DELETE FROM poll_unavailable WHERE poll_id = ? AND restaurant_id = ?")
+        .bind(id)
+        .bind(restaurant_id)
+        .execute(&mut *tx)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound("that restaurant is not unavailable in this poll".to_string()));
+    }
+    let version: i64 = sqlx::query_scalar("SELECT version FROM polls WHERE id = ?").bind(id).fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    Ok((StatusCode::NO_CONTENT, [(ETAG, etag(version))]))
+}
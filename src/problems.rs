@@ -0,0 +1,307 @@
+// Every error comes back as application/problem+json, RFC 7807's problem details:
+// {"type": "/problems/poll_closed", "title": "The poll is closed", "status": 409, "detail": "poll 1f0c... is closed",
+// "code": "poll_closed"}. detail is the message, in the language asked for; code is the same for every occurrence
+// of a problem in any language, which is what clients branch on, and type is where it's described. The codes are
+// the CATALOG below, also served as GET /problems. An ApiError's code comes with it from where it's raised: the
+// catalogs' error messages carry theirs (see error::Message), and other errors have their kind's; errors that come
+// from axum itself, a body that isn't JSON or a route that doesn't exist, are turned into problems here on their way
+// out
+// https://datatracker.ietf.org/doc/html/rfc7807
+use axum::body::to_bytes;
+use axum::extract::{Path, Request};
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::error::ApiError;
+
+pub const CONTENT_TYPE_PROBLEM: &str = "application/problem+json";
+
+// What's read of an error body axum wrote, for its detail; its rejections are a line or two
+const MAX_DETAIL_BYTES: usize = 64 * 1024;
+
+#[derive(Serialize)]
+pub struct Problem {
+    code: &'static str,
+    title: &'static str,
+    description: &'static str, // when it happens, and what to do about it
+}
+
+pub const CATALOG: &[Problem] = &[
+    Problem {
+        code: "unknown_restaurant",
+        title: "The restaurant isn't registered",
+        description: "No restaurant goes by that name or id. A name close to registered ones comes with \
+            matches, the restaurants meant perhaps; vote for one of those, or register the restaurant first",
+    },
+    Problem {
+        code: "similar_restaurant",
+        title: "A restaurant like it exists",
+        description: "The name being registered is close to registered ones, which come as matches; resend with \
+            allow_similar to register it anyway",
+    },
+    Problem {
+        code: "poll_closed",
+        title: "The poll is closed",
+        description: "The poll has stopped taking votes, RSVPs and reactions; its results are final",
+    },
+    Problem {
+        code: "duplicate_vote",
+        title: "Already voted",
+        description: "The ballot is in already: the voter has voted in this poll, or today outside polls, so \
+            can't vote or abstain again, or the one-click voting link has been used. A vote in a poll is changed \
+            by taking it back with DELETE /polls/:id/votes/:voter and voting again, save in a sealed poll; quadratic \
+            ballots add up instead",
+    },
+    Problem {
+        code: "quota_exceeded",
+        title: "Too many requests",
+        description: "The client or API key is over its rate limit; try again once Retry-After seconds have passed",
+    },
+    Problem {
+        code: "version_conflict",
+        title: "The poll has changed",
+        description: "The poll is at a newer version than the If-Match given; fetch it again and redo the change",
+    },
+    Problem {
+        code: "wrong_ballot",
+        title: "The ballot doesn't fit the poll",
+        description: "The ballot is of another kind than the poll's voting method takes: a ranking for a ranked \
+            poll, an allocation for a quadratic one, and restaurant_name with perhaps a backup_restaurant_name for \
+            the rest, never more than one of them; detail says which the poll wants",
+    },
+    Problem {
+        code: "invalid_allocation",
+        title: "The allocation can't be counted",
+        description: "A quadratic ballot's allocation gives a restaurant fewer than 1 vote, or names one twice",
+    },
+    Problem {
+        code: "seal_broken",
+        title: "The poll's seal is broken",
+        description: "The poll is sealed, but its key is missing or SEALING_KEY has changed since it was made, so \
+            no ballot can be sealed for it; an admin has to restore the key",
+    },
+    Problem {
+        code: "not_the_picker",
+        title: "Someone else is picking",
+        description: "A rotation poll only takes the vote of the voter whose turn it is, or nobody's when no one \
+            in the rotation could pick",
+    },
+    Problem {
+        code: "invalid_poll_details",
+        title: "The poll's details can't be used",
+        description: "The title or description is too long, or the metadata isn't a JSON object or is too large",
+    },
+    Problem {
+        code: "invalid_schedule",
+        title: "The poll's times can't be used",
+        description: "A time isn't a local time like 2024-05-17 12:30, both a time and an _after_minutes offset \
+            were given for the same thing, an offset is under 1, or nominations wouldn't close before voting does",
+    },
+    Problem {
+        code: "invalid_poll_settings",
+        title: "The poll's settings don't go together",
+        description: "A setting is out of its range, or doesn't go with the others: options with restaurant \
+            filters, or fewer than two of them, a sealed quadratic poll, or a runoff for a lottery or rotation",
+    },
+    Problem {
+        code: "not_nominating",
+        title: "The poll isn't taking nominations",
+        description: "Nominations are only taken before the poll opens for votes, while nominations_close_at is \
+            still ahead",
+    },
+    Problem {
+        code: "not_nominable",
+        title: "The restaurant can't be nominated",
+        description: "The restaurant doesn't pass the poll's filters, so it couldn't be voted for",
+    },
+    Problem {
+        code: "already_nominated",
+        title: "Already nominated",
+        description: "Someone has nominated the restaurant in this poll already",
+    },
+    Problem {
+        code: "body_too_large",
+        title: "The request body is too large",
+        description: "Send less at once",
+    },
+    Problem {
+        code: "bad_request",
+        title: "Bad request",
+        description: "Something in the request can't be used; detail says what",
+    },
+    Problem {
+        code: "invalid_body",
+        title: "The body doesn't fit the endpoint",
+        description: "The JSON body is missing a field, or has one of the wrong type",
+    },
+    Problem {
+        code: "unsupported_media_type",
+        title: "Unsupported media type",
+        description: "The body needs a Content-Type the endpoint reads, application/json for most",
+    },
+    Problem {
+        code: "unauthorized",
+        title: "Unauthorized",
+        description: "The endpoint needs the admin token or an API key as the bearer token, and none or a wrong one \
+            was given",
+    },
+    Problem {
+        code: "forbidden",
+        title: "Forbidden",
+        description: "Whoever is asking isn't allowed this, like a voter outside the poll's team",
+    },
+    Problem {
+        code: "not_found",
+        title: "Not found",
+        description: "Nothing is there, or nothing by that id",
+    },
+    Problem {
+        code: "method_not_allowed",
+        title: "Method not allowed",
+        description: "The path is there, but doesn't take this method",
+    },
+    Problem {
+        code: "conflict",
+        title: "Conflict",
+        description: "The change clashes with how things stand, a name already taken say; detail says how",
+    },
+    Problem {
+        code: "precondition_required",
+        title: "If-Match required",
+        description: "Changing a poll needs the version it was changed from as If-Match; see GET /polls/:id",
+    },
+    Problem {
+        code: "upstream_failed",
+        title: "A service we depend on failed",
+        description: "A third-party API, for places, weather or payments, failed or answered with something unusable",
+    },
+    Problem {
+        code: "not_configured",
+        title: "Not configured",
+        description: "The feature needs configuration this instance hasn't been given",
+    },
+    Problem {
+        code: "unavailable",
+        title: "Unavailable for the moment",
+        description: "The database is out of reach; try again once Retry-After seconds have passed",
+    },
+    Problem {
+        code: "internal_error",
+        title: "Internal error",
+        description: "Something went wrong on our side; the server's log has more",
+    },
+];
+
+// Whether `code` is in the CATALOG, for error::Message to check its codes with at compile time
+pub const fn is_code(code: &str) -> bool {
+    let mut i = 0;
+    while i < CATALOG.len() {
+        let known = CATALOG[i].code.as_bytes();
+        let (code, mut at) = (code.as_bytes(), 0);
+        while at < code.len() && at < known.len() && code[at] == known[at] {
+            at += 1;
+        }
+        if at == code.len() && at == known.len() {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+fn find(code: &str) -> Option<&'static Problem> {
+    CATALOG.iter().find(|problem| problem.code == code)
+}
+
+// A problem's response; `extensions` are members of its own beyond the standard ones, matches say
+pub fn respond(
+    status: StatusCode,
+    code: &'static str,
+    detail: Option<String>,
+    extensions: Map<String, Value>,
+) -> Response {
+    let title = find(code).map_or_else(|| status.canonical_reason().unwrap_or_default(), |problem| problem.title);
+    let mut body = json!({ "type": type_of(code), "title": title, "status": status.as_u16(), "code": code });
+    if let Some(detail) = detail {
+        body["detail"] = Value::String(detail);
+    }
+    body.as_object_mut().expect("a problem is an object").extend(extensions);
+    let mut response = (status, Json(body)).into_response();
+    response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE_PROBLEM));
+    response
+}
+
+// The code for an error only known by its status
+pub fn for_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "body_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "invalid_body",
+        StatusCode::PRECONDITION_REQUIRED => "precondition_required",
+        StatusCode::TOO_MANY_REQUESTS => "quota_exceeded",
+        StatusCode::BAD_GATEWAY => "upstream_failed",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        status if status.is_server_error() => "internal_error",
+        _ => "bad_request",
+    }
+}
+
+// Middleware: an error answered in plain text, or with no body at all, is axum's own rejection, "Failed to parse the
+// request body as JSON", or its 404 for a route that isn't there; it goes out as a problem too. Errors that are JSON
+// already are left be, problems and GET /readyz's report alike
+pub async fn standardize(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let plain = content_type.is_none_or(|content_type| content_type.starts_with("text/plain"));
+    if !(status.is_client_error() || status.is_server_error()) || !plain {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let detail = match to_bytes(body, MAX_DETAIL_BYTES).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).trim().to_string(),
+        Err(_) => String::new(),
+    };
+    let detail = Some(detail).filter(|detail| !detail.is_empty());
+    let mut problem = respond(status, for_status(status), detail, Map::new());
+    // Whatever else it said, Allow on a 405 say, still goes; what described the old body doesn't
+    for (name, value) in parts.headers {
+        if let Some(name) = name.filter(|name| !problem.headers().contains_key(name) && *name != CONTENT_LENGTH) {
+            problem.headers_mut().insert(name, value);
+        }
+    }
+    problem
+}
+
+// GET /problems: the catalog, each code with its type, title and what it means
+pub async fn list_problems() -> Json<Value> {
+    Json(Value::Array(CATALOG.iter().map(describe).collect()))
+}
+
+// GET /problems/:code: a type from the catalog, for following a problem's type to
+pub async fn get_problem(Path(code): Path<String>) -> Result<Json<Value>, ApiError> {
+    let problem = find(&code).ok_or_else(|| ApiError::NotFound(format!("no problem type {code}")))?;
+    Ok(Json(describe(problem)))
+}
+
+fn describe(problem: &Problem) -> Value {
+    let mut value = serde_json::to_value(problem).expect("a problem serializes");
+    value["type"] = Value::String(type_of(problem.code));
+    value
+}
+
+// Relative, like the _links (see links.rs), so it resolves against wherever the client reached the API from
+fn type_of(code: &str) -> String {
+    format!("/problems/{code}")
+}
+
@@ -0,0 +1,73 @@
+// Polls and restaurants are known to API clients by a public UUID only; see migrations/0034_public_ids.sql.
+// These extractors turn the UUID in a path into the internal id, answering 404 for anything else (integer ids
+// included), and the resolve functions do the same for ids sent in request bodies
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::error::{ApiError, Message};
+use crate::AppState;
+
+// The poll named by the :id path segment: its internal id, and the public one the path gave, for messages
+pub struct PollId(pub i64, pub String);
+
+// The restaurant named by the :restaurant_id path segment, or by :id on /restaurants/... routes
+pub struct RestaurantId(pub i64);
+
+// The path segments by name; an extractor can read them without knowing the rest of the route
+async fn path_segment(parts: &mut Parts, state: &AppState, names: &[&str]) -> Result<String, ApiError> {
+    let Path(segments) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+        .await
+        .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    names
+        .iter()
+        .find_map(|name| segments.get(*name).cloned())
+        .ok_or_else(|| ApiError::BadRequest(format!("the route has no :{} segment", names[0])))
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for PollId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let public_id = path_segment(parts, state, &["id"]).await?;
+        Ok(PollId(poll(&state.db, &public_id).await?, public_id))
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for RestaurantId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let public_id = path_segment(parts, state, &["restaurant_id", "id"]).await?;
+        Ok(RestaurantId(restaurant(&state.db, &public_id).await?))
+    }
+}
+
+pub async fn poll(db: &SqlitePool, public_id: &str) -> Result<i64, ApiError> {
+    sqlx::query_scalar("SELECT id FROM polls WHERE public_id = ?")
+        .bind(public_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ApiError::from(Message::no_poll(public_id)))
+}
+
+pub async fn restaurant(db: &SqlitePool, public_id: &str) -> Result<i64, ApiError> {
+    sqlx::query_scalar("SELECT id FROM restaurants WHERE public_id = ? AND deleted_at IS NULL")
+        .bind(public_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| ApiError::from(Message::no_restaurant(public_id)))
+}
+
+// Whether a client-supplied id has the shape of a UUID, lowercase: 8-4-4-4-12 hex digits
+pub fn is_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_digit() || ('a'..='f').contains(&c),
+        })
+}
@@ -0,0 +1,189 @@
+// Quadratic voting: each voter gets a budget of credits for the poll and spreads votes across restaurants as they
+// like, but n votes for one restaurant cost n² credits, so strong feelings are expensive and broad support is cheap.
+// A voter can send several ballots; the cost is always worked out on their running total for each restaurant,
+// so three votes cost nine credits whether they arrive together or one at a time
+// https://en.wikipedia.org/wiki/Quadratic_voting
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::Serialize;
+use sqlx::types::Json as JsonColumn;
+use sqlx::Row;
+
+use crate::error::{ApiError, Message};
+use crate::hours::OPEN_AT_SQL;
+use crate::polls::{self, Poll};
+use crate::public_ids::PollId;
+use crate::trash::TRASHED_VOTES_SQL;
+use crate::{database, names, vote_events, AppState, Restaurant, SaveVoteError, VoteChannel};
+
+// The budget for quadratic polls that don't set credit_budget: enough for ten votes on one place, or one on a hundred
+pub const DEFAULT_CREDIT_BUDGET: i64 = 100;
+
+// Records a ballot and its ledger entries after checking the voter can afford it. Reading what's been spent and
+// writing the new spending happen in one transaction, so two ballots sent at once can't spend the same credits
+pub async fn cast(
+    state: &AppState,
+    poll: &Poll,
+    voter_name: &str,
+    allocation: &[(String, i64)],
+    comment: Option<&str>,
+    channel: VoteChannel,
+) -> Result<(), SaveVoteError> {
+    let voter_key = names::fold(voter_name);
+    let budget = poll.credit_budget.unwrap_or(DEFAULT_CREDIT_BUDGET);
+    // Immediate, since the credits already spent are read before more are: see database.rs
+    let mut tx = database::Immediate::begin(&state.db).await?;
+    // Credits spent by a ballot in the trash are back in the budget while it's there
+    let spent: Vec<(String, i64, i64)> = sqlx::query_as(&format!(
+        "SELECT restaurant_name, SUM(votes), SUM(credits) FROM vote_credits
+        WHERE poll_id = ? AND voter_key = ? AND vote_id NOT IN ({TRASHED_VOTES_SQL}) GROUP BY restaurant_name"
+    ))
+    .bind(poll.id)
+    .bind(&voter_key)
+    .fetch_all(&mut *tx)
+    .await?;
+    let remaining = budget - spent.iter().map(|(_, _, credits)| credits).sum::<i64>();
+
+    let mut entries = Vec::new();
+    for (restaurant, votes) in allocation {
+        let before = spent.iter().find(|(name, _, _)| name == restaurant).map_or(0, |(_, votes, _)| *votes);
+        entries.push((restaurant, votes, (before + votes).pow(2) - before.pow(2)));
+    }
+    let cost: i64 = entries.iter().map(|(_, _, credits)| credits).sum();
+    if cost > remaining {
+        return Err(SaveVoteError::OverBudget { cost, remaining });
+    }
+
+    // The vote row keeps its usual meaning for everything else that reads votes: one ballot, for the restaurant it
+    // put the most votes on
+    let top_choice = allocation.iter().max_by_key(|(_, votes)| *votes).map(|(name, _)| name);
+    let cast = vote_events::CastVote {
+        voter_name: voter_name.to_string(),
+        restaurant_name: top_choice.cloned().unwrap_or_default(),
+        poll_id: Some(poll.public_id.clone()),
+        backup_restaurant_name: None,
+        ranking: None,
+        comment: comment.map(str::to_string),
+        channel,
+        cast_at: None,
+        anonymized_at: None,
+    };
+    let vote_id = vote_events::cast(&mut tx, cast).await?;
+    for (restaurant, votes, credits) in entries {
+        sqlx::query(
+            "INSERT INTO vote_credits (poll_id, vote_id, voter_key, voter_name, restaurant_name, votes, credits)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(poll.id)
+        .bind(vote_id)
+        .bind(&voter_key)
+        .bind(voter_name)
+        .bind(restaurant)
+        .bind(votes)
+        .bind(credits)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    state.events.vote_cast(vote_id);
+    Ok(())
+}
+
+// Every restaurant that received votes, scored by its total votes. Credits spent on a restaurant that drops out of
+// the poll are not refunded. A poll's options aren't restaurants, so for those the join finds nothing and isn't
+// required to
+pub async fn tally(state: &AppState, poll: &Poll, unavailable: &[String]) -> Result<Vec<Restaurant>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT c.voter_name, c.restaurant_name, SUM(c.votes) AS votes,
+            r.address, r.latitude, r.longitude, r.cuisine, r.price_tier, r.average_cost,
+            r.distance_meters, r.travel_minutes, r.walking_minutes, COALESCE(r.dietary_tags, '[]') AS dietary_tags
+        FROM vote_credits c
+        LEFT JOIN restaurants r ON r.name = c.restaurant_name AND r.deleted_at IS NULL AND NOT ?
+        WHERE (r.id IS NOT NULL OR ?) AND c.poll_id = ? AND c.restaurant_name NOT IN (SELECT value FROM json_each(?))
+        AND c.vote_id NOT IN ({TRASHED_VOTES_SQL})
+        AND (? OR {OPEN_AT_SQL})
+        GROUP BY c.voter_key, c.restaurant_name
+        ORDER BY MIN(c.id)"
+    ))
+    .bind(poll.options().is_some())
+    .bind(poll.options().is_some())
+    .bind(poll.id)
+    .bind(JsonColumn(unavailable))
+    .bind(poll.ignore_opening_hours)
+    .bind(&poll.lunch_at)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut restaurants: Vec<Restaurant> = Vec::new();
+    for row in rows {
+        let name: String = row.get("restaurant_name");
+        let votes: i64 = row.get("votes");
+        let restaurant = match restaurants.iter_mut().find(|restaurant| restaurant.name == name) {
+            Some(restaurant) => restaurant,
+            None => {
+                restaurants.push(Restaurant {
+                    name,
+                    voters: Vec::new(),
+                    backup_voters: Vec::new(),
+                    details: sqlx::FromRow::from_row(&row)?,
+                    score: 0,
+                    borda_score: None,
+                    effective_votes: Some(0),
+                });
+                restaurants.last_mut().unwrap()
+            }
+        };
+        restaurant.voters.push(row.get("voter_name"));
+        restaurant.score += votes;
+        restaurant.effective_votes = Some(restaurant.score);
+    }
+    Ok(restaurants)
+}
+
+#[derive(Serialize)]
+pub struct Credits {
+    voter_name: String,
+    budget: i64,
+    spent: i64,
+    remaining: i64,
+    allocations: Vec<Allocation>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct Allocation {
+    restaurant_name: String,
+    votes: i64,
+    credits: i64,
+}
+
+// GET /polls/:id/credits/:voter: what a voter has spent in a quadratic poll and what they have left
+pub async fn get_credits(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<Json<Credits>, ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.voting_method != polls::VotingMethod::Quadratic {
+        return Err(ApiError::BadRequest("the poll doesn't use quadratic voting".to_string()));
+    }
+    let allocations = sqlx::query_as::<_, Allocation>(&format!(
+        "SELECT restaurant_name, SUM(votes) AS votes, SUM(credits) AS credits FROM vote_credits
+        WHERE poll_id = ? AND voter_key = ? AND vote_id NOT IN ({TRASHED_VOTES_SQL})
+        GROUP BY restaurant_name ORDER BY votes DESC, restaurant_name"
+    ))
+    .bind(id)
+    .bind(names::fold(&voter))
+    .fetch_all(&state.db)
+    .await?;
+    let budget = poll.credit_budget.unwrap_or(DEFAULT_CREDIT_BUDGET);
+    let spent = allocations.iter().map(|allocation| allocation.credits).sum();
+    Ok(Json(Credits {
+        voter_name: names::canonical_voter_name(&state.db, &voter).await?,
+        budget,
+        spent,
+        remaining: budget - spent,
+        allocations,
+    }))
+}
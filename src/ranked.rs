@@ -0,0 +1,289 @@
+// Tallies for ranked ballots, where each voter lists the candidates in order of preference, favourite first.
+// A restaurant a voter left off their ranking counts as below everything they did rank, and restaurants that drop
+// out of the poll (ruled unavailable, or closed at lunch_at) are struck from every ranking before counting
+use serde::Serialize;
+use sqlx::types::Json as JsonColumn;
+use sqlx::Row;
+use std::collections::BTreeMap;
+
+use crate::hours::OPEN_AT_SQL;
+use crate::polls::Poll;
+use crate::{ballots, AppState, Restaurant};
+
+// The ballots of a poll, with the rankings turned into positions in `restaurants`
+struct Ballots {
+    restaurants: Vec<Restaurant>, // every restaurant still ranked on some ballot, in order of first appearance
+    rankings: Vec<Vec<usize>>,
+}
+
+// Each voter's ballot that counts, see ballots.rs
+async fn ballots(state: &AppState, poll: &Poll, unavailable: &[String]) -> Result<Ballots, sqlx::Error> {
+    let votes: Vec<(String, JsonColumn<Vec<String>>)> = sqlx::query_as(&format!(
+        "SELECT voter_name, ranking FROM votes
+        WHERE poll_id = ? AND ranking IS NOT NULL AND id IN ({})
+        ORDER BY id",
+        ballots::counted_votes_sql()
+    ))
+    .bind(poll.id)
+    .fetch_all(&state.db)
+    .await?;
+    let mut names: Vec<&String> = Vec::new();
+    for name in votes.iter().flat_map(|(_, ranking)| ranking.iter()) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    // The restaurants that are still in the running, with their details for the results. A poll's options have no
+    // details, and only drop out when ruled unavailable
+    let rows = sqlx::query(&format!(
+        "SELECT r.name AS restaurant_name,
+            r.address, r.latitude, r.longitude, r.cuisine, r.price_tier, r.average_cost,
+            r.distance_meters, r.travel_minutes, r.walking_minutes, r.dietary_tags
+        FROM restaurants r
+        WHERE r.name IN (SELECT value FROM json_each(?)) AND r.name NOT IN (SELECT value FROM json_each(?))
+        AND r.deleted_at IS NULL
+        AND (? OR {OPEN_AT_SQL})"
+    ))
+    .bind(JsonColumn(&names))
+    .bind(JsonColumn(unavailable))
+    .bind(poll.ignore_opening_hours)
+    .bind(&poll.lunch_at)
+    .fetch_all(&state.db)
+    .await?;
+    let mut details = BTreeMap::new();
+    for row in rows {
+        details.insert(row.get::<String, _>("restaurant_name"), sqlx::FromRow::from_row(&row)?);
+    }
+    let mut restaurants: Vec<Restaurant> = names
+        .into_iter()
+        .filter_map(|name| {
+            Some(Restaurant {
+                name: name.clone(),
+                voters: Vec::new(),
+                backup_voters: Vec::new(),
+                details: match poll.options() {
+                    Some(_) if unavailable.contains(name) => return None,
+                    Some(_) => Default::default(),
+                    None => details.remove(name)?,
+                },
+                score: 0,
+                borda_score: None,
+                effective_votes: None,
+            })
+        })
+        .collect();
+
+    // Each voter is listed under their first choice that's still standing; if that isn't the one they put first,
+    // they're also a backup voter there
+    let mut rankings = Vec::new();
+    for (voter, JsonColumn(ranking)) in votes {
+        let positions: Vec<usize> = ranking
+            .iter()
+            .filter_map(|name| restaurants.iter().position(|restaurant| &restaurant.name == name))
+            .collect();
+        if let Some(&first) = positions.first() {
+            if restaurants[first].name != ranking[0] {
+                restaurants[first].backup_voters.push(voter.clone());
+            }
+            restaurants[first].voters.push(voter);
+        }
+        rankings.push(positions);
+    }
+    Ok(Ballots { restaurants, rankings })
+}
+
+#[derive(Clone, Serialize)]
+pub struct CondorcetResult {
+    // The restaurant that beats every other one head to head, if there is one
+    winner: Option<String>,
+    // Without a winner: the smallest group of restaurants that all beat everyone outside it (the Smith set),
+    // whose members go round in a cycle or draw with each other
+    // https://en.wikipedia.org/wiki/Smith_set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cycle: Vec<String>,
+    // One point per head-to-head win and half a point per draw; the results are ordered by this
+    copeland_scores: BTreeMap<String, f64>,
+    head_to_head: Vec<HeadToHead>,
+}
+
+#[derive(Clone, Serialize)]
+struct HeadToHead {
+    restaurant: String,
+    opponent: String,
+    preferred_by: usize, // ballots ranking restaurant above opponent
+    opponent_preferred_by: usize,
+}
+
+// Condorcet: compare every pair of restaurants and count the ballots preferring each. A Condorcet winner beats
+// every other restaurant that way. Preferences can go round in a cycle (A beats B, B beats C, C beats A), in which
+// case there's no winner and the documented fallback is the Copeland score: wins minus losses, in effect.
+// A Condorcet winner always has the highest Copeland score, so ordering by it covers both cases, and equal
+// scores are left to the poll's tiebreak
+// https://en.wikipedia.org/wiki/Condorcet_method
+// https://en.wikipedia.org/wiki/Copeland%27s_method
+pub async fn condorcet(
+    state: &AppState,
+    poll: &Poll,
+    unavailable: &[String],
+) -> Result<(Vec<Restaurant>, CondorcetResult), sqlx::Error> {
+    let Ballots { mut restaurants, rankings } = ballots(state, poll, unavailable).await?;
+    let Pairwise { preferred, scores, winner, order, smith_size } = pairwise(restaurants.len(), &rankings);
+    for (restaurant, score) in restaurants.iter_mut().zip(scores) {
+        restaurant.score = score;
+    }
+    let cycle = match winner {
+        None if smith_size > 1 => order[..smith_size].iter().map(|&a| restaurants[a].name.clone()).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut head_to_head = Vec::new();
+    for (i, &a) in order.iter().enumerate() {
+        for &b in &order[i + 1..] {
+            head_to_head.push(HeadToHead {
+                restaurant: restaurants[a].name.clone(),
+                opponent: restaurants[b].name.clone(),
+                preferred_by: preferred[a][b],
+                opponent_preferred_by: preferred[b][a],
+            });
+        }
+    }
+    let result = CondorcetResult {
+        winner: winner.map(|a| restaurants[a].name.clone()),
+        cycle,
+        copeland_scores: restaurants.iter().map(|r| (r.name.clone(), r.score as f64 / 2.0)).collect(),
+        head_to_head,
+    };
+    Ok((restaurants, result))
+}
+
+// The head-to-head counts between `count` restaurants, over rankings of their positions, and what follows from them
+struct Pairwise {
+    preferred: Vec<Vec<usize>>, // preferred[a][b] is how many ballots rank a above b
+    // Copeland scores, kept doubled so they stay whole numbers and can be the restaurants' score
+    scores: Vec<i64>,
+    winner: Option<usize>,
+    order: Vec<usize>, // the restaurants by score, highest first
+    smith_size: usize, // how many of the first in `order` are the Smith set
+}
+
+fn pairwise(count: usize, rankings: &[Vec<usize>]) -> Pairwise {
+    let mut preferred = vec![vec![0usize; count]; count];
+    for ranking in rankings {
+        for (place, &a) in ranking.iter().enumerate() {
+            // b is below a if it comes later in the ranking or isn't ranked at all
+            for (b, ballots) in preferred[a].iter_mut().enumerate() {
+                if b != a && !ranking[..place].contains(&b) {
+                    *ballots += 1;
+                }
+            }
+        }
+    }
+    let beats = |a: usize, b: usize| preferred[a][b] > preferred[b][a];
+
+    let scores: Vec<i64> = (0..count)
+        .map(|a| {
+            (0..count)
+                .filter(|&b| b != a)
+                .map(|b| match preferred[a][b].cmp(&preferred[b][a]) {
+                    std::cmp::Ordering::Greater => 2,
+                    std::cmp::Ordering::Equal => 1,
+                    std::cmp::Ordering::Less => 0,
+                })
+                .sum()
+        })
+        .collect();
+    let winner = (0..count).find(|&a| (0..count).all(|b| b == a || beats(a, b)));
+
+    // Members of the Smith set always outscore everyone outside it, so it's the shortest run of top scorers
+    // that beats everybody after it
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_key(|&a| std::cmp::Reverse(scores[a]));
+    let smith_size = (1..=count)
+        .find(|&size| order[..size].iter().all(|&a| order[size..].iter().all(|&b| beats(a, b))))
+        .unwrap_or(0);
+    Pairwise { preferred, scores, winner, order, smith_size }
+}
+
+// Borda count: on each ballot, a restaurant ranked first among n still standing gets n - 1 points, the next n - 2,
+// and so on down to the last; restaurants left off a ballot get nothing from it. The most points wins, and unlike
+// Condorcet there's always an order, though equal totals still go to the poll's tiebreak
+// https://en.wikipedia.org/wiki/Borda_count
+pub async fn borda(state: &AppState, poll: &Poll, unavailable: &[String]) -> Result<Vec<Restaurant>, sqlx::Error> {
+    let Ballots { mut restaurants, rankings } = ballots(state, poll, unavailable).await?;
+    let points = borda_points(restaurants.len(), &rankings);
+    for (restaurant, points) in restaurants.iter_mut().zip(points) {
+        restaurant.score = points;
+        restaurant.borda_score = Some(points);
+    }
+    Ok(restaurants)
+}
+
+fn borda_points(count: usize, rankings: &[Vec<usize>]) -> Vec<i64> {
+    let mut points = vec![0; count];
+    for ranking in rankings {
+        for (place, &restaurant) in ranking.iter().enumerate() {
+            points[restaurant] += (count - 1 - place) as i64;
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The Smith set, as positions, in no particular order
+    fn smith_set(pairwise: &Pairwise) -> Vec<usize> {
+        let mut smith = pairwise.order[..pairwise.smith_size].to_vec();
+        smith.sort();
+        smith
+    }
+
+    #[test]
+    fn counts_each_pair_with_unranked_restaurants_below_the_ranked() {
+        let pairwise = pairwise(3, &[vec![0, 1, 2], vec![1, 0], vec![2]]);
+        assert_eq!(pairwise.preferred, vec![vec![0, 1, 2], vec![1, 0, 2], vec![1, 1, 0]]);
+    }
+
+    #[test]
+    fn a_condorcet_winner_is_the_smith_set_alone() {
+        let pairwise = pairwise(3, &[vec![0, 1, 2], vec![0, 2, 1], vec![1, 2, 0]]);
+        assert_eq!(pairwise.winner, Some(0));
+        assert_eq!(pairwise.scores, vec![4, 2, 0]);
+        assert_eq!(smith_set(&pairwise), vec![0]);
+    }
+
+    #[test]
+    fn a_cycle_has_no_winner_and_all_of_it_in_the_smith_set() {
+        // 0 beats 1, 1 beats 2 and 2 beats 0, two ballots to one each time; 3 loses to all of them
+        let pairwise = pairwise(4, &[vec![0, 1, 2, 3], vec![1, 2, 0, 3], vec![2, 0, 1, 3]]);
+        assert_eq!(pairwise.winner, None);
+        assert_eq!(pairwise.scores, vec![4, 4, 4, 0]);
+        assert_eq!(smith_set(&pairwise), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn a_draw_at_the_top_puts_both_in_the_smith_set() {
+        // 0 and 1 draw, one ballot each; both beat 2
+        let pairwise = pairwise(3, &[vec![0, 1, 2], vec![1, 0, 2]]);
+        assert_eq!(pairwise.winner, None);
+        assert_eq!(pairwise.scores, vec![3, 3, 0]);
+        assert_eq!(smith_set(&pairwise), vec![0, 1]);
+    }
+
+    #[test]
+    fn no_ballots_leave_everyone_drawn() {
+        let pairwise = pairwise(2, &[]);
+        assert_eq!(pairwise.winner, None);
+        assert_eq!(smith_set(&pairwise), vec![0, 1]);
+        assert_eq!(super::pairwise(0, &[]).smith_size, 0);
+    }
+
+    #[test]
+    fn borda_gives_points_by_place_among_everyone_standing() {
+        // Of three, first place is worth 2 and second 1; a restaurant left off a ballot gets nothing from it
+        assert_eq!(borda_points(3, &[vec![0, 1, 2], vec![1], vec![2, 1]]), vec![2, 4, 2]);
+        assert_eq!(borda_points(2, &[]), vec![0, 0]);
+    }
+}
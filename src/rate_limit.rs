@@ -0,0 +1,112 @@
+// Limits how many requests one client can send: RATE_LIMIT a minute from each address, counted in fixed windows
+// that start on the minute. Past that the answer is 429 Too Many Requests, with a Retry-After header saying how many
+// seconds are left until the next window. The counts are kept in this process, which is all one instance needs; with
+// REDIS_URL set they're kept in Redis, so that instances behind a load balancer enforce one limit between them rather
+// than one each. Should Redis be out of reach, each instance goes back to counting on its own until it's back.
+// Behind a proxy every request comes from the proxy, so with RATE_LIMIT_BEHIND_PROXY the client is the address the
+// proxy appended to X-Forwarded-For instead, the last one there. Admin requests aren't limited
+// https://www.rfc-editor.org/rfc/rfc6585#section-4
+// https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::{ApiError, Message};
+use crate::{auth, redis, AppState};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// How long to count here after Redis couldn't be reached, before trying it again
+const REDIS_RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Default)]
+pub struct Limiter {
+    local: Arc<Mutex<Window>>,
+    redis: Arc<tokio::sync::Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    connection: Option<redis::Connection>,
+    retry_at: Option<Instant>,
+}
+
+// The requests each client has sent in the current window
+#[derive(Default)]
+struct Window {
+    started: u64, // the window's number, counted in windows since the epoch
+    counts: HashMap<String, i64>,
+}
+
+impl Limiter {
+    // How many requests `client` has sent in `window`, this one included
+    async fn count(&self, state: &AppState, client: &str, window: u64) -> i64 {
+        if let Some(url) = &state.config.redis_url {
+            let key = format!("{}:{window}:{client}", state.config.rate_limit_key);
+            let mut shared = self.redis.lock().await;
+            if shared.retry_at.is_none_or(|retry_at| Instant::now() >= retry_at) {
+                let connection = &mut shared.connection;
+                let counted = async {
+                    if connection.is_none() {
+                        *connection = Some(redis::Connection::connect(url).await?);
+                    }
+                    connection.as_mut().expect("connected above").count(&key, WINDOW).await
+                };
+                match counted.await {
+                    Ok(count) => {
+                        shared.retry_at = None;
+                        return count;
+                    }
+                    Err(err) => {
+                        eprintln!("rate limit: could not count in Redis, counting here for now: {err}");
+                        *shared = Shared { connection: None, retry_at: Some(Instant::now() + REDIS_RETRY) };
+                    }
+                }
+            }
+        }
+        let mut local = self.local.lock().unwrap();
+        if local.started != window {
+            *local = Window { started: window, counts: HashMap::new() };
+        }
+        let count = local.counts.entry(client.to_string()).or_default();
+        *count += 1;
+        *count
+    }
+}
+
+// Middleware: counts the request against its client, and turns it away once they're over the limit
+pub async fn limit(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limit) = state.config.rate_limit else {
+        return next.run(request).await;
+    };
+    if auth::is_admin(request.headers(), &state) {
+        return next.run(request).await;
+    }
+    let forwarded = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .map(|client| client.trim().to_string());
+    let client = match (state.config.rate_limit_behind_proxy, forwarded) {
+        (true, Some(client)) => client,
+        _ => remote_addr.ip().to_string(),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    if state.limiter.count(&state, &client, now / WINDOW.as_secs()).await > limit {
+        let retry_after = WINDOW.as_secs() - now % WINDOW.as_secs();
+        let message = Message::rate_limited(retry_after);
+        return ApiError::TooManyRequests { message, retry_after }.into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,125 @@
+// Emoji reactions to the candidates of a poll: a quick "🔥" or "💸" that says how people feel about a place without
+// being a ballot. They're kept apart from votes and never touch the tally
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::{ApiError, Message};
+use crate::moderation::{self, NameKind};
+use crate::polls::{self, PollStatus};
+use crate::public_ids::PollId;
+use crate::{names, AppState};
+
+// The reactions on offer; anything else is turned away so the summary stays readable
+pub const REACTIONS: [&str; 6] = ["🔥", "😋", "👍", "🤢", "💸", "🐌"];
+
+#[derive(Deserialize)]
+pub struct NewReaction {
+    voter_name: String,
+    restaurant_id: String, // the restaurant's public id
+    emoji: String,
+}
+
+#[derive(Serialize)]
+pub struct CandidateReactions {
+    restaurant_id: String,
+    restaurant_name: String,
+    // Each emoji with the voters who left it, in the order they did
+    reactions: BTreeMap<String, Vec<String>>,
+}
+
+// Checks a reaction against the poll, and returns the voter's canonical name and the restaurant's internal id
+async fn validate(state: &AppState, id: i64, public_id: &str, req: &NewReaction) -> Result<(String, i64), ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(public_id))?;
+    if poll.status == PollStatus::Closed {
+        return Err(Message::poll_closed().into());
+    }
+    if !REACTIONS.contains(&req.emoji.as_str()) {
+        return Err(ApiError::BadRequest(format!("emoji must be one of {}", REACTIONS.join(" "))));
+    }
+    let candidates = polls::candidates(state, &poll, Default::default()).await?;
+    let Some(restaurant) = candidates.iter().find(|restaurant| restaurant.public_id == req.restaurant_id) else {
+        return Err(ApiError::BadRequest(format!("restaurant {} is not a candidate in this poll", req.restaurant_id)));
+    };
+    let voter_name = names::canonical_voter_name(&state.db, &req.voter_name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_empty().into());
+    }
+    Ok((voter_name, restaurant.id))
+}
+
+// POST /polls/:id/reactions while the poll is running; reacting the same way twice is harmless
+pub async fn add_reaction(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewReaction>,
+) -> Result<StatusCode, ApiError> {
+    let (voter_name, restaurant_id) = validate(&state, id, &public_id, &req).await?;
+    moderation::screen(&state, NameKind::Voter, &voter_name).await?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO reactions (poll_id, restaurant_id, voter_key, voter_name, emoji) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(restaurant_id)
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(&req.emoji)
+    .execute(&state.db)
+    .await?;
+    Ok(StatusCode::CREATED)
+}
+
+// DELETE /polls/:id/reactions, with the same body as the reaction being taken back
+pub async fn remove_reaction(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewReaction>,
+) -> Result<StatusCode, ApiError> {
+    let (voter_name, restaurant_id) = validate(&state, id, &public_id, &req).await?;
+    let removed =
+        sqlx::query("DELETE FROM reactions WHERE poll_id = ? AND restaurant_id = ? AND voter_key = ? AND emoji = ?")
+            .bind(id)
+            .bind(restaurant_id)
+            .bind(names::fold(&voter_name))
+            .bind(&req.emoji)
+            .execute(&state.db)
+            .await?;
+    if removed.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{voter_name} hadn't reacted {} to that restaurant", req.emoji)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// GET /polls/:id/reactions: the restaurants people reacted to, in the order they first did
+pub async fn list_reactions(
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Vec<CandidateReactions>>, ApiError> {
+    let rows: Vec<(String, String, String, String)> = sqlx::query_as(
+        "SELECT r.public_id, r.name, x.emoji, x.voter_name
+        FROM reactions x
+        JOIN restaurants r ON r.id = x.restaurant_id AND r.deleted_at IS NULL
+        WHERE x.poll_id = ?
+        ORDER BY x.created_at, x.rowid",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut candidates: Vec<CandidateReactions> = Vec::new();
+    for (restaurant_id, restaurant_name, emoji, voter_name) in rows {
+        let candidate = match candidates.iter_mut().position(|c| c.restaurant_id == restaurant_id) {
+            Some(index) => &mut candidates[index],
+            None => {
+                candidates.push(CandidateReactions { restaurant_id, restaurant_name, reactions: BTreeMap::new() });
+                candidates.last_mut().unwrap()
+            }
+        };
+        candidate.reactions.entry(emoji).or_default().push(voter_name);
+    }
+    Ok(Json(candidates))
+}
@@ -0,0 +1,286 @@
+// Receipts for ballots, for settling "my vote disappeared". Each accepted ballot gets a receipt signed with an HMAC
+// under RECEIPT_KEY (so only this server could have issued it) and is appended to a hash chain of every ballot
+// (so the record can't be quietly edited afterwards). Handing a receipt back to POST /receipts/verify says whether
+// the server signed it, whether the chain still holds it unchanged, and whether the vote is still counted
+// https://docs.rs/ring/latest/ring/hmac/index.html
+// https://en.wikipedia.org/wiki/Hash_chain
+use axum::extract::State;
+use axum::Json;
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::ApiError;
+use crate::{database, AppState};
+
+// What a ballot chose, as it was recorded
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub restaurant_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_restaurant_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allocation: Option<BTreeMap<String, i64>>,
+}
+
+// The signed part of a receipt, and the ballot's record in the chain. serde_json writes fields in declaration order
+// and the allocation is a BTreeMap, so the same receipt always serializes to the same bytes
+#[derive(Serialize, Deserialize)]
+struct ReceiptBody {
+    sequence: i64, // the ballot's place in the chain
+    poll_id: Option<String>, // the poll's public id
+    voter_name: String,
+    #[serde(flatten)]
+    choice: Choice,
+    cast_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Receipt {
+    #[serde(flatten)]
+    body: ReceiptBody,
+    hash: String,      // the ballot's link in the chain, hex-encoded SHA-256
+    signature: String, // hex-encoded HMAC-SHA256 of the body
+}
+
+#[derive(Serialize)]
+pub struct Verification {
+    signature_valid: bool, // this server issued the receipt, and nothing in it was changed
+    recorded: bool,        // the chain has the ballot, exactly as on the receipt
+    // The ballot is there, but under a different hash: the chain was re-linked from an earlier ballot on, which
+    // only erasing a voter's data does (see erasure.rs), and the audit log will show when
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    relinked: bool,
+    chain_intact: bool,    // every hash in the chain still follows from the ones before
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broken_at: Option<i64>, // the first link whose hash doesn't, when one doesn't
+    counted: bool,         // a matching vote is still in the tally (or still sealed, in a sealed poll)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn link_hash(prev_hash: &str, body: &str) -> String {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(prev_hash.as_bytes());
+    context.update(body.as_bytes());
+    hex(context.finish().as_ref())
+}
+
+// The first of the chain's links, (sequence, body, prev_hash, hash) in order, whose hash doesn't follow from the one
+// before it, if any doesn't
+fn broken_link(links: &[(i64, String, String, String)]) -> Option<i64> {
+    let mut expected_prev = "";
+    for (sequence, body, prev_hash, hash) in links {
+        if prev_hash != expected_prev || &link_hash(prev_hash, body) != hash {
+            return Some(*sequence);
+        }
+        expected_prev = hash;
+    }
+    None
+}
+
+fn key(state: &AppState) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key)
+}
+
+// Adds an accepted ballot to the chain and signs its receipt. The chain's head is read and extended in one
+// transaction, so two ballots can't claim the same place; an immediate one, so two ballots that have both read the
+// head don't deadlock over extending it (see database.rs)
+pub async fn issue(
+    state: &AppState,
+    voter_name: &str,
+    poll_id: Option<String>,
+    choice: Choice,
+) -> Result<Receipt, sqlx::Error> {
+    let mut tx = database::Immediate::begin(&state.db).await?;
+    let receipt = append(state, &mut tx, voter_name, poll_id, choice).await?;
+    tx.commit().await?;
+    Ok(receipt)
+}
+
+// The same in the caller's transaction, for ballots written in a batch along with their receipts; see
+// vote_batches.rs
+pub async fn append(
+    state: &AppState,
+    conn: &mut SqliteConnection,
+    voter_name: &str,
+    poll_id: Option<String>,
+    choice: Choice,
+) -> Result<Receipt, sqlx::Error> {
+    let (sequence, prev_hash, cast_at): (i64, String, String) = sqlx::query_as(
+        "SELECT COALESCE(MAX(sequence), 0) + 1,
+            COALESCE((SELECT hash FROM receipts ORDER BY sequence DESC LIMIT 1), ''),
+            datetime('now')
+        FROM receipts",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let body = ReceiptBody { sequence, poll_id, voter_name: voter_name.to_string(), choice, cast_at };
+    let json = serde_json::to_string(&body).expect("a receipt always serializes");
+    let hash = link_hash(&prev_hash, &json);
+    sqlx::query("INSERT INTO receipts (sequence, body, prev_hash, hash) VALUES (?, ?, ?, ?)")
+        .bind(sequence)
+        .bind(&json)
+        .bind(&prev_hash)
+        .bind(&hash)
+        .execute(&mut *conn)
+        .await?;
+    let signature = hex(hmac::sign(&key(state), json.as_bytes()).as_ref());
+    Ok(Receipt { body, hash, signature })
+}
+
+// Renames voters throughout the chain, for erasing or anonymizing their data: `rename` gets each ballot's voter and
+// time cast, and gives the new name for those to be renamed. Their links' bodies change, so every hash from the
+// first of them on is recomputed and the chain still verifies; the receipts the voters were given stop matching
+// it, which is the point, and later ones verify as relinked. Returns how many receipts were renamed
+pub async fn rename_voters(
+    conn: &mut SqliteConnection,
+    rename: impl Fn(&str, &str) -> Option<String>,
+) -> Result<u64, sqlx::Error> {
+    let links: Vec<(i64, String)> = sqlx::query_as("SELECT sequence, body FROM receipts ORDER BY sequence")
+        .fetch_all(&mut *conn)
+        .await?;
+    let mut prev_hash = String::new();
+    let mut renamed = 0;
+    for (sequence, mut json) in links {
+        let mut body: ReceiptBody = serde_json::from_str(&json).map_err(|err| sqlx::Error::Decode(err.into()))?;
+        if let Some(new_name) = rename(&body.voter_name, &body.cast_at) {
+            body.voter_name = new_name;
+            json = serde_json::to_string(&body).expect("a receipt always serializes");
+            renamed += 1;
+        }
+        // Links before the first renamed one come out as they were, so only the rest need writing back
+        let hash = link_hash(&prev_hash, &json);
+        if renamed > 0 {
+            sqlx::query("UPDATE receipts SET body = ?, prev_hash = ?, hash = ? WHERE sequence = ?")
+                .bind(&json)
+                .bind(&prev_hash)
+                .bind(&hash)
+                .bind(sequence)
+                .execute(&mut *conn)
+                .await?;
+        }
+        prev_hash = hash;
+    }
+    Ok(renamed)
+}
+
+// POST /receipts/verify, with a receipt exactly as /vote returned it
+pub async fn verify(
+    State(state): State<AppState>,
+    Json(receipt): Json<Receipt>,
+) -> Result<Json<Verification>, ApiError> {
+    let json = serde_json::to_string(&receipt.body).expect("a receipt always serializes");
+    // hmac::verify compares in constant time, so timing gives nothing away about the right signature
+    let signature_valid = (0..receipt.signature.len())
+        .step_by(2)
+        .map(|i| receipt.signature.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .is_some_and(|signature| hmac::verify(&key(&state), json.as_bytes(), &signature).is_ok());
+
+    // Walk the whole chain, recomputing each hash from the one before
+    let links: Vec<(i64, String, String, String)> =
+        sqlx::query_as("SELECT sequence, body, prev_hash, hash FROM receipts ORDER BY sequence")
+            .fetch_all(&state.db)
+            .await?;
+    let broken_at = broken_link(&links);
+    let mut recorded = false;
+    let mut relinked = false;
+    for (sequence, body, _, hash) in &links {
+        if *sequence == receipt.body.sequence {
+            recorded = body == &json;
+            relinked = recorded && hash != &receipt.hash;
+        }
+    }
+
+    let counted: bool = sqlx::query_scalar(
+        "SELECT EXISTS (
+            SELECT 1 FROM votes WHERE voter_name = ? AND restaurant_name = ? AND deleted_at IS NULL
+            AND poll_id IS (SELECT id FROM polls WHERE public_id = ?)
+        ) OR EXISTS (
+            SELECT 1 FROM sealed_ballots WHERE voter_name = ? AND poll_id = (SELECT id FROM polls WHERE public_id = ?)
+        )",
+    )
+    .bind(&receipt.body.voter_name)
+    .bind(&receipt.body.choice.restaurant_name)
+    .bind(&receipt.body.poll_id)
+    .bind(&receipt.body.voter_name)
+    .bind(&receipt.body.poll_id)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(Verification {
+        signature_valid,
+        recorded,
+        relinked,
+        chain_intact: broken_at.is_none(),
+        broken_at,
+        counted,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A chain of these bodies, linked the way append links them
+    fn chain(bodies: &[&str]) -> Vec<(i64, String, String, String)> {
+        let mut links = Vec::new();
+        let mut prev_hash = String::new();
+        for (sequence, body) in (1..).zip(bodies) {
+            let hash = link_hash(&prev_hash, body);
+            links.push((sequence, body.to_string(), prev_hash, hash.clone()));
+            prev_hash = hash;
+        }
+        links
+    }
+
+    #[test]
+    fn links_hash_the_previous_hash_then_the_body() {
+        // SHA-256 of "{}", and of "abc" then "{\"sequence\":1}"
+        let (empty, linked) = (
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+            "a97368e412902be8cf90de86b0ff69a017e9f54d6b1a918d0e579758625d6eec",
+        );
+        assert_eq!(link_hash("", "{}"), empty);
+        assert_eq!(link_hash("abc", r#"{"sequence":1}"#), linked);
+    }
+
+    #[test]
+    fn an_untouched_chain_holds() {
+        assert_eq!(broken_link(&chain(&["one", "two", "three"])), None);
+        assert_eq!(broken_link(&[]), None);
+    }
+
+    #[test]
+    fn an_edited_body_breaks_the_chain_at_its_link() {
+        let mut links = chain(&["one", "two", "three"]);
+        links[1].1 = "TWO".to_string();
+        assert_eq!(broken_link(&links), Some(2));
+    }
+
+    #[test]
+    fn a_link_taken_out_breaks_the_chain_after_it() {
+        let mut links = chain(&["one", "two", "three"]);
+        links.remove(1);
+        assert_eq!(broken_link(&links), Some(3));
+    }
+
+    #[test]
+    fn a_chain_relinked_from_an_edit_on_holds_again() {
+        // What rename_voters does: every hash from the edited link on is worked out again, so the chain verifies and
+        // the edited ballot's old receipt doesn't match it
+        let links = chain(&["one", "TWO", "three"]);
+        assert_eq!(broken_link(&links), None);
+        assert_ne!(links[1].3, chain(&["one", "two", "three"])[1].3);
+    }
+}
@@ -0,0 +1,198 @@
+// Recommends restaurants from the team's own history, for poll creators who'd rather accept a shortlist than build one.
+// Each restaurant gets four scores between 0 and 1, and the recommendation is their weighted sum:
+//   affinity   how much it has been voted for, with a vote's weight halving every 30 days
+//   rating     its average rating; unrated places sit in the middle
+//   weekday    how much of its support comes on the weekday being planned for ("Friday is pizza day")
+//   freshness  how long since it last won, so yesterday's winner doesn't top the list again; two weeks is fully fresh
+// Given the voters who are coming, places that miss someone's dietary needs or budget are dropped, and when any of
+// them has listed favourite cuisines, the share of them who like the place's cuisine is blended into the score.
+// Places that would cost more than one of them has left of their monthly budget stay in, with a budget_warning
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::ApiError;
+use crate::hours::{self, WEEKDAYS};
+use crate::restaurants::{RestaurantDetails, RestaurantStatus};
+use crate::voters::GroupPreferences;
+use crate::{budgets, stats, timezones, AppState};
+
+const AFFINITY_WEIGHT: f64 = 0.35;
+const RATING_WEIGHT: f64 = 0.25;
+const WEEKDAY_WEIGHT: f64 = 0.15;
+const FRESHNESS_WEIGHT: f64 = 0.25;
+const PREFERENCE_WEIGHT: f64 = 0.3; // the share of the final score that comes from cuisine likes, when there are any
+const AFFINITY_HALF_LIFE_DAYS: f64 = 30.0;
+const FRESH_AFTER_DAYS: f64 = 14.0;
+
+#[derive(Deserialize)]
+pub struct RecommendationQuery {
+    #[serde(default = "default_limit")]
+    limit: usize,
+    weekday: Option<String>, // defaults to today
+    voters: Option<String>, // comma-separated names whose preferences apply
+}
+
+fn default_limit() -> usize {
+    5
+}
+
+#[derive(Serialize)]
+pub struct Recommendations {
+    weekday: String,
+    pub recommendations: Vec<Recommendation>,
+    candidate_ids: Vec<String>, // the shortlist, ready to send as a new poll's candidate_ids
+}
+
+#[derive(Serialize)]
+pub struct Recommendation {
+    id: String, // the restaurant's public id
+    pub name: String,
+    pub score: f64,
+    affinity: f64,
+    rating: f64,
+    weekday: f64,
+    freshness: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preference: Option<f64>, // only when some of the voters listed cuisines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget_warning: Option<String>, // whose monthly budget it would blow; see budgets.rs
+}
+
+#[derive(sqlx::FromRow)]
+struct RestaurantRow {
+    id: String,
+    name: String,
+    #[sqlx(flatten)]
+    details: RestaurantDetails,
+    average_rating: Option<f64>,
+    days_since_win: Option<f64>,
+}
+
+// GET /recommendations?limit=5&weekday=friday&voters=Zoë,Sam
+pub async fn recommend(
+    State(state): State<AppState>,
+    Query(query): Query<RecommendationQuery>,
+) -> Result<Json<Recommendations>, ApiError> {
+    let weekday = weekday_or_today(&state, query.weekday.as_deref()).await?;
+    let voters: Vec<String> = query.voters.unwrap_or_default().split(',').map(str::to_string).collect();
+    let preferences = GroupPreferences::load(&state.db, &voters).await?;
+    let budgets = budgets::remaining(&state.db, &voters).await?;
+    Ok(Json(shortlist(&state, weekday, query.limit, &preferences, &budgets).await?))
+}
+
+// The weekday number (0 is Sunday) for a name like "friday", or today's when none is given
+pub async fn weekday_or_today(state: &AppState, name: Option<&str>) -> Result<i64, ApiError> {
+    if let Some(name) = name {
+        let weekday = hours::parse_weekday(name).ok_or_else(|| ApiError::BadRequest(format!("unknown weekday {name}")))?;
+        return Ok(weekday as i64);
+    }
+    let today = sqlx::query_scalar("SELECT CAST(strftime('%w', 'now', 'localtime') AS INTEGER)")
+        .fetch_one(&state.db)
+        .await?;
+    Ok(today)
+}
+
+// The best `limit` restaurants for the given weekday and group, best first
+pub async fn shortlist(
+    state: &AppState,
+    weekday: i64,
+    limit: usize,
+    preferences: &GroupPreferences,
+    budgets: &[budgets::Remaining],
+) -> Result<Recommendations, ApiError> {
+    // Daily winners are worked out the same way as in /stats/cuisines; see stats::daily_winners_sql
+    let restaurants = sqlx::query_as::<_, RestaurantRow>(&format!(
+        "{}
+        SELECT r.public_id AS id, r.name, r.address, r.latitude, r.longitude, r.cuisine, r.price_tier, r.average_cost,
+            r.distance_meters, r.travel_minutes, r.walking_minutes, r.dietary_tags,
+            (SELECT AVG(score) FROM ratings WHERE restaurant_id = r.id) AS average_rating,
+            julianday(date('now', 'localtime')) - julianday((
+                SELECT MAX(day) FROM winners WHERE place = 1 AND restaurant_name = r.name
+            )) AS days_since_win
+        FROM restaurants r
+        WHERE r.status = ? AND r.active AND r.deleted_at IS NULL",
+        stats::daily_winners_sql()
+    ))
+    .bind(RestaurantStatus::Approved)
+    .fetch_all(&state.db)
+    .await?;
+
+    // Every vote with its age and weekday; the decay is done here because SQLite's math functions are optional
+    let votes: Vec<(String, f64, i64)> = sqlx::query_as(&format!(
+        "SELECT restaurant_name, julianday('now') - julianday(created_at), CAST(strftime('%w', {}) AS INTEGER)
+        FROM votes WHERE deleted_at IS NULL",
+        timezones::vote_day_sql("votes")
+    ))
+    .fetch_all(&state.db)
+    .await?;
+    #[derive(Default)]
+    struct History {
+        affinity: f64,
+        votes: f64,
+        weekday_votes: f64,
+    }
+    let mut history: HashMap<String, History> = HashMap::new();
+    for (name, age_days, vote_weekday) in votes {
+        let entry = history.entry(name).or_default();
+        entry.affinity += 0.5f64.powf(age_days.max(0.0) / AFFINITY_HALF_LIFE_DAYS);
+        entry.votes += 1.0;
+        if vote_weekday == weekday {
+            entry.weekday_votes += 1.0;
+        }
+    }
+    let max_affinity = history.values().map(|h| h.affinity).fold(0.0, f64::max);
+
+    let mut recommendations: Vec<Recommendation> = restaurants
+        .into_iter()
+        .filter(|restaurant| preferences.allows(&restaurant.details))
+        .map(|restaurant| {
+            let past = history.get(&restaurant.name);
+            let affinity = match past {
+                Some(past) if max_affinity > 0.0 => past.affinity / max_affinity,
+                _ => 0.0,
+            };
+            let rating = restaurant.average_rating.map_or(0.5, |average| (average - 1.0) / 4.0);
+            // One in seven votes landing on this weekday is what no pattern at all looks like, so that scores 0;
+            // the share is smoothed a little so a single vote doesn't make a habit
+            let weekday_fit = past.map_or(0.0, |past| {
+                let share = (past.weekday_votes + 1.0) / (past.votes + 7.0);
+                ((share - 1.0 / 7.0) / (1.0 - 1.0 / 7.0)).clamp(0.0, 1.0)
+            });
+            let freshness = restaurant.days_since_win.map_or(1.0, |days| (days / FRESH_AFTER_DAYS).clamp(0.0, 1.0));
+            let mut score = AFFINITY_WEIGHT * affinity
+                + RATING_WEIGHT * rating
+                + WEEKDAY_WEIGHT * weekday_fit
+                + FRESHNESS_WEIGHT * freshness;
+            let preference = preferences.cuisine_fit(&restaurant.details);
+            if let Some(fit) = preference {
+                score = (1.0 - PREFERENCE_WEIGHT) * score + PREFERENCE_WEIGHT * fit;
+            }
+            Recommendation {
+                id: restaurant.id,
+                name: restaurant.name,
+                score: round(score),
+                affinity: round(affinity),
+                rating: round(rating),
+                weekday: round(weekday_fit),
+                freshness: round(freshness),
+                preference: preference.map(round),
+                budget_warning: budgets::warning(&state.config, &restaurant.details, budgets),
+            }
+        })
+        .collect();
+    recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    recommendations.truncate(limit);
+
+    Ok(Recommendations {
+        weekday: WEEKDAYS[weekday as usize].to_string(),
+        candidate_ids: recommendations.iter().map(|r| r.id.clone()).collect(),
+        recommendations,
+    })
+}
+
+// Three decimals is plenty for a score people read
+fn round(score: f64) -> f64 {
+    (score * 1000.0).round() / 1000.0
+}
@@ -0,0 +1,184 @@
+// Telling clients a tally has changed, so a results page can fetch it again rather than asking every few seconds.
+// Whatever changes votes sends a refresh on a tokio broadcast channel, and GET /results/stream passes each one on to
+// every listener as a server-sent event. Refreshes aren't kept: a client that wasn't listening just fetches as usual.
+// With several instances behind a load balancer, REDIS_URL has them share refreshes through Redis pub/sub, so a
+// client streaming from one instance hears of votes cast on any: each publishes its own refreshes to REDIS_CHANNEL
+// and passes on the ones the others publish there. Each refresh has the tallies kept for its poll forgotten before
+// it goes out, so whoever it reaches is counted a fresh one; see tallies.rs
+// https://docs.rs/tokio/latest/tokio/sync/broadcast/index.html
+// https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::redis::{self, RedisError};
+use crate::tallies::Tallies;
+use crate::{config, AppState};
+
+// How many refreshes a slow listener can fall behind by before it misses some
+const BACKLOG: usize = 64;
+
+#[derive(Clone)]
+pub struct Refreshes {
+    sender: broadcast::Sender<TallyRefresh>,
+    tallies: Tallies,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cause {
+    Vote,       // a ballot was cast or retracted
+    Correction, // an admin reassigned, deleted or restored a vote
+    Reopened,   // a closed poll is taking votes again, so the result it closed with no longer stands
+    Missed,     // the listener fell behind and some refreshes were lost, so every tally may have changed
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TallyRefresh {
+    pub poll_id: Option<String>, // the poll's public id; none for the loose votes at /results
+    pub cause: Cause,
+    #[serde(skip)]
+    pub relayed: bool, // heard from another instance, so not for publishing again
+}
+
+impl Refreshes {
+    pub fn new(tallies: Tallies) -> Refreshes {
+        Refreshes { sender: broadcast::channel(BACKLOG).0, tallies }
+    }
+
+    pub fn send(&self, poll_id: Option<String>, cause: Cause) {
+        self.forget(poll_id.as_deref(), cause);
+        // send only fails when nobody is listening, which is nothing to worry about
+        let _ = self.sender.send(TallyRefresh { poll_id, cause, relayed: false });
+    }
+
+    // The refreshes from now on, for a listener of its own; GraphQL subscriptions have one each
+    pub fn subscribe(&self) -> broadcast::Receiver<TallyRefresh> {
+        self.sender.subscribe()
+    }
+
+    fn relay(&self, poll_id: Option<String>, cause: Cause) {
+        self.forget(poll_id.as_deref(), cause);
+        let _ = self.sender.send(TallyRefresh { poll_id, cause, relayed: true });
+    }
+
+    fn forget(&self, poll_id: Option<&str>, cause: Cause) {
+        match cause {
+            Cause::Missed => self.tallies.forget_all(),
+            _ => self.tallies.forget(poll_id),
+        }
+    }
+}
+
+// GET /results/stream: a "tally" event, {"poll_id": ..., "cause": "vote"}, whenever a tally changes
+pub async fn stream(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // unfold turns the receiver into a stream, one recv() per item; it ends when the channel closes
+    // https://docs.rs/futures-util/latest/futures_util/stream/fn.unfold.html
+    let events = futures_util::stream::unfold(state.refreshes.sender.subscribe(), |mut receiver| async move {
+        let refresh = match receiver.recv().await {
+            Ok(refresh) => refresh,
+            Err(RecvError::Lagged(_)) => TallyRefresh { poll_id: None, cause: Cause::Missed, relayed: false },
+            Err(RecvError::Closed) => return None,
+        };
+        let event = Event::default().event("tally").json_data(&refresh).expect("a refresh serializes to JSON");
+        Some((Ok(event), receiver))
+    });
+    // The keep-alive comments stop proxies from closing a stream that's quiet between lunches
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+// How long to wait before reconnecting to Redis after losing it
+const REDIS_RETRY: Duration = Duration::from_secs(5);
+
+// A refresh as it goes through Redis, with the random id of the instance it comes from, so that one doesn't pass
+// its own back on
+#[derive(Serialize, Deserialize)]
+struct Relayed {
+    instance: String,
+    poll_id: Option<String>,
+    cause: Cause,
+}
+
+// Sharing refreshes with the other instances, when REDIS_URL is set
+pub fn spawn_relay(state: AppState) {
+    let Some(url) = state.config.redis_url.clone() else {
+        return;
+    };
+    let instance: String = config::random_key()[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+    let channel = state.config.redis_channel.clone();
+
+    // Out: each refresh sent here, as it's sent. A publish that fails drops the connection, and the next reconnects
+    let mut receiver = state.refreshes.sender.subscribe();
+    let (out_url, out_channel, out_instance) = (url.clone(), channel.clone(), instance.clone());
+    tokio::spawn(async move {
+        let mut connection: Option<redis::Connection> = None;
+        loop {
+            let refresh = match receiver.recv().await {
+                Ok(refresh) if refresh.relayed => continue,
+                Ok(refresh) => refresh,
+                Err(RecvError::Lagged(_)) => TallyRefresh { poll_id: None, cause: Cause::Missed, relayed: false },
+                Err(RecvError::Closed) => return,
+            };
+            let relayed = Relayed { instance: out_instance.clone(), poll_id: refresh.poll_id, cause: refresh.cause };
+            let message = serde_json::to_string(&relayed).expect("a refresh serializes to JSON");
+            let published = async {
+                if connection.is_none() {
+                    connection = Some(redis::Connection::connect(&out_url).await?);
+                }
+                connection.as_mut().expect("connected above").publish(&out_channel, &message).await
+            };
+            if let Err(err) = published.await {
+                eprintln!("refresh: could not publish to Redis: {err}");
+                connection = None;
+            }
+        }
+    });
+
+    // In: the other instances' refreshes, for our listeners. Whatever was published while the subscription was down
+    // is lost, so once it's back every tally may have changed
+    let refreshes = state.refreshes.clone();
+    tokio::spawn(async move {
+        let mut reconnecting = false;
+        loop {
+            let err = listen(&url, &channel, &instance, &refreshes, reconnecting).await;
+            eprintln!("refresh: lost the Redis subscription: {err}");
+            reconnecting = true;
+            tokio::time::sleep(REDIS_RETRY).await;
+        }
+    });
+}
+
+async fn listen(
+    url: &str,
+    channel: &str,
+    instance: &str,
+    refreshes: &Refreshes,
+    reconnecting: bool,
+) -> RedisError {
+    let mut connection = match redis::Connection::connect(url).await {
+        Ok(connection) => connection,
+        Err(err) => return err,
+    };
+    if let Err(err) = connection.subscribe(channel).await {
+        return err;
+    }
+    if reconnecting {
+        refreshes.relay(None, Cause::Missed);
+    }
+    loop {
+        let message = match connection.next_message().await {
+            Ok(message) => message,
+            Err(err) => return err,
+        };
+        match serde_json::from_str::<Relayed>(&message) {
+            Ok(relayed) if relayed.instance == instance => {}
+            Ok(relayed) => refreshes.relay(relayed.poll_id, relayed.cause),
+            Err(_) => eprintln!("refresh: ignored a message on {channel} that isn't a refresh: {message}"),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// Reminders to vote. REMINDER_LEAD_MINUTES before an open poll's closes_at, the scheduler looks for those on its
+// roster who haven't voted or abstained yet and reminds them: all together in one POST to REMINDER_WEBHOOK_URL, for
+// a chat bot to mention them, and one by one on the channels each has chosen for them. The roster is the poll's
+// attendees and, on a runoff, the voters it's for; a rotation poll's is just its picker. Anyone who has said they're
+// not coming is left alone, and so is anyone away that day (see away.rs) or who has turned reminders off with
+// PUT /voters/:name/notifications (see notifications.rs). Each lead time is one round, sent once; when the server
+// was down through several, only the latest goes out
+use serde::Serialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+
+use crate::error::ApiError;
+use crate::i18n::{self, Locale};
+use crate::notifications::{self, Event};
+use crate::offices::POLL_NOW_SQL;
+use crate::polls::{self, Poll, VotingMethod};
+use crate::{away, names, participation, vote_links, AppState};
+
+#[derive(Serialize)]
+pub struct Reminder {
+    poll_id: String,
+    join_code: Option<String>,
+    title: Option<String>,
+    metadata: Option<serde_json::Value>,
+    lunch_at: String,
+    closes_at: String,
+    minutes_left: i64,
+    voters: Vec<String>, // who hasn't voted yet
+    rotation: bool,      // they're the picker, and it's their turn to choose for everyone
+}
+
+// Called by the scheduler every minute
+pub async fn send_due(state: &AppState) -> Result<(), ApiError> {
+    let Some(&longest) = state.config.reminder_lead_minutes.first() else {
+        return Ok(());
+    };
+    let now = POLL_NOW_SQL;
+    let open: Vec<(i64, i64)> = sqlx::query_as(&format!(
+        "SELECT id, CAST(ROUND((julianday(closes_at) - julianday({now})) * 1440) AS INTEGER) AS minutes_left
+        FROM polls WHERE status = 'open' AND closes_at > {now}"
+    ))
+    .fetch_all(&state.db)
+    .await?;
+    for (id, minutes_left) in open.into_iter().filter(|(_, minutes_left)| *minutes_left <= longest) {
+        let due: Vec<i64> =
+            state.config.reminder_lead_minutes.iter().copied().filter(|lead| minutes_left <= *lead).collect();
+        let sent: Vec<i64> = sqlx::query_scalar("SELECT lead_minutes FROM poll_reminders WHERE poll_id = ?")
+            .bind(id)
+            .fetch_all(&state.db)
+            .await?;
+        if due.iter().all(|lead| sent.contains(lead)) {
+            continue;
+        }
+        let poll = polls::find_poll(&state.db, id).await?.ok_or(sqlx::Error::RowNotFound)?;
+        let reminder = reminder(&state.db, &poll, minutes_left).await?;
+        // The round counts as sent even when a channel fails, so a broken webhook doesn't get retried every minute
+        for lead in due {
+            sqlx::query("INSERT OR IGNORE INTO poll_reminders (poll_id, lead_minutes, reminded) VALUES (?, ?, ?)")
+                .bind(id)
+                .bind(lead)
+                .bind(reminder.voters.len() as i64)
+                .execute(&state.db)
+                .await?;
+        }
+        if reminder.voters.is_empty() {
+            continue;
+        }
+        if let Some(url) = &state.config.reminder_webhook_url {
+            if let Err(err) = post(state, url, &reminder).await {
+                eprintln!("poll {id}: could not post the reminder: {err:?}");
+            }
+        }
+        // Each gets links of their own to vote from the reminder; without them it's still worth sending
+        let mut links: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+        for voter in &reminder.voters {
+            match vote_links::links(state, &poll, voter).await {
+                Ok(voter_links) => {
+                    links.insert(names::fold(voter), voter_links);
+                }
+                Err(err) => eprintln!("poll {id}: could not make {voter} voting links: {err:?}"),
+            }
+        }
+        let data = json!(reminder);
+        let team = poll.team_id.as_deref();
+        notifications::dispatch(state, Event::Reminder, &reminder.voters, team, &data, |voter, locale| {
+            message(voter, locale, &reminder, links.get(&names::fold(voter)).map_or(&[], Vec::as_slice))
+        })
+        .await?;
+        println!("poll {id}: reminded {} voters, {minutes_left} minutes before it closes", reminder.voters.len());
+    }
+    Ok(())
+}
+
+async fn reminder(db: &SqlitePool, poll: &Poll, minutes_left: i64) -> Result<Reminder, sqlx::Error> {
+    let rotation = poll.voting_method == VotingMethod::Rotation;
+    let roster: Vec<String> =
+        if rotation { poll.picker.iter().cloned().collect() } else { notifications::roster(poll) };
+    let participants = participation::participants(db, poll.id).await?;
+    let not_coming: Vec<String> = sqlx::query_scalar("SELECT voter_key FROM rsvps WHERE poll_id = ? AND NOT coming")
+        .bind(poll.id)
+        .fetch_all(db)
+        .await?;
+    let opted_out = notifications::opted_out(db, Event::Reminder).await?;
+    let away = away::away_on(db, &poll.lunch_at).await?;
+    let mut voters: Vec<String> = Vec::new();
+    for voter in roster {
+        let key = names::fold(&voter);
+        let done = participants.iter().any(|participant| names::fold(participant) == key)
+            || voters.iter().any(|known| names::fold(known) == key);
+        if !done && !not_coming.contains(&key) && !opted_out.contains(&key) && !away.contains(&key) {
+            voters.push(voter);
+        }
+    }
+    Ok(Reminder {
+        poll_id: poll.public_id.clone(),
+        join_code: poll.join_code.clone(),
+        title: poll.title.clone(),
+        metadata: poll.metadata.as_ref().map(|metadata| metadata.0.clone()),
+        lunch_at: poll.lunch_at.clone(),
+        closes_at: poll.closes_at.clone().unwrap_or_default(),
+        minutes_left,
+        voters,
+        rotation,
+    })
+}
+
+async fn post(state: &AppState, url: &str, reminder: &Reminder) -> Result<(), ApiError> {
+    let response = state.http.post(url).json(reminder).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("the reminder webhook returned {status}: {body}")));
+    }
+    Ok(())
+}
+
+fn message(voter: &str, locale: Locale, reminder: &Reminder, links: &[(String, String)]) -> (String, String) {
+    let poll = reminder.join_code.as_deref().unwrap_or(&reminder.poll_id);
+    let mut buttons = String::new();
+    if !links.is_empty() {
+        let heading = if reminder.rotation { "reminder-pick-links" } else { "reminder-vote-links" };
+        buttons.push_str(&format!("\n{}\n", i18n::text(locale, heading, &[])));
+        for (restaurant, url) in links {
+            buttons.push_str(&format!("  {restaurant}: {url}\n"));
+        }
+    }
+    let minutes = reminder.minutes_left.to_string();
+    let args = [
+        ("voter", voter),
+        ("poll", poll),
+        ("lunch_at", &reminder.lunch_at),
+        ("closes_at", &reminder.closes_at),
+        ("minutes", &minutes),
+        ("links", &buttons),
+    ];
+    let (subject, text) = match reminder.rotation {
+        true => ("rotation-subject", "rotation-text"),
+        false => ("reminder-subject", "reminder-text"),
+    };
+    (i18n::text(locale, subject, &args), format!("{}\n", i18n::text(locale, text, &args)))
+}
@@ -0,0 +1,171 @@
+// Reservations at the winning restaurant. A poll created with "reserve": true books a table when it closes on a
+// winner, for the headcount its RSVPs confirmed (see rsvps.rs), at its lunch_at, and keeps the confirmation
+// reference. OpenTable and Resy only open their booking APIs to partners, so the request goes to RESERVATION_API_URL
+// in a small provider-neutral form, for the partner integration (or a stand-in) behind it; RESERVATION_PROVIDER
+// names the provider, and a restaurant's id there is its restaurant_external_ids entry under the same source.
+// Booking runs in the background so closing isn't held up by the provider; an admin can retry one that failed
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::polls::{self, Poll};
+use crate::public_ids::PollId;
+use crate::i18n::Locale;
+use crate::{rsvps, AppState};
+
+#[derive(Serialize)]
+struct BookingRequest<'a> {
+    provider: &'a str,
+    restaurant_id: Option<String>, // the provider's id for the restaurant, when it's known
+    restaurant_name: &'a str,
+    address: Option<String>,
+    party_size: i64,
+    time: &'a str, // the poll's lunch_at, in the office's local time
+    reference: &'a str, // the poll's id, for the provider to log
+}
+
+#[derive(Deserialize)]
+struct BookingResponse {
+    confirmation: String,
+}
+
+// When a closed poll asked for a reservation: books one without holding up the close
+pub fn spawn_booking(state: &AppState, poll_id: i64) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        match book(&state, poll_id).await {
+            Ok(poll) => println!(
+                "poll {poll_id}: reserved a table, confirmation {}",
+                poll.reservation_reference.unwrap_or_default()
+            ),
+            Err(err) => {
+                eprintln!("poll {poll_id}: no reservation: {err:?}");
+                // Whatever stopped it before the booking API was asked, the poll says so
+                let skipped = sqlx::query(
+                    "UPDATE polls SET reservation_status = 'skipped', reservation_note = ?
+                    WHERE id = ? AND reservation_status IS NULL",
+                )
+                .bind(describe(&err))
+                .bind(poll_id)
+                .execute(&state.db)
+                .await;
+                if let Err(err) = skipped {
+                    eprintln!("poll {poll_id}: could not record the skipped reservation: {err:?}");
+                }
+            }
+        }
+    });
+}
+
+// What went wrong, in words for the poll's reservation_note
+fn describe(err: &ApiError) -> String {
+    match err {
+        ApiError::BadRequest(message)
+        | ApiError::Unauthorized(message)
+        | ApiError::Forbidden(message)
+        | ApiError::NotFound(message)
+        | ApiError::Conflict(message)
+        | ApiError::Upstream(message)
+        | ApiError::NotConfigured(message) => message.clone(),
+        ApiError::Ambiguous { message, .. }
+        | ApiError::TooManyRequests { message, .. }
+        | ApiError::Unavailable { message, .. }
+        | ApiError::Catalogued(message) => message.render(Locale::En),
+        ApiError::DbError(_) => Message::database().render(Locale::En),
+    }
+}
+
+// Books a table for the poll's winner, unless one is booked or being booked already
+async fn book(state: &AppState, poll_id: i64) -> Result<Poll, ApiError> {
+    let url = state.config.reservation_api_url.as_deref().ok_or_else(|| {
+        ApiError::NotConfigured("set RESERVATION_API_URL to book tables at the winner".to_string())
+    })?;
+    let poll = polls::find_poll(&state.db, poll_id)
+        .await?
+        .ok_or_else(|| Message::no_poll(poll_id))?;
+    let winner = polls::winner(state, &poll).await?;
+    let party_size = rsvps::headcount(&state.db, &poll).await?.coming;
+    if party_size == 0 {
+        return Err(ApiError::Conflict("nobody has said they're coming, so there's no headcount to book".to_string()));
+    }
+    let claimed = sqlx::query(
+        "UPDATE polls SET reservation_status = 'pending', reservation_note = NULL
+        WHERE id = ? AND (reservation_status IS NULL OR reservation_status IN ('failed', 'skipped'))",
+    )
+    .bind(poll_id)
+    .execute(&state.db)
+    .await?;
+    if claimed.rows_affected() == 0 {
+        return Err(ApiError::Conflict("the poll's table is booked, or being booked, already".to_string()));
+    }
+
+    let provider = state.config.reservation_provider.as_str();
+    let (address, restaurant_id): (Option<String>, Option<String>) = sqlx::query_as(
+        "SELECT r.address, (
+            SELECT external_id FROM restaurant_external_ids WHERE restaurant_id = r.id AND source = ?
+        ) FROM restaurants r WHERE r.name = ? AND r.deleted_at IS NULL",
+    )
+    .bind(provider)
+    .bind(&winner)
+    .fetch_optional(&state.db)
+    .await?
+    .unwrap_or_default();
+    let booking = BookingRequest {
+        provider,
+        restaurant_id,
+        restaurant_name: &winner,
+        address,
+        party_size,
+        time: &poll.lunch_at,
+        reference: &poll.public_id,
+    };
+    match request(state, url, &booking).await {
+        Ok(confirmation) => {
+            sqlx::query(
+                "UPDATE polls SET reservation_status = 'confirmed', reservation_reference = ?, reservation_note = ?
+                WHERE id = ?",
+            )
+            .bind(confirmation)
+            .bind(format!("{party_size} at {winner}, {}", poll.lunch_at))
+            .bind(poll_id)
+            .execute(&state.db)
+            .await?;
+        }
+        Err(err) => {
+            sqlx::query("UPDATE polls SET reservation_status = 'failed', reservation_note = ? WHERE id = ?")
+                .bind(describe(&err))
+                .bind(poll_id)
+                .execute(&state.db)
+                .await?;
+            return Err(err);
+        }
+    }
+    Ok(polls::find_poll(&state.db, poll_id).await?.ok_or(sqlx::Error::RowNotFound)?)
+}
+
+async fn request(state: &AppState, url: &str, booking: &BookingRequest<'_>) -> Result<String, ApiError> {
+    let mut request = state.http.post(format!("{}/reservations", url.trim_end_matches('/'))).json(booking);
+    if let Some(key) = &state.config.reservation_api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("the booking API returned {status}: {body}")));
+    }
+    let booked: BookingResponse = response.json().await?;
+    Ok(booked.confirmation)
+}
+
+// POST /polls/:id/reservation (admin): books the table now, for a poll that didn't ask for it when it was created
+// or whose booking failed or was skipped. Returns the poll with its reservation
+pub async fn reserve_now(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, _): PollId,
+) -> Result<Json<Poll>, ApiError> {
+    Ok(Json(book(&state, id).await?))
+}
@@ -0,0 +1,930 @@
+// The restaurants table holds what we know about each place beyond its name; votes still reference restaurants by name
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::geo::{self, Coordinates};
+use crate::i18n::{self, Locale};
+use crate::links::RestaurantLinks;
+use crate::moderation::{self, NameKind};
+use crate::public_ids::{self, RestaurantId};
+use crate::{fuzzy, names, offices, teams, AppState};
+
+// Every query that builds a Restaurant starts from the same SELECT, so it is spelled out once here; callers append
+// their own WHERE and ORDER BY. The restaurants outside the trash are aliased as r, and the rating aggregates are
+// joined in from a grouped subquery so restaurants nobody has rated yet still show up
+pub const RESTAURANT_SELECT: &str = "SELECT r.id, r.public_id, r.name, r.address, r.latitude, r.longitude, r.cuisine, r.price_tier, r.average_cost,
+        r.distance_meters, r.travel_minutes, r.walking_minutes, r.dietary_tags, r.open_on_holidays, r.outdoor_seating, r.active, r.inactive_reason,
+        r.status, r.suggested_by, r.review_note, r.notes, r.google_rating, r.office_id,
+        r.yelp_rating, r.yelp_categories, r.yelp_hours, r.yelp_photos,
+        rating.average_rating, COALESCE(rating.rating_count, 0) AS rating_count,
+        (SELECT json_group_array(alias) FROM restaurant_aliases WHERE restaurant_id = r.id) AS aliases,
+        r.created_at, COALESCE(r.updated_at, r.created_at) AS updated_at
+    FROM (SELECT * FROM restaurants WHERE deleted_at IS NULL) r
+    LEFT JOIN (
+        SELECT restaurant_id, AVG(score) AS average_rating, COUNT(*) AS rating_count
+        FROM ratings GROUP BY restaurant_id
+    ) rating ON rating.restaurant_id = r.id";
+
+// Restaurants in the pool of the office bound (twice) here, see offices.rs, or every restaurant when that's NULL
+pub const OFFICE_POOL_SQL: &str = "(? IS NULL OR r.office_id IS NULL OR r.office_id = ?)";
+
+// FromRow lets sqlx map a result row straight onto the struct by column name
+// https://docs.rs/sqlx/latest/sqlx/trait.FromRow.html
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Restaurant {
+    #[serde(skip)]
+    pub id: i64,
+    // Clients only ever see the public UUID, under the name id; see public_ids.rs
+    #[serde(rename = "id")]
+    pub public_id: String,
+    pub name: String,
+    // flatten on both sides: sqlx reads the detail columns from the same row, and serde writes them
+    // as top-level JSON fields instead of a nested object
+    // https://serde.rs/attr-flatten.html
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub details: RestaurantDetails,
+    open_on_holidays: bool, // keeps its usual opening hours on public holidays
+    pub outdoor_seating: bool, // eating there means sitting outside
+    pub active: bool,
+    inactive_reason: Option<String>, // why it was deactivated, e.g. "closed down"
+    pub status: RestaurantStatus,
+    suggested_by: Option<String>, // the voter who suggested it, for restaurants that came in as suggestions
+    review_note: Option<String>, // the admin's note when approving or rejecting
+    notes: Option<String>, // anything else worth knowing, "by the station", for GET /restaurants/search to find
+    pub office_id: Option<String>, // only on this office's ballots; None puts it on every office's. See offices.rs
+    google_rating: Option<f64>, // Google's rating from the last Google Places import
+    // Filled in by the Yelp enrichment task; all None until the restaurant has been matched on Yelp
+    yelp_rating: Option<f64>,
+    yelp_categories: Option<JsonColumn<Vec<String>>>,
+    yelp_hours: Option<JsonColumn<serde_json::Value>>,
+    yelp_photos: Option<JsonColumn<Vec<String>>>,
+    average_rating: Option<f64>, // None until someone rates the place
+    rating_count: i64,
+    aliases: JsonColumn<Vec<String>>,
+    created_at: String,
+    updated_at: String, // the last change to the restaurant itself; new ratings and aliases don't count
+    // Only filled in on poll candidates, when the poll's forecast makes this place a poor choice.
+    // sqlx(skip) leaves it out of the row mapping and starts it as None
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weather_warning: Option<String>,
+    // Worked out from the id, active and status above, which sqlx reads a second time for them; see links.rs
+    #[sqlx(flatten)]
+    #[serde(rename = "_links")]
+    links: RestaurantLinks,
+}
+
+// Restaurants suggested by voters start out pending and only become voteable once an admin approves them.
+// sqlx::Type stores the variants as lowercase text, matching the CHECK constraint on the column
+// https://docs.rs/sqlx/latest/sqlx/trait.Type.html
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RestaurantStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+// How listings are ordered. Sorting by rating puts the best-rated places first and unrated ones last
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestaurantOrder {
+    #[default]
+    Name,
+    Rating,
+}
+
+impl RestaurantOrder {
+    // Names sort the way the locale's readers expect; see names::collate
+    pub fn order_by_sql(self, locale: Locale) -> String {
+        let name = format!("r.name COLLATE {}", names::collation(locale));
+        match self {
+            RestaurantOrder::Name => name,
+            RestaurantOrder::Rating => {
+                format!("average_rating IS NULL, average_rating DESC, rating_count DESC, {name}")
+            }
+        }
+    }
+}
+
+// The optional facts about a restaurant. Option maps to a nullable column: None means nobody filled it in yet.
+// Default gives us an all-None value for restaurants that are voted for but were never registered
+#[derive(Clone, Default, Serialize, sqlx::FromRow)]
+pub struct RestaurantDetails {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    location: Location,
+    cuisine: Option<String>,
+    price_tier: Option<i64>,
+    average_cost: Option<f64>,
+    distance_meters: Option<i64>,
+    travel_minutes: Option<i64>,
+    walking_minutes: Option<i64>, // from the routing service; see routing.rs
+    // sqlx's Json wrapper decodes the JSON text column into a Vec, and serializes back out as a plain array
+    // https://docs.rs/sqlx/latest/sqlx/types/struct.Json.html
+    dietary_tags: JsonColumn<Vec<String>>,
+}
+
+impl RestaurantDetails {
+    // How far the restaurant is from the office: the recorded distance if there is one, otherwise worked out
+    // from its coordinates. None when neither is known
+    pub fn distance_from(&self, office: Option<Coordinates>) -> Option<f64> {
+        if let Some(meters) = self.distance_meters {
+            return Some(meters as f64);
+        }
+        Some(geo::distance_meters(office?, self.location.coordinates()?))
+    }
+
+    // The travel time an admin recorded wins, then the routed walking time, then an estimate from the distance
+    pub fn walking_minutes(&self, office: Option<Coordinates>) -> Option<f64> {
+        if let Some(minutes) = self.travel_minutes.or(self.walking_minutes) {
+            return Some(minutes as f64);
+        }
+        Some(self.distance_from(office)? / geo::WALKING_METERS_PER_MINUTE)
+    }
+
+    pub fn cuisine(&self) -> Option<&str> {
+        self.cuisine.as_deref()
+    }
+
+    pub fn price_tier(&self) -> Option<i64> {
+        self.price_tier
+    }
+
+    pub fn average_cost(&self) -> Option<f64> {
+        self.average_cost
+    }
+
+    pub fn dietary_tags(&self) -> &[String] {
+        &self.dietary_tags
+    }
+
+    // Whether the restaurant is inside both limits. A place whose distance or walking time isn't known passes,
+    // since leaving it out would punish restaurants for missing data rather than for being far away
+    pub fn within(&self, office: Option<Coordinates>, max_meters: Option<i64>, max_minutes: Option<i64>) -> bool {
+        let too_far = max_meters
+            .zip(self.distance_from(office))
+            .is_some_and(|(max, meters)| meters > max as f64);
+        let too_slow = max_minutes
+            .zip(self.walking_minutes(office))
+            .is_some_and(|(max, minutes)| minutes > max as f64);
+        !too_far && !too_slow
+    }
+}
+
+// Where a restaurant is. Serialized by hand so the JSON can carry map links worked out from the stored fields
+#[derive(Clone, Default, sqlx::FromRow)]
+pub struct Location {
+    address: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+impl Location {
+    pub fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+        })
+    }
+}
+
+// A Serialize impl written out by hand: serialize_map lets us emit a field that isn't stored anywhere,
+// and it still works under #[serde(flatten)]
+// https://serde.rs/impl-serialize.html
+impl Serialize for Location {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("address", &self.address)?;
+        map.serialize_entry("latitude", &self.latitude)?;
+        map.serialize_entry("longitude", &self.longitude)?;
+        map.serialize_entry("map_links", &geo::map_links(self.coordinates(), self.address.as_deref()))?;
+        map.end()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewRestaurant {
+    // A public id to keep, for a restaurant coming over from another instance; new ones get a fresh UUID
+    id: Option<String>,
+    name: String,
+    // Set to true to save the restaurant even though it looks like one that already exists
+    #[serde(default)]
+    allow_similar: bool,
+    #[serde(flatten)]
+    details: RestaurantUpdate,
+}
+
+// Fields missing from a PATCH body deserialize to None and leave the stored value untouched
+#[derive(Deserialize)]
+pub struct RestaurantUpdate {
+    address: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    cuisine: Option<String>,
+    price_tier: Option<i64>,
+    average_cost: Option<f64>,
+    distance_meters: Option<i64>,
+    travel_minutes: Option<i64>,
+    dietary_tags: Option<Vec<String>>, // replaces the whole tag list when present
+    open_on_holidays: Option<bool>,
+    outdoor_seating: Option<bool>,
+    office_id: Option<String>,
+    notes: Option<String>,
+}
+
+impl RestaurantUpdate {
+    fn validate(&self) -> Result<(), ApiError> {
+        // Half a coordinate pair pins nothing, so the two always travel together
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err(ApiError::BadRequest(
+                        "latitude must be within ±90 and longitude within ±180".to_string(),
+                    ));
+                }
+            }
+            (None, None) => {}
+            _ => return Err(ApiError::BadRequest("latitude and longitude must be given together".to_string())),
+        }
+        if let Some(tier) = self.price_tier {
+            if !(1..=4).contains(&tier) {
+                return Err(ApiError::BadRequest("price_tier must be between 1 and 4".to_string()));
+            }
+        }
+        if self.average_cost.is_some_and(|cost| cost < 0.0)
+            || self.distance_meters.is_some_and(|meters| meters < 0)
+            || self.travel_minutes.is_some_and(|minutes| minutes < 0)
+        {
+            return Err(ApiError::BadRequest(
+                "average_cost, distance_meters and travel_minutes must not be negative".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Cuisine tags are compared in analytics, so "Pizza " and "pizza" should land in the same group
+pub fn normalize_cuisine(cuisine: Option<String>) -> Option<String> {
+    cuisine
+        .map(|c| names::clean(&c).to_lowercase())
+        .filter(|c| !c.is_empty())
+}
+
+// Tags are matched exactly against poll requirements, so "Gluten Free" and "gluten-free" must become the same tag.
+// The result is sorted and free of duplicates
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|tag| names::clean(tag).to_lowercase().replace([' ', '_'], "-"))
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+pub async fn find_by_id(db: &SqlitePool, id: i64) -> Result<Option<Restaurant>, sqlx::Error> {
+    sqlx::query_as::<_, Restaurant>(&format!("{RESTAURANT_SELECT} WHERE r.id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn find_by_name(db: &SqlitePool, name: &str) -> Result<Option<Restaurant>, sqlx::Error> {
+    sqlx::query_as::<_, Restaurant>(&format!("{RESTAURANT_SELECT} WHERE r.name = ?"))
+        .bind(name)
+        .fetch_optional(db)
+        .await
+}
+
+async fn insert_restaurant(
+    state: &AppState,
+    req: NewRestaurant,
+    status: RestaurantStatus,
+    suggested_by: Option<String>,
+) -> Result<Restaurant, ApiError> {
+    let db = &state.db;
+    let name = names::clean(&req.name);
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("restaurant name must not be empty".to_string()));
+    }
+    req.details.validate()?;
+    offices::lookup(db, req.details.office_id.as_deref()).await?;
+    if let Some(id) = &req.id {
+        if !public_ids::is_uuid(id) {
+            return Err(ApiError::BadRequest("id must be a lowercase UUID".to_string()));
+        }
+    }
+
+    // A name that only differs in case or punctuation is the same restaurant, full stop. Merely similar names
+    // are sent back for the caller to confirm with allow_similar, since "Pho 1" and "Pho 2" may well both exist
+    let matches = fuzzy::similar_restaurants(db, &name, state.config.fuzzy_match_threshold).await?;
+    let key = fuzzy::match_key(&name);
+    if let Some(same) = matches.iter().find(|m| fuzzy::match_key(&m.name) == key) {
+        return Err(ApiError::Conflict(format!("restaurant {name} already exists as {}", same.name)));
+    }
+    let trashed: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM restaurants WHERE deleted_at IS NOT NULL").fetch_all(db).await?;
+    if let Some(trashed) = trashed.iter().find(|trashed| fuzzy::match_key(trashed) == key) {
+        return Err(ApiError::Conflict(format!("{trashed} is in the trash; restore it rather than adding it again")));
+    }
+    if let Some(aliased) = alias_owner(db, &key).await? {
+        return Err(ApiError::Conflict(format!("{name} is already an alias of {aliased}")));
+    }
+    if !matches.is_empty() && !req.allow_similar {
+        return Err(ApiError::Ambiguous {
+            message: Message::looks_like(name),
+            matches,
+        });
+    }
+
+    // RETURNING hands back the new row's id without a separate query
+    // https://www.sqlite.org/lang_returning.html
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO restaurants
+            (name, address, latitude, longitude, cuisine, price_tier, average_cost, distance_meters, travel_minutes,
+            dietary_tags, open_on_holidays, outdoor_seating, office_id, notes, status, suggested_by, public_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+    )
+    .bind(&name)
+    .bind(req.details.address.map(|address| names::clean(&address)))
+    .bind(req.details.latitude)
+    .bind(req.details.longitude)
+    .bind(normalize_cuisine(req.details.cuisine))
+    .bind(req.details.price_tier)
+    .bind(req.details.average_cost)
+    .bind(req.details.distance_meters)
+    .bind(req.details.travel_minutes)
+    .bind(JsonColumn(normalize_tags(req.details.dietary_tags.unwrap_or_default())))
+    .bind(req.details.open_on_holidays.unwrap_or(false))
+    .bind(req.details.outdoor_seating.unwrap_or(false))
+    .bind(&req.details.office_id)
+    .bind(req.details.notes.map(|notes| notes.trim().to_string()).filter(|notes| !notes.is_empty()))
+    .bind(status)
+    .bind(suggested_by)
+    .bind(&req.id)
+    .fetch_one(db)
+    .await
+    .map_err(|err| match err {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => match &req.id {
+            Some(id) if db_err.message().contains("public_id") => {
+                ApiError::Conflict(format!("a restaurant with id {id} already exists"))
+            }
+            _ => ApiError::Conflict(format!("restaurant {name} already exists")),
+        },
+        other => ApiError::DbError(other),
+    })?;
+
+    find_by_id(db, id).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))
+}
+
+// What a free-typed restaurant name turned out to refer to
+// Found is boxed because a Restaurant is much larger than the other variants
+pub enum NameResolution {
+    Found(Box<Restaurant>),
+    Similar(Vec<fuzzy::NameMatch>), // no single restaurant, but these look close
+    Unknown,
+}
+
+// Works out which registered restaurant a name typed by a voter refers to: the exact name first, then an alias,
+// then a restaurant whose name is identical once case and punctuation are ignored ("luigi's" for "Luigis")
+pub async fn resolve_name(state: &AppState, name: &str) -> Result<NameResolution, sqlx::Error> {
+    let db = &state.db;
+    if let Some(restaurant) = find_by_name(db, &names::clean(name)).await? {
+        return Ok(NameResolution::Found(Box::new(restaurant)));
+    }
+
+    let key = fuzzy::match_key(name);
+    let aliased: Option<i64> = sqlx::query_scalar("SELECT restaurant_id FROM restaurant_aliases WHERE alias_key = ?")
+        .bind(&key)
+        .fetch_optional(db)
+        .await?;
+    if let Some(id) = aliased {
+        if let Some(restaurant) = find_by_id(db, id).await? {
+            return Ok(NameResolution::Found(Box::new(restaurant)));
+        }
+    }
+
+    let matches = fuzzy::similar_restaurants(db, name, state.config.fuzzy_match_threshold).await?;
+    if let Some(same) = matches.iter().find(|m| fuzzy::match_key(&m.name) == key) {
+        if let Some(restaurant) = find_by_id(db, same.id).await? {
+            return Ok(NameResolution::Found(Box::new(restaurant)));
+        }
+    }
+    if matches.is_empty() {
+        Ok(NameResolution::Unknown)
+    } else {
+        Ok(NameResolution::Similar(matches))
+    }
+}
+
+// POST /restaurants (admin): registers a restaurant that is immediately voteable
+pub async fn create_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Json(req): Json<NewRestaurant>,
+) -> Result<(StatusCode, Json<Restaurant>), ApiError> {
+    let restaurant = insert_restaurant(&state, req, RestaurantStatus::Approved, None).await?;
+    Ok((StatusCode::CREATED, Json(restaurant)))
+}
+
+#[derive(Deserialize)]
+pub struct NewSuggestion {
+    suggested_by: String,
+    #[serde(flatten)]
+    restaurant: NewRestaurant,
+}
+
+// POST /restaurants/suggestions: anyone can propose a restaurant; it waits as pending until an admin reviews it
+pub async fn suggest_restaurant(
+    State(state): State<AppState>,
+    Json(req): Json<NewSuggestion>,
+) -> Result<(StatusCode, Json<Restaurant>), ApiError> {
+    let suggested_by = names::clean(&req.suggested_by);
+    if suggested_by.is_empty() {
+        return Err(ApiError::BadRequest("suggested_by must not be empty".to_string()));
+    }
+    moderation::screen(&state, NameKind::Voter, &suggested_by).await?;
+    moderation::screen(&state, NameKind::Restaurant, &names::clean(&req.restaurant.name)).await?;
+    let restaurant = insert_restaurant(&state, req.restaurant, RestaurantStatus::Pending, Some(suggested_by)).await?;
+    Ok((StatusCode::CREATED, Json(restaurant)))
+}
+
+// GET /restaurants/suggestions (admin): the review queue, oldest first
+pub async fn list_suggestions(_admin: Admin, State(state): State<AppState>) -> Result<Json<Vec<Restaurant>>, ApiError> {
+    let suggestions = sqlx::query_as::<_, Restaurant>(&format!("{RESTAURANT_SELECT} WHERE r.status = ? ORDER BY r.id"))
+        .bind(RestaurantStatus::Pending)
+        .fetch_all(&state.db)
+        .await?;
+    Ok(Json(suggestions))
+}
+
+#[derive(Deserialize)]
+pub struct Review {
+    note: Option<String>,
+}
+
+// POST /restaurants/:id/approve (admin)
+pub async fn approve_suggestion(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    body: Option<Json<Review>>,
+) -> Result<Json<Restaurant>, ApiError> {
+    review(&state.db, id, RestaurantStatus::Approved, body.and_then(|Json(body)| body.note)).await
+}
+
+// POST /restaurants/:id/reject (admin)
+pub async fn reject_suggestion(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    body: Option<Json<Review>>,
+) -> Result<Json<Restaurant>, ApiError> {
+    review(&state.db, id, RestaurantStatus::Rejected, body.and_then(|Json(body)| body.note)).await
+}
+
+// Only pending suggestions can be reviewed; the status check in the WHERE clause makes that atomic
+async fn review(
+    db: &SqlitePool,
+    id: i64,
+    outcome: RestaurantStatus,
+    note: Option<String>,
+) -> Result<Json<Restaurant>, ApiError> {
+    let note = note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    let updated = sqlx::query("UPDATE restaurants SET status = ?, review_note = ? WHERE id = ? AND status = ?")
+        .bind(outcome)
+        .bind(note)
+        .bind(id)
+        .bind(RestaurantStatus::Pending)
+        .execute(db)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return match find_by_id(db, id).await? {
+            Some(_) => Err(ApiError::Conflict(format!("restaurant {id} is not awaiting review"))),
+            None => Err(Message::no_restaurant(id).into()),
+        };
+    }
+    let restaurant = find_by_id(db, id).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))?;
+    Ok(Json(restaurant))
+}
+
+#[derive(Deserialize)]
+pub struct ListRestaurantsQuery {
+    #[serde(default)]
+    sort: RestaurantOrder,
+    #[serde(default)]
+    include_inactive: bool,
+    office: Option<String>, // an office's id
+    team_id: Option<String>, // sorts the names for this team's locale
+}
+
+// GET /restaurants?sort=name|rating&include_inactive=true&office=berlin&team_id=platform
+// Only approved restaurants are listed; pending suggestions live in the review queue. With an office, only the ones
+// its polls can pick from: its own and those without an office. Names sort for the team's locale, or else the one
+// Accept-Language asks for
+pub async fn list_restaurants(
+    State(state): State<AppState>,
+    Query(query): Query<ListRestaurantsQuery>,
+) -> Result<Json<Vec<Restaurant>>, ApiError> {
+    teams::lookup(&state.db, query.team_id.as_deref()).await?;
+    let locale = i18n::sorting_locale(&state.db, query.team_id.as_deref()).await?;
+    let restaurants = sqlx::query_as::<_, Restaurant>(&format!(
+        "{RESTAURANT_SELECT} WHERE r.status = ? AND (r.active OR ?) AND {OFFICE_POOL_SQL} ORDER BY {}",
+        query.sort.order_by_sql(locale)
+    ))
+    .bind(RestaurantStatus::Approved)
+    .bind(query.include_inactive)
+    .bind(&query.office)
+    .bind(&query.office)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(restaurants))
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    #[serde(default = "default_search_limit")]
+    limit: i64,
+}
+
+fn default_search_limit() -> i64 {
+    20
+}
+
+// GET /restaurants/search?q=noodles near the station&limit=20: approved, active restaurants whose name, aliases,
+// cuisine, notes or address have the words in them, best match first. FTS5's bm25 ranks rarer words and shorter
+// fields higher, and the weights below count a word in the name most, then in an alias, and so on down the columns
+// https://www.sqlite.org/fts5.html#the_bm25_function
+pub async fn search_restaurants(
+    State(state): State<AppState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<Restaurant>>, ApiError> {
+    if !(1..=100).contains(&query.limit) {
+        return Err(ApiError::BadRequest("limit must be between 1 and 100".to_string()));
+    }
+    let words = match_words(&query.q);
+    if words.is_empty() {
+        return Err(ApiError::BadRequest("q needs a word to search for".to_string()));
+    }
+    let restaurants = sqlx::query_as::<_, Restaurant>(&format!(
+        "{RESTAURANT_SELECT}
+        JOIN restaurant_search ON restaurant_search.rowid = r.id
+        WHERE restaurant_search MATCH ? AND r.status = ? AND r.active
+        ORDER BY bm25(restaurant_search, 10.0, 8.0, 4.0, 2.0, 1.0)
+        LIMIT ?"
+    ))
+    .bind(words)
+    .bind(RestaurantStatus::Approved)
+    .bind(query.limit)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(restaurants))
+}
+
+// The words of a search as an FTS5 query. Each is quoted, so nothing typed is taken for FTS5's own syntax, and those
+// of three letters or more match as the start of a word too, "nood" finding "noodles". They're joined with OR rather
+// than all being required, since much of what people type, "that" or "place", is in no restaurant at all; the ones
+// matching the most words still come first
+// https://www.sqlite.org/fts5.html#full_text_query_syntax
+fn match_words(q: &str) -> String {
+    q.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| match word.chars().count() >= 3 {
+            true => format!("\"{word}\"*"),
+            false => format!("\"{word}\""),
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+#[derive(Deserialize)]
+pub struct RandomQuery {
+    cuisine: Option<String>,
+    max_distance_meters: Option<i64>,
+    max_walking_minutes: Option<i64>,
+}
+
+// GET /restaurants/random?cuisine=thai&max_distance_meters=800&max_walking_minutes=10
+// For the days nobody can be bothered to run a poll: one random approved, active restaurant
+pub async fn random_restaurant(
+    State(state): State<AppState>,
+    Query(query): Query<RandomQuery>,
+) -> Result<Json<Restaurant>, ApiError> {
+    // SQLite shuffles the candidates with ORDER BY RANDOM(); the distance limits can only be checked here,
+    // so the first shuffled restaurant that passes them is the pick
+    // https://www.sqlite.org/lang_corefunc.html#random
+    let cuisine = normalize_cuisine(query.cuisine);
+    let shuffled = sqlx::query_as::<_, Restaurant>(&format!(
+        "{RESTAURANT_SELECT} WHERE r.status = ? AND r.active AND (? IS NULL OR r.cuisine = ?) ORDER BY RANDOM()"
+    ))
+    .bind(RestaurantStatus::Approved)
+    .bind(&cuisine)
+    .bind(&cuisine)
+    .fetch_all(&state.db)
+    .await?;
+    let office = state.config.office_location;
+    shuffled
+        .into_iter()
+        .find(|restaurant| {
+            restaurant
+                .details
+                .within(office, query.max_distance_meters, query.max_walking_minutes)
+        })
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("no restaurant matches those filters".to_string()))
+}
+
+// GET /restaurants/:id
+// RestaurantId pulls the :id segment out of the URL and looks up the restaurant it names
+pub async fn get_restaurant(
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+) -> Result<Json<Restaurant>, ApiError> {
+    find_by_id(&state.db, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::from(Message::no_restaurant(id)))
+}
+
+// PATCH /restaurants/:id (admin)
+pub async fn update_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    Json(req): Json<RestaurantUpdate>,
+) -> Result<Json<Restaurant>, ApiError> {
+    req.validate()?;
+    offices::lookup(&state.db, req.office_id.as_deref()).await?;
+
+    // COALESCE keeps the current value whenever the corresponding bind is NULL, i.e. the field was left out
+    let updated = sqlx::query(
+        "UPDATE restaurants SET
+            address = COALESCE(?, address),
+            latitude = COALESCE(?, latitude),
+            longitude = COALESCE(?, longitude),
+            cuisine = COALESCE(?, cuisine),
+            price_tier = COALESCE(?, price_tier),
+            average_cost = COALESCE(?, average_cost),
+            distance_meters = COALESCE(?, distance_meters),
+            travel_minutes = COALESCE(?, travel_minutes),
+            dietary_tags = COALESCE(?, dietary_tags),
+            open_on_holidays = COALESCE(?, open_on_holidays),
+            outdoor_seating = COALESCE(?, outdoor_seating),
+            office_id = COALESCE(?, office_id),
+            notes = COALESCE(?, notes)
+        WHERE id = ?",
+    )
+    .bind(req.address.map(|address| names::clean(&address)))
+    .bind(req.latitude)
+    .bind(req.longitude)
+    .bind(normalize_cuisine(req.cuisine))
+    .bind(req.price_tier)
+    .bind(req.average_cost)
+    .bind(req.distance_meters)
+    .bind(req.travel_minutes)
+    .bind(req.dietary_tags.map(|tags| JsonColumn(normalize_tags(tags))))
+    .bind(req.open_on_holidays)
+    .bind(req.outdoor_seating)
+    .bind(req.office_id)
+    // Empty notes clear them, where a missing field would leave them be
+    .bind(req.notes.map(|notes| notes.trim().to_string()))
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Message::no_restaurant(id).into());
+    }
+
+    let restaurant = find_by_id(&state.db, id).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))?;
+    Ok(Json(restaurant))
+}
+
+#[derive(Deserialize)]
+pub struct Deactivation {
+    reason: Option<String>,
+}
+
+// POST /restaurants/:id/deactivate (admin)
+// Option<Json<..>> makes the body optional, so a bare POST works when there's no reason worth recording
+pub async fn deactivate_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    body: Option<Json<Deactivation>>,
+) -> Result<Json<Restaurant>, ApiError> {
+    let reason = body
+        .and_then(|Json(body)| body.reason)
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    set_active(&state.db, id, false, reason).await
+}
+
+// POST /restaurants/:id/reactivate (admin)
+pub async fn reactivate_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+) -> Result<Json<Restaurant>, ApiError> {
+    set_active(&state.db, id, true, None).await
+}
+
+async fn set_active(
+    db: &SqlitePool,
+    id: i64,
+    active: bool,
+    reason: Option<String>,
+) -> Result<Json<Restaurant>, ApiError> {
+    let updated = sqlx::query("UPDATE restaurants SET active = ?, inactive_reason = ? WHERE id = ?")
+        .bind(active)
+        .bind(reason)
+        .bind(id)
+        .execute(db)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Message::no_restaurant(id).into());
+    }
+    let restaurant = find_by_id(db, id).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))?;
+    Ok(Json(restaurant))
+}
+
+// DELETE /restaurants/:id (admin): moves the restaurant to the trash, off every list and ballot until it's restored.
+// Votes already cast for it stop counting, the way they do for a restaurant ruled unavailable, and it can't get more
+pub async fn delete_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query("UPDATE restaurants SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Rating {
+    id: i64,
+    restaurant_id: String, // the restaurant's public id
+    rater_name: String,
+    score: i64,
+    comment: Option<String>,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewRating {
+    rater_name: String,
+    score: i64,
+    comment: Option<String>,
+}
+
+const MAX_RATING_COMMENT_CHARS: usize = 500;
+
+// POST /restaurants/:id/ratings
+pub async fn rate_restaurant(
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    Json(req): Json<NewRating>,
+) -> Result<(StatusCode, Json<Rating>), ApiError> {
+    if !(1..=5).contains(&req.score) {
+        return Err(ApiError::BadRequest("score must be between 1 and 5".to_string()));
+    }
+    let rater_name = names::clean(&req.rater_name);
+    if rater_name.is_empty() {
+        return Err(ApiError::BadRequest("rater_name must not be empty".to_string()));
+    }
+    moderation::screen(&state, NameKind::Voter, &rater_name).await?;
+    let comment = req.comment.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    // chars() counts Unicode characters rather than bytes, so an emoji-heavy review isn't cut short
+    if comment.as_ref().is_some_and(|c| c.chars().count() > MAX_RATING_COMMENT_CHARS) {
+        return Err(ApiError::BadRequest(format!(
+            "comment must be at most {MAX_RATING_COMMENT_CHARS} characters"
+        )));
+    }
+    if find_by_id(&state.db, id).await?.is_none() {
+        return Err(Message::no_restaurant(id).into());
+    }
+
+    let rating = sqlx::query_as::<_, Rating>(
+        "INSERT INTO ratings (restaurant_id, rater_name, score, comment) VALUES (?, ?, ?, ?)
+        RETURNING id, (SELECT public_id FROM restaurants WHERE id = restaurant_id) AS restaurant_id, rater_name, score,
+            comment, created_at",
+    )
+    .bind(id)
+    .bind(rater_name)
+    .bind(req.score)
+    .bind(comment)
+    .fetch_one(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(rating)))
+}
+
+async fn alias_owner(db: &SqlitePool, key: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT r.name FROM restaurant_aliases a JOIN restaurants r ON r.id = a.restaurant_id AND r.deleted_at IS NULL
+        WHERE a.alias_key = ?",
+    )
+    .bind(key)
+    .fetch_optional(db)
+    .await
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Alias {
+    id: i64,
+    restaurant_id: String, // the restaurant's public id
+    alias: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewAlias {
+    alias: String,
+}
+
+// GET /restaurants/:id/aliases
+pub async fn list_aliases(
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+) -> Result<Json<Vec<Alias>>, ApiError> {
+    let aliases = sqlx::query_as::<_, Alias>(
+        "SELECT a.id, r.public_id AS restaurant_id, a.alias
+        FROM restaurant_aliases a JOIN restaurants r ON r.id = a.restaurant_id AND r.deleted_at IS NULL
+        WHERE a.restaurant_id = ?
+        ORDER BY a.alias",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(aliases))
+}
+
+// POST /restaurants/:id/aliases (admin)
+pub async fn add_alias(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    Json(req): Json<NewAlias>,
+) -> Result<(StatusCode, Json<Alias>), ApiError> {
+    let alias = names::clean(&req.alias);
+    let key = fuzzy::match_key(&alias);
+    if key.is_empty() {
+        return Err(ApiError::BadRequest("alias must contain letters or digits".to_string()));
+    }
+    if find_by_id(&state.db, id).await?.is_none() {
+        return Err(Message::no_restaurant(id).into());
+    }
+    // An alias that reads the same as another restaurant's real name would make votes for that restaurant ambiguous
+    let names: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM restaurants WHERE deleted_at IS NULL").fetch_all(&state.db).await?;
+    if let Some(existing) = names.iter().find(|name| fuzzy::match_key(name) == key) {
+        return Err(ApiError::Conflict(format!("{alias} is already the name of {existing}")));
+    }
+
+    let created = sqlx::query_as::<_, Alias>(
+        "INSERT INTO restaurant_aliases (restaurant_id, alias, alias_key) VALUES (?, ?, ?)
+        RETURNING id, (SELECT public_id FROM restaurants WHERE id = restaurant_id) AS restaurant_id, alias",
+    )
+    .bind(id)
+    .bind(&alias)
+    .bind(&key)
+    .fetch_one(&state.db)
+    .await;
+    match created {
+        Ok(created) => Ok((StatusCode::CREATED, Json(created))),
+        Err(sqlx::Error::Database(ref db_err)) if db_err.is_unique_violation() => {
+            let owner = alias_owner(&state.db, &key).await?.unwrap_or_default();
+            Err(ApiError::Conflict(format!("{alias} is already an alias of {owner}")))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+// DELETE /restaurants/:id/aliases/:alias_id (admin)
+pub async fn delete_alias(
+    _admin: Admin,
+    State(state): State<AppState>,
+    RestaurantId(id): RestaurantId,
+    Path((_, alias_id)): Path<(String, i64)>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM restaurant_aliases WHERE id = ? AND restaurant_id = ?")
+        .bind(alias_id)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("restaurant {id} has no alias with id {alias_id}")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,241 @@
+// Keeping data no longer than needed. Once VOTE_RETENTION_DAYS have passed, a vote's voter name is replaced by an
+// opaque token. The token comes from an HMAC of the folded name, so one person's old votes all get the same one and
+// statistics that count voters still add up, but nobody without ANONYMIZATION_KEY can tell whose it is. Only votes
+// in finished polls (or in none) are touched, so a live tally never sees a voter split in two.
+// Once PRUNE_AFTER_DAYS have passed, closed polls and loose votes are deleted outright, so the database stops growing
+// https://docs.rs/ring/latest/ring/hmac/index.html
+use axum::extract::{Query, State};
+use axum::Json;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqliteConnection;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::{names, receipts, AppState};
+
+// Starts the hourly retention jobs that are configured. Like the other jobs, they run once at startup too
+pub fn spawn(state: AppState) {
+    if state.config.vote_retention_days.is_none() && state.config.prune_after_days.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if !state.lease.held(&state.config).await {
+                continue;
+            }
+            if let Some(days) = state.config.vote_retention_days {
+                match anonymize_old_votes(&state, days).await {
+                    Ok(0) => {}
+                    Ok(count) => println!("retention: anonymized {count} votes older than {days} days"),
+                    Err(err) => eprintln!("retention: could not anonymize old votes: {err:?}"),
+                }
+            }
+            if let Some(days) = state.config.prune_after_days {
+                match prune(&state, days, state.config.prune_dry_run).await {
+                    Ok(run) => println!(
+                        "retention: {} {} rows older than {days} days",
+                        if run.dry_run { "would prune" } else { "pruned" },
+                        run.removed.values().sum::<u64>()
+                    ),
+                    Err(err) => eprintln!("retention: could not prune: {err:?}"),
+                }
+            }
+        }
+    });
+}
+
+// The same voter always gets the same token, however their name was spelled
+fn token(state: &AppState, voter_name: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.anonymization_key);
+    let tag = hmac::sign(&key, names::fold(voter_name).as_bytes());
+    format!("anon-{}", &receipts::hex(tag.as_ref())[..12])
+}
+
+// Swaps the names on old votes for tokens, and on everything that repeats them: the events the votes are projected
+// from, their quadratic credits and their receipts. One transaction, so it's all or nothing
+async fn anonymize_old_votes(state: &AppState, days: i64) -> Result<u64, sqlx::Error> {
+    let mut tx = state.db.begin().await?;
+    let (cutoff, now): (String, String) = sqlx::query_as("SELECT datetime('now', ?), CURRENT_TIMESTAMP")
+        .bind(format!("-{days} days"))
+        .fetch_one(&mut *tx)
+        .await?;
+    let votes: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, voter_name FROM votes
+        WHERE anonymized_at IS NULL AND created_at < ?
+        AND (poll_id IS NULL OR poll_id IN (SELECT id FROM polls WHERE status = 'closed'))",
+    )
+    .bind(&cutoff)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut tokens = BTreeMap::new();
+    for (id, voter_name) in &votes {
+        let token = tokens.entry(voter_name.clone()).or_insert_with(|| token(state, voter_name));
+        sqlx::query("UPDATE votes SET voter_name = ?, anonymized_at = ? WHERE id = ?")
+            .bind(&*token)
+            .bind(&now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        // Rewriting the cast, and any correction that moved the vote to this voter, keeps a replay from bringing
+        // the name back; like erasure, it's an edit the stream otherwise never takes
+        sqlx::query(
+            "UPDATE vote_events SET payload = json_set(payload, '$.voter_name', ?, '$.anonymized_at', ?)
+            WHERE vote_id = ? AND type = 'cast'",
+        )
+        .bind(&*token)
+        .bind(&now)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE vote_events SET payload = json_set(payload, '$.voter_name', ?)
+            WHERE vote_id = ? AND type = 'changed' AND json_extract(payload, '$.voter_name') IS NOT NULL",
+        )
+        .bind(&*token)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE vote_credits SET voter_name = ?, voter_key = ? WHERE vote_id = ?")
+            .bind(&*token)
+            .bind(names::fold(token))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    // Receipts say who cast them too; those from before the cutoff by the voters just anonymized go the same way
+    receipts::rename_voters(&mut tx, |voter_name, cast_at| {
+        tokens.get(voter_name).filter(|_| cast_at < cutoff.as_str()).cloned()
+    })
+    .await?;
+    tx.commit().await?;
+    Ok(votes.len() as u64)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PruneRun {
+    id: i64,
+    dry_run: bool,
+    horizon_days: i64,
+    removed: JsonColumn<BTreeMap<String, u64>>, // rows taken, or that would have been, by table
+    created_at: String,
+}
+
+// Deletes closed polls created before the horizon, with everything that hangs off them, and votes from before it
+// that were in no poll; a dry run counts the same rows and rolls back. Every run is recorded in prune_runs.
+// Receipts stay, since the hash chain can't lose links without breaking, and so does the audit log
+async fn prune(state: &AppState, days: i64, dry_run: bool) -> Result<PruneRun, sqlx::Error> {
+    let mut tx = state.db.begin().await?;
+    let removed = delete_before(&mut tx, days).await?;
+    match dry_run {
+        true => tx.rollback().await?,
+        false => tx.commit().await?,
+    }
+    sqlx::query_as(
+        "INSERT INTO prune_runs (dry_run, horizon_days, removed) VALUES (?, ?, ?)
+        RETURNING id, dry_run, horizon_days, removed, created_at",
+    )
+    .bind(dry_run)
+    .bind(days)
+    .bind(JsonColumn(&removed))
+    .fetch_one(&state.db)
+    .await
+}
+
+async fn delete_before(conn: &mut SqliteConnection, days: i64) -> Result<BTreeMap<String, u64>, sqlx::Error> {
+    let cutoff: String =
+        sqlx::query_scalar("SELECT datetime('now', ?)").bind(format!("-{days} days")).fetch_one(&mut *conn).await?;
+    let polls: Vec<i64> = sqlx::query_scalar("SELECT id FROM polls WHERE status = 'closed' AND created_at < ?")
+        .bind(&cutoff)
+        .fetch_all(&mut *conn)
+        .await?;
+    let votes: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM votes
+        WHERE poll_id IN (SELECT value FROM json_each(?)) OR (poll_id IS NULL AND created_at < ?)",
+    )
+    .bind(JsonColumn(&polls))
+    .bind(&cutoff)
+    .fetch_all(&mut *conn)
+    .await?;
+    // A retracted vote has no row left, only its events, which go once the retraction is old enough
+    let mut event_votes: Vec<i64> =
+        sqlx::query_scalar("SELECT vote_id FROM vote_events WHERE type = 'retracted' AND created_at < ?")
+            .bind(&cutoff)
+            .fetch_all(&mut *conn)
+            .await?;
+    event_votes.extend(&votes);
+
+    // Children before parents, so no foreign key is left pointing at a deleted row. The table and column names are
+    // our own, never the client's, so formatting them into the SQL is safe
+    let mut removed = BTreeMap::new();
+    for (table, column, ids) in [
+        ("vote_credits", "vote_id", &votes),
+        ("vote_events", "vote_id", &event_votes),
+        ("votes", "id", &votes),
+        ("nominations", "poll_id", &polls),
+        ("poll_unavailable", "poll_id", &polls),
+        ("abstentions", "poll_id", &polls),
+        ("reactions", "poll_id", &polls),
+        ("poll_invitations", "poll_id", &polls),
+        ("rsvps", "poll_id", &polls),
+        ("bill_entries", "poll_id", &polls),
+        ("bill_payments", "poll_id", &polls),
+        ("poll_orders", "poll_id", &polls),
+        ("poll_reminders", "poll_id", &polls),
+        ("vote_links", "poll_id", &polls),
+        ("sealing_keys", "poll_id", &polls),
+    ] {
+        let sql = format!("DELETE FROM {table} WHERE {column} IN (SELECT value FROM json_each(?))");
+        let deleted = sqlx::query(&sql).bind(JsonColumn(ids)).execute(&mut *conn).await?;
+        removed.insert(table.to_string(), deleted.rows_affected());
+    }
+    // A runoff can outlive the poll it settled, or the other way round; the survivor just loses the link
+    for column in ["runoff_poll_id", "runoff_of"] {
+        let sql = format!("UPDATE polls SET {column} = NULL WHERE {column} IN (SELECT value FROM json_each(?))");
+        sqlx::query(&sql).bind(JsonColumn(&polls)).execute(&mut *conn).await?;
+    }
+    let deleted = sqlx::query("DELETE FROM polls WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(JsonColumn(&polls))
+        .execute(&mut *conn)
+        .await?;
+    removed.insert("polls".to_string(), deleted.rows_affected());
+    Ok(removed)
+}
+
+#[derive(Deserialize)]
+pub struct PruneQuery {
+    #[serde(default)]
+    dry_run: bool,
+    days: Option<i64>, // the horizon, when it isn't PRUNE_AFTER_DAYS
+}
+
+// POST /retention/prune?dry_run=true&days=90 (admin): prunes now rather than at the next hourly run
+pub async fn prune_now(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneRun>, ApiError> {
+    let days = query.days.or(state.config.prune_after_days).ok_or_else(|| {
+        ApiError::NotConfigured("give ?days= or set PRUNE_AFTER_DAYS to say what counts as old".to_string())
+    })?;
+    if days < 1 {
+        return Err(ApiError::BadRequest("days must be at least 1".to_string()));
+    }
+    Ok(Json(prune(&state, days, query.dry_run).await?))
+}
+
+// GET /retention/prunes (admin): the last 100 runs, newest first
+pub async fn list_prunes(_admin: Admin, State(state): State<AppState>) -> Result<Json<Vec<PruneRun>>, ApiError> {
+    let runs = sqlx::query_as::<_, PruneRun>(
+        "SELECT id, dry_run, horizon_days, removed, created_at FROM prune_runs ORDER BY id DESC LIMIT 100",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(runs))
+}
@@ -0,0 +1,202 @@
+// Rotation polls: no vote, just one person picking lunch for everyone, taking turns. The rotation is an ordered list
+// of voters, a team's for the team's polls and the instance's for the rest, set with PUT /rotation. A rotation
+// poll's picker is whoever in the rotation has gone longest without a turn, those who never had one first in
+// rotation order, skipping anyone who isn't among the poll's attendees or is away that day (see away.rs). A turn is
+// being a poll's picker, so when the picker says they're not coming (or an admin skips them) and the poll passes to
+// someone else, they're first in line next time. The pick is an ordinary ballot, the only one the poll takes, so it
+// shows in the results, the receipts and the stats like any other poll's winner
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::polls::{self, Poll};
+use crate::public_ids::PollId;
+use crate::{away, names, teams, AppState};
+
+#[derive(Clone, Serialize)]
+pub struct Turn {
+    voter_name: String,
+    last_turn: Option<String>, // the lunch_at of the last poll they were picker for
+}
+
+#[derive(Serialize)]
+pub struct Rotation {
+    team_id: Option<String>,
+    voters: Vec<Turn>, // in rotation order
+    next: Option<String>, // who a new poll would go to, if everyone is around
+}
+
+#[derive(Deserialize)]
+pub struct RotationQuery {
+    team_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewRotation {
+    voters: Vec<String>,
+}
+
+// The rotation with everyone's last turn, leaving out the turn they have in the poll `except`
+async fn turns(db: &SqlitePool, team_id: Option<&str>, except: Option<i64>) -> Result<Vec<Turn>, sqlx::Error> {
+    let voters: Vec<String> =
+        sqlx::query_scalar("SELECT voter_name FROM rotations WHERE team_id = ? ORDER BY position")
+            .bind(team_id.unwrap_or_default())
+            .fetch_all(db)
+            .await?;
+    let picked: Vec<(String, String)> = sqlx::query_as(
+        "SELECT picker, lunch_at FROM polls
+        WHERE voting_method = 'rotation' AND picker IS NOT NULL AND team_id IS ? AND id IS NOT ?",
+    )
+    .bind(team_id)
+    .bind(except)
+    .fetch_all(db)
+    .await?;
+    Ok(voters
+        .into_iter()
+        .map(|voter_name| {
+            let key = names::fold(&voter_name);
+            let last_turn = picked.iter().filter(|(picker, _)| names::fold(picker) == key).map(|(_, at)| at).max();
+            Turn { last_turn: last_turn.cloned(), voter_name }
+        })
+        .collect())
+}
+
+// Whose turn it is among those `available` says can pick. min_by_key keeps the first of equals, and None sorts
+// before any time, so it's rotation order among those who haven't had a turn
+fn next(turns: Vec<Turn>, available: impl Fn(&str) -> bool) -> Option<String> {
+    turns
+        .into_iter()
+        .filter(|turn| available(&turn.voter_name))
+        .min_by_key(|turn| turn.last_turn.clone())
+        .map(|turn| turn.voter_name)
+}
+
+fn attending<'a>(attendees: &'a [String]) -> impl Fn(&str) -> bool + 'a {
+    move |voter: &str| attendees.is_empty() || attendees.iter().any(|name| names::fold(name) == names::fold(voter))
+}
+
+// The picker for a new rotation poll for lunch at `lunch_at`
+pub async fn picker_for(
+    db: &SqlitePool,
+    team_id: Option<&str>,
+    attendees: &[String],
+    lunch_at: &str,
+) -> Result<String, ApiError> {
+    let turns = turns(db, team_id, None).await?;
+    if turns.is_empty() {
+        return Err(ApiError::BadRequest("there's nobody in the rotation yet; set it with PUT /rotation".to_string()));
+    }
+    let away = away::away_on(db, lunch_at).await?;
+    let attendees = attending(attendees);
+    next(turns, |voter| attendees(voter) && !away.contains(&names::fold(voter))).ok_or_else(|| {
+        ApiError::BadRequest("nobody in the rotation is among the attendees and around that day".to_string())
+    })
+}
+
+// Passes the poll to the next in the rotation, skipping the picker and whoever is away or has said they're not
+// coming. Not once the pick is in. Returns the new picker, None when there's nobody left to ask
+pub async fn pass_on(db: &SqlitePool, poll: &Poll) -> Result<Option<String>, ApiError> {
+    let Some(picker) = &poll.picker else {
+        return Ok(None);
+    };
+    let picked: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM votes WHERE poll_id = ? AND deleted_at IS NULL)")
+            .bind(poll.id)
+            .fetch_one(db)
+            .await?;
+    if picked {
+        return Err(ApiError::Conflict(format!("{picker} has already picked")));
+    }
+    let mut away: Vec<String> = sqlx::query_scalar("SELECT voter_key FROM rsvps WHERE poll_id = ? AND NOT coming")
+        .bind(poll.id)
+        .fetch_all(db)
+        .await?;
+    away.extend(away::away_on(db, &poll.lunch_at).await?);
+    let attendees = attending(&poll.attendees);
+    let available = |voter: &str| {
+        let key = names::fold(voter);
+        key != names::fold(picker) && !away.contains(&key) && attendees(voter)
+    };
+    let next = next(turns(db, poll.team_id.as_deref(), Some(poll.id)).await?, available);
+    sqlx::query("UPDATE polls SET picker = ? WHERE id = ?").bind(&next).bind(poll.id).execute(db).await?;
+    match &next {
+        Some(next) => println!("poll {}: {picker} passed the pick to {next}", poll.id),
+        None => println!("poll {}: {picker} passed the pick, but there's nobody left in the rotation", poll.id),
+    }
+    Ok(next)
+}
+
+// GET /rotation?team_id=platform: the order turns go in, everyone's last turn and who's next. Without team_id,
+// the rotation for polls without a team
+pub async fn get_rotation(
+    State(state): State<AppState>,
+    Query(query): Query<RotationQuery>,
+) -> Result<Json<Rotation>, ApiError> {
+    teams::lookup(&state.db, query.team_id.as_deref()).await?;
+    let turns = turns(&state.db, query.team_id.as_deref(), None).await?;
+    let next = next(turns.clone(), |_| true);
+    Ok(Json(Rotation { team_id: query.team_id, voters: turns, next }))
+}
+
+// PUT /rotation?team_id=platform (admin): {"voters": ["Zoë", "Sam", "Ann"]} replaces the rotation. A team's can
+// only have its members. Turns already taken still count
+pub async fn put_rotation(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<RotationQuery>,
+    Json(req): Json<NewRotation>,
+) -> Result<Json<Rotation>, ApiError> {
+    let team = teams::lookup(&state.db, query.team_id.as_deref()).await?;
+    let mut voters: Vec<String> = Vec::new();
+    for voter in &req.voters {
+        let voter = names::canonical_voter_name(&state.db, voter).await?;
+        if voter.is_empty() {
+            return Err(ApiError::BadRequest("voter names must not be empty".to_string()));
+        }
+        if let Some(team) = &team {
+            if !teams::is_member(&state.db, &team.id, &voter).await? {
+                return Err(Message::not_on_team(voter, &team.id).with_status(StatusCode::BAD_REQUEST).into());
+            }
+        }
+        if !voters.iter().any(|known| names::fold(known) == names::fold(&voter)) {
+            voters.push(voter);
+        }
+    }
+    let team_id = query.team_id.clone().unwrap_or_default();
+    let mut tx = state.db.begin().await?;
+    sqlx::query("DELETE FROM rotations WHERE team_id = ?").bind(&team_id).execute(&mut *tx).await?;
+    for (position, voter) in voters.iter().enumerate() {
+        sqlx::query("INSERT INTO rotations (team_id, position, voter_key, voter_name) VALUES (?, ?, ?, ?)")
+            .bind(&team_id)
+            .bind(position as i64)
+            .bind(names::fold(voter))
+            .bind(voter)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    get_rotation(State(state), Query(query)).await
+}
+
+// POST /polls/:id/picker/skip (admin): the picker can't make it, so the poll goes to the next in the rotation
+pub async fn skip_picker(
+    _admin: Admin,
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+) -> Result<Json<Poll>, ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.voting_method != polls::VotingMethod::Rotation {
+        return Err(ApiError::Conflict("only a rotation poll has a picker".to_string()));
+    }
+    if poll.status == polls::PollStatus::Closed {
+        return Err(Message::poll_closed().into());
+    }
+    pass_on(&state.db, &poll).await?;
+    Ok(Json(polls::find_poll(&state.db, id).await?.ok_or(sqlx::Error::RowNotFound)?))
+}
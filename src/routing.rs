@@ -0,0 +1,151 @@
+// Walking times from the office to each restaurant, from an OSRM routing service. A restaurant of an office (see
+// offices.rs) is routed from that office, any other from OFFICE_LATITUDE and OFFICE_LONGITUDE. The results are cached
+// on the restaurant and only looked up again when the office or the restaurant moves
+// https://project-osrm.org/docs/v5.24.0/api/#route-service
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::geo::Coordinates;
+use crate::{offices, AppState};
+
+#[derive(Deserialize)]
+struct RouteResponse {
+    code: String,
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+#[derive(Deserialize)]
+struct Route {
+    duration: f64, // seconds
+}
+
+#[derive(Default, Serialize)]
+pub struct RoutingSummary {
+    updated: Vec<String>,
+    failed: Vec<String>,
+}
+
+// A restaurant with coordinates, and the office it's routed from when that office has coordinates too
+#[derive(sqlx::FromRow)]
+struct Pending {
+    id: i64,
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    office_id: Option<String>,
+    office_latitude: Option<f64>,
+    office_longitude: Option<f64>,
+    walking_route: Option<String>,
+    // The restaurant's half of its route key, ";lat,lon"; the office half is 'lat,lon' formatted in Rust
+    destination: String,
+}
+
+// Starts the background lookup. Most runs find nothing to do, since routes are only looked up for restaurants that
+// are new or have moved, and there are none to look up until an office location is known
+pub fn spawn_walking_times(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+        loop {
+            interval.tick().await;
+            match update_walking_times(&state).await {
+                Ok(summary) if summary.updated.is_empty() && summary.failed.is_empty() => {}
+                Ok(summary) => println!(
+                    "walking times: {} updated, {} failed",
+                    summary.updated.len(),
+                    summary.failed.len()
+                ),
+                Err(err) => eprintln!("walking times failed: {err:?}"),
+            }
+        }
+    });
+}
+
+// POST /restaurants/enrich/walking-times (admin): looks up any missing or outdated walking times now
+pub async fn update_now(_admin: Admin, State(state): State<AppState>) -> Result<Json<RoutingSummary>, ApiError> {
+    let offices = offices::all(&state.db).await?;
+    if state.config.office_location.is_none() && offices.iter().all(|office| office.location().is_none()) {
+        return Err(ApiError::NotConfigured(
+            "set OFFICE_LATITUDE and OFFICE_LONGITUDE, or an office's coordinates, for walking times".to_string(),
+        ));
+    }
+    Ok(Json(update_walking_times(&state).await?))
+}
+
+async fn update_walking_times(state: &AppState) -> Result<RoutingSummary, ApiError> {
+    let restaurants = sqlx::query_as::<_, Pending>(
+        "SELECT r.id, r.name, r.latitude, r.longitude, r.office_id, o.latitude AS office_latitude,
+            o.longitude AS office_longitude, r.walking_route, ';' || r.latitude || ',' || r.longitude AS destination
+        FROM restaurants r LEFT JOIN offices o ON o.id = r.office_id
+        WHERE r.latitude IS NOT NULL AND r.longitude IS NOT NULL AND r.deleted_at IS NULL
+        ORDER BY r.id",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut summary = RoutingSummary::default();
+    for restaurant in restaurants {
+        let office = match restaurant.office_id {
+            Some(_) => restaurant.office_latitude.zip(restaurant.office_longitude).map(|(latitude, longitude)| {
+                Coordinates { latitude, longitude }
+            }),
+            None => state.config.office_location,
+        };
+        let Some(office) = office else {
+            continue;
+        };
+        let route = format!("{},{}{}", office.latitude, office.longitude, restaurant.destination);
+        if restaurant.walking_route.as_ref() == Some(&route) {
+            continue;
+        }
+        let Pending { id, name, latitude, longitude, .. } = restaurant;
+        // One restaurant the router can't reach shouldn't stop the rest; it's retried on the next run
+        let minutes = match walking_minutes(state, office, Coordinates { latitude, longitude }).await {
+            Ok(minutes) => minutes,
+            Err(err) => {
+                eprintln!("walking time for {name} failed: {err:?}");
+                summary.failed.push(name);
+                continue;
+            }
+        };
+        sqlx::query("UPDATE restaurants SET walking_minutes = ?, walking_route = ? WHERE id = ?")
+            .bind(minutes)
+            .bind(route)
+            .bind(id)
+            .execute(&state.db)
+            .await?;
+        summary.updated.push(name);
+        // The public OSRM servers ask for at most one request per second
+        // https://github.com/Project-OSRM/osrm-backend/wiki/Demo-server
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    Ok(summary)
+}
+
+// Asks the router for a walking route and rounds its duration up to whole minutes
+async fn walking_minutes(state: &AppState, from: Coordinates, to: Coordinates) -> Result<i64, ApiError> {
+    // OSRM takes coordinates as longitude,latitude
+    let url = format!(
+        "{}/route/v1/foot/{},{};{},{}",
+        state.config.routing_url.trim_end_matches('/'),
+        from.longitude,
+        from.latitude,
+        to.longitude,
+        to.latitude
+    );
+    let response = state.http.get(url).query(&[("overview", "false")]).send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("routing service returned {status}: {body}")));
+    }
+    let route: RouteResponse = response.json().await?;
+    match route.routes.first() {
+        Some(first) if route.code == "Ok" => Ok((first.duration / 60.0).ceil() as i64),
+        _ => Err(ApiError::Upstream(format!("routing service found no route ({})", route.code))),
+    }
+}
@@ -0,0 +1,126 @@
+// RSVPs: who is coming to a poll's lunch, whatever they voted for. Voting and abstaining say where someone would
+// like to go; an RSVP says whether they'll be there, so the results can give the headcount to book a table for.
+// Anyone can answer, on the roster or not, until the poll closes, and answer again to change their mind. A rotation
+// poll's picker saying they're not coming passes the pick on (see rotation::pass_on)
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::moderation::{self, NameKind};
+use crate::polls::{self, Poll, PollStatus};
+use crate::public_ids::PollId;
+use crate::{away, names, rotation, AppState};
+
+#[derive(Deserialize)]
+pub struct NewRsvp {
+    voter_name: String,
+    coming: bool,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Rsvp {
+    voter_name: String,
+    coming: bool,
+    created_at: String,
+    updated_at: Option<String>, // when they last changed their answer
+}
+
+// The expected headcount: the yeses, the nos, and the poll's attendees who haven't said either way
+#[derive(Clone, Serialize)]
+pub struct Headcount {
+    pub coming: i64,
+    not_coming: i64,
+    no_answer: Vec<String>,
+    away: Vec<String>, // attendees who haven't answered but are out of the office that day; see away.rs
+}
+
+pub async fn headcount(db: &SqlitePool, poll: &Poll) -> Result<Headcount, sqlx::Error> {
+    let answered: Vec<(String, bool)> = sqlx::query_as("SELECT voter_key, coming FROM rsvps WHERE poll_id = ?")
+        .bind(poll.id)
+        .fetch_all(db)
+        .await?;
+    let coming = answered.iter().filter(|(_, coming)| *coming).count() as i64;
+    let out_of_office = away::away_on(db, &poll.lunch_at).await?;
+    let (away, no_answer) = poll
+        .attendees
+        .iter()
+        .filter(|attendee| !answered.iter().any(|(key, _)| *key == names::fold(attendee)))
+        .cloned()
+        .partition(|attendee| out_of_office.contains(&names::fold(attendee)));
+    Ok(Headcount { coming, not_coming: answered.len() as i64 - coming, no_answer, away })
+}
+
+async fn open_poll(db: &SqlitePool, id: i64, public_id: &str) -> Result<Poll, ApiError> {
+    let poll = polls::find_poll(db, id).await?.ok_or_else(|| Message::no_poll(public_id))?;
+    if poll.status == PollStatus::Closed {
+        return Err(Message::poll_closed().into());
+    }
+    Ok(poll)
+}
+
+// POST /polls/:id/rsvps: {"voter_name": "Zoë", "coming": true}, while the poll is nominating or open. Answering
+// again replaces the earlier answer
+pub async fn rsvp(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Json(req): Json<NewRsvp>,
+) -> Result<(StatusCode, Json<Rsvp>), ApiError> {
+    let poll = open_poll(&state.db, id, &public_id).await?;
+    let voter_name = names::canonical_voter_name(&state.db, &req.voter_name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_empty().into());
+    }
+    moderation::screen(&state, NameKind::Voter, &voter_name).await?;
+    let rsvp = sqlx::query_as::<_, Rsvp>(
+        "INSERT INTO rsvps (poll_id, voter_key, voter_name, coming) VALUES (?, ?, ?, ?)
+        ON CONFLICT (poll_id, voter_key) DO UPDATE SET coming = excluded.coming, updated_at = CURRENT_TIMESTAMP
+        RETURNING voter_name, coming, created_at, updated_at",
+    )
+    .bind(id)
+    .bind(names::fold(&voter_name))
+    .bind(&voter_name)
+    .bind(req.coming)
+    .fetch_one(&state.db)
+    .await?;
+    // Someone who's already picked isn't let off that easily, but that's no reason to turn the answer away
+    let picking = poll.picker.as_deref().map(names::fold) == Some(names::fold(&voter_name));
+    if poll.voting_method == polls::VotingMethod::Rotation && picking && !req.coming {
+        if let Err(err) = rotation::pass_on(&state.db, &poll).await {
+            eprintln!("poll {id}: could not pass the pick on from {voter_name}: {err:?}");
+        }
+    }
+    Ok((StatusCode::CREATED, Json(rsvp)))
+}
+
+// GET /polls/:id/rsvps: everyone who has answered, those coming first
+pub async fn list_rsvps(State(state): State<AppState>, PollId(id, _): PollId) -> Result<Json<Vec<Rsvp>>, ApiError> {
+    let rsvps = sqlx::query_as::<_, Rsvp>(
+        "SELECT voter_name, coming, created_at, updated_at FROM rsvps WHERE poll_id = ?
+        ORDER BY coming DESC, voter_key",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(rsvps))
+}
+
+// DELETE /polls/:id/rsvps/:voter: takes an answer back, leaving them undecided again
+pub async fn withdraw_rsvp(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    open_poll(&state.db, id, &public_id).await?;
+    let withdrawn = sqlx::query("DELETE FROM rsvps WHERE poll_id = ? AND voter_key = ?")
+        .bind(id)
+        .bind(names::fold(&voter))
+        .execute(&state.db)
+        .await?;
+    if withdrawn.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{voter} hasn't answered for this poll")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
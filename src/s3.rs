@@ -0,0 +1,176 @@
+// Just enough of the S3 API for backups: putting, listing and deleting objects, with each request signed by AWS
+// Signature Version 4, an HMAC-SHA256 over the request's method, path, query and headers. Buckets are addressed by
+// path, {endpoint}/{bucket}/{key}, which S3 itself still takes and every S3-compatible service (MinIO, Ceph, R2...)
+// does, whatever DNS names its buckets get
+// https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-authenticating-requests.html
+// https://docs.aws.amazon.com/AmazonS3/latest/API/API_ListObjectsV2.html
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Method, Url};
+use ring::{digest, hmac};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::ApiError;
+use crate::receipts::hex;
+use crate::timezones;
+
+// A backup is a whole database, so it gets longer than the other outside APIs do
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+pub struct Bucket<'a> {
+    pub http: &'a reqwest::Client,
+    pub endpoint: &'a str,
+    pub region: &'a str,
+    pub name: &'a str,
+    pub access_key_id: &'a str,
+    pub secret_access_key: &'a str,
+}
+
+impl Bucket<'_> {
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), ApiError> {
+        self.send(Method::PUT, key, &[], body).await.map(|_| ())
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), ApiError> {
+        self.send(Method::DELETE, key, &[], Vec::new()).await.map(|_| ())
+    }
+
+    // The keys of the objects whose keys start with prefix, in the order S3 keeps them, which is by key. They come a
+    // page of up to 1000 at a time, each page saying where the next one starts
+    pub async fn list(&self, prefix: &str) -> Result<Vec<String>, ApiError> {
+        let mut keys = Vec::new();
+        let mut continuation = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string()), ("prefix", prefix.to_string())];
+            if let Some(token) = continuation {
+                query.push(("continuation-token", token));
+            }
+            let page = self.send(Method::GET, "", &query, Vec::new()).await?;
+            keys.extend(elements(&page, "Key"));
+            continuation = elements(&page, "NextContinuationToken").into_iter().next();
+            if continuation.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+
+    // One signed request, for an object or, with an empty key, the bucket itself; the body of the answer
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<String, ApiError> {
+        let endpoint = Url::parse(self.endpoint)
+            .map_err(|err| ApiError::NotConfigured(format!("BACKUP_ENDPOINT is not a URL: {err}")))?;
+        let host = match endpoint.port() {
+            Some(port) => format!("{}:{port}", endpoint.host_str().unwrap_or_default()),
+            None => endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let mut path = format!("{}/{}", endpoint.path().trim_end_matches('/'), encode(self.name, false));
+        if !key.is_empty() {
+            path.push_str(&format!("/{}", encode(key, true)));
+        }
+        let mut query: Vec<String> =
+            query.iter().map(|(name, value)| format!("{}={}", encode(name, false), encode(value, false))).collect();
+        query.sort();
+        let query = query.join("&");
+
+        // The canonical request: what the signature covers, every part of it written just so
+        let payload_hash = hex(digest::digest(&digest::SHA256, &body).as_ref());
+        let (date, time) = now();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical = format!(
+            "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{time}\n\n\
+            {signed_headers}\n{payload_hash}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+            hex(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref())
+        );
+        // The signing key is the secret run through the date, the region and the service in turn
+        let mut signing_key = format!("AWS4{}", self.secret_access_key).into_bytes();
+        for part in [date.as_str(), self.region, "s3", "aws4_request"] {
+            signing_key = sign(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&sign(&signing_key, to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        );
+
+        let url = match query.as_str() {
+            "" => format!("{}://{host}{path}", endpoint.scheme()),
+            query => format!("{}://{host}{path}?{query}", endpoint.scheme()),
+        };
+        let response = self
+            .http
+            .request(method.clone(), url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", time)
+            .header(AUTHORIZATION, authorization)
+            .body(body)
+            .timeout(TIMEOUT)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            let what = if key.is_empty() { self.name } else { key };
+            let message = format!("the storage service answered {method} {what} with {status}: {text}");
+            return Err(ApiError::Upstream(message));
+        }
+        Ok(text)
+    }
+}
+
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+// The date, 20240105, and the time, 20240105T123000Z, in UTC, as the signature wants them
+fn now() -> (String, String) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    let (year, month, day) = timezones::civil_from_days(seconds.div_euclid(86400));
+    let of_day = seconds.rem_euclid(86400);
+    let date = format!("{year:04}{month:02}{day:02}");
+    let time = format!("{date}T{:02}{:02}{:02}Z", of_day / 3600, of_day / 60 % 60, of_day % 60);
+    (date, time)
+}
+
+// Percent-encoding the way the signature wants: everything but letters, digits and -_.~ is escaped, and / too unless
+// it separates the parts of a key. GraphQL and gRPC build their paths and query strings with it too
+pub fn encode(text: &str, keep_slashes: bool) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            b'/' if keep_slashes => "/".to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+// The contents of every <name> element in an XML answer. Listings are flat enough that this is all the XML there
+// is to read, once the five entities are undone
+fn elements(xml: &str, name: &str) -> Vec<String> {
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(
+            rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
@@ -0,0 +1,105 @@
+// Background jobs that run on the clock: every minute, polls whose nomination window or voting deadline has passed
+// move on to their next phase (closing a poll may open a runoff), those yet to vote in polls closing soon are
+// reminded, at DAILY_POLL_TIME on weekdays that aren't public holidays the day's poll opens, and on Fridays the
+// weekly digest goes out. Offices with a daily poll time of their own get their own poll, opened at that time on
+// the office's clock, which follows its time zone when it has one
+use std::time::Duration;
+
+use crate::offices::{self, Office};
+use crate::{digest, holidays, notifications, poll_templates, polls, reminders, timezones, AppState};
+
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        // Checking once a minute is plenty for these jobs, and it copes with the clock jumping (DST, suspend)
+        // without any date arithmetic on our side. MissedTickBehavior::Skip avoids a burst of catch-up ticks
+        // https://docs.rs/tokio/latest/tokio/time/enum.MissedTickBehavior.html
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            // With several instances, only the one holding the lease runs the jobs; see leader.rs
+            if !state.lease.held(&state.config).await {
+                continue;
+            }
+            // Clocks first, so everything after runs on this minute's offsets
+            match timezones::refresh(&state).await {
+                Ok(0) => {}
+                Ok(changed) => println!("scheduler: {changed} clocks changed with their time zones"),
+                Err(err) => eprintln!("scheduler: could not follow the time zones: {err:?}"),
+            }
+            match polls::advance_due_polls(&state.db).await {
+                Ok(advanced) => {
+                    for (id, status) in advanced {
+                        println!("scheduler: poll {id} is now {status:?}");
+                        if status == polls::PollStatus::Open {
+                            notifications::spawn_poll_opened(&state, id);
+                        } else if let Err(err) = polls::on_close(&state, id).await {
+                            eprintln!("scheduler: could not finish closing poll {id}: {err:?}");
+                        }
+                    }
+                }
+                Err(err) => eprintln!("scheduler: could not advance polls: {err:?}"),
+            }
+            if let Err(err) = reminders::send_due(&state).await {
+                eprintln!("scheduler: could not send reminders: {err:?}");
+            }
+            if let Err(err) = digest::send_if_due(&state).await {
+                eprintln!("scheduler: could not send the digest: {err:?}");
+            }
+            let offices = offices::all(&state.db).await.unwrap_or_else(|err| {
+                eprintln!("scheduler: could not load the offices: {err:?}");
+                Vec::new()
+            });
+            let daily = std::iter::once((None, state.config.daily_poll_time.as_deref()))
+                .chain(offices.iter().map(|office| (Some(office), office.daily_poll_time.as_deref())));
+            for (office, poll_time) in daily {
+                let Some(poll_time) = poll_time else {
+                    continue;
+                };
+                let of = office.map_or(String::new(), |office| format!(" in {}", office.id));
+                match open_todays_poll(&state, poll_time, office).await {
+                    Ok(Some(poll)) => println!("scheduler: opened poll {} for today{of}", poll.id),
+                    Ok(None) => {}
+                    Err(err) => eprintln!("scheduler: could not open today's poll{of}: {err:?}"),
+                }
+            }
+        }
+    });
+}
+
+// Opens today's poll once the poll time has passed, unless it's the weekend, a holiday, or already done
+async fn open_todays_poll(
+    state: &AppState,
+    poll_time: &str,
+    office: Option<&Office>,
+) -> Result<Option<polls::Poll>, crate::error::ApiError> {
+    // Local date, time and weekday (0 is Sunday) all come from SQLite, which reads the server's TZ; an office's from
+    // its offset instead
+    let (today, now, weekday): (String, String, i64) = sqlx::query_as(&format!(
+        "SELECT {}, strftime('%H:%M', 'now', ?1), CAST(strftime('%w', 'now', ?1) AS INTEGER)",
+        timezones::day_sql("'now'", "?1")
+    ))
+    .bind(offices::clock(office))
+    .fetch_one(&state.db)
+    .await?;
+    if now.as_str() < poll_time || weekday == 0 || weekday == 6 || holidays::is_holiday(&state.db, &today).await? {
+        return Ok(None);
+    }
+    let office_id = office.map(|office| office.id.clone());
+    let already_open: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM polls WHERE scheduled_for = ? AND office_id IS ?)")
+            .bind(&today)
+            .bind(&office_id)
+            .fetch_one(&state.db)
+            .await?;
+    if already_open {
+        return Ok(None);
+    }
+    let template = match office {
+        Some(office) => office.daily_poll_template.as_deref(),
+        None => state.config.daily_poll_template.as_deref(),
+    };
+    let req = poll_templates::daily_poll(&state.db, template, office_id).await?;
+    let poll = polls::insert_poll(state, req, Some(today)).await?;
+    Ok(Some(poll))
+}
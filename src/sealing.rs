@@ -0,0 +1,205 @@
+// Sealed ballots, for contentious polls: until the poll closes, what each voter chose is stored encrypted, with a key
+// of the poll's own. That key is kept beside the poll (see migrations/0070_sealing_keys.sql) but only wrapped, itself
+// encrypted under SEALING_KEY, which is in the config and never the database; no endpoint returns either, so nobody
+// with the database alone, admins included, can see how the vote is going. Closing the poll reveals the ballots:
+// they're decrypted into ordinary votes and the key is thrown away. Since the wrapped key is in the database, a
+// restart or another instance sharing it can seal and reveal the poll's ballots just the same
+// https://docs.rs/ring/latest/ring/aead/index.html
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteExecutor;
+
+use crate::error::ApiError;
+use crate::{ballots, vote_events, AppState, VoteChannel};
+
+// What a sealed ballot hides: everything in the vote except who cast it
+#[derive(Serialize, Deserialize)]
+pub struct Ballot {
+    pub restaurant_name: String,
+    pub backup_restaurant_name: Option<String>,
+    pub ranking: Option<Vec<String>>,
+    pub comment: Option<String>,
+    pub channel: VoteChannel,
+}
+
+#[derive(sqlx::FromRow)]
+struct SealedBallot {
+    voter_name: String,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+    created_at: String,
+}
+
+#[derive(Debug)]
+pub enum SealError {
+    DbError(sqlx::Error),
+    // The key is gone, or wrapped under another SEALING_KEY, or the ciphertext doesn't match it; the ballot can't be
+    // read
+    Unreadable,
+    AlreadyVoted, // the voter's ballot is in already; see ballots.rs
+}
+
+impl From<sqlx::Error> for SealError {
+    fn from(err: sqlx::Error) -> Self {
+        SealError::DbError(err)
+    }
+}
+
+impl From<SealError> for ApiError {
+    fn from(err: SealError) -> Self {
+        match err {
+            SealError::DbError(err) => ApiError::DbError(err),
+            SealError::Unreadable => ApiError::Conflict("the sealed ballots can't be opened".to_string()),
+            SealError::AlreadyVoted => ApiError::Conflict("the voter has already voted in this poll".to_string()),
+        }
+    }
+}
+
+// The voter name and poll go into the additional data, so a ballot copied onto another voter's row won't open
+fn aad(poll_id: i64, voter_name: &str) -> Vec<u8> {
+    format!("{poll_id}:{voter_name}").into_bytes()
+}
+
+fn random<const N: usize>() -> Result<[u8; N], SealError> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| SealError::Unreadable)?;
+    Ok(bytes)
+}
+
+fn cipher(key: &[u8]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("a 32-byte key"))
+}
+
+// What the poll keys are wrapped with: SEALING_KEY, hashed to the 32 bytes the cipher takes, so it can be any
+// string. The poll's id is the additional data, so a wrapped key copied onto another poll won't open
+fn wrapping_key(state: &AppState) -> LessSafeKey {
+    cipher(digest::digest(&digest::SHA256, &state.config.sealing_key).as_ref())
+}
+
+// A fresh key for a new sealed poll, or one being reopened, stored wrapped
+pub async fn create<'e>(state: &AppState, db: impl SqliteExecutor<'e>, poll_id: i64) -> Result<(), SealError> {
+    let (mut wrapped, nonce) = (random::<32>()?.to_vec(), random::<NONCE_LEN>()?);
+    wrapping_key(state)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(poll_id.to_string()),
+            &mut wrapped,
+        )
+        .map_err(|_| SealError::Unreadable)?;
+    sqlx::query("INSERT OR REPLACE INTO sealing_keys (poll_id, nonce, wrapped_key) VALUES (?, ?, ?)")
+        .bind(poll_id)
+        .bind(&nonce[..])
+        .bind(wrapped)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+// The poll's key, unwrapped; None once it's been revealed, or if it never was sealed
+async fn key(state: &AppState, poll_id: i64) -> Result<Option<LessSafeKey>, SealError> {
+    let wrapped: Option<(Vec<u8>, Vec<u8>)> =
+        sqlx::query_as("SELECT nonce, wrapped_key FROM sealing_keys WHERE poll_id = ?")
+            .bind(poll_id)
+            .fetch_optional(&state.db)
+            .await?;
+    let Some((nonce, mut wrapped)) = wrapped else {
+        return Ok(None);
+    };
+    let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| SealError::Unreadable)?;
+    // Fails when SEALING_KEY isn't the one the key was wrapped with
+    let key = wrapping_key(state)
+        .open_in_place(nonce, Aad::from(poll_id.to_string()), &mut wrapped)
+        .map_err(|_| SealError::Unreadable)?;
+    Ok(Some(cipher(key)))
+}
+
+// Stores a ballot encrypted with its poll's key, under a random nonce of its own
+pub async fn seal(state: &AppState, poll_id: i64, voter_name: &str, ballot: &Ballot) -> Result<(), SealError> {
+    let nonce = random::<NONCE_LEN>()?;
+    let mut ciphertext = serde_json::to_vec(ballot).expect("a ballot always serializes");
+    let key = key(state, poll_id).await?.ok_or(SealError::Unreadable)?;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad(poll_id, voter_name)),
+        &mut ciphertext,
+    )
+    .map_err(|_| SealError::Unreadable)?;
+    // Only if they haven't a ballot in yet, in the one statement, so one of two sent at once is turned away
+    let sealed = sqlx::query(&format!(
+        "INSERT INTO sealed_ballots (poll_id, voter_name, nonce, ciphertext)
+        SELECT ?, ?, ?, ? WHERE NOT EXISTS (SELECT 1 FROM sealed_ballots WHERE poll_id = ? AND {} = ?)",
+        ballots::voter_sql("voter_name")
+    ))
+    .bind(poll_id)
+    .bind(voter_name)
+    .bind(&nonce[..])
+    .bind(ciphertext)
+    .bind(poll_id)
+    .bind(voter_name)
+    .execute(&state.db)
+    .await?;
+    if sealed.rows_affected() == 0 {
+        return Err(SealError::AlreadyVoted);
+    }
+    Ok(())
+}
+
+// The reveal step of closing a sealed poll: decrypts its ballots into votes, in the order they were cast and with
+// their original times, then deletes them and its key. Returns how many were revealed
+pub async fn reveal(state: &AppState, poll_id: i64) -> Result<usize, SealError> {
+    let sealed = sqlx::query_as::<_, SealedBallot>(
+        "SELECT voter_name, nonce, ciphertext, created_at FROM sealed_ballots WHERE poll_id = ? ORDER BY id",
+    )
+    .bind(poll_id)
+    .fetch_all(&state.db)
+    .await?;
+    // Everything is decrypted before anything is written, and the key is only dropped along with the ballots, so a
+    // failed reveal can be tried again
+    let key = match key(state, poll_id).await? {
+        Some(key) => key,
+        None if sealed.is_empty() => return Ok(0),
+        None => return Err(SealError::Unreadable),
+    };
+    let mut ballots = Vec::new();
+    for SealedBallot { voter_name, nonce, mut ciphertext, created_at } in sealed {
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| SealError::Unreadable)?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(aad(poll_id, &voter_name)), &mut ciphertext)
+            .map_err(|_| SealError::Unreadable)?;
+        let ballot: Ballot = serde_json::from_slice(plaintext).map_err(|_| SealError::Unreadable)?;
+        ballots.push((voter_name, ballot, created_at));
+    }
+
+    let public_id: String = sqlx::query_scalar("SELECT public_id FROM polls WHERE id = ?")
+        .bind(poll_id)
+        .fetch_one(&state.db)
+        .await?;
+    let count = ballots.len();
+    let mut tx = state.db.begin().await?;
+    let mut vote_ids = Vec::new();
+    for (voter_name, ballot, created_at) in ballots {
+        let cast = vote_events::CastVote {
+            voter_name,
+            restaurant_name: ballot.restaurant_name,
+            poll_id: Some(public_id.clone()),
+            backup_restaurant_name: ballot.backup_restaurant_name,
+            ranking: ballot.ranking,
+            comment: ballot.comment,
+            channel: ballot.channel,
+            cast_at: Some(created_at),
+            anonymized_at: None,
+        };
+        vote_ids.push(vote_events::cast(&mut tx, cast).await?);
+    }
+    for table in ["sealed_ballots", "sealing_keys"] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE poll_id = ?")).bind(poll_id).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    // Sealed ballots count as cast once they're opened, which is the first time anyone can see them
+    for vote_id in vote_ids {
+        state.events.vote_cast(vote_id);
+    }
+    Ok(count)
+}
@@ -0,0 +1,195 @@
+// Aggregated, read-only views over the votes table, for charting and arguing rather than for deciding today's lunch.
+// They're read from the replica when there is one, since a chart a moment out of date is no harm
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::error::ApiError;
+use crate::polls::HIDDEN_POLLS_SQL;
+use crate::{ballots, timezones, AppState};
+
+// serde can derive Deserialize for enums too; rename_all maps ?granularity=week onto Granularity::Week
+// and any other value is rejected by axum with a 400 before our handler runs
+// https://serde.rs/container-attrs.html#rename_all
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Week,
+    Month,
+}
+
+impl Granularity {
+    // SQLite date modifiers that snap a vote's timestamp to the first day of its bucket.
+    // Weeks start on Monday: step back six days, then forward to the next Monday
+    // https://www.sqlite.org/lang_datefunc.html#modifiers
+    fn bucket_start_sql(self) -> String {
+        let day = timezones::vote_day_sql("votes");
+        match self {
+            Granularity::Week => format!("date({day}, '-6 days', 'weekday 1')"),
+            Granularity::Month => format!("date({day}, 'start of month')"),
+        }
+    }
+}
+
+// WITH clauses naming each day's restaurants, `winners`, placed by that day's votes: most votes first, and on a tie
+// whichever got its first vote earliest. One vote a day counts per voter, the last they cast that day, so voting
+// again, or in a second poll, doesn't count twice; voters are told apart and days are worked out as for the
+// ballots that count in a poll (see ballots.rs and timezones::vote_day_sql). /stats,
+// the recommendations, the recent-wins tiebreak and the LLM's context all decide daily winners with this
+// https://www.sqlite.org/lang_with.html
+// https://www.sqlite.org/windowfunctions.html
+pub fn daily_winners_sql() -> String {
+    format!(
+        "WITH dated AS (
+            SELECT id, voter_name, restaurant_name, {} AS day
+            FROM votes
+            WHERE deleted_at IS NULL AND (poll_id IS NULL OR poll_id NOT IN ({HIDDEN_POLLS_SQL}))
+        ),
+        counted AS (
+            SELECT *, ROW_NUMBER() OVER (PARTITION BY day, {} ORDER BY id DESC) AS latest FROM dated
+        ),
+        daily AS (
+            SELECT day, restaurant_name, COUNT(*) AS votes, MIN(id) AS first_vote
+            FROM counted WHERE latest = 1
+            GROUP BY day, restaurant_name
+        ),
+        winners AS (
+            SELECT day, restaurant_name, votes,
+                ROW_NUMBER() OVER (PARTITION BY day ORDER BY votes DESC, first_vote) AS place
+            FROM daily
+        )",
+        timezones::vote_day_sql("votes"),
+        ballots::voter_sql("voter_name")
+    )
+}
+
+#[derive(Deserialize)]
+pub struct TrendsQuery {
+    #[serde(default)]
+    granularity: Granularity,
+}
+
+#[derive(Serialize)]
+pub struct Trends {
+    granularity: Granularity,
+    buckets: Vec<TrendBucket>,
+}
+
+#[derive(Serialize)]
+struct TrendBucket {
+    start: String, // first day of the bucket, as YYYY-MM-DD
+    restaurants: Vec<RestaurantCount>,
+}
+
+#[derive(Serialize)]
+struct RestaurantCount {
+    name: String,
+    votes: i64,
+}
+
+// GET /stats/trends?granularity=week|month
+// Query is the extractor for the URL's query string, parsed into TrendsQuery by serde
+// https://docs.rs/axum/latest/axum/extract/struct.Query.html
+pub async fn trends(
+    State(state): State<AppState>,
+    Query(query): Query<TrendsQuery>,
+) -> Result<Json<Trends>, ApiError> {
+    // The bucket expression comes from our own enum, never from user input, so formatting it into the SQL is safe
+    let sql = format!(
+        "SELECT {} AS bucket_start, restaurant_name, COUNT(*) AS votes
+        FROM votes
+        WHERE deleted_at IS NULL AND (poll_id IS NULL OR poll_id NOT IN ({HIDDEN_POLLS_SQL}))
+        GROUP BY bucket_start, restaurant_name
+        ORDER BY bucket_start, votes DESC, restaurant_name",
+        query.granularity.bucket_start_sql()
+    );
+    let rows = sqlx::query(&sql).fetch_all(&state.reads).await?;
+
+    // Rows arrive sorted by bucket, so we only ever need to look at the last bucket to know where a row belongs
+    let mut buckets: Vec<TrendBucket> = Vec::new();
+    for row in rows {
+        let start: String = row.get("bucket_start");
+        let count = RestaurantCount {
+            name: row.get("restaurant_name"),
+            votes: row.get("votes"),
+        };
+        match buckets.last_mut() {
+            Some(bucket) if bucket.start == start => bucket.restaurants.push(count),
+            _ => buckets.push(TrendBucket {
+                start,
+                restaurants: vec![count],
+            }),
+        }
+    }
+
+    Ok(Json(Trends {
+        granularity: query.granularity,
+        buckets,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct CuisineStats {
+    cuisines: Vec<CuisineCount>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct CuisineCount {
+    cuisine: String,
+    votes: i64,
+    wins: i64,
+}
+
+// GET /stats/cuisines
+// A "win" is a restaurant finishing first on a given day (see daily_winners_sql), and its votes are the ones that
+// counted that day. Votes for restaurants without a cuisine tag (or not registered at all) are grouped under "untagged".
+// Restaurants in the trash are joined too, so the votes they had keep their cuisine; names are unique across the trash
+// as well, so none is counted twice
+pub async fn cuisines(State(state): State<AppState>) -> Result<Json<CuisineStats>, ApiError> {
+    let cuisines = sqlx::query_as::<_, CuisineCount>(&format!(
+        "{}
+        SELECT COALESCE(r.cuisine, 'untagged') AS cuisine,
+            SUM(winners.votes) AS votes,
+            SUM(winners.place = 1) AS wins
+        FROM winners
+        LEFT JOIN restaurants r ON r.name = winners.restaurant_name
+        GROUP BY 1
+        ORDER BY wins DESC, votes DESC, cuisine",
+        daily_winners_sql()
+    ))
+    .fetch_all(&state.reads)
+    .await?;
+
+    Ok(Json(CuisineStats { cuisines }))
+}
+
+#[derive(Serialize)]
+pub struct ChannelStats {
+    channels: Vec<ChannelCount>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct ChannelCount {
+    channel: String,
+    votes: i64,
+    voters: i64, // different people voting this way
+    last_vote_at: String,
+}
+
+// GET /stats/channels: votes per channel (web, slack, cli, link, grpc, api), busiest first
+pub async fn channels(State(state): State<AppState>) -> Result<Json<ChannelStats>, ApiError> {
+    let channels = sqlx::query_as::<_, ChannelCount>(&format!(
+        "SELECT channel, COUNT(*) AS votes, COUNT(DISTINCT {}) AS voters, MAX(created_at) AS last_vote_at
+        FROM votes
+        WHERE deleted_at IS NULL AND (poll_id IS NULL OR poll_id NOT IN ({HIDDEN_POLLS_SQL}))
+        GROUP BY channel
+        ORDER BY votes DESC, channel",
+        ballots::voter_sql("voter_name")
+    ))
+    .fetch_all(&state.reads)
+    .await?;
+
+    Ok(Json(ChannelStats { channels }))
+}
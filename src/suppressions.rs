@@ -0,0 +1,149 @@
+// The suppression list: addresses that get no notifications at all, whichever voter's settings they're in and
+// whatever those say. Every email ends with a link to put its address on the list, and every POST to a voter's own
+// webhook carries one as unsubscribe_url, for a chat or SMS bridge to offer. The link is the address signed with the
+// receipt key, like the digest's unsubscribe link, so it works without logging in and can't be made for someone
+// else's address. dispatch checks the list before sending anything on either channel; an admin can look through
+// it and take an address off again with DELETE /suppressions, say after someone unsubscribed by mistake
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::{self, Admin};
+use crate::error::ApiError;
+use crate::notifications::Channel;
+use crate::receipts::hex;
+use crate::AppState;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Suppression {
+    channel: Channel,
+    address: String,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct Unsubscribe {
+    #[serde(default = "email")]
+    channel: Channel,
+    address: String,
+    token: String,
+}
+
+fn email() -> Channel {
+    Channel::Email
+}
+
+#[derive(Deserialize)]
+pub struct SuppressionQuery {
+    channel: Option<Channel>,
+    address: Option<String>,
+}
+
+// Email addresses compare without case, as everyone but the RFC treats them; URLs are left as they are
+fn normalize(channel: Channel, address: &str) -> String {
+    match channel {
+        Channel::Email => address.trim().to_lowercase(),
+        Channel::Webhook => address.trim().to_string(),
+    }
+}
+
+pub async fn suppressed(db: &SqlitePool, channel: Channel, address: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM suppressions WHERE channel = ? AND address = ?)")
+        .bind(channel)
+        .bind(normalize(channel, address))
+        .fetch_one(db)
+        .await
+}
+
+// The prefix keeps this signature from passing for any other token signed with the receipt key
+fn token(state: &AppState, channel: Channel, address: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key);
+    hex(hmac::sign(&key, format!("unsubscribe:{}:{address}", channel.as_str()).as_bytes()).as_ref())
+}
+
+pub fn unsubscribe_url(state: &AppState, channel: Channel, address: &str) -> String {
+    let base = format!("{}/unsubscribe", state.config.public_url.trim_end_matches('/'));
+    let address = normalize(channel, address);
+    let token = token(state, channel, &address);
+    let params = [("channel", channel.as_str()), ("address", &address), ("token", &token)];
+    match reqwest::Url::parse_with_params(&base, &params) {
+        Ok(url) => url.to_string(),
+        Err(_) => format!("{base}?token={token}"),
+    }
+}
+
+// GET /unsubscribe?channel=email&address=zoe@example.com&token=...: the link in every notification. A GET, so it
+// works straight from an email; unsubscribing twice is no different from once
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<Unsubscribe>,
+) -> Result<Json<Suppression>, ApiError> {
+    let address = normalize(query.channel, &query.address);
+    let expected = token(&state, query.channel, &address);
+    if !auth::constant_time_eq(expected.as_bytes(), query.token.as_bytes()) {
+        return Err(ApiError::Forbidden("that unsubscribe link isn't valid".to_string()));
+    }
+    sqlx::query("INSERT OR IGNORE INTO suppressions (channel, address) VALUES (?, ?)")
+        .bind(query.channel)
+        .bind(&address)
+        .execute(&state.db)
+        .await?;
+    println!("suppressions: {address} unsubscribed from everything by {:?}", query.channel);
+    let suppression = sqlx::query_as::<_, Suppression>(
+        "SELECT channel, address, created_at FROM suppressions WHERE channel = ? AND address = ?",
+    )
+    .bind(query.channel)
+    .bind(&address)
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(suppression))
+}
+
+// GET /suppressions?channel=email (admin): the suppressed addresses, newest first; address=... narrows it to one
+pub async fn list_suppressions(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<SuppressionQuery>,
+) -> Result<Json<Vec<Suppression>>, ApiError> {
+    let address = match (query.channel, &query.address) {
+        (Some(channel), Some(address)) => Some(normalize(channel, address)),
+        (None, Some(address)) => Some(address.trim().to_string()),
+        (_, None) => None,
+    };
+    let suppressions = sqlx::query_as::<_, Suppression>(
+        "SELECT channel, address, created_at FROM suppressions
+        WHERE (?1 IS NULL OR channel = ?1) AND (?2 IS NULL OR lower(address) = lower(?2))
+        ORDER BY created_at DESC, address",
+    )
+    .bind(query.channel)
+    .bind(address)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(suppressions))
+}
+
+// DELETE /suppressions?channel=email&address=zoe@example.com (admin): takes the address off the list, so whoever
+// has it in their settings gets notifications there again
+pub async fn clear_suppression(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<SuppressionQuery>,
+) -> Result<StatusCode, ApiError> {
+    let (Some(channel), Some(address)) = (query.channel, &query.address) else {
+        return Err(ApiError::BadRequest("channel and address are both needed".to_string()));
+    };
+    let address = normalize(channel, address);
+    let cleared = sqlx::query("DELETE FROM suppressions WHERE channel = ? AND address = ?")
+        .bind(channel)
+        .bind(&address)
+        .execute(&state.db)
+        .await?;
+    if cleared.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{address} isn't suppressed")));
+    }
+    println!("suppressions: {address} taken off the list");
+    Ok(StatusCode::NO_CONTENT)
+}
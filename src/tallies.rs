@@ -0,0 +1,90 @@
+// Keeping tallies once they're counted, so the dashboard on the office TV asking for the results every second doesn't
+// have every vote counted again each time. A tally is kept per poll, per ?tiebreak= and per version of the poll (see
+// polls.rs), so any change to the poll itself, ruling a restaurant out of it say, starts on a fresh one. Whatever
+// changes votes sends a refresh, and refresh.rs has the poll's tallies forgotten before the refresh goes out, so the
+// clients it reaches fetch new ones; refreshes heard from the other instances through Redis do the same. Not all a
+// tally shows comes with one, though: RSVPs, abstentions and the restaurants' own details are caught up with once
+// it's MAX_AGE old. The loose votes at /results drawing their tiebreak at random aren't kept, since that draw is
+// meant to be made afresh every time
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::polls::Poll;
+use crate::tiebreaks::Tiebreak;
+use crate::LunchVoting;
+
+const MAX_AGE: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Default)]
+pub struct Tallies(Arc<RwLock<Kept>>);
+
+#[derive(Default)]
+struct Kept {
+    tallies: HashMap<Key, Tally>,
+    // One more with everything forgotten, so a tally being counted while the votes under it changed isn't kept
+    generation: u64,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub struct Key {
+    poll_id: Option<String>, // the poll's public id, as refreshes have it
+    version: i64,
+    tiebreak: Option<Tiebreak>,
+}
+
+struct Tally {
+    voting: LunchVoting,
+    counted_at: Instant,
+}
+
+impl Key {
+    // None for a tally that isn't to be kept
+    pub fn new(poll: Option<&Poll>, tiebreak: Option<Tiebreak>) -> Option<Key> {
+        if poll.is_none() && tiebreak == Some(Tiebreak::Random) {
+            return None;
+        }
+        Some(Key {
+            poll_id: poll.map(|poll| poll.public_id.clone()),
+            version: poll.map_or(0, |poll| poll.version),
+            tiebreak,
+        })
+    }
+}
+
+impl Tallies {
+    pub fn get(&self, key: &Key) -> Option<LunchVoting> {
+        let kept = self.0.read().expect("tallies poisoned");
+        let tally = kept.tallies.get(key).filter(|tally| tally.counted_at.elapsed() < MAX_AGE)?;
+        Some(tally.voting.clone())
+    }
+
+    // To be read before counting, and passed on to keep() after
+    pub fn generation(&self) -> u64 {
+        self.0.read().expect("tallies poisoned").generation
+    }
+
+    // Keeps a tally counted since `generation`, unless something has been forgotten in the meantime. Whatever has
+    // grown too old goes at the same time, so tallies of past versions and polls nobody asks about don't pile up
+    pub fn keep(&self, key: Key, generation: u64, counted_at: Instant, voting: &LunchVoting) {
+        let mut kept = self.0.write().expect("tallies poisoned");
+        if kept.generation != generation {
+            return;
+        }
+        kept.tallies.retain(|_, tally| tally.counted_at.elapsed() < MAX_AGE);
+        kept.tallies.insert(key, Tally { voting: voting.clone(), counted_at });
+    }
+
+    // A poll's tallies, or with None the loose votes'
+    pub fn forget(&self, poll_id: Option<&str>) {
+        let mut kept = self.0.write().expect("tallies poisoned");
+        kept.generation += 1;
+        kept.tallies.retain(|key, _| key.poll_id.as_deref() != poll_id);
+    }
+
+    pub fn forget_all(&self) {
+        let mut kept = self.0.write().expect("tallies poisoned");
+        kept.generation += 1;
+        kept.tallies.clear();
+    }
+}
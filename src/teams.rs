@@ -0,0 +1,264 @@
+// Teams: named groups of voters, each possibly one office's, with captains among the members. A poll for a team
+// starts with the members as its attendees and only takes ballots and abstentions from whoever is on the team when
+// they come in, so a name typed by someone outside it isn't counted as one of the team. Captains are marked here for
+// the features that need someone to answer for a team. A team's locale is the language its notifications and winner
+// announcements are written in; see i18n.rs
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::i18n::{self, Locale};
+use crate::{names, offices, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Team {
+    pub id: String,
+    name: String,
+    pub office_id: Option<String>, // the team's polls are this office's unless they say otherwise
+    locale: Option<Locale>,
+    created_at: String,
+    #[sqlx(skip)]
+    pub members: Vec<Member>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Member {
+    pub voter_name: String,
+    captain: bool,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewTeam {
+    name: String,
+    office_id: Option<String>,
+    locale: Option<String>, // "de", or a tag like "de-CH"
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TeamUpdate {
+    locale: Option<String>, // empty goes back to English
+}
+
+const TEAM_COLUMNS: &str = "id, name, office_id, locale, created_at";
+
+pub async fn find(db: &SqlitePool, id: &str) -> Result<Option<Team>, sqlx::Error> {
+    let team = sqlx::query_as::<_, Team>(&format!("SELECT {TEAM_COLUMNS} FROM teams WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+    let Some(mut team) = team else {
+        return Ok(None);
+    };
+    team.members = members(db, id).await?;
+    Ok(Some(team))
+}
+
+// Captains first, then by name
+async fn members(db: &SqlitePool, team_id: &str) -> Result<Vec<Member>, sqlx::Error> {
+    sqlx::query_as::<_, Member>(
+        "SELECT voter_name, captain, created_at FROM team_members WHERE team_id = ? ORDER BY captain DESC, voter_key",
+    )
+    .bind(team_id)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn is_member(db: &SqlitePool, team_id: &str, voter_name: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM team_members WHERE team_id = ? AND voter_key = ?)")
+        .bind(team_id)
+        .bind(names::fold(voter_name))
+        .fetch_one(db)
+        .await
+}
+
+// The team a team_id in a request body names, which must exist
+pub async fn lookup(db: &SqlitePool, id: Option<&str>) -> Result<Option<Team>, ApiError> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    match find(db, id).await? {
+        Some(team) => Ok(Some(team)),
+        None => Err(Message::no_team(id).with_status(StatusCode::BAD_REQUEST).into()),
+    }
+}
+
+async fn existing(db: &SqlitePool, id: &str) -> Result<Team, ApiError> {
+    find(db, id).await?.ok_or_else(|| ApiError::from(Message::no_team(id)))
+}
+
+// GET /teams, with their members
+pub async fn list_teams(State(state): State<AppState>) -> Result<Json<Vec<Team>>, ApiError> {
+    let mut teams = sqlx::query_as::<_, Team>(&format!("SELECT {TEAM_COLUMNS} FROM teams ORDER BY id"))
+        .fetch_all(&state.db)
+        .await?;
+    for team in &mut teams {
+        team.members = members(&state.db, &team.id).await?;
+    }
+    Ok(Json(teams))
+}
+
+// GET /teams/:id
+pub async fn get_team(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<Team>, ApiError> {
+    Ok(Json(existing(&state.db, &id).await?))
+}
+
+// POST /teams (admin): {"name": "Platform", "office_id": "berlin", "locale": "de", "members": ["Zoë", "Sam"]}
+pub async fn create_team(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Json(req): Json<NewTeam>,
+) -> Result<(StatusCode, Json<Team>), ApiError> {
+    let name = names::clean(&req.name);
+    let id = names::slug(&name);
+    if id.is_empty() {
+        return Err(ApiError::BadRequest("a team needs a name with letters or digits in it".to_string()));
+    }
+    offices::lookup(&state.db, req.office_id.as_deref()).await?;
+    let locale = i18n::parse_setting(req.locale.as_deref().unwrap_or_default())?;
+    let mut tx = state.db.begin().await?;
+    let created =
+        sqlx::query("INSERT INTO teams (id, name, office_id, locale) VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING")
+            .bind(&id)
+            .bind(&name)
+            .bind(&req.office_id)
+            .bind(locale)
+            .execute(&mut *tx)
+            .await?;
+    if created.rows_affected() == 0 {
+        return Err(ApiError::Conflict(format!("there's already a team called {id}")));
+    }
+    for member in &req.members {
+        let voter_name = names::canonical_voter_name(&state.db, member).await?;
+        if voter_name.is_empty() {
+            return Err(ApiError::BadRequest("member names must not be empty".to_string()));
+        }
+        sqlx::query("INSERT OR IGNORE INTO team_members (team_id, voter_name, voter_key) VALUES (?, ?, ?)")
+            .bind(&id)
+            .bind(&voter_name)
+            .bind(names::fold(&voter_name))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(existing(&state.db, &id).await?)))
+}
+
+// PATCH /teams/:id (admin): {"locale": "de"}
+pub async fn update_team(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(req): Json<TeamUpdate>,
+) -> Result<Json<Team>, ApiError> {
+    existing(&state.db, &id).await?;
+    if let Some(locale) = &req.locale {
+        sqlx::query("UPDATE teams SET locale = ? WHERE id = ?")
+            .bind(i18n::parse_setting(locale)?)
+            .bind(&id)
+            .execute(&state.db)
+            .await?;
+    }
+    Ok(Json(existing(&state.db, &id).await?))
+}
+
+// DELETE /teams/:id (admin). Not while one of its polls is still running, since that would open the poll up to
+// everyone; the polls it already had lose their team
+pub async fn delete_team(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let running: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM polls WHERE team_id = ? AND status != 'closed')")
+            .bind(&id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if running {
+        return Err(ApiError::Conflict(format!("team {id} has a poll that hasn't closed yet")));
+    }
+    sqlx::query("UPDATE polls SET team_id = NULL WHERE team_id = ?").bind(&id).execute(&mut *tx).await?;
+    let deleted = sqlx::query("DELETE FROM teams WHERE id = ?").bind(&id).execute(&mut *tx).await?;
+    if deleted.rows_affected() == 0 {
+        return Err(Message::no_team(id).into());
+    }
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// PUT /teams/:id/members/:name (admin): adding someone already on the team is harmless
+pub async fn add_member(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Json<Team>, ApiError> {
+    existing(&state.db, &id).await?;
+    let voter_name = names::canonical_voter_name(&state.db, &name).await?;
+    if voter_name.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    sqlx::query("INSERT OR IGNORE INTO team_members (team_id, voter_name, voter_key) VALUES (?, ?, ?)")
+        .bind(&id)
+        .bind(&voter_name)
+        .bind(names::fold(&voter_name))
+        .execute(&state.db)
+        .await?;
+    Ok(Json(existing(&state.db, &id).await?))
+}
+
+// DELETE /teams/:id/members/:name (admin)
+pub async fn remove_member(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let removed = sqlx::query("DELETE FROM team_members WHERE team_id = ? AND voter_key = ?")
+        .bind(&id)
+        .bind(names::fold(&name))
+        .execute(&state.db)
+        .await?;
+    if removed.rows_affected() == 0 {
+        return Err(Message::not_on_team(name, id).into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// PUT /teams/:id/captains/:name (admin): makes a member a captain; a team can have several
+pub async fn add_captain(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Json<Team>, ApiError> {
+    set_captain(&state.db, &id, &name, true).await?;
+    Ok(Json(existing(&state.db, &id).await?))
+}
+
+// DELETE /teams/:id/captains/:name (admin): back to an ordinary member
+pub async fn remove_captain(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Json<Team>, ApiError> {
+    set_captain(&state.db, &id, &name, false).await?;
+    Ok(Json(existing(&state.db, &id).await?))
+}
+
+async fn set_captain(db: &SqlitePool, id: &str, name: &str, captain: bool) -> Result<(), ApiError> {
+    let updated = sqlx::query("UPDATE team_members SET captain = ? WHERE team_id = ? AND voter_key = ?")
+        .bind(captain)
+        .bind(id)
+        .bind(names::fold(name))
+        .execute(db)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Err(Message::not_on_team(name, id).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// Ways of ordering restaurants that finish on the same number of votes. A poll picks one when it's created and
+// the results say which was used, which restaurants were tied for first, and anything needed to check the outcome
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::polls::Poll;
+use crate::{offices, stats, AppState, Restaurant};
+
+// Only daily wins in this many days before the lunch count towards fewest_recent_wins
+const RECENT_WINS_DAYS: i64 = 30;
+
+// Stored as snake_case text in polls.tiebreak, and read from ?tiebreak= the same way
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum Tiebreak {
+    #[default]
+    FirstVote, // whichever got its first vote earliest
+    Closest, // the shortest walk from the office; places with no known walking time go last
+    Random, // a draw from a seed that's reported with the results
+    FewestRecentWins, // whichever has won the fewest days lately, to spread lunches around
+    // a runoff poll between the tied restaurants, opened when the poll closes; until it's decided, first vote
+    Runoff,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TiebreakRecord {
+    strategy: Tiebreak,
+    pub tied: Vec<String>, // the restaurants that shared first place, in the order the tiebreak put them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recent_wins: Option<BTreeMap<String, i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runoff_poll_id: Option<String>, // the runoff's public id
+}
+
+// Reorders restaurants with equal scores according to the strategy; `votes` must already be sorted best score first.
+// Returns what happened at the top, or None when there was no tie for first place
+pub async fn apply(
+    state: &AppState,
+    poll: Option<&Poll>,
+    strategy: Tiebreak,
+    votes: &mut [Restaurant],
+) -> Result<Option<TiebreakRecord>, sqlx::Error> {
+    let top_score = votes.first().map_or(0, |restaurant| restaurant.score);
+    let tied_for_first = votes.iter().filter(|restaurant| restaurant.score == top_score).count();
+    let mut record = TiebreakRecord {
+        strategy,
+        tied: Vec::new(),
+        seed: None,
+        recent_wins: None,
+        runoff_poll_id: None,
+    };
+
+    // Every strategy only compares restaurants on the same score, and sort_by is stable,
+    // so anything the strategy can't separate stays in first-vote order
+    let by_score = |a: &Restaurant, b: &Restaurant| b.score.cmp(&a.score);
+    match strategy {
+        Tiebreak::FirstVote => {}
+        Tiebreak::Closest => {
+            let office = offices::find(&state.db, poll.and_then(|poll| poll.office_id.as_deref())).await?;
+            let office = offices::location(state, office.as_ref());
+            votes.sort_by(|a, b| {
+                let (walk_a, walk_b) = (a.details.walking_minutes(office), b.details.walking_minutes(office));
+                by_score(a, b).then_with(|| match (walk_a, walk_b) {
+                    (Some(walk_a), Some(walk_b)) => walk_a.total_cmp(&walk_b),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                })
+            });
+        }
+        Tiebreak::Random => {
+            // A poll keeps its seed so the draw never changes; plain /results draws afresh every time
+            let seed = match poll {
+                Some(poll) => poll.tiebreak_seed,
+                None => sqlx::query_scalar("SELECT random()").fetch_one(&state.db).await?,
+            };
+            votes.sort_by_cached_key(|restaurant| {
+                (std::cmp::Reverse(restaurant.score), draw(seed, &restaurant.name))
+            });
+            record.seed = Some(seed);
+        }
+        Tiebreak::FewestRecentWins => {
+            let wins = recent_wins(state, poll).await?;
+            let wins_of = |restaurant: &Restaurant| wins.get(&restaurant.name).copied().unwrap_or(0);
+            votes.sort_by(|a, b| by_score(a, b).then_with(|| wins_of(a).cmp(&wins_of(b))));
+            record.recent_wins = Some(
+                votes[..tied_for_first]
+                    .iter()
+                    .map(|restaurant| (restaurant.name.clone(), wins_of(restaurant)))
+                    .collect(),
+            );
+        }
+        Tiebreak::Runoff => record.runoff_poll_id = poll.and_then(|poll| poll.runoff_poll_public_id.clone()),
+    }
+
+    if tied_for_first < 2 {
+        return Ok(None);
+    }
+    record.tied = votes[..tied_for_first].iter().map(|restaurant| restaurant.name.clone()).collect();
+    Ok(Some(record))
+}
+
+// A restaurant's place in the draw: the seed and the name hashed together (FNV-1a, then SplitMix64's finalizer
+// to spread the bits), so anyone with the seed and the names can redo the draw. Lotteries draw with it too
+// https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function
+// https://prng.di.unimi.it/splitmix64.c
+pub fn draw(seed: i64, name: &str) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64 ^ seed as u64;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+// How many days each restaurant won in the RECENT_WINS_DAYS before the poll's lunch (or before today).
+// Daily winners are decided the same way as in /stats/cuisines; see stats::daily_winners_sql
+async fn recent_wins(state: &AppState, poll: Option<&Poll>) -> Result<BTreeMap<String, i64>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(&format!(
+        "{},
+        lunch AS (SELECT COALESCE(date(?), date('now', 'localtime')) AS day)
+        SELECT restaurant_name, COUNT(*) FROM winners, lunch
+        WHERE place = 1 AND winners.day < lunch.day AND winners.day >= date(lunch.day, '-{RECENT_WINS_DAYS} days')
+        GROUP BY restaurant_name",
+        stats::daily_winners_sql()
+    ))
+    .bind(poll.map(|poll| &poll.lunch_at))
+    .fetch_all(&state.db)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
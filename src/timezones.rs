@@ -0,0 +1,304 @@
+// IANA time zones, like "Australia/Sydney", for polls and offices. Everything that compares times in SQL shifts
+// 'now' by a fixed number of minutes, so a zone comes down to its UTC offset at the moment: a poll or office with a
+// timezone has its utc_offset_minutes kept to the zone's, set when the zone is and refreshed by the scheduler every
+// minute, which is how it follows daylight saving. The zones are read from the system's zone database, compiled
+// TZif files under /usr/share/zoneinfo or wherever ZONEINFO points, and the rule at the end of each file covers the
+// years after its last listed change
+// https://www.rfc-editor.org/rfc/rfc8536
+// https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/V1_chap08.html#tag_08_03
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{ApiError, Message};
+use crate::offices::POLL_CLOCK_SQL;
+use crate::AppState;
+
+// A zone as its file describes it: when the offset changed, and what to it, in seconds east of UTC
+struct Zone {
+    transitions: Vec<(i64, i64)>, // (UTC seconds since 1970, offset from then on), in order
+    first: i64,                   // the offset before the first transition
+    rule: Option<Rule>,           // the offset after the last one
+}
+
+// The POSIX TZ string at the end of a file: a standard offset and maybe a daylight-saving one with its dates
+struct Rule {
+    standard: i64,
+    dst: Option<(i64, Change, Change)>, // the offset, when it starts and when it ends
+}
+
+// A change of offset every year: the `week`th (5 is the last) `weekday` (0 is Sunday) of `month`, at `time` seconds
+// past local midnight
+#[derive(Clone, Copy)]
+struct Change {
+    month: i64,
+    week: i64,
+    weekday: i64,
+    time: i64,
+}
+
+// The zone's file, for names that look like a zone's and nothing else: no absolute paths or ..
+fn path(state: &AppState, name: &str) -> Option<PathBuf> {
+    let safe = |part: &str| {
+        !part.is_empty() && part != "." && part.chars().all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+    };
+    if !name.split('/').all(safe) {
+        return None;
+    }
+    Some(PathBuf::from(&state.config.zoneinfo_dir).join(name))
+}
+
+fn load(state: &AppState, name: &str) -> Option<Zone> {
+    parse(&std::fs::read(path(state, name)?).ok()?)
+}
+
+// The zone's name as given, trimmed, or BadRequest when there's no such zone
+pub fn validate(state: &AppState, name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    match load(state, name) {
+        Some(_) => Ok(name.to_string()),
+        None => Err(Message::not_time_zone(name).into()),
+    }
+}
+
+// The zone's UTC offset right now, in minutes; None for a zone that can't be read
+pub fn offset_now(state: &AppState, name: &str) -> Option<i64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+    Some(load(state, name)?.offset_at(now) / 60)
+}
+
+// Brings every office's and running poll's utc_offset_minutes in line with its timezone. Called by the scheduler
+// every minute; returns how many changed, which is none outside the nights the clocks change
+pub async fn refresh(state: &AppState) -> Result<u64, sqlx::Error> {
+    let mut changed = 0;
+    for table in ["offices", "polls"] {
+        let open = if table == "polls" { "AND status != 'closed'" } else { "" };
+        let zoned: Vec<(String, String, Option<i64>)> = sqlx::query_as(&format!(
+            "SELECT CAST(id AS TEXT), timezone, utc_offset_minutes FROM {table} WHERE timezone IS NOT NULL {open}"
+        ))
+        .fetch_all(&state.db)
+        .await?;
+        for (id, zone, current) in zoned {
+            let Some(offset) = offset_now(state, &zone) else {
+                eprintln!("timezones: can't read the time zone {zone}");
+                continue;
+            };
+            if current != Some(offset) {
+                sqlx::query(&format!("UPDATE {table} SET utc_offset_minutes = ? WHERE CAST(id AS TEXT) = ?"))
+                    .bind(offset)
+                    .bind(&id)
+                    .execute(&state.db)
+                    .await?;
+                changed += 1;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+// Days, as YYYY-MM-DD. Whatever is grouped or compared by the day goes through these, so that a day means the same
+// everywhere: the one on the clock of the poll or office it belongs to, or the server's outside both. A vote cast at
+// 9:00 in Sydney counts for that day, not for the evening before as its UTC time would have it
+
+// The day the UTC time `at` falls on by `clock`, a SQLite modifier like offices::clock's
+pub fn day_sql(at: &str, clock: &str) -> String {
+    format!("date({at}, {clock})")
+}
+
+// The day a vote was cast on, `votes` being the votes table or its alias in the query
+pub fn vote_day_sql(votes: &str) -> String {
+    let clock = format!("COALESCE((SELECT {POLL_CLOCK_SQL} FROM polls WHERE polls.id = {votes}.poll_id), 'localtime')");
+    day_sql(&format!("{votes}.created_at"), &clock)
+}
+
+impl Zone {
+    fn offset_at(&self, time: i64) -> i64 {
+        match self.transitions.iter().rposition(|&(at, _)| at <= time) {
+            None => self.first,
+            Some(last) if last + 1 == self.transitions.len() => match &self.rule {
+                Some(rule) => rule.offset_at(time),
+                None => self.transitions[last].1,
+            },
+            Some(index) => self.transitions[index].1,
+        }
+    }
+}
+
+impl Rule {
+    fn offset_at(&self, time: i64) -> i64 {
+        let Some((dst, start, end)) = self.dst else {
+            return self.standard;
+        };
+        let (year, _, _) = civil_from_days((time + self.standard).div_euclid(86400));
+        // Daylight saving starts on standard time and ends on daylight time, both local
+        let starts = start.at(year) - self.standard;
+        let ends = end.at(year) - dst;
+        let in_dst = if starts < ends { starts <= time && time < ends } else { !(ends <= time && time < starts) };
+        if in_dst {
+            dst
+        } else {
+            self.standard
+        }
+    }
+}
+
+impl Change {
+    // Seconds since 1970 of the change in `year`, on the local clock
+    fn at(self, year: i64) -> i64 {
+        let first = days_from_civil(year, self.month, 1);
+        let first_weekday = (first + 4).rem_euclid(7); // 1970-01-01 was a Thursday
+        let mut day = 1 + (self.weekday - first_weekday).rem_euclid(7) + (self.week - 1) * 7;
+        while day > days_in_month(year, self.month) {
+            day -= 7;
+        }
+        (first + day - 1) * 86400 + self.time
+    }
+}
+
+fn parse(data: &[u8]) -> Option<Zone> {
+    // The six counts after the magic, the version and 15 reserved bytes: UT/local indicators, standard/wall
+    // indicators, leap seconds, transitions, local time types and bytes of abbreviations
+    let counts = |at: usize| -> Option<[usize; 6]> {
+        if data.get(at..at + 4)? != b"TZif" {
+            return None;
+        }
+        let mut counts = [0; 6];
+        for (i, count) in counts.iter_mut().enumerate() {
+            let start = at + 20 + i * 4;
+            *count = u32::from_be_bytes(data.get(start..start + 4)?.try_into().ok()?) as usize;
+        }
+        Some(counts)
+    };
+    let block_len = |[isut, isstd, leap, time, types, chars]: [usize; 6], time_size: usize| {
+        time * time_size + time + types * 6 + chars + leap * (time_size + 4) + isstd + isut
+    };
+    let mut at = 0;
+    let mut time_size = 4;
+    let mut header = counts(0)?;
+    // Version 2 and later repeat everything with 64-bit times after the 32-bit block, and end with the rule
+    if *data.get(4)? >= b'2' {
+        at = 44 + block_len(header, 4);
+        time_size = 8;
+        header = counts(at)?;
+    }
+    let [_, _, _, time_count, type_count, _] = header;
+    let body = at + 44;
+    let read = |start: usize, size: usize| -> Option<i64> {
+        let bytes = data.get(start..start + size)?;
+        Some(match size {
+            8 => i64::from_be_bytes(bytes.try_into().ok()?),
+            _ => i32::from_be_bytes(bytes.try_into().ok()?) as i64,
+        })
+    };
+    let types_at = body + time_count * time_size + time_count;
+    let offset_of = |index: usize| read(types_at + index * 6, 4);
+    let mut transitions = Vec::with_capacity(time_count);
+    for i in 0..time_count {
+        let time = read(body + i * time_size, time_size)?;
+        let index = *data.get(body + time_count * time_size + i)? as usize;
+        if index >= type_count {
+            return None;
+        }
+        transitions.push((time, offset_of(index)?));
+    }
+    let first = offset_of(0)?;
+    let footer = body + block_len(header, time_size);
+    let rule = match time_size {
+        8 => data
+            .get(footer..)
+            .and_then(|rest| std::str::from_utf8(rest).ok())
+            .and_then(|rest| rest.trim_matches('\n').lines().next())
+            .and_then(parse_rule),
+        _ => None,
+    };
+    Some(Zone { transitions, first, rule })
+}
+
+// "AEST-10AEDT,M10.1.0,M4.1.0/3": a name, the offset west of UTC, and for zones with daylight saving its name,
+// its offset if it isn't an hour ahead, and the dates it starts and ends. Only the M form of dates, which every zone
+// in use has, is read
+fn parse_rule(rule: &str) -> Option<Rule> {
+    let mut rest = skip_name(rule)?;
+    let (standard, after) = parse_offset(rest)?;
+    let standard = -standard;
+    rest = after;
+    if rest.is_empty() {
+        return Some(Rule { standard, dst: None });
+    }
+    rest = skip_name(rest)?;
+    let dst = match parse_offset(rest) {
+        Some((offset, after)) => {
+            rest = after;
+            -offset
+        }
+        None => standard + 3600,
+    };
+    let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+    Some(Rule { standard, dst: Some((dst, parse_change(start)?, parse_change(end)?)) })
+}
+
+fn skip_name(text: &str) -> Option<&str> {
+    if let Some(quoted) = text.strip_prefix('<') {
+        return Some(&quoted[quoted.find('>')? + 1..]);
+    }
+    let length = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+    (length >= 3).then(|| &text[length..])
+}
+
+// [+-]hh[:mm[:ss]], in seconds, and what follows it
+fn parse_offset(text: &str) -> Option<(i64, &str)> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let length = digits.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(digits.len());
+    if length == 0 {
+        return None;
+    }
+    let mut seconds = 0;
+    for (part, scale) in digits[..length].split(':').zip([3600, 60, 1]) {
+        seconds += part.parse::<i64>().ok()? * scale;
+    }
+    Some((sign * seconds, &digits[length..]))
+}
+
+// Mm.w.d[/time], the time defaulting to 02:00
+fn parse_change(text: &str) -> Option<Change> {
+    let (date, time) = match text.split_once('/') {
+        Some((date, time)) => (date, parse_offset(time)?.0),
+        None => (text, 7200),
+    };
+    let mut parts = date.strip_prefix('M')?.split('.').map(|part| part.parse::<i64>().ok());
+    let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+        return None;
+    }
+    Some(Change { month, week, weekday, time })
+}
+
+// Days since 1970-01-01 of a date on the proleptic Gregorian calendar, and back
+// https://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let next = if month == 12 { days_from_civil(year + 1, 1, 1) } else { days_from_civil(year, month + 1, 1) };
+    next - days_from_civil(year, month, 1)
+}
@@ -0,0 +1,85 @@
+// HTTPS, when TLS_CERT_FILE and TLS_KEY_FILE are set: a second listener on TLS_PORT, beside the plain one on 3000,
+// serving the same app. ALPN offers HTTP/2 ahead of HTTP/1.1, so a browser or dashboard gets HTTP/2 and multiplexes
+// its requests and its long-lived event streams (/results/stream, GraphQL subscriptions) over one connection. Over
+// HTTP/1.1 each stream would hold a connection of its own, and browsers open at most six to a host, which a few open
+// tabs use up. The plain listener speaks HTTP/2 too, to clients that start with it rather than asking (prior
+// knowledge), which is how gRPC gets it; see grpc.rs. HTTP/3 would need an HTTP/3 crate on top of QUIC, and there
+// isn't one to use here, so there's none
+// https://docs.rs/rustls/latest/rustls/server/struct.ServerConfig.html
+// https://docs.rs/hyper-util/latest/hyper_util/server/conn/auto/index.html
+// https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events#sect1
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request};
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+
+use crate::config::Config;
+
+// The certificate chain and its key, read once at startup; none without TLS_CERT_FILE and TLS_KEY_FILE
+pub fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>, String> {
+    let (Some(cert_file), Some(key_file)) = (&config.tls_cert_file, &config.tls_key_file) else {
+        return Ok(None);
+    };
+    let open = |path: &str| File::open(path).map(BufReader::new).map_err(|err| format!("{path}: {err}"));
+    let certs = rustls_pemfile::certs(&mut open(cert_file)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{cert_file}: {err}"))?;
+    if certs.is_empty() {
+        return Err(format!("{cert_file} has no certificates in it"));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key_file)?)
+        .map_err(|err| format!("{key_file}: {err}"))?
+        .ok_or_else(|| format!("{key_file} has no private key in it"))?;
+    let mut server_config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| err.to_string())?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("{cert_file} and {key_file}: {err}"))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+// Accepts connections for as long as the server runs, each on a task of its own. Which HTTP a connection speaks is
+// read off its first bytes, so it's whichever ALPN settled on
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                eprintln!("tls: could not accept a connection: {err}");
+                continue;
+            }
+        };
+        let (acceptor, app) = (acceptor.clone(), app.clone());
+        tokio::spawn(async move {
+            // A handshake that fails, plain HTTP sent to this port or a certificate the client doesn't trust, only
+            // ends that connection
+            let Ok(stream) = acceptor.accept(stream).await else {
+                return;
+            };
+            // The client's address, as axum::serve gives it to the plain listener's requests, for the audit log and
+            // the rate limit
+            let app = app.map_request(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo::<SocketAddr>(remote_addr));
+                request
+            });
+            // A connection ending in an error is nearly always a client hanging up without saying goodbye first,
+            // close_notify in TLS, so they're let go without a word, as axum::serve does
+            let _ = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(app))
+                .await;
+        });
+    }
+}
@@ -0,0 +1,95 @@
+// The trash: restaurants and votes an admin deleted. Deleting only sets deleted_at (see migrations/0040), so nothing
+// is gone for good and an accidental deletion is one restore away. Every other query passes over trashed rows
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::refresh::Cause;
+use crate::restaurants::{self, Restaurant};
+use crate::{vote_events, AppState};
+
+// The votes in the trash, for queries that can't join votes to leave them out, e.g. over vote_credits
+pub const TRASHED_VOTES_SQL: &str = "SELECT id FROM votes WHERE deleted_at IS NOT NULL";
+
+#[derive(Serialize)]
+pub struct Trash {
+    restaurants: Vec<TrashedRestaurant>,
+    votes: Vec<TrashedVote>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct TrashedRestaurant {
+    id: String, // the restaurant's public id
+    name: String,
+    deleted_at: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct TrashedVote {
+    id: i64,
+    voter_name: String,
+    restaurant_name: String,
+    poll_id: Option<String>, // the poll's public id
+    created_at: String,
+    deleted_at: String,
+}
+
+// GET /trash (admin): everything deleted, most recently deleted first
+pub async fn list_trash(_admin: Admin, State(state): State<AppState>) -> Result<Json<Trash>, ApiError> {
+    let restaurants = sqlx::query_as::<_, TrashedRestaurant>(
+        "SELECT public_id AS id, name, deleted_at FROM restaurants WHERE deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC, id DESC",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let votes = sqlx::query_as::<_, TrashedVote>(
+        "SELECT v.id, v.voter_name, v.restaurant_name, p.public_id AS poll_id, v.created_at, v.deleted_at
+        FROM votes v LEFT JOIN polls p ON p.id = v.poll_id
+        WHERE v.deleted_at IS NOT NULL
+        ORDER BY v.deleted_at DESC, v.id DESC",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(Trash { restaurants, votes }))
+}
+
+// POST /trash/restaurants/:id/restore (admin): back on the lists and ballots, with its votes counting again
+pub async fn restore_restaurant(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(public_id): Path<String>,
+) -> Result<Json<Restaurant>, ApiError> {
+    let id: i64 = sqlx::query_scalar(
+        "UPDATE restaurants SET deleted_at = NULL WHERE public_id = ? AND deleted_at IS NOT NULL RETURNING id",
+    )
+    .bind(&public_id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| ApiError::NotFound(format!("no restaurant with id {public_id} in the trash")))?;
+    let restaurant = restaurants::find_by_id(&state.db, id).await?.ok_or(ApiError::DbError(sqlx::Error::RowNotFound))?;
+    Ok(Json(restaurant))
+}
+
+// POST /trash/votes/:id/restore (admin): the vote counts again, as it was
+pub async fn restore_vote(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let trashed: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM votes WHERE id = ? AND deleted_at IS NOT NULL)")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if !trashed {
+        return Err(ApiError::NotFound(format!("no vote with id {id} in the trash")));
+    }
+    vote_events::restore(&mut tx, id).await?;
+    tx.commit().await?;
+    state.refreshes.send(vote_events::poll_of(&state.db, id).await?, Cause::Correction);
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,134 @@
+// Writing ballots in batches. Everyone votes in the minute after the poll is posted, and a transaction each means a
+// write lock each, taken in turn, with the ones that wait too long failing as busy; worse, the receipt chain is read
+// before it's extended, and two transactions holding a read each can only deadlock once both want to write. Instead
+// each ballot that's been checked is queued, and one task per database writes whatever has queued up, up to
+// MAX_BATCH, in one transaction, each ballot with its receipt, waiting LINGER after the first for more to arrive.
+// The voter waits for their own ballot to be written, so nothing answers before it's in the database. Each ballot
+// gets a savepoint of its own within the batch: one that fails is undone alone, and answered with its error. Should
+// the batch not commit at all, every ballot in it is written again on its own, so each gets its own result rather
+// than the batch's. With the one writer, this is also where a voter's second ballot is sure to be caught, one sent
+// alongside the first included; see ballots.rs. The queue holds QUEUE ballots; past that, voters wait for room
+// https://www.sqlite.org/lang_savepoint.html
+use std::time::Duration;
+
+use sqlx::{Connection, SqliteConnection};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::receipts::{self, Choice, Receipt};
+use crate::vote_events::{self, CastVote};
+use crate::{ballots, AppState};
+
+const QUEUE: usize = 1024;
+const MAX_BATCH: usize = 100;
+const LINGER: Duration = Duration::from_millis(5);
+
+pub struct Queued {
+    vote: CastVote,
+    poll_id: Option<i64>, // the vote's poll_id is the public one
+    choice: Choice,       // for the receipt
+    written: oneshot::Sender<Result<(i64, Receipt), CastError>>,
+}
+
+pub enum CastError {
+    AlreadyVoted, // the voter has a ballot in already
+    DbError(sqlx::Error),
+}
+
+impl From<sqlx::Error> for CastError {
+    fn from(err: sqlx::Error) -> Self {
+        CastError::DbError(err)
+    }
+}
+
+#[derive(Clone)]
+pub struct Ballots(mpsc::Sender<Queued>);
+
+pub fn channel() -> (Ballots, mpsc::Receiver<Queued>) {
+    let (sender, receiver) = mpsc::channel(QUEUE);
+    (Ballots(sender), receiver)
+}
+
+impl Ballots {
+    // Queues the ballot and waits until it's written; the id of the vote and its receipt. The writer only goes away
+    // with the runtime, so a closed channel is the database going away too
+    pub async fn cast(
+        &self,
+        vote: CastVote,
+        poll_id: Option<i64>,
+        choice: Choice,
+    ) -> Result<(i64, Receipt), CastError> {
+        let (written, result) = oneshot::channel();
+        self.0.send(Queued { vote, poll_id, choice, written }).await.map_err(|_| sqlx::Error::PoolClosed)?;
+        result.await.map_err(|_| sqlx::Error::PoolClosed)?
+    }
+}
+
+pub fn spawn(state: AppState, mut queue: mpsc::Receiver<Queued>) {
+    tokio::spawn(async move {
+        while let Some(first) = queue.recv().await {
+            let mut batch = vec![first];
+            let until = tokio::time::Instant::now() + LINGER;
+            while batch.len() < MAX_BATCH {
+                match tokio::time::timeout_at(until, queue.recv()).await {
+                    Ok(Some(queued)) => batch.push(queued),
+                    _ => break,
+                }
+            }
+            write(&state, batch).await;
+        }
+    });
+}
+
+// The vote and then its receipt, in the caller's transaction, unless the voter has voted already
+async fn cast(state: &AppState, conn: &mut SqliteConnection, queued: &Queued) -> Result<(i64, Receipt), CastError> {
+    let (vote, choice) = (queued.vote.clone(), queued.choice.clone());
+    if ballots::has_voted(&mut *conn, queued.poll_id, &vote.voter_name).await? {
+        return Err(CastError::AlreadyVoted);
+    }
+    let (voter_name, poll_id) = (vote.voter_name.clone(), vote.poll_id.clone());
+    let vote_id = vote_events::cast(conn, vote).await?;
+    Ok((vote_id, receipts::append(state, conn, &voter_name, poll_id, choice).await?))
+}
+
+async fn write(state: &AppState, batch: Vec<Queued>) {
+    let written = async {
+        let mut tx = state.db.begin().await?;
+        let mut results = Vec::with_capacity(batch.len());
+        for queued in &batch {
+            let mut savepoint = tx.begin().await?;
+            match cast(state, &mut savepoint, queued).await {
+                Ok(written) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(written));
+                }
+                Err(err) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(err));
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(results)
+    };
+    match written.await {
+        Ok(results) => {
+            for (queued, result) in batch.into_iter().zip(results) {
+                // A voter who has given up waiting has dropped the other end; their vote counts all the same
+                let _ = queued.written.send(result);
+            }
+        }
+        Err(err) => {
+            eprintln!("vote batches: a batch of {} didn't commit, writing them one at a time: {err}", batch.len());
+            for queued in batch {
+                let result = async {
+                    let mut tx = state.db.begin().await?;
+                    let written = cast(state, &mut tx, &queued).await?;
+                    tx.commit().await?;
+                    Ok(written)
+                };
+                let result = result.await;
+                let _ = queued.written.send(result);
+            }
+        }
+    }
+}
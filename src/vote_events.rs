@@ -0,0 +1,454 @@
+// Votes as an append-only stream of events: a ballot is cast, changed (by an admin taking its comment down or
+// correcting it), deleted into the trash by an admin and maybe restored from it, or retracted. The stream is the
+// record; the votes table everything else reads is a projection of it, updated in the same transaction as each event
+// is written and rebuilt from scratch by POST /vote-events/replay
+// https://martinfowler.com/eaaDev/EventSourcing.html
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::Response;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::auth::Admin;
+use crate::error::{ApiError, Message};
+use crate::polls::{self, PollStatus};
+use crate::public_ids::{self, PollId};
+use crate::refresh::Cause;
+use crate::{cursors, names, participation, AppState, VoteChannel};
+
+// Internally tagged: the event's kind is a "type" field next to its data, {"type": "cast", "voter_name": ...}
+// https://serde.rs/enum-representations.html#internally-tagged
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum VoteEvent {
+    Cast(CastVote),
+    Changed(VoteChange),
+    Deleted,
+    Restored,
+    Retracted,
+}
+
+impl VoteEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            VoteEvent::Cast(_) => "cast",
+            VoteEvent::Changed(_) => "changed",
+            VoteEvent::Deleted => "deleted",
+            VoteEvent::Restored => "restored",
+            VoteEvent::Retracted => "retracted",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CastVote {
+    pub voter_name: String,
+    pub restaurant_name: String,
+    pub poll_id: Option<String>, // the poll's public id, so the stream doesn't depend on internal ids
+    pub backup_restaurant_name: Option<String>,
+    pub ranking: Option<Vec<String>>,
+    pub comment: Option<String>,
+    pub channel: VoteChannel,
+    // When the ballot was cast, if not when the event was written: a sealed ballot keeps the time it was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cast_at: Option<String>,
+    // When the voter's name was swapped for a token, once the vote outlived VOTE_RETENTION_DAYS; see retention.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anonymized_at: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct VoteChange {
+    #[serde(default)]
+    pub comment_removed: bool,
+    // An admin's correction: the vote moved to another restaurant or voter, and why
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restaurant_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voter_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// Writes an event and brings the projection up to date with it; the caller's transaction makes the two one step.
+// Returns the id of the vote it's about, which for a cast is a new one
+async fn record(conn: &mut SqliteConnection, vote_id: Option<i64>, event: VoteEvent) -> Result<i64, sqlx::Error> {
+    let at: String = sqlx::query_scalar("SELECT CURRENT_TIMESTAMP").fetch_one(&mut *conn).await?;
+    let vote_id = apply(conn, vote_id, &event, &at).await?;
+    sqlx::query("INSERT INTO vote_events (vote_id, type, payload, created_at) VALUES (?, ?, ?, ?)")
+        .bind(vote_id)
+        .bind(event.kind())
+        .bind(JsonColumn(&event))
+        .bind(&at)
+        .execute(&mut *conn)
+        .await?;
+    Ok(vote_id)
+}
+
+// The projection: what one event does to the votes table. A cast during replay gets back the id it had before
+async fn apply(
+    conn: &mut SqliteConnection,
+    vote_id: Option<i64>,
+    event: &VoteEvent,
+    at: &str,
+) -> Result<i64, sqlx::Error> {
+    match event {
+        VoteEvent::Cast(vote) => {
+            // An INTEGER PRIMARY KEY given NULL picks the next id itself
+            // https://www.sqlite.org/autoinc.html
+            sqlx::query_scalar(
+                "INSERT INTO votes
+                    (id, voter_name, restaurant_name, poll_id, backup_restaurant_name, ranking, comment, channel,
+                    created_at, anonymized_at)
+                VALUES (?, ?, ?, (SELECT id FROM polls WHERE public_id = ?), ?, ?, ?, ?, ?, ?)
+                RETURNING id",
+            )
+            .bind(vote_id)
+            .bind(&vote.voter_name)
+            .bind(&vote.restaurant_name)
+            .bind(&vote.poll_id)
+            .bind(&vote.backup_restaurant_name)
+            .bind(vote.ranking.as_ref().map(JsonColumn))
+            .bind(&vote.comment)
+            .bind(vote.channel)
+            .bind(vote.cast_at.as_deref().unwrap_or(at))
+            .bind(&vote.anonymized_at)
+            .fetch_one(&mut *conn)
+            .await
+        }
+        VoteEvent::Changed(change) => {
+            let vote_id = vote_id.ok_or(sqlx::Error::RowNotFound)?;
+            // Setting updated_at here keeps the event's time through a replay; the votes_updated_at trigger only
+            // steps in when an update leaves it alone
+            sqlx::query(
+                "UPDATE votes SET comment_removed_at = CASE WHEN ? THEN ? ELSE comment_removed_at END,
+                    restaurant_name = COALESCE(?, restaurant_name), voter_name = COALESCE(?, voter_name), updated_at = ?
+                WHERE id = ?",
+            )
+            .bind(change.comment_removed)
+            .bind(at)
+            .bind(&change.restaurant_name)
+            .bind(&change.voter_name)
+            .bind(at)
+            .bind(vote_id)
+            .execute(&mut *conn)
+            .await?;
+            // A quadratic ballot's credits are its voter's, so they follow it to whoever it now belongs to
+            if let Some(voter_name) = &change.voter_name {
+                sqlx::query("UPDATE vote_credits SET voter_name = ?, voter_key = ? WHERE vote_id = ?")
+                    .bind(voter_name)
+                    .bind(names::fold(voter_name))
+                    .bind(vote_id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+            Ok(vote_id)
+        }
+        VoteEvent::Deleted | VoteEvent::Restored => {
+            let vote_id = vote_id.ok_or(sqlx::Error::RowNotFound)?;
+            let deleted_at = matches!(event, VoteEvent::Deleted).then_some(at);
+            sqlx::query("UPDATE votes SET deleted_at = ?, updated_at = ? WHERE id = ?")
+                .bind(deleted_at)
+                .bind(at)
+                .bind(vote_id)
+                .execute(&mut *conn)
+                .await?;
+            Ok(vote_id)
+        }
+        VoteEvent::Retracted => {
+            let vote_id = vote_id.ok_or(sqlx::Error::RowNotFound)?;
+            // A quadratic ballot's credits go with it
+            sqlx::query("DELETE FROM vote_credits WHERE vote_id = ?")
+                .bind(vote_id)
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query("DELETE FROM votes WHERE id = ?")
+                .bind(vote_id)
+                .execute(&mut *conn)
+                .await?;
+            Ok(vote_id)
+        }
+    }
+}
+
+pub async fn cast(conn: &mut SqliteConnection, vote: CastVote) -> Result<i64, sqlx::Error> {
+    record(conn, None, VoteEvent::Cast(vote)).await
+}
+
+pub async fn change(conn: &mut SqliteConnection, vote_id: i64, change: VoteChange) -> Result<(), sqlx::Error> {
+    record(conn, Some(vote_id), VoteEvent::Changed(change)).await?;
+    Ok(())
+}
+
+pub async fn delete(conn: &mut SqliteConnection, vote_id: i64) -> Result<(), sqlx::Error> {
+    record(conn, Some(vote_id), VoteEvent::Deleted).await?;
+    Ok(())
+}
+
+pub async fn restore(conn: &mut SqliteConnection, vote_id: i64) -> Result<(), sqlx::Error> {
+    record(conn, Some(vote_id), VoteEvent::Restored).await?;
+    Ok(())
+}
+
+pub async fn retract(conn: &mut SqliteConnection, vote_id: i64) -> Result<(), sqlx::Error> {
+    record(conn, Some(vote_id), VoteEvent::Retracted).await?;
+    Ok(())
+}
+
+// DELETE /votes/:id (admin): moves a vote to the trash, out of every tally, until it's restored
+pub async fn delete_vote(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let mut tx = state.db.begin().await?;
+    let live: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM votes WHERE id = ? AND deleted_at IS NULL)")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !live {
+        return Err(Message::no_vote(id).into());
+    }
+    delete(&mut tx, id).await?;
+    tx.commit().await?;
+    state.refreshes.send(poll_of(&state.db, id).await?, Cause::Correction);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The public id of the poll a vote is in, if it's in one
+pub async fn poll_of(db: &SqlitePool, vote_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let poll: Option<Option<String>> =
+        sqlx::query_scalar("SELECT p.public_id FROM votes v LEFT JOIN polls p ON p.id = v.poll_id WHERE v.id = ?")
+            .bind(vote_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(poll.flatten())
+}
+
+#[derive(Deserialize)]
+pub struct Correction {
+    restaurant_name: Option<String>,
+    voter_name: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Correctable {
+    poll_id: Option<i64>,
+    backup_restaurant_name: Option<String>,
+    ranked: bool,
+    quadratic: bool,
+}
+
+// PATCH /votes/:id (admin): corrects a vote, moving it to the restaurant that was meant (a fat-fingered choice) or to
+// the voter it really came from (someone voting as somebody else). The vote is checked like a new ballot would be.
+// The correction is an event like any other, so GET /vote-events shows what changed and why
+pub async fn correct_vote(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<Correction>,
+) -> Result<StatusCode, ApiError> {
+    if req.restaurant_name.is_none() && req.voter_name.is_none() {
+        return Err(ApiError::BadRequest("send the restaurant_name or voter_name the vote should have".to_string()));
+    }
+    let vote = sqlx::query_as::<_, Correctable>(
+        "SELECT poll_id, backup_restaurant_name, ranking IS NOT NULL AS ranked,
+            EXISTS (SELECT 1 FROM vote_credits WHERE vote_id = votes.id) AS quadratic
+        FROM votes WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or_else(|| Message::no_vote(id))?;
+
+    let restaurant_name = match req.restaurant_name {
+        // Which restaurants a ranked or quadratic ballot backs is spread over the whole ballot, so there's no one
+        // name to swap; the voter casts it again instead
+        Some(_) if vote.ranked || vote.quadratic => {
+            return Err(ApiError::Conflict(format!(
+                "vote {id} is a ranked or quadratic ballot, so it can't be moved to another restaurant; delete it \
+                and have the voter vote again"
+            )));
+        }
+        Some(name) => {
+            let poll = match vote.poll_id {
+                Some(poll_id) => Some(polls::find_poll(&state.db, poll_id).await?.ok_or(sqlx::Error::RowNotFound)?),
+                None => None,
+            };
+            let name = crate::ballot_name(&state, poll.as_ref(), name).await?;
+            if vote.backup_restaurant_name.as_ref() == Some(&name) {
+                return Err(ApiError::BadRequest(format!("{name} is this vote's backup choice")));
+            }
+            if let Some(poll) = &poll {
+                if !polls::choices(&state, poll).await?.contains(&name) {
+                    return Err(ApiError::BadRequest(format!("{name} is not a candidate in this vote's poll")));
+                }
+            }
+            Some(name)
+        }
+        None => None,
+    };
+    let voter_name = match req.voter_name {
+        Some(voter) => {
+            let voter = names::canonical_voter_name(&state.db, &voter).await?;
+            if voter.is_empty() {
+                return Err(Message::voter_name_empty().into());
+            }
+            Some(voter)
+        }
+        None => None,
+    };
+    let reason = req.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty());
+
+    let mut tx = state.db.begin().await?;
+    let correction = VoteChange { restaurant_name, voter_name: voter_name.clone(), reason, ..Default::default() };
+    change(&mut tx, id, correction).await?;
+    tx.commit().await?;
+    // Like a vote of their own, it takes back an abstention of theirs in the poll
+    if let (Some(poll_id), Some(voter_name)) = (vote.poll_id, &voter_name) {
+        participation::withdraw_abstention(&state.db, poll_id, voter_name).await?;
+    }
+    println!("corrected vote {id}");
+    state.refreshes.send(poll_of(&state.db, id).await?, Cause::Correction);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// DELETE /polls/:id/votes/:voter: takes back everything a voter has cast in a poll that's still open, trashed votes
+// included. Quadratic credits are refunded. Sealed ballots can't be picked out before the poll closes, so they stay
+pub async fn retract_votes(
+    State(state): State<AppState>,
+    PollId(id, public_id): PollId,
+    Path((_, voter)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let poll = polls::find_poll(&state.db, id)
+        .await?
+        .ok_or_else(|| Message::no_poll(&public_id))?;
+    if poll.status != PollStatus::Open {
+        return Err(ApiError::Conflict("votes can only be retracted while the poll is open".to_string()));
+    }
+    if poll.sealed {
+        return Err(ApiError::Conflict("ballots in a sealed poll can't be retracted".to_string()));
+    }
+    let voter_name = names::canonical_voter_name(&state.db, &voter).await?;
+    let mut tx = state.db.begin().await?;
+    let vote_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM votes WHERE poll_id = ? AND voter_name = ?")
+        .bind(id)
+        .bind(&voter_name)
+        .fetch_all(&mut *tx)
+        .await?;
+    if vote_ids.is_empty() {
+        return Err(Message::not_voted(voter_name).into());
+    }
+    for vote_id in vote_ids {
+        retract(&mut tx, vote_id).await?;
+    }
+    tx.commit().await?;
+    state.refreshes.send(Some(poll.public_id), Cause::Vote);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+pub struct VoteEventEntry {
+    sequence: i64,
+    vote_id: i64,
+    #[serde(flatten)]
+    event: VoteEvent,
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct VoteEventQuery {
+    poll_id: Option<String>, // the poll's public id
+    voter: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+    cursor: Option<String>, // from the Link of the page before; see cursors.rs
+}
+
+fn default_limit() -> i64 {
+    1000
+}
+
+// GET /vote-events?poll_id=...&voter=...&limit=1000 (admin): the stream in order, each vote's whole history. Filters
+// apply to the cast, so the changes and retractions of the votes that match come along. The whole stream can be long,
+// so it comes a page at a time, the Link header leading on to the next, and it's read from the replica when there is
+// one
+pub async fn list_events(
+    _admin: Admin,
+    State(state): State<AppState>,
+    Query(query): Query<VoteEventQuery>,
+    uri: Uri,
+) -> Result<Response, ApiError> {
+    if !(1..=1000).contains(&query.limit) {
+        return Err(ApiError::BadRequest("limit must be between 1 and 1000".to_string()));
+    }
+    let after = cursors::position("vote-events", query.cursor.as_deref())?;
+    if let Some(poll_id) = &query.poll_id {
+        public_ids::poll(&state.db, poll_id).await?;
+    }
+    let voter = match &query.voter {
+        Some(voter) => Some(names::canonical_voter_name(&state.db, voter).await?),
+        None => None,
+    };
+    let rows: Vec<(i64, i64, JsonColumn<VoteEvent>, String)> = sqlx::query_as(
+        "SELECT sequence, vote_id, payload, created_at FROM vote_events
+        WHERE vote_id IN (
+            SELECT vote_id FROM vote_events WHERE type = 'cast'
+            AND (? IS NULL OR json_extract(payload, '$.poll_id') = ?)
+            AND (? IS NULL OR json_extract(payload, '$.voter_name') = ?)
+        )
+        AND (? IS NULL OR sequence > ?)
+        ORDER BY sequence
+        LIMIT ?",
+    )
+    .bind(&query.poll_id)
+    .bind(&query.poll_id)
+    .bind(&voter)
+    .bind(&voter)
+    .bind(after)
+    .bind(after)
+    .bind(query.limit + 1)
+    .fetch_all(&state.reads)
+    .await?;
+    let entries = rows
+        .into_iter()
+        .map(|(sequence, vote_id, JsonColumn(event), created_at)| VoteEventEntry {
+            sequence,
+            vote_id,
+            event,
+            created_at,
+        })
+        .collect();
+    Ok(cursors::page("vote-events", &uri, query.limit, entries, |entry| entry.sequence))
+}
+
+#[derive(Serialize)]
+pub struct Replay {
+    events: usize,
+    votes: i64, // in the rebuilt projection
+}
+
+// POST /vote-events/replay (admin): throws the votes table away and rebuilds it by replaying every event, e.g.
+// after changing how the projection works or to check it hasn't drifted from the stream
+pub async fn replay(_admin: Admin, State(state): State<AppState>) -> Result<Json<Replay>, ApiError> {
+    let mut tx = state.db.begin().await?;
+    // vote_credits points at votes, which are all gone for a moment; the check waits for the commit, by when the
+    // votes are back under the same ids
+    // https://www.sqlite.org/pragma.html#pragma_defer_foreign_keys
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM votes").execute(&mut *tx).await?;
+    let events: Vec<(i64, JsonColumn<VoteEvent>, String)> =
+        sqlx::query_as("SELECT vote_id, payload, created_at FROM vote_events ORDER BY sequence")
+            .fetch_all(&mut *tx)
+            .await?;
+    for (vote_id, JsonColumn(event), at) in &events {
+        apply(&mut tx, Some(*vote_id), event, at).await?;
+    }
+    let votes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM votes").fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    println!("replayed {} vote events into {votes} votes", events.len());
+    Ok(Json(Replay { events: events.len(), votes }))
+}
@@ -0,0 +1,237 @@
+// One-click voting links, for notifications: GET /vote?token=...&restaurant=... votes for that restaurant as the
+// voter the token was made for, in the poll it was made for, without logging in, so a reminder can carry one link
+// per candidate. Like an invitation's, the token is its row's id signed with the receipt key, so it can't be guessed
+// or moved to another voter or poll. It's spent by the first vote cast with it, whichever of the links that was, and
+// won't vote again after that; the ballot itself still has to pass everything POST /vote checks. Only polls where a
+// single choice makes a whole ballot get links, so not ranked or quadratic ones. On a poll over options the links
+// carry ?option=... instead of the restaurant. Opening a link doesn't vote by itself: the GET answers with a page
+// that posts the same to POST /vote/link as soon as a browser shows it, since mail scanners open every link in a
+// message to check it, and browsers fetch links ahead of a click, and either would spend the token otherwise
+use axum::extract::Query;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, REFERRER_POLICY};
+use axum::response::{IntoResponse, Response};
+use ring::hmac;
+use serde::Deserialize;
+
+use crate::auth;
+use crate::error::{ApiError, Message};
+use crate::i18n;
+use crate::polls::{self, Poll, VotingMethod};
+use crate::receipts::hex;
+use crate::restaurants::RestaurantOrder;
+use crate::{names, AppState};
+
+#[derive(Deserialize)]
+pub struct LinkVote {
+    pub token: String,
+    pub restaurant: Option<String>, // the restaurant's public id
+    pub option: Option<String>,
+}
+
+// What a spent token votes as
+pub struct Ballot {
+    pub link_id: i64,
+    pub poll_id: String, // the poll's public id
+    pub voter_name: String,
+    pub restaurant_name: String,
+}
+
+// The prefix keeps this signature from passing for a receipt's, an invitation's or an unsubscribe link's, which
+// share the key
+fn signature(state: &AppState, id: i64, poll_id: &str, voter_key: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &state.config.receipt_key);
+    hex(hmac::sign(&key, format!("vote-link:{id}:{poll_id}:{voter_key}").as_bytes()).as_ref())
+}
+
+fn one_click(method: VotingMethod) -> bool {
+    !method.is_ranked() && method != VotingMethod::Quadratic
+}
+
+// A fresh token for `voter_name` in `poll`, and a link with it for each of the poll's candidates or options, by name.
+// Empty for polls a single choice can't vote in
+pub async fn links(state: &AppState, poll: &Poll, voter_name: &str) -> Result<Vec<(String, String)>, ApiError> {
+    if !one_click(poll.voting_method) {
+        return Ok(Vec::new());
+    }
+    let candidates: Vec<(String, (&str, String))> = match poll.options() {
+        Some(options) => options.iter().map(|option| (option.clone(), ("option", option.clone()))).collect(),
+        None => polls::candidates(state, poll, RestaurantOrder::Name)
+            .await?
+            .into_iter()
+            .map(|restaurant| (restaurant.name, ("restaurant", restaurant.public_id)))
+            .collect(),
+    };
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let voter_key = names::fold(voter_name);
+    let id: i64 =
+        sqlx::query_scalar("INSERT INTO vote_links (poll_id, voter_key, voter_name) VALUES (?, ?, ?) RETURNING id")
+            .bind(poll.id)
+            .bind(&voter_key)
+            .bind(voter_name)
+            .fetch_one(&state.db)
+            .await?;
+    let token = format!("{id}.{}", signature(state, id, &poll.public_id, &voter_key));
+    let base = format!("{}/vote", state.config.public_url.trim_end_matches('/'));
+    Ok(candidates
+        .into_iter()
+        .map(|(name, (param, value))| {
+            let url = match reqwest::Url::parse_with_params(&base, &[("token", token.as_str()), (param, &value)]) {
+                Ok(url) => url.to_string(),
+                Err(_) => format!("{base}?token={token}&{param}={value}"),
+            };
+            (name, url)
+        })
+        .collect())
+}
+
+// GET /vote?token=...&restaurant=...: the page a link opens, with the link's fields in a form it submits straight
+// away, or on its button without scripts. It's written for a page being prerendered as well, which only votes once
+// it's shown. Nothing is looked up, so a bad token is only turned down by the POST. Never cached or sent on as a
+// referrer, since the token is in it
+pub async fn confirm(Query(link): Query<LinkVote>) -> Response {
+    let locale = i18n::accepted().unwrap_or_default();
+    let mut fields = vec![("token", &link.token)];
+    fields.extend(link.restaurant.iter().map(|restaurant| ("restaurant", restaurant)));
+    fields.extend(link.option.iter().map(|option| ("option", option)));
+    let inputs: String = fields
+        .into_iter()
+        .map(|(name, value)| format!("<input type=\"hidden\" name=\"{name}\" value=\"{}\">\n", escape(value)))
+        .collect();
+    // The action is relative, so it stays under PUBLIC_URL's path
+    let page = format!(
+        "<!doctype html>
+<html lang=\"{lang}\">
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width\">
+<title>{title}</title>
+<form method=\"post\" action=\"vote/link\">
+{inputs}<button>{button}</button>
+</form>
+<script>
+const vote = () => document.forms[0].submit();
+if (document.prerendering) document.addEventListener(\"prerenderingchange\", vote, {{ once: true }}); else vote();
+</script>
+",
+        lang = locale.code(),
+        title = escape(&i18n::text(locale, "vote-link-title", &[])),
+        button = escape(&i18n::text(locale, "vote-link-button", &[])),
+    );
+    let headers = [
+        (CONTENT_TYPE, "text/html; charset=utf-8"),
+        (CACHE_CONTROL, "no-store"),
+        (REFERRER_POLICY, "no-referrer"),
+    ];
+    (headers, page).into_response()
+}
+
+// Text as it has to be written inside an HTML attribute or element
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Spends the token on a vote for `restaurant`. Forbidden for a token whose signature doesn't hold, Conflict once it
+// has been spent. The claim comes first, so two clicks at once can't both get through; release gives it back when
+// the vote is then turned down
+pub async fn redeem(state: &AppState, link: &LinkVote) -> Result<Ballot, ApiError> {
+    let invalid = || ApiError::Forbidden("that voting link isn't valid".to_string());
+    let id = link.token.split_once('.').and_then(|(id, _)| id.parse::<i64>().ok()).ok_or_else(invalid)?;
+    let row: Option<(String, String, String)> = sqlx::query_as(
+        "SELECT p.public_id, l.voter_key, l.voter_name FROM vote_links l JOIN polls p ON p.id = l.poll_id
+        WHERE l.id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let (poll_id, voter_key, voter_name) = row.ok_or_else(invalid)?;
+    let expected = format!("{id}.{}", signature(state, id, &poll_id, &voter_key));
+    if !auth::constant_time_eq(expected.as_bytes(), link.token.as_bytes()) {
+        return Err(invalid());
+    }
+    // An option is checked against the poll by the vote itself
+    let restaurant_name = match (&link.restaurant, &link.option) {
+        (Some(restaurant), None) => sqlx::query_scalar("SELECT name FROM restaurants WHERE public_id = ?")
+            .bind(restaurant)
+            .fetch_optional(&state.db)
+            .await?
+            .ok_or_else(|| Message::no_restaurant(restaurant))?,
+        (None, Some(option)) => option.clone(),
+        _ => return Err(ApiError::BadRequest("a voting link names either a restaurant or an option".to_string())),
+    };
+    let claimed = sqlx::query("UPDATE vote_links SET used_at = CURRENT_TIMESTAMP WHERE id = ? AND used_at IS NULL")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+    if claimed.rows_affected() == 0 {
+        return Err(Message::link_used().into());
+    }
+    Ok(Ballot { link_id: id, poll_id, voter_name, restaurant_name })
+}
+
+pub async fn release(state: &AppState, link_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE vote_links SET used_at = NULL WHERE id = ?").bind(link_id).execute(&state.db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Method, Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    // A GET, prefetched or not, gets the page and nothing else: the router has no state to spend the token with,
+    // and the token only goes on in the form the page posts
+    #[tokio::test]
+    async fn prefetching_a_link_leaves_the_voting_to_the_page() {
+        let router = Router::new().route("/vote", get(confirm));
+        for (method, header) in [
+            (Method::GET, None),
+            (Method::GET, Some(("Sec-Purpose", "prefetch"))),
+            (Method::GET, Some(("Purpose", "prefetch"))),
+            (Method::HEAD, None),
+        ] {
+            let mut request = Request::builder().method(method.clone()).uri("/vote?token=7.abc&option=Pizza");
+            if let Some((name, value)) = header {
+                request = request.header(name, value);
+            }
+            let response = router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+            assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let page = String::from_utf8(body.to_vec()).unwrap();
+            if method == Method::HEAD {
+                assert!(page.is_empty());
+                continue;
+            }
+            assert!(page.contains("<form method=\"post\" action=\"vote/link\">"));
+            assert!(page.contains("<input type=\"hidden\" name=\"token\" value=\"7.abc\">"));
+            assert!(page.contains("<input type=\"hidden\" name=\"option\" value=\"Pizza\">"));
+        }
+    }
+
+    #[tokio::test]
+    async fn what_the_link_carries_is_escaped_on_the_page() {
+        let option = Some("<b>Fish & 'Chips'".to_string());
+        let link = LinkVote { token: "1.a\"b".to_string(), restaurant: None, option };
+        let body = to_bytes(confirm(Query(link)).await.into_body(), usize::MAX).await.unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("value=\"1.a&quot;b\""));
+        assert!(page.contains("value=\"&lt;b&gt;Fish &amp; &#39;Chips&#39;\""));
+        assert!(!page.contains("<b>"));
+    }
+}
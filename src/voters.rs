@@ -0,0 +1,278 @@
+// Per-voter settings, and the profile. Voters aren't registered anywhere; they're identified by name, compared in
+// folded form so "Zoë" and "zoe" share one blacklist and one set of preferences
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as JsonColumn;
+use sqlx::SqlitePool;
+
+use crate::error::{ApiError, Message};
+use crate::public_ids::RestaurantId;
+use crate::restaurants::{self, RestaurantDetails};
+use crate::{badges, names, AppState};
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct BlacklistEntry {
+    restaurant_id: String, // the restaurant's public id
+    restaurant_name: String,
+    created_at: String,
+}
+
+fn voter_key(name: &str) -> Result<String, ApiError> {
+    let key = names::fold(name);
+    if key.is_empty() {
+        return Err(Message::voter_name_missing().into());
+    }
+    Ok(key)
+}
+
+// What there is to show about a voter: how much they've taken part, and their badges (see badges.rs). Only closed
+// polls count, so nothing here gives away who has voted in a poll whose results are still hidden
+#[derive(Serialize)]
+pub struct Profile {
+    name: String,
+    polls_voted: i64,
+    first_voted_at: Option<String>,
+    last_voted_at: Option<String>,
+    badges: Vec<badges::Badge>,
+}
+
+// GET /voters/:name
+pub async fn get_profile(State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<Profile>, ApiError> {
+    let key = voter_key(&name)?;
+    let voted: Vec<(String, i64, String, String)> = sqlx::query_as(
+        "SELECT v.voter_name, COUNT(DISTINCT v.poll_id), MIN(v.created_at), MAX(v.created_at)
+        FROM votes v JOIN polls p ON p.id = v.poll_id
+        WHERE v.deleted_at IS NULL AND p.status = 'closed' GROUP BY v.voter_name",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    // Each spelling of their name is counted, so a poll they voted in under two would count twice; merging the
+    // names (see merge.rs) puts that right
+    let theirs: Vec<_> = voted.into_iter().filter(|(voter, ..)| names::fold(voter) == key).collect();
+    let profile = Profile {
+        name: names::canonical_voter_name(&state.db, &name).await?,
+        polls_voted: theirs.iter().map(|(_, polls, ..)| polls).sum(),
+        first_voted_at: theirs.iter().map(|(_, _, first, _)| first).min().cloned(),
+        last_voted_at: theirs.iter().map(|(.., last)| last).max().cloned(),
+        badges: badges::badges(&state.db, &key).await?,
+    };
+    if profile.polls_voted == 0 && profile.badges.is_empty() {
+        return Err(ApiError::NotFound(format!("{name} hasn't voted in a poll that's closed")));
+    }
+    Ok(Json(profile))
+}
+
+// The restaurant ids a voter has blacklisted
+pub async fn blacklisted_ids(db: &SqlitePool, voter: &str) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT restaurant_id FROM voter_blacklist WHERE voter_key = ?")
+        .bind(names::fold(voter))
+        .fetch_all(db)
+        .await
+}
+
+// GET /voters/:name/blacklist
+pub async fn get_blacklist(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<BlacklistEntry>>, ApiError> {
+    let entries = sqlx::query_as::<_, BlacklistEntry>(
+        "SELECT r.public_id AS restaurant_id, r.name AS restaurant_name, b.created_at
+        FROM voter_blacklist b JOIN restaurants r ON r.id = b.restaurant_id AND r.deleted_at IS NULL
+        WHERE b.voter_key = ?
+        ORDER BY r.name",
+    )
+    .bind(voter_key(&name)?)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(Json(entries))
+}
+
+// PUT /voters/:name/blacklist/:restaurant_id: never again. Putting the same restaurant twice is harmless
+pub async fn add_to_blacklist(
+    State(state): State<AppState>,
+    Path((name, _)): Path<(String, String)>,
+    RestaurantId(restaurant_id): RestaurantId,
+) -> Result<StatusCode, ApiError> {
+    let key = voter_key(&name)?;
+    sqlx::query("INSERT OR IGNORE INTO voter_blacklist (voter_key, restaurant_id) VALUES (?, ?)")
+        .bind(key)
+        .bind(restaurant_id)
+        .execute(&state.db)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// DELETE /voters/:name/blacklist/:restaurant_id: second chances
+pub async fn remove_from_blacklist(
+    State(state): State<AppState>,
+    Path((name, _)): Path<(String, String)>,
+    RestaurantId(restaurant_id): RestaurantId,
+) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM voter_blacklist WHERE voter_key = ? AND restaurant_id = ?")
+        .bind(voter_key(&name)?)
+        .bind(restaurant_id)
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("that restaurant is not on {name}'s blacklist")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// A voter's standing preferences. Dietary needs and the price limit are hard requirements when they apply;
+// cuisines are only likes, used to rank recommendations
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Preferences {
+    voter_name: String,
+    cuisines: JsonColumn<Vec<String>>,
+    max_price_tier: Option<i64>, // 1-4, like a restaurant's price_tier; None means no limit
+    dietary_needs: JsonColumn<Vec<String>>, // same tags as restaurants' dietary_tags
+    updated_at: String,
+}
+
+// PUT replaces the whole profile, so a field left out is cleared
+#[derive(Deserialize)]
+pub struct NewPreferences {
+    #[serde(default)]
+    cuisines: Vec<String>,
+    max_price_tier: Option<i64>,
+    #[serde(default)]
+    dietary_needs: Vec<String>,
+}
+
+const PREFERENCES_COLUMNS: &str = "voter_name, cuisines, max_price_tier, dietary_needs, updated_at";
+
+// GET /voters/:name/preferences
+pub async fn get_preferences(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Preferences>, ApiError> {
+    sqlx::query_as::<_, Preferences>(&format!(
+        "SELECT {PREFERENCES_COLUMNS} FROM voter_preferences WHERE voter_key = ?"
+    ))
+    .bind(voter_key(&name)?)
+    .fetch_optional(&state.db)
+    .await?
+    .map(Json)
+    .ok_or_else(|| ApiError::NotFound(format!("{name} has no preferences saved")))
+}
+
+// PUT /voters/:name/preferences
+pub async fn put_preferences(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<NewPreferences>,
+) -> Result<Json<Preferences>, ApiError> {
+    let key = voter_key(&name)?;
+    if req.max_price_tier.is_some_and(|tier| !(1..=4).contains(&tier)) {
+        return Err(ApiError::BadRequest("max_price_tier must be between 1 and 4".to_string()));
+    }
+    let mut cuisines: Vec<String> =
+        req.cuisines.into_iter().filter_map(|cuisine| restaurants::normalize_cuisine(Some(cuisine))).collect();
+    cuisines.sort();
+    cuisines.dedup();
+    let voter_name = names::canonical_voter_name(&state.db, &name).await?;
+
+    // An upsert: the first PUT creates the row, later ones overwrite it
+    // https://www.sqlite.org/lang_upsert.html
+    let preferences = sqlx::query_as::<_, Preferences>(&format!(
+        "INSERT INTO voter_preferences (voter_key, voter_name, cuisines, max_price_tier, dietary_needs)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (voter_key) DO UPDATE SET voter_name = excluded.voter_name, cuisines = excluded.cuisines,
+            max_price_tier = excluded.max_price_tier, dietary_needs = excluded.dietary_needs,
+            updated_at = CURRENT_TIMESTAMP
+        RETURNING {PREFERENCES_COLUMNS}"
+    ))
+    .bind(key)
+    .bind(voter_name)
+    .bind(JsonColumn(cuisines))
+    .bind(req.max_price_tier)
+    .bind(JsonColumn(restaurants::normalize_tags(req.dietary_needs)))
+    .fetch_one(&state.db)
+    .await?;
+    Ok(Json(preferences))
+}
+
+// DELETE /voters/:name/preferences
+pub async fn delete_preferences(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = sqlx::query("DELETE FROM voter_preferences WHERE voter_key = ?")
+        .bind(voter_key(&name)?)
+        .execute(&state.db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("{name} has no preferences saved")));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The preferences of everyone in a group, combined so that a restaurant has to suit all of them.
+// Voters without saved preferences don't constrain anything
+#[derive(Default)]
+pub struct GroupPreferences {
+    dietary_needs: Vec<String>, // everyone's needs together
+    max_price_tier: Option<i64>, // the tightest budget
+    cuisines: Vec<Vec<String>>, // one entry per voter who listed any cuisines
+}
+
+impl GroupPreferences {
+    pub async fn load(db: &SqlitePool, voters: &[String]) -> Result<GroupPreferences, sqlx::Error> {
+        let keys: Vec<String> = voters.iter().map(|name| names::fold(name)).collect();
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            cuisines: JsonColumn<Vec<String>>,
+            max_price_tier: Option<i64>,
+            dietary_needs: JsonColumn<Vec<String>>,
+        }
+        let rows = sqlx::query_as::<_, Row>(
+            "SELECT cuisines, max_price_tier, dietary_needs FROM voter_preferences
+            WHERE voter_key IN (SELECT value FROM json_each(?))",
+        )
+        .bind(JsonColumn(keys))
+        .fetch_all(db)
+        .await?;
+
+        let mut group = GroupPreferences::default();
+        for row in rows {
+            group.dietary_needs.extend(row.dietary_needs.0);
+            group.max_price_tier = match (group.max_price_tier, row.max_price_tier) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if !row.cuisines.is_empty() {
+                group.cuisines.push(row.cuisines.0);
+            }
+        }
+        group.dietary_needs.sort();
+        group.dietary_needs.dedup();
+        Ok(group)
+    }
+
+    // Whether the restaurant meets every dietary need and fits every budget. An unknown price tier passes,
+    // for the same reason unknown distances do
+    pub fn allows(&self, details: &RestaurantDetails) -> bool {
+        let tags = details.dietary_tags();
+        let affordable = self
+            .max_price_tier
+            .zip(details.price_tier())
+            .is_none_or(|(max, tier)| tier <= max);
+        affordable && self.dietary_needs.iter().all(|need| tags.contains(need))
+    }
+
+    // The share of voters with cuisine likes who like this restaurant's cuisine, between 0 and 1.
+    // None when nobody in the group listed any
+    pub fn cuisine_fit(&self, details: &RestaurantDetails) -> Option<f64> {
+        if self.cuisines.is_empty() {
+            return None;
+        }
+        let fans = match details.cuisine() {
+            Some(cuisine) => self.cuisines.iter().filter(|liked| liked.iter().any(|c| c == cuisine)).count(),
+            None => 0,
+        };
+        Some(fans as f64 / self.cuisines.len() as f64)
+    }
+}
@@ -0,0 +1,114 @@
+// The forecast at lunch time, from Open-Meteo, so polls can warn about places that are miserable to reach in the rain
+// https://open-meteo.com/en/docs
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::geo::Coordinates;
+use crate::restaurants::Restaurant;
+use crate::AppState;
+
+// What the poll records about the weather; stored as JSON on the poll and returned with it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Weather {
+    time: String, // the forecast hour, local time
+    temperature_celsius: Option<f64>,
+    precipitation_probability: Option<f64>, // percent
+    precipitation_mm: Option<f64>,
+    weather_code: Option<i64>, // WMO code, see the Open-Meteo docs
+    pub rainy: bool,
+}
+
+// Open-Meteo answers with parallel arrays, one entry per hour
+#[derive(Deserialize)]
+struct ForecastResponse {
+    hourly: Hourly,
+}
+
+#[derive(Deserialize)]
+struct Hourly {
+    time: Vec<String>,
+    #[serde(default)]
+    temperature_2m: Vec<Option<f64>>,
+    #[serde(default)]
+    precipitation_probability: Vec<Option<f64>>,
+    #[serde(default)]
+    precipitation: Vec<Option<f64>>,
+    #[serde(default)]
+    weather_code: Vec<Option<i64>>,
+}
+
+// Looks up the forecast for lunch_at ("YYYY-MM-DD HH:MM") at the office. The weather is a nice-to-have,
+// so no office location, a lunch outside the forecast range or an unreachable API all just mean no weather
+pub async fn forecast(state: &AppState, office: Option<Coordinates>, lunch_at: &str) -> Option<Weather> {
+    let office = office?;
+    match fetch(state, office.latitude, office.longitude, lunch_at).await {
+        Ok(weather) => weather,
+        Err(err) => {
+            eprintln!("weather forecast failed: {err:?}");
+            None
+        }
+    }
+}
+
+async fn fetch(state: &AppState, latitude: f64, longitude: f64, lunch_at: &str) -> Result<Option<Weather>, ApiError> {
+    let (Some(day), Some(hour)) = (lunch_at.get(..10), lunch_at.get(11..13)) else {
+        return Ok(None);
+    };
+    let response = state
+        .http
+        .get(&state.config.weather_url)
+        .query(&[
+            ("latitude", latitude.to_string()),
+            ("longitude", longitude.to_string()),
+            ("hourly", "temperature_2m,precipitation_probability,precipitation,weather_code".to_string()),
+            ("timezone", "auto".to_string()),
+            ("start_date", day.to_string()),
+            ("end_date", day.to_string()),
+        ])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("Open-Meteo returned {status}: {body}")));
+    }
+    let forecast: ForecastResponse = response.json().await?;
+
+    // Hours come back as "2024-05-17T12:00"; lunch at 12:30 uses the 12:00 forecast
+    let hourly = forecast.hourly;
+    let wanted = format!("{day}T{hour}:00");
+    let Some(index) = hourly.time.iter().position(|time| *time == wanted) else {
+        return Ok(None);
+    };
+    let value = |values: &[Option<f64>]| values.get(index).copied().flatten();
+    let precipitation_probability = value(&hourly.precipitation_probability);
+    let precipitation_mm = value(&hourly.precipitation);
+    let rainy = precipitation_probability.is_some_and(|chance| chance >= state.config.rain_probability_percent)
+        || precipitation_mm.is_some_and(|mm| mm >= 0.5);
+    Ok(Some(Weather {
+        time: wanted,
+        temperature_celsius: value(&hourly.temperature_2m),
+        precipitation_probability,
+        precipitation_mm,
+        weather_code: hourly.weather_code.get(index).copied().flatten(),
+        rainy,
+    }))
+}
+
+// Why a restaurant is a poor choice in this weather, if it is: sitting outside, or a long walk from the office
+pub fn warning(
+    state: &AppState,
+    weather: &Weather,
+    office: Option<Coordinates>,
+    restaurant: &Restaurant,
+) -> Option<String> {
+    if !weather.rainy {
+        return None;
+    }
+    if restaurant.outdoor_seating {
+        return Some("rain is likely and the seating is outdoors".to_string());
+    }
+    let minutes = restaurant.details.walking_minutes(office)?;
+    (minutes > state.config.rain_max_walking_minutes as f64)
+        .then(|| format!("rain is likely and it's a {} minute walk", minutes.ceil()))
+}
@@ -0,0 +1,203 @@
+// Enriches registered restaurants with data from the Yelp Fusion API: rating, categories, opening hours and photos.
+// Runs as a background task when YELP_API_KEY is set, and can be triggered by an admin
+// https://docs.developer.yelp.com/docs/fusion-intro
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json as JsonColumn;
+use std::time::Duration;
+
+use crate::auth::Admin;
+use crate::error::ApiError;
+use crate::{fuzzy, AppState};
+
+const API_BASE: &str = "https://api.yelp.com/v3";
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    businesses: Vec<BusinessSummary>,
+}
+
+#[derive(Deserialize)]
+struct BusinessSummary {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Business {
+    rating: Option<f64>,
+    #[serde(default)]
+    categories: Vec<Category>,
+    #[serde(default)]
+    hours: Vec<Hours>,
+    #[serde(default)]
+    photos: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Hours {
+    #[serde(default)]
+    open: Vec<Value>, // kept exactly as Yelp sends it: {day, start, end, is_overnight}
+}
+
+#[derive(Default, Serialize)]
+pub struct EnrichSummary {
+    matched: Vec<String>,
+    unmatched: Vec<String>,
+}
+
+// Starts the periodic enrichment loop if Yelp is configured. tokio::spawn runs it alongside the web server
+// https://docs.rs/tokio/latest/tokio/fn.spawn.html
+pub fn spawn_enrichment(state: AppState) {
+    if state.config.yelp_api_key.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        // interval's first tick completes immediately, so restaurants are enriched right after startup too
+        // https://docs.rs/tokio/latest/tokio/time/fn.interval.html
+        let mut interval = tokio::time::interval(state.config.yelp_refresh_interval);
+        loop {
+            interval.tick().await;
+            match enrich(&state, false).await {
+                Ok(summary) => println!(
+                    "yelp enrichment: {} matched, {} unmatched",
+                    summary.matched.len(),
+                    summary.unmatched.len()
+                ),
+                Err(err) => eprintln!("yelp enrichment failed: {err:?}"),
+            }
+        }
+    });
+}
+
+// POST /restaurants/enrich/yelp (admin): enriches every restaurant now, rather than waiting for the next run
+pub async fn enrich_now(_admin: Admin, State(state): State<AppState>) -> Result<Json<EnrichSummary>, ApiError> {
+    if state.config.yelp_api_key.is_none() {
+        return Err(ApiError::NotConfigured("set YELP_API_KEY to enrich restaurants from Yelp".to_string()));
+    }
+    Ok(Json(enrich(&state, true).await?))
+}
+
+// Restaurants enriched within the last refresh interval are skipped unless everything was asked for
+async fn enrich(state: &AppState, everything: bool) -> Result<EnrichSummary, ApiError> {
+    let max_age = format!("-{} seconds", state.config.yelp_refresh_interval.as_secs());
+    let restaurants: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, name, address FROM restaurants
+        WHERE status = 'approved' AND deleted_at IS NULL
+        AND (? OR yelp_enriched_at IS NULL OR yelp_enriched_at < datetime('now', ?))
+        ORDER BY id",
+    )
+    .bind(everything)
+    .bind(max_age)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut summary = EnrichSummary::default();
+    for (id, name, address) in restaurants {
+        let business = find_business(state, id, &name, address.as_deref()).await?;
+        let Some(business) = business else {
+            sqlx::query("UPDATE restaurants SET yelp_enriched_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(id)
+                .execute(&state.db)
+                .await?;
+            summary.unmatched.push(name);
+            continue;
+        };
+        let categories: Vec<String> = business.categories.into_iter().map(|c| c.title).collect();
+        let hours: Vec<Value> = business.hours.into_iter().flat_map(|h| h.open).collect();
+        sqlx::query(
+            "UPDATE restaurants SET yelp_rating = ?, yelp_categories = ?, yelp_hours = ?, yelp_photos = ?,
+                yelp_enriched_at = CURRENT_TIMESTAMP
+            WHERE id = ?",
+        )
+        .bind(business.rating)
+        .bind(JsonColumn(categories))
+        .bind(JsonColumn(hours))
+        .bind(JsonColumn(business.photos))
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+        summary.matched.push(name);
+        // Stay well inside Yelp's per-second rate limit
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    Ok(summary)
+}
+
+// Looks the restaurant up on Yelp. A previously matched business is fetched directly by id; otherwise we search
+// near the office (or the restaurant's address) and accept the first result whose name is close enough
+async fn find_business(
+    state: &AppState,
+    id: i64,
+    name: &str,
+    address: Option<&str>,
+) -> Result<Option<Business>, ApiError> {
+    let known: Option<String> =
+        sqlx::query_scalar("SELECT external_id FROM restaurant_external_ids WHERE source = 'yelp' AND restaurant_id = ?")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await?;
+    let business_id = match known {
+        Some(business_id) => business_id,
+        None => {
+            let mut params = vec![("term", name.to_string()), ("limit", "5".to_string())];
+            match (state.config.office_location, address) {
+                (Some(office), _) => {
+                    params.push(("latitude", office.latitude.to_string()));
+                    params.push(("longitude", office.longitude.to_string()));
+                }
+                (None, Some(address)) => params.push(("location", address.to_string())),
+                // Yelp needs somewhere to search around
+                (None, None) => return Ok(None),
+            }
+            let search: SearchResponse = get(state, "/businesses/search", &params).await?;
+            let threshold = state.config.fuzzy_match_threshold;
+            let Some(found) = search
+                .businesses
+                .into_iter()
+                .find(|business| {
+                    fuzzy::similarity(name, &business.name) >= threshold || fuzzy::words_contained(name, &business.name)
+                })
+            else {
+                return Ok(None);
+            };
+            sqlx::query("INSERT OR IGNORE INTO restaurant_external_ids (restaurant_id, source, external_id) VALUES (?, 'yelp', ?)")
+                .bind(id)
+                .bind(&found.id)
+                .execute(&state.db)
+                .await?;
+            found.id
+        }
+    };
+    Ok(Some(get(state, &format!("/businesses/{business_id}"), &[]).await?))
+}
+
+// Generic function: T is whatever response type the caller expects, as long as serde can deserialize it
+async fn get<T: serde::de::DeserializeOwned>(
+    state: &AppState,
+    path: &str,
+    params: &[(&str, String)],
+) -> Result<T, ApiError> {
+    let api_key = state.config.yelp_api_key.as_deref().unwrap_or_default();
+    let response = state
+        .http
+        .get(format!("{API_BASE}{path}"))
+        .bearer_auth(api_key)
+        .query(params)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ApiError::Upstream(format!("Yelp returned {status}: {body}")));
+    }
+    Ok(response.json().await?)
+}